@@ -1,27 +1,49 @@
+use crate::agent::message_builder::{ClaudeApiRequest, ToolChoice};
+use crate::agent::loop_detector::{self, LoopCheck, LoopDetector};
+use crate::agent::plan::{self, PLAN_TOOL};
+use crate::agent::structured_output;
+use crate::agent::sub_agent::{self, DelegateRequest, DELEGATE_TOOL};
 use crate::agent::{
     AgentConfig, AgentContent, AgentEvent, AgentMessage, ContentBlock, MessageBuilder,
-    PlanStepInfo, ToolExecutor, ToolUse,
+    PlanStepInfo, StopReason, ToolExecutor, ToolResult, ToolUse,
 };
-use crate::llm_client::{ApiFormat, ProviderConfig};
+use crate::llm_client::routing::{ModelRoute, RoutePurpose};
+use crate::llm_client::{ApiFormat, LLMClient, LLMError, LlmProvider, LlmResponse, StreamEvent};
 use crate::mcp::MCPManager;
+use futures::future::{join_all, BoxFuture};
 use regex::Regex;
-use reqwest::Client;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+
+/// Sent as the final turn when `max_turns` is reached
+const WRAP_UP_PROMPT: &str = "You have reached the turn limit for this run and cannot call any more tools. \
+Reply with a short wrap-up for the user:\n\
+1. What has been completed so far\n\
+2. What remains to be done, in order\n\
+3. Anything the user should check or decide before continuing";
 
 #[allow(dead_code)]
 pub struct AgentLoop {
-    client: Client,
-    api_key: String,
-    base_url: String,
+    provider: Arc<dyn LlmProvider>,
     config: AgentConfig,
     model: String,
     max_tokens: u32,
     temperature: Option<f32>,
     tool_executor: ToolExecutor,
     message_builder: MessageBuilder,
-    /// Provider configuration for determining API format
-    provider_config: ProviderConfig,
+    mcp_manager: Arc<MCPManager>,
+    /// Limits how many sub-agents run at once, shared by the whole agent tree
+    sub_agent_slots: Arc<Semaphore>,
+    /// Slot this sub-agent runs in; given back while it waits on its own sub-agents
+    slot: Mutex<Option<OwnedSemaphorePermit>>,
+    /// Tried in order when the primary model fails with a retryable error
+    fallbacks: Vec<ModelRoute>,
+    /// Models for specific jobs, in place of the primary one
+    routes: HashMap<RoutePurpose, ModelRoute>,
+    /// Position in the primary + fallbacks chain; stays put once a fallback is used
+    active_route: AtomicUsize,
 }
 
 impl AgentLoop {
@@ -48,42 +70,89 @@ impl AgentLoop {
         temperature: Option<f32>,
         mcp_manager: Arc<MCPManager>,
         provider_id: Option<&str>,
+    ) -> Self {
+        // Infer provider from provider_id or model, keeping the preset URL if none is set
+        let provider = LLMClient::new(api_key, Some(base_url), provider_id, Some(&model)).into_provider();
+
+        Self::from_provider(provider, config, model, max_tokens, temperature, mcp_manager)
+    }
+
+    /// Agent driving an already configured provider
+    pub fn from_provider(
+        provider: Arc<dyn LlmProvider>,
+        config: AgentConfig,
+        model: String,
+        max_tokens: u32,
+        temperature: Option<f32>,
+        mcp_manager: Arc<MCPManager>,
     ) -> Self {
         let tool_executor = ToolExecutor::new(config.project_path.clone())
+            .with_project_settings(config.bash_policy.clone(), config.docker_defaults.clone())
             .with_mcp_manager(mcp_manager.clone());
         let message_builder = MessageBuilder::new(
             config.clone(),
             model.clone(),
             max_tokens,
             temperature,
-        ).with_mcp_manager(mcp_manager);
-
-        // Infer config from provider_id or model
-        let mut provider_config = if let Some(pid) = provider_id {
-            ProviderConfig::from_preset(pid)
-        } else {
-            ProviderConfig::from_model(&model)
-        };
-
-        // Use custom base_url
-        if !base_url.is_empty() {
-            provider_config.base_url = base_url.clone();
-        }
+        )
+        .with_mcp_manager(mcp_manager.clone())
+        .with_prompt_caching(provider.config().api_format.wire_format(&model) == ApiFormat::Anthropic);
+        let sub_agent_slots = Arc::new(Semaphore::new(config.max_concurrent_sub_agents.max(1)));
 
         Self {
-            client: Client::new(),
-            api_key,
-            base_url: provider_config.base_url.clone(),
+            provider,
             config,
             model,
             max_tokens,
             temperature,
             tool_executor,
             message_builder,
-            provider_config,
+            mcp_manager,
+            sub_agent_slots,
+            slot: Mutex::new(None),
+            fallbacks: Vec::new(),
+            routes: HashMap::new(),
+            active_route: AtomicUsize::new(0),
         }
     }
 
+    pub fn with_fallbacks(mut self, fallbacks: Vec<ModelRoute>) -> Self {
+        self.fallbacks = fallbacks;
+        self
+    }
+
+    pub fn with_route(mut self, purpose: RoutePurpose, route: ModelRoute) -> Self {
+        self.routes.insert(purpose, route);
+        self
+    }
+
+    /// Create a sub-agent that shares this agent's MCP servers, routes and sub-agent
+    /// slots, running in `slot`. It uses the sub-agent route if one is set, else the
+    /// model this agent currently uses and what's left of its fallback chain.
+    fn spawn_child(&self, config: AgentConfig, slot: OwnedSemaphorePermit) -> AgentLoop {
+        let (route, fallbacks) = match self.routes.get(&RoutePurpose::SubAgent) {
+            Some(route) => (route.clone(), self.fallbacks.clone()),
+            None => {
+                let active = self.active_route.load(Ordering::Relaxed);
+                (self.current_route(), self.fallbacks[active..].to_vec())
+            }
+        };
+
+        let mut child = Self::from_provider(
+            route.provider,
+            config,
+            route.model,
+            self.max_tokens,
+            self.temperature,
+            self.mcp_manager.clone(),
+        )
+        .with_fallbacks(fallbacks);
+        child.routes = self.routes.clone();
+        child.sub_agent_slots = self.sub_agent_slots.clone();
+        child.slot = Mutex::new(Some(slot));
+        child
+    }
+
     pub async fn run(
         &self,
         initial_message: String,
@@ -104,13 +173,31 @@ impl AgentLoop {
         event_tx: mpsc::Sender<AgentEvent>,
     ) -> Result<Vec<AgentMessage>, String> {
         let mut turn = 0;
+        // Once the model uses `update_plan`, text markers are no longer scraped
+        let mut structured_plan = false;
+        let mut loop_detector = LoopDetector::new();
+
+        // Report project config and instruction files picked up for this run
+        if let Some(info) = &self.config.project_context {
+            let _ = event_tx
+                .send(AgentEvent::ProjectContext {
+                    config_file: info.config_file.clone(),
+                    instruction_files: info.instruction_files.clone(),
+                    error: info.error.clone(),
+                })
+                .await;
+        }
 
         loop {
             turn += 1;
 
             if turn > self.config.max_turns {
+                if self.config.wrap_up_on_max_turns {
+                    self.wrap_up(&mut messages, &event_tx).await;
+                }
                 let _ = event_tx
-                    .send(AgentEvent::Error {
+                    .send(AgentEvent::Stopped {
+                        reason: StopReason::TurnLimit,
                         message: format!("Reached maximum turns ({})", self.config.max_turns),
                     })
                     .await;
@@ -120,21 +207,33 @@ impl AgentLoop {
             // Build and send request
             let request = self.message_builder.build_request(&messages).await;
 
-            let response = self.send_request(&request, &event_tx).await?;
+            let response = match self.send_request(&request, &event_tx).await {
+                Ok(response) => response,
+                Err(e) => {
+                    let _ = event_tx.send(AgentEvent::Error { message: e.clone() }).await;
+                    return Err(e);
+                }
+            };
 
-            // Parse response
-            let (text_content, tool_uses) = self.parse_response(&response)?;
+            let LlmResponse {
+                text: text_content,
+                thinking,
+                tool_uses,
+                ..
+            } = response;
+
+            // Fall back to <plan> tags and step markers for models that don't call update_plan
+            structured_plan |= tool_uses.iter().any(|tu| tu.name == PLAN_TOOL);
+            if !structured_plan {
+                if let Some(plan_steps) = self.parse_plan(&text_content) {
+                    let _ = event_tx
+                        .send(AgentEvent::Plan { steps: plan_steps })
+                        .await;
+                }
 
-            // Parse and emit plan if present
-            if let Some(plan_steps) = self.parse_plan(&text_content) {
-                let _ = event_tx
-                    .send(AgentEvent::Plan { steps: plan_steps })
-                    .await;
+                self.emit_step_markers(&text_content, &event_tx).await;
             }
 
-            // Parse and emit step markers
-            self.emit_step_markers(&text_content, &event_tx).await;
-
             // Emit text content
             if !text_content.is_empty() {
                 let _ = event_tx
@@ -148,7 +247,8 @@ impl AgentLoop {
             let assistant_content = if tool_uses.is_empty() {
                 AgentContent::Text(text_content)
             } else {
-                let mut blocks = Vec::new();
+                // Thinking must precede the text and tool calls it belongs to
+                let mut blocks = thinking;
                 if !text_content.is_empty() {
                     blocks.push(ContentBlock::Text { text: text_content });
                 }
@@ -170,16 +270,24 @@ impl AgentLoop {
 
             // If no tool uses, we're done
             if tool_uses.is_empty() {
+                if let Some(schema) = &self.config.response_schema {
+                    if let Err(e) = self.structured_answer(&mut messages, schema, &event_tx).await {
+                        let _ = event_tx.send(AgentEvent::Error { message: e.clone() }).await;
+                        return Err(e);
+                    }
+                }
                 let _ = event_tx
                     .send(AgentEvent::Done { total_turns: turn })
                     .await;
                 break;
             }
 
-            // Execute tools
-            let mut tool_results = Vec::new();
+            // Execute tools. Delegations run concurrently after the other tools;
+            // results keep the order of the tool calls.
+            let mut tool_results: Vec<Option<ToolResult>> = vec![None; tool_uses.len()];
+            let mut delegations = Vec::new();
 
-            for tool_use in &tool_uses {
+            for (index, tool_use) in tool_uses.iter().enumerate() {
                 // Emit tool start
                 let _ = event_tx
                     .send(AgentEvent::ToolStart {
@@ -188,19 +296,61 @@ impl AgentLoop {
                     })
                     .await;
 
+                if tool_use.name == DELEGATE_TOOL {
+                    delegations.push((index, tool_use));
+                    continue;
+                }
+
                 // Execute tool
-                let result = self.tool_executor.execute(tool_use).await;
+                let result = if tool_use.name == PLAN_TOOL {
+                    self.apply_plan_update(tool_use, &event_tx).await
+                } else {
+                    self.tool_executor.execute(tool_use).await
+                };
+                self.emit_tool_end(tool_use, &result, &event_tx).await;
+                tool_results[index] = Some(result);
+            }
 
-                // Emit tool end
-                let _ = event_tx
-                    .send(AgentEvent::ToolEnd {
-                        tool: tool_use.name.clone(),
-                        result: result.content.clone(),
-                        success: result.is_error.is_none(),
-                    })
-                    .await;
+            let outcomes = if delegations.is_empty() {
+                Vec::new()
+            } else {
+                self.waiting_for_sub_agents(join_all(
+                    delegations
+                        .iter()
+                        .map(|(_, tool_use)| self.run_sub_agent(tool_use, &event_tx)),
+                ))
+                .await
+            };
+            for ((index, tool_use), result) in delegations.into_iter().zip(outcomes) {
+                self.emit_tool_end(tool_use, &result, &event_tx).await;
+                tool_results[index] = Some(result);
+            }
 
-                tool_results.push(result);
+            let mut tool_results: Vec<ToolResult> = tool_results.into_iter().flatten().collect();
+
+            // Check for repeated identical calls
+            let mut stuck = None;
+            for (tool_use, result) in tool_uses.iter().zip(tool_results.iter_mut()) {
+                match loop_detector.record(tool_use, result) {
+                    LoopCheck::Ok => {}
+                    LoopCheck::Repeated(count) => {
+                        result.content.push_str(&loop_detector::corrective_note(&tool_use.name, count));
+                        let _ = event_tx
+                            .send(AgentEvent::Warning {
+                                message: format!(
+                                    "`{}` was called {} times with the same input and result",
+                                    tool_use.name, count
+                                ),
+                            })
+                            .await;
+                    }
+                    LoopCheck::Stuck(count) => {
+                        stuck = Some(format!(
+                            "Stopped: `{}` was called {} times with the same input and result",
+                            tool_use.name, count
+                        ));
+                    }
+                }
             }
 
             // Add tool results as user message
@@ -209,6 +359,16 @@ impl AgentLoop {
                 content: AgentContent::ToolResults(tool_results),
             });
 
+            if let Some(message) = stuck {
+                let _ = event_tx
+                    .send(AgentEvent::Stopped {
+                        reason: StopReason::LoopDetected,
+                        message,
+                    })
+                    .await;
+                break;
+            }
+
             // Emit turn complete
             let _ = event_tx.send(AgentEvent::TurnComplete { turn }).await;
         }
@@ -216,740 +376,295 @@ impl AgentLoop {
         Ok(messages)
     }
 
-    async fn send_request(
+    async fn emit_tool_end(
         &self,
-        request: &crate::agent::message_builder::ClaudeApiRequest,
+        tool_use: &ToolUse,
+        result: &ToolResult,
         event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        match self.provider_config.api_format {
-            ApiFormat::Anthropic => self.send_anthropic_request(request, event_tx).await,
-            ApiFormat::OpenAI | ApiFormat::OpenAICompatible => {
-                self.send_openai_request(request, event_tx).await
-            }
-            ApiFormat::Google => self.send_google_request(request, event_tx).await,
-            _ => Err(format!("Unsupported API format: {:?}", self.provider_config.api_format)),
-        }
+    ) {
+        let _ = event_tx
+            .send(AgentEvent::ToolEnd {
+                tool: tool_use.name.clone(),
+                result: result.content.clone(),
+                success: result.is_error.is_none(),
+            })
+            .await;
     }
 
-    /// Send Anthropic format request
-    async fn send_anthropic_request(
-        &self,
-        request: &crate::agent::message_builder::ClaudeApiRequest,
-        event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        let url = format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
-
-        let mut req = self.client.post(&url)
-            .header("Content-Type", "application/json")
-            .header("anthropic-version", "2023-06-01");
+    /// Final tool-free turn asking the model to summarise progress and remaining work
+    async fn wrap_up(&self, messages: &mut Vec<AgentMessage>, event_tx: &mpsc::Sender<AgentEvent>) {
+        messages.push(AgentMessage {
+            role: "user".to_string(),
+            content: AgentContent::Text(WRAP_UP_PROMPT.to_string()),
+        });
 
-        // Add authentication
-        if !self.api_key.is_empty() {
-            req = req.header("x-api-key", &self.api_key);
-        }
+        let mut request = self.message_builder.build_request(messages).await;
+        request.tool_choice = Some(ToolChoice::None);
 
-        let response = req
-            .json(request)
-            .send()
-            .await
-            .map_err(|e| format!("HTTP error: {}", e))?;
+        let response = match self.routes.get(&RoutePurpose::Summary) {
+            Some(route) => match self.stream_from(route, &route.retarget(&request), event_tx).await {
+                Err(e) if e.is_retryable() => {
+                    self.warn_switch(&route.label(), &e, &self.current_route().label(), event_tx).await;
+                    self.send_request(&request, event_tx).await
+                }
+                response => response.map_err(|e| e.to_string()),
+            },
+            None => self.send_request(&request, event_tx).await,
+        };
 
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("API error: {}", error_text));
+        match response.map(|response| response.text) {
+            Ok(text) if !text.trim().is_empty() => {
+                let _ = event_tx
+                    .send(AgentEvent::Text {
+                        content: text.clone(),
+                    })
+                    .await;
+                messages.push(AgentMessage {
+                    role: "assistant".to_string(),
+                    content: AgentContent::Text(text),
+                });
+            }
+            Ok(_) => {
+                messages.pop();
+            }
+            Err(e) => {
+                messages.pop();
+                let _ = event_tx
+                    .send(AgentEvent::Warning {
+                        message: format!("Wrap-up turn failed: {}", e),
+                    })
+                    .await;
+            }
         }
-
-        self.handle_stream_response(response, event_tx).await
     }
 
-    /// Send OpenAI compatible format request
-    async fn send_openai_request(
+    /// Final answer matching the response schema. A final message that already
+    /// validates is used as is; otherwise a tool-free, schema-constrained turn asks
+    /// for it, with one repair turn if that answer fails validation.
+    async fn structured_answer(
         &self,
-        request: &crate::agent::message_builder::ClaudeApiRequest,
+        messages: &mut Vec<AgentMessage>,
+        schema: &serde_json::Value,
         event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        let base = self.base_url.trim_end_matches('/');
-        let url = if base.ends_with("/v1") {
-            format!("{}/chat/completions", base)
-        } else {
-            format!("{}/v1/chat/completions", base)
+    ) -> Result<(), String> {
+        let answered = match messages.last().map(|m| &m.content) {
+            Some(AgentContent::Text(text)) => structured_output::valid_answer(schema, text),
+            _ => None,
         };
 
-        // Convert request format to OpenAI format
-        let openai_request = self.convert_to_openai_format(request);
-
-        let mut req = self.client.post(&url)
-            .header("Content-Type", "application/json");
-
-        // Add authentication (if needed)
-        if !self.api_key.is_empty() {
-            req = req.header("Authorization", format!("Bearer {}", self.api_key));
-        }
-
-        let response = req
-            .json(&openai_request)
-            .send()
-            .await
-            .map_err(|e| format!("HTTP error: {}", e))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("API error: {}", error_text));
-        }
-
-        self.handle_openai_stream_response(response, event_tx).await
-    }
-
-    /// Convert Claude request format to OpenAI format
-    fn convert_to_openai_format(
-        &self,
-        request: &crate::agent::message_builder::ClaudeApiRequest,
-    ) -> serde_json::Value {
-        use crate::agent::message_builder::ApiContent;
-
-        // Build messages, including system prompt
-        let mut messages: Vec<serde_json::Value> = Vec::new();
-
-        // Add system message
-        if !request.system.is_empty() {
-            messages.push(serde_json::json!({
-                "role": "system",
-                "content": request.system
-            }));
-        }
-
-        // Convert conversation messages
-        for msg in &request.messages {
-            let role = &msg.role;
-
-            match &msg.content {
-                ApiContent::Text(text) => {
-                    messages.push(serde_json::json!({
-                        "role": role,
-                        "content": text
-                    }));
-                }
-                ApiContent::Blocks(blocks) => {
-                    // Handle content blocks (text, tool_use, tool_result)
-                    let mut text_parts: Vec<String> = Vec::new();
-                    let mut tool_calls: Vec<serde_json::Value> = Vec::new();
-
-                    for block in blocks {
-                        let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                        match block_type {
-                            "text" => {
-                                if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
-                                    text_parts.push(text.to_string());
-                                }
-                            }
-                            "tool_use" => {
-                                tool_calls.push(serde_json::json!({
-                                    "id": block.get("id"),
-                                    "type": "function",
-                                    "function": {
-                                        "name": block.get("name"),
-                                        "arguments": serde_json::to_string(block.get("input").unwrap_or(&serde_json::json!({}))).unwrap_or_default()
-                                    }
-                                }));
-                            }
-                            "tool_result" => {
-                                // OpenAI uses tool role to represent tool results
-                                messages.push(serde_json::json!({
-                                    "role": "tool",
-                                    "tool_call_id": block.get("tool_use_id"),
-                                    "content": block.get("content")
-                                }));
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    // If there's text content
-                    if !text_parts.is_empty() {
-                        let mut msg_obj = serde_json::json!({
-                            "role": role,
-                            "content": text_parts.join("\n")
-                        });
-
-                        // If there are tool_calls
-                        if !tool_calls.is_empty() {
-                            msg_obj["tool_calls"] = serde_json::json!(tool_calls);
-                        }
-
-                        messages.push(msg_obj);
-                    } else if !tool_calls.is_empty() {
-                        // Only tool_calls, no text
-                        messages.push(serde_json::json!({
-                            "role": role,
-                            "content": serde_json::Value::Null,
-                            "tool_calls": tool_calls
-                        }));
-                    }
-                }
-            }
-        }
-
-        // Convert tools definition
-        let tools: Vec<serde_json::Value> = request.tools.iter().map(|tool| {
-            serde_json::json!({
-                "type": "function",
-                "function": {
-                    "name": tool.name,
-                    "description": tool.description,
-                    "parameters": tool.input_schema
-                }
-            })
-        }).collect();
-
-        let mut openai_request = serde_json::json!({
-            "model": request.model,
-            "stream": request.stream,
-            "messages": messages
-        });
-
-        // Use correct max tokens parameter based on model
-        let model_lower = request.model.to_lowercase();
-        let is_legacy = model_lower.contains("gpt-3.5")
-            || (model_lower.contains("gpt-4") && !model_lower.contains("gpt-4o") && !model_lower.contains("gpt-4-turbo"));
-
-        if is_legacy {
-            openai_request["max_tokens"] = serde_json::json!(request.max_tokens);
-        } else {
-            openai_request["max_completion_tokens"] = serde_json::json!(request.max_tokens);
-        }
-
-        // Only add temperature for non-reasoning models (o1, o3, gpt-5 don't support custom temperature)
-        let is_reasoning = model_lower.starts_with("o1") || model_lower.starts_with("o3") || model_lower.starts_with("gpt-5")
-            || model_lower.contains("-o1") || model_lower.contains("-o3")
-            || model_lower.contains("o1-") || model_lower.contains("o3-");
-
-        if !is_reasoning {
-            if let Some(temp) = request.temperature {
-                openai_request["temperature"] = serde_json::json!(temp);
+        let output = match answered {
+            Some(value) => value,
+            None => {
+                messages.push(AgentMessage {
+                    role: "user".to_string(),
+                    content: AgentContent::Text(structured_output::STRUCTURED_OUTPUT_PROMPT.to_string()),
+                });
+                let mut request = self.message_builder.build_request(messages).await;
+                request.tool_choice = Some(ToolChoice::None);
+
+                let value = structured_output::complete(request, schema, |request| async move {
+                    self.send_request(&request, event_tx).await
+                })
+                .await?;
+                messages.push(AgentMessage {
+                    role: "assistant".to_string(),
+                    content: AgentContent::Text(value.to_string()),
+                });
+                value
             }
-        }
-
-        if !tools.is_empty() {
-            openai_request["tools"] = serde_json::json!(tools);
-            openai_request["tool_choice"] = serde_json::json!("auto");
-        }
+        };
 
-        openai_request
+        let _ = event_tx.send(AgentEvent::StructuredOutput { output }).await;
+        Ok(())
     }
 
-    /// Send Google Gemini format request
-    async fn send_google_request(
+    /// Apply an `update_plan` tool call and emit the new plan
+    async fn apply_plan_update(
         &self,
-        request: &crate::agent::message_builder::ClaudeApiRequest,
+        tool_use: &ToolUse,
         event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        let base = self.base_url.trim_end_matches('/');
-        let url = format!("{}/v1beta/models/{}:streamGenerateContent?alt=sse", base, request.model);
-
-        // Convert request format to Google format
-        let google_request = self.convert_to_google_format(request);
-
-        let response = self.client.post(&url)
-            .header("Content-Type", "application/json")
-            .header("x-goog-api-key", &self.api_key)
-            .json(&google_request)
-            .send()
-            .await
-            .map_err(|e| format!("HTTP error: {}", e))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("API error: {}", error_text));
+    ) -> ToolResult {
+        match plan::parse_update(&tool_use.input) {
+            Ok(steps) => {
+                let rendered = plan::render(&steps);
+                let _ = event_tx.send(AgentEvent::Plan { steps }).await;
+                ToolResult::success(tool_use.id.clone(), rendered)
+            }
+            Err(e) => ToolResult::error(tool_use.id.clone(), e),
         }
-
-        self.handle_google_stream_response(response, event_tx).await
     }
 
-    /// Convert Claude request format to Google Gemini format
-    fn convert_to_google_format(
-        &self,
-        request: &crate::agent::message_builder::ClaudeApiRequest,
-    ) -> serde_json::Value {
-        use crate::agent::message_builder::ApiContent;
-
-        // Build a map of tool_use_id -> thought_signature for looking up when building functionResponse
-        let mut thought_signatures: std::collections::HashMap<String, String> = std::collections::HashMap::new();
-        for msg in &request.messages {
-            if let ApiContent::Blocks(blocks) = &msg.content {
-                for block in blocks {
-                    if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
-                        if let (Some(id), Some(sig)) = (
-                            block.get("id").and_then(|v| v.as_str()),
-                            block.get("thought_signature").and_then(|v| v.as_str()),
-                        ) {
-                            thought_signatures.insert(id.to_string(), sig.to_string());
-                        }
-                    }
-                }
+    /// Run a `delegate` tool call in a child agent and return its final summary.
+    ///
+    /// Child events are forwarded to `event_tx` wrapped in `AgentEvent::SubAgent`.
+    /// Boxed because the child runs `run_with_history` recursively.
+    fn run_sub_agent<'a>(
+        &'a self,
+        tool_use: &'a ToolUse,
+        event_tx: &'a mpsc::Sender<AgentEvent>,
+    ) -> BoxFuture<'a, ToolResult> {
+        Box::pin(async move {
+            if !self.config.can_delegate() {
+                return ToolResult::error(
+                    tool_use.id.clone(),
+                    format!(
+                        "Delegation not allowed at depth {} (max {})",
+                        self.config.delegation_depth, self.config.max_delegation_depth
+                    ),
+                );
             }
-        }
 
-        // Build contents array
-        let mut contents: Vec<serde_json::Value> = Vec::new();
+            let request = match DelegateRequest::from_input(&tool_use.input) {
+                Ok(r) => r,
+                Err(e) => return ToolResult::error(tool_use.id.clone(), e),
+            };
 
-        // Convert messages to Google format
-        for msg in &request.messages {
-            // Google uses "user" and "model" instead of "user" and "assistant"
-            let role = if msg.role == "assistant" { "model" } else { &msg.role };
+            let slot = match self.sub_agent_slots.clone().acquire_owned().await {
+                Ok(p) => p,
+                Err(e) => return ToolResult::error(tool_use.id.clone(), e.to_string()),
+            };
 
-            let parts = match &msg.content {
-                ApiContent::Text(text) => {
-                    vec![serde_json::json!({"text": text})]
-                }
-                ApiContent::Blocks(blocks) => {
-                    let mut parts_list = Vec::new();
-                    for block in blocks {
-                        let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                        match block_type {
-                            "text" => {
-                                if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
-                                    parts_list.push(serde_json::json!({"text": text}));
-                                }
-                            }
-                            "tool_use" => {
-                                // Convert to functionCall format with thoughtSignature if present
-                                let mut fc_part = serde_json::json!({
-                                    "functionCall": {
-                                        "name": block.get("name"),
-                                        "args": block.get("input")
-                                    }
-                                });
-                                // Include thoughtSignature if present (for Gemini 3)
-                                if let Some(sig) = block.get("thought_signature").and_then(|v| v.as_str()) {
-                                    fc_part["thoughtSignature"] = serde_json::json!(sig);
-                                }
-                                parts_list.push(fc_part);
-                            }
-                            "tool_result" => {
-                                // Convert to functionResponse format with thoughtSignature
-                                let tool_use_id = block.get("tool_use_id").and_then(|v| v.as_str()).unwrap_or("unknown");
-
-                                let mut fr_part = serde_json::json!({
-                                    "functionResponse": {
-                                        "name": tool_use_id,
-                                        "response": {
-                                            "content": block.get("content")
-                                        }
-                                    }
-                                });
-
-                                // Include thoughtSignature from matching tool_use (required for Gemini 3)
-                                if let Some(sig) = thought_signatures.get(tool_use_id) {
-                                    fr_part["thoughtSignature"] = serde_json::json!(sig);
-                                }
-
-                                parts_list.push(fr_part);
-                            }
-                            _ => {}
-                        }
+            let config = sub_agent::child_config(&self.config, &request);
+            let depth = config.delegation_depth;
+            let child = self.spawn_child(config, slot);
+            let sub_agent_id = uuid::Uuid::new_v4().to_string();
+
+            let _ = event_tx
+                .send(AgentEvent::SubAgentStart {
+                    sub_agent_id: sub_agent_id.clone(),
+                    depth,
+                    task: request.task.clone(),
+                })
+                .await;
+
+            // Forward child events to the parent, tagged with the sub-agent id
+            let (child_tx, mut child_rx) = mpsc::channel::<AgentEvent>(100);
+            let parent_tx = event_tx.clone();
+            let forward_id = sub_agent_id.clone();
+            let forwarder = tokio::spawn(async move {
+                let mut stopped = None;
+                while let Some(event) = child_rx.recv().await {
+                    if let AgentEvent::Stopped { message, .. } = &event {
+                        stopped = Some(message.clone());
                     }
-                    parts_list
+                    let _ = parent_tx
+                        .send(AgentEvent::SubAgent {
+                            sub_agent_id: forward_id.clone(),
+                            event: Box::new(event),
+                        })
+                        .await;
                 }
-            };
+                stopped
+            });
 
-            if !parts.is_empty() {
-                contents.push(serde_json::json!({
-                    "role": role,
-                    "parts": parts
-                }));
+            let outcome = child.run(request.prompt(), child_tx).await;
+            let stopped = forwarder.await.ok().flatten();
+
+            match outcome {
+                Ok(messages) => match (sub_agent::final_summary(&messages), stopped) {
+                    (Some(summary), None) => ToolResult::success(tool_use.id.clone(), summary),
+                    (Some(summary), Some(reason)) => ToolResult::success(
+                        tool_use.id.clone(),
+                        format!("[Sub-agent did not finish: {}]\n{}", reason, summary),
+                    ),
+                    (None, _) => ToolResult::error(
+                        tool_use.id.clone(),
+                        format!("Sub-agent {} finished without a summary", sub_agent_id),
+                    ),
+                },
+                Err(e) => ToolResult::error(tool_use.id.clone(), format!("Sub-agent failed: {}", e)),
             }
-        }
-
-        // Convert tools to Google functionDeclarations format
-        let function_declarations: Vec<serde_json::Value> = request.tools.iter().map(|tool| {
-            serde_json::json!({
-                "name": tool.name,
-                "description": tool.description,
-                "parameters": tool.input_schema
-            })
-        }).collect();
+        })
+    }
 
-        let mut google_request = serde_json::json!({
-            "contents": contents,
-            "generationConfig": {
-                "maxOutputTokens": request.max_tokens
+    /// Await `sub_agents` without holding this agent's slot, so sub-agents waiting
+    /// on their own children can't take every slot and deadlock
+    async fn waiting_for_sub_agents<T>(&self, sub_agents: impl std::future::Future<Output = T>) -> T {
+        let released = self.slot.lock().unwrap().take().is_some();
+        let output = sub_agents.await;
+        if released {
+            if let Ok(slot) = self.sub_agent_slots.clone().acquire_owned().await {
+                *self.slot.lock().unwrap() = Some(slot);
             }
-        });
-
-        // Add system instruction if present
-        if !request.system.is_empty() {
-            google_request["systemInstruction"] = serde_json::json!({
-                "parts": [{"text": request.system}]
-            });
-        }
-
-        // Add tools if present
-        if !function_declarations.is_empty() {
-            google_request["tools"] = serde_json::json!([{
-                "functionDeclarations": function_declarations
-            }]);
         }
-
-        google_request
+        output
     }
 
-    /// Handle Google Gemini streaming response
-    async fn handle_google_stream_response(
-        &self,
-        response: reqwest::Response,
-        event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        use futures::StreamExt;
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-        let mut accumulated_text = String::new();
-        let mut tool_calls: Vec<serde_json::Value> = Vec::new();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            while let Some(pos) = buffer.find('\n') {
-                let line = buffer[..pos].trim().to_string();
-                buffer = buffer[pos + 1..].to_string();
-
-                if line.is_empty() {
-                    continue;
-                }
-
-                // Parse SSE data: prefix
-                let json_str = if let Some(data) = line.strip_prefix("data: ") {
-                    data
-                } else {
-                    continue;
-                };
-
-                if let Ok(event) = serde_json::from_str::<serde_json::Value>(json_str) {
-                    // Extract text and function calls from candidates
-                    if let Some(candidates) = event.get("candidates").and_then(|v| v.as_array()) {
-                        for candidate in candidates {
-                            if let Some(parts) = candidate.get("content")
-                                .and_then(|c| c.get("parts"))
-                                .and_then(|p| p.as_array())
-                            {
-                                for part in parts {
-                                    // Handle text
-                                    if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-                                        if !text.is_empty() {
-                                            accumulated_text.push_str(text);
-                                            let _ = event_tx.send(AgentEvent::Text {
-                                                content: accumulated_text.clone(),
-                                            }).await;
-                                        }
-                                    }
-                                    // Handle function calls (with thoughtSignature for Gemini 3)
-                                    if let Some(fc) = part.get("functionCall") {
-                                        let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string();
-                                        let args = fc.get("args").cloned().unwrap_or(serde_json::json!({}));
-                                        let id = format!("fc_{}", uuid::Uuid::new_v4());
-
-                                        // Capture thoughtSignature from the same part (required for Gemini 3)
-                                        let thought_sig = part.get("thoughtSignature").and_then(|v| v.as_str());
-
-                                        let mut tool_use = serde_json::json!({
-                                            "type": "tool_use",
-                                            "id": id,
-                                            "name": name,
-                                            "input": args
-                                        });
-
-                                        // Store thoughtSignature with tool_use for later use in functionResponse
-                                        if let Some(sig) = thought_sig {
-                                            tool_use["thought_signature"] = serde_json::json!(sig);
-                                        }
-
-                                        tool_calls.push(tool_use);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    /// The primary model as a route
+    fn primary_route(&self) -> ModelRoute {
+        ModelRoute::new(self.provider.clone(), self.model.clone())
+    }
 
-        // Build Claude format response
-        let mut content = Vec::new();
-        if !accumulated_text.is_empty() {
-            content.push(serde_json::json!({
-                "type": "text",
-                "text": accumulated_text
-            }));
+    /// The model requests currently go to
+    fn current_route(&self) -> ModelRoute {
+        match self.active_route.load(Ordering::Relaxed) {
+            0 => self.primary_route(),
+            i => self.fallbacks[i - 1].clone(),
         }
-        content.extend(tool_calls);
-
-        Ok(serde_json::json!({
-            "content": content
-        }))
     }
 
-    /// Handle OpenAI streaming response
-    async fn handle_openai_stream_response(
+    /// Send a request to the current model, moving down the fallback chain when a
+    /// model fails with a retryable error. Later requests start from the model that
+    /// last worked.
+    async fn send_request(
         &self,
-        response: reqwest::Response,
+        request: &ClaudeApiRequest,
         event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        use futures::StreamExt;
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-        let mut accumulated_text = String::new();
-        let mut tool_calls: Vec<serde_json::Value> = Vec::new();
-        let mut current_tool_calls: std::collections::HashMap<i64, (String, String, String)> = std::collections::HashMap::new();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            while let Some(pos) = buffer.find('\n') {
-                let line = buffer[..pos].to_string();
-                buffer = buffer[pos + 1..].to_string();
-
-                if let Some(data) = line.strip_prefix("data: ") {
-                    if data.trim() == "[DONE]" {
-                        continue;
-                    }
+    ) -> Result<LlmResponse, String> {
+        let mut index = self.active_route.load(Ordering::Relaxed);
 
-                    if let Ok(event) = serde_json::from_str::<serde_json::Value>(data) {
-                        if let Some(choices) = event.get("choices").and_then(|v| v.as_array()) {
-                            for choice in choices {
-                                if let Some(delta) = choice.get("delta") {
-                                    // Handle text content
-                                    if let Some(content) = delta.get("content").and_then(|v| v.as_str()) {
-                                        accumulated_text.push_str(content);
-                                        let _ = event_tx.send(AgentEvent::Text {
-                                            content: accumulated_text.clone(),
-                                        }).await;
-                                    }
-
-                                    // Handle tool_calls
-                                    if let Some(tcs) = delta.get("tool_calls").and_then(|v| v.as_array()) {
-                                        for tc in tcs {
-                                            let index = tc.get("index").and_then(|v| v.as_i64()).unwrap_or(0);
-
-                                            let entry = current_tool_calls.entry(index).or_insert_with(|| {
-                                                (String::new(), String::new(), String::new())
-                                            });
-
-                                            if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
-                                                entry.0 = id.to_string();
-                                            }
-                                            if let Some(func) = tc.get("function") {
-                                                if let Some(name) = func.get("name").and_then(|v| v.as_str()) {
-                                                    entry.1 = name.to_string();
-                                                }
-                                                if let Some(args) = func.get("arguments").and_then(|v| v.as_str()) {
-                                                    entry.2.push_str(args);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                // Check if finished
-                                if choice.get("finish_reason").and_then(|v| v.as_str()).is_some() {
-                                    // When finished, convert collected tool_calls to Claude format
-                                    for (id, name, args) in current_tool_calls.values() {
-                                        if !id.is_empty() && !name.is_empty() {
-                                            let input: serde_json::Value = serde_json::from_str(args)
-                                                .unwrap_or(serde_json::json!({}));
-                                            tool_calls.push(serde_json::json!({
-                                                "type": "tool_use",
-                                                "id": id,
-                                                "name": name,
-                                                "input": input
-                                            }));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+        loop {
+            let route = self.current_route();
+            let result = if index == 0 {
+                self.stream_from(&route, request, event_tx).await
+            } else {
+                self.stream_from(&route, &route.retarget(request), event_tx).await
+            };
+
+            match result {
+                Err(e) if e.is_retryable() && index < self.fallbacks.len() => {
+                    index += 1;
+                    self.active_route.store(index, Ordering::Relaxed);
+                    self.warn_switch(&route.label(), &e, &self.current_route().label(), event_tx).await;
                 }
+                result => return result.map_err(|e| e.to_string()),
             }
         }
-
-        // Build Claude format response
-        let mut content = Vec::new();
-        if !accumulated_text.is_empty() {
-            content.push(serde_json::json!({
-                "type": "text",
-                "text": accumulated_text
-            }));
-        }
-        content.extend(tool_calls);
-
-        Ok(serde_json::json!({
-            "content": content
-        }))
     }
 
-    async fn handle_stream_response(
+    /// Send a request to one model, forwarding streamed text, thinking and usage
+    async fn stream_from(
         &self,
-        response: reqwest::Response,
+        route: &ModelRoute,
+        request: &ClaudeApiRequest,
         event_tx: &mpsc::Sender<AgentEvent>,
-    ) -> Result<serde_json::Value, String> {
-        use futures::StreamExt;
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-        let mut full_response: Option<serde_json::Value> = None;
-        let mut accumulated_text = String::new();
-        let mut tool_uses: Vec<serde_json::Value> = Vec::new();
-        let mut current_tool_input = String::new();
-        let mut current_tool_id = String::new();
-        let mut current_tool_name = String::new();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            while let Some(pos) = buffer.find('\n') {
-                let line = buffer[..pos].to_string();
-                buffer = buffer[pos + 1..].to_string();
-
-                if let Some(data) = line.strip_prefix("data: ") {
-                    if data == "[DONE]" {
-                        continue;
-                    }
-
-                    if let Ok(event) = serde_json::from_str::<serde_json::Value>(data) {
-                        let event_type = event.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                        match event_type {
-                            "content_block_start" => {
-                                if let Some(block) = event.get("content_block") {
-                                    if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
-                                        current_tool_id = block
-                                            .get("id")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("")
-                                            .to_string();
-                                        current_tool_name = block
-                                            .get("name")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("")
-                                            .to_string();
-                                        current_tool_input.clear();
-                                    }
-                                }
-                            }
-                            "content_block_delta" => {
-                                if let Some(delta) = event.get("delta") {
-                                    let delta_type = delta.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                                    if delta_type == "text_delta" {
-                                        if let Some(text) = delta.get("text").and_then(|v| v.as_str()) {
-                                            accumulated_text.push_str(text);
-                                            // Emit streaming text
-                                            let _ = event_tx
-                                                .send(AgentEvent::Text {
-                                                    content: accumulated_text.clone(),
-                                                })
-                                                .await;
-                                        }
-                                    } else if delta_type == "input_json_delta" {
-                                        if let Some(partial) = delta.get("partial_json").and_then(|v| v.as_str()) {
-                                            current_tool_input.push_str(partial);
-                                        }
-                                    }
-                                }
-                            }
-                            "content_block_stop" => {
-                                if !current_tool_id.is_empty() {
-                                    let input: serde_json::Value = serde_json::from_str(&current_tool_input)
-                                        .unwrap_or(serde_json::json!({}));
-
-                                    tool_uses.push(serde_json::json!({
-                                        "type": "tool_use",
-                                        "id": current_tool_id,
-                                        "name": current_tool_name,
-                                        "input": input
-                                    }));
-
-                                    current_tool_id.clear();
-                                    current_tool_name.clear();
-                                    current_tool_input.clear();
-                                }
-                            }
-                            "message_stop" => {
-                                // Build final response
-                                let mut content = Vec::new();
-                                if !accumulated_text.is_empty() {
-                                    content.push(serde_json::json!({
-                                        "type": "text",
-                                        "text": accumulated_text
-                                    }));
-                                }
-                                content.extend(tool_uses.clone());
-
-                                full_response = Some(serde_json::json!({
-                                    "content": content
-                                }));
-                            }
-                            _ => {}
-                        }
-                    }
-                }
+    ) -> Result<LlmResponse, LLMError> {
+        let (stream_tx, mut stream_rx) = mpsc::channel::<StreamEvent>(100);
+        let forward = async {
+            while let Some(event) = stream_rx.recv().await {
+                let event = match event {
+                    StreamEvent::Text(content) => AgentEvent::Text { content },
+                    StreamEvent::Thinking(content) => AgentEvent::Thinking { content },
+                };
+                let _ = event_tx.send(event).await;
             }
-        }
+        };
 
-        full_response.ok_or_else(|| "No response received".to_string())
-    }
+        let (response, _) = tokio::join!(route.provider.stream(request, stream_tx), forward);
+        let response = response?;
 
-    fn parse_response(
-        &self,
-        response: &serde_json::Value,
-    ) -> Result<(String, Vec<ToolUse>), String> {
-        let content = response
-            .get("content")
-            .and_then(|v| v.as_array())
-            .ok_or("Invalid response: missing content")?;
-
-        let mut text_parts = Vec::new();
-        let mut tool_uses = Vec::new();
-
-        for block in content {
-            let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-            match block_type {
-                "text" => {
-                    if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
-                        text_parts.push(text.to_string());
-                    }
-                }
-                "tool_use" => {
-                    let id = block
-                        .get("id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string();
-                    let name = block
-                        .get("name")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string();
-                    let input = block.get("input").cloned().unwrap_or(serde_json::json!({}));
-                    let thought_signature = block
-                        .get("thought_signature")
-                        .and_then(|v| v.as_str())
-                        .map(|s| s.to_string());
-
-                    tool_uses.push(ToolUse { id, name, input, thought_signature });
-                }
-                _ => {}
-            }
+        if let Some(usage) = response.usage {
+            let _ = event_tx.send(AgentEvent::Usage(usage)).await;
         }
+        Ok(response)
+    }
 
-        Ok((text_parts.join(""), tool_uses))
+    async fn warn_switch(&self, failed: &str, error: &LLMError, next: &str, event_tx: &mpsc::Sender<AgentEvent>) {
+        let _ = event_tx
+            .send(AgentEvent::Warning {
+                message: format!("{} failed ({}); switching to {}", failed, error, next),
+            })
+            .await;
     }
 
     /// Parse plan from text content
@@ -969,6 +684,7 @@ impl AgentLoop {
                     steps.push(PlanStepInfo {
                         step: step_num,
                         description: desc.as_str().trim().to_string(),
+                        status: "pending".to_string(),
                     });
                 }
             }
@@ -1006,7 +722,7 @@ impl AgentLoop {
 }
 
 // Make ClaudeApiRequest cloneable for non-stream fallback
-impl Clone for crate::agent::message_builder::ClaudeApiRequest {
+impl Clone for ClaudeApiRequest {
     fn clone(&self) -> Self {
         Self {
             model: self.model.clone(),
@@ -1015,7 +731,651 @@ impl Clone for crate::agent::message_builder::ClaudeApiRequest {
             messages: self.messages.clone(),
             tools: self.tools.clone(),
             temperature: self.temperature,
+            tool_choice: self.tool_choice,
+            thinking: self.thinking,
             stream: self.stream,
+            prompt_caching: self.prompt_caching,
+            response_schema: self.response_schema.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_client::mock::{MockResponse, MockServer, MockTurn};
+
+    /// Project directory with a README for `read_file`
+    fn temp_project() -> String {
+        let dir = std::env::temp_dir().join(format!("kuse-agent-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("README.md"), "# Demo\n\nA demo project.\n").unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    fn test_config() -> AgentConfig {
+        AgentConfig {
+            project_path: Some(temp_project()),
+            allowed_tools: vec!["read_file".to_string(), PLAN_TOOL.to_string()],
+            ..Default::default()
+        }
+    }
+
+    /// Run the agent against `base_url`, collecting every event it emits
+    async fn run_agent(
+        base_url: &str,
+        api_key: &str,
+        provider_id: Option<&str>,
+        model: &str,
+        config: AgentConfig,
+        prompt: &str,
+    ) -> (Result<Vec<AgentMessage>, String>, Vec<AgentEvent>) {
+        run_loop(test_agent(base_url, api_key, provider_id, model, config), prompt).await
+    }
+
+    fn test_agent(base_url: &str, api_key: &str, provider_id: Option<&str>, model: &str, config: AgentConfig) -> AgentLoop {
+        AgentLoop::new_with_provider(
+            api_key.to_string(),
+            base_url.to_string(),
+            config,
+            model.to_string(),
+            1024,
+            None,
+            Arc::new(MCPManager::new()),
+            provider_id,
+        )
+    }
+
+    async fn run_loop(agent: AgentLoop, prompt: &str) -> (Result<Vec<AgentMessage>, String>, Vec<AgentEvent>) {
+        let (event_tx, mut event_rx) = mpsc::channel(100);
+        let collector = tokio::spawn(async move {
+            let mut events = Vec::new();
+            while let Some(event) = event_rx.recv().await {
+                events.push(event);
+            }
+            events
+        });
+
+        let result = agent.run(prompt.to_string(), event_tx).await;
+        (result, collector.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_tool_round_trip() {
+        let cases = [
+            (Some("anthropic"), "claude-sonnet-4-20250514", ApiFormat::Anthropic),
+            (Some("openai"), "gpt-4o", ApiFormat::OpenAI),
+            (None, "gpt-5", ApiFormat::OpenAIResponses),
+            (Some("google"), "gemini-2.5-pro", ApiFormat::Google),
+            (Some("azure"), "gpt4o-prod", ApiFormat::Azure),
+            (Some("ollama"), "qwen3:8b", ApiFormat::Ollama),
+        ];
+
+        for (provider_id, model, format) in cases {
+            let server = MockServer::scripted(vec![
+                MockTurn::text("Let me check.")
+                    .with_thinking("Need the README.")
+                    .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                    .response(&format),
+                MockTurn::text("It is a demo project.").response(&format),
+            ])
+            .await;
+
+            let (result, events) =
+                run_agent(server.base_url(), "test-key", provider_id, model, test_config(), "What is this?").await;
+            let messages = result.unwrap_or_else(|e| panic!("{model}: {e}"));
+
+            assert_eq!(messages.len(), 4, "{model}");
+            assert!(events.iter().any(|e| matches!(e, AgentEvent::Thinking { content } if content == "Need the README.")), "{model}");
+            assert!(events.iter().any(|e| matches!(e, AgentEvent::ToolEnd { tool, success: true, result } if tool == "read_file" && result.contains("A demo project."))), "{model}");
+            assert!(matches!(events.last(), Some(AgentEvent::Done { total_turns: 2 })), "{model}");
+            assert_eq!(events.iter().filter(|e| matches!(e, AgentEvent::Usage(_))).count(), 2, "{model}");
+
+            // The file contents went back to the model in the second request
+            let requests = server.requests();
+            assert_eq!(requests.len(), 2, "{model}");
+            assert!(requests[1].to_string().contains("A demo project."), "{model}");
         }
     }
+
+    #[tokio::test]
+    async fn test_structured_output() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"title": {"type": "string"}, "files": {"type": "integer"}},
+            "required": ["title", "files"]
+        });
+        let config = AgentConfig {
+            response_schema: Some(schema.clone()),
+            ..test_config()
+        };
+
+        // Anthropic answers through the forced tool; the first answer fails validation
+        let format = ApiFormat::Anthropic;
+        let server = MockServer::scripted(vec![
+            MockTurn::text("It is a demo project with one file.").response(&format),
+            MockTurn::text("")
+                .with_tool_call("structured_output", serde_json::json!({"title": "Demo"}))
+                .response(&format),
+            MockTurn::text("")
+                .with_tool_call("structured_output", serde_json::json!({"title": "Demo", "files": 1}))
+                .response(&format),
+        ])
+        .await;
+
+        let (result, events) =
+            run_agent(server.base_url(), "test-key", Some("anthropic"), "claude-sonnet-4-20250514", config.clone(), "Describe").await;
+        let messages = result.unwrap();
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::StructuredOutput { output } if output["files"] == 1)));
+        assert!(matches!(events.last(), Some(AgentEvent::Done { total_turns: 1 })));
+        assert!(matches!(&messages.last().unwrap().content, AgentContent::Text(t) if t.contains("\"files\":1")));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1]["tool_choice"]["name"], "structured_output");
+        assert!(requests[2].to_string().contains("does not match the required schema"));
+
+        // A final message that already validates needs no extra turn
+        let format = ApiFormat::OpenAI;
+        let server = MockServer::scripted(vec![MockTurn::text(r#"{"title": "Demo", "files": 1}"#).response(&format)]).await;
+        let (result, events) = run_agent(server.base_url(), "test-key", Some("openai"), "gpt-4o", config, "Describe").await;
+        assert!(result.is_ok());
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::StructuredOutput { output } if output["title"] == "Demo")));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_plan_parsing() {
+        let format = ApiFormat::Anthropic;
+        let server = MockServer::scripted(vec![
+            MockTurn::text("<plan>\n1. Read the README\n2. Summarize it\n</plan>\n[STEP 1 START]")
+                .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                .response(&format),
+            MockTurn::text("[STEP 1 DONE] It is a demo project.").response(&format),
+        ])
+        .await;
+
+        let (result, events) =
+            run_agent(server.base_url(), "test-key", Some("anthropic"), "claude-sonnet-4-20250514", test_config(), "Summarize").await;
+        assert!(result.is_ok());
+
+        let plan = events.iter().find_map(|e| match e {
+            AgentEvent::Plan { steps } => Some(steps),
+            _ => None,
+        });
+        let descriptions: Vec<&str> = plan.unwrap().iter().map(|s| s.description.as_str()).collect();
+        assert_eq!(descriptions, ["Read the README", "Summarize it"]);
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::StepStart { step: 1 })));
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::StepDone { step: 1 })));
+    }
+
+    #[tokio::test]
+    async fn test_structured_plan_stops_marker_parsing() {
+        let format = ApiFormat::OpenAI;
+        let server = MockServer::scripted(vec![
+            MockTurn::text("<plan>\n1. Ignored\n</plan>")
+                .with_tool_call(
+                    PLAN_TOOL,
+                    serde_json::json!({"steps": [{"description": "Read the README", "status": "in_progress"}]}),
+                )
+                .response(&format),
+            MockTurn::text("<plan>\n1. Also ignored\n</plan> Done.").response(&format),
+        ])
+        .await;
+
+        let (result, events) = run_agent(server.base_url(), "test-key", Some("openai"), "gpt-4o", test_config(), "Plan").await;
+        assert!(result.is_ok());
+
+        let plans: Vec<&Vec<PlanStepInfo>> = events
+            .iter()
+            .filter_map(|e| match e {
+                AgentEvent::Plan { steps } => Some(steps),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0][0].description, "Read the README");
+        assert_eq!(plans[0][0].status, "running");
+    }
+
+    #[tokio::test]
+    async fn test_turn_limit_wrap_up() {
+        let format = ApiFormat::Anthropic;
+        let script = || {
+            vec![
+                MockTurn::text("Let me check.")
+                    .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                    .response(&format),
+                MockTurn::text("Read the README. Still to do: summarise it.").response(&format),
+            ]
+        };
+        let config = AgentConfig {
+            max_turns: 1,
+            ..test_config()
+        };
+
+        // The last turn asks for a tool-free summary, which ends the run
+        let server = MockServer::scripted(script()).await;
+        let (result, events) =
+            run_agent(server.base_url(), "test-key", Some("anthropic"), "claude-sonnet-4-20250514", config.clone(), "Summarize").await;
+        let messages = result.unwrap();
+        assert!(matches!(&messages.last().unwrap().content, AgentContent::Text(t) if t.starts_with("Read the README.")));
+        let last_text = events.iter().rev().find_map(|e| match e {
+            AgentEvent::Text { content } => Some(content.as_str()),
+            _ => None,
+        });
+        assert_eq!(last_text, Some("Read the README. Still to do: summarise it."));
+        assert!(matches!(events.last(), Some(AgentEvent::Stopped { reason: StopReason::TurnLimit, .. })));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["tool_choice"], serde_json::json!({"type": "none"}));
+        assert!(requests[1].to_string().contains("reached the turn limit"));
+
+        // Without wrap-up the run stops after the last tool turn
+        let server = MockServer::scripted(script()).await;
+        let config = AgentConfig {
+            wrap_up_on_max_turns: false,
+            ..config
+        };
+        let (result, events) =
+            run_agent(server.base_url(), "test-key", Some("anthropic"), "claude-sonnet-4-20250514", config, "Summarize").await;
+        assert!(matches!(&result.unwrap().last().unwrap().content, AgentContent::ToolResults(_)));
+        assert!(matches!(events.last(), Some(AgentEvent::Stopped { reason: StopReason::TurnLimit, .. })));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_loop_detection() {
+        let format = ApiFormat::OpenAI;
+        let read = || {
+            MockTurn::default()
+                .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                .response(&format)
+        };
+        let server = MockServer::scripted((0..5).map(|_| read()).collect()).await;
+
+        let (result, events) = run_agent(server.base_url(), "test-key", Some("openai"), "gpt-4o", test_config(), "Read").await;
+        assert!(result.is_ok());
+
+        // The third identical call warns the model, the fifth ends the run
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        assert!(!requests[2].to_string().contains("[Loop detected]"));
+        assert!(requests[3].to_string().contains("[Loop detected] You have called `read_file` 3 times"));
+        let warnings = events.iter().filter(|e| matches!(e, AgentEvent::Warning { message }
+            if message.contains("`read_file` was called"))).count();
+        assert_eq!(warnings, 2);
+        assert!(matches!(events.last(), Some(AgentEvent::Stopped { reason: StopReason::LoopDetected, message })
+            if message.contains("called 5 times")));
+    }
+
+    #[tokio::test]
+    async fn test_delegate() {
+        let format = ApiFormat::Anthropic;
+        let model = "claude-sonnet-4-20250514";
+        let delegate = |task: &str| serde_json::json!({"task": task});
+        let config = AgentConfig {
+            allowed_tools: vec!["read_file".to_string(), DELEGATE_TOOL.to_string()],
+            max_delegation_depth: 2,
+            max_concurrent_sub_agents: 1,
+            ..test_config()
+        };
+
+        // Nested delegation with a single slot: a sub-agent waiting on its own
+        // sub-agent gives up its slot, and the deepest one can't delegate further
+        let server = MockServer::scripted(vec![
+            MockTurn::default().with_tool_call(DELEGATE_TOOL, delegate("Investigate the project")).response(&format),
+            MockTurn::default().with_tool_call(DELEGATE_TOOL, delegate("Read the README")).response(&format),
+            MockTurn::default().with_tool_call(DELEGATE_TOOL, delegate("Go deeper")).response(&format),
+            MockTurn::text("The README describes a demo.").response(&format),
+            MockTurn::text("Child summary: a demo project.").response(&format),
+            MockTurn::text("It is a demo project.").response(&format),
+        ])
+        .await;
+        let (result, events) = run_agent(server.base_url(), "test-key", Some("anthropic"), model, config.clone(), "What is this?").await;
+        assert!(result.is_ok());
+        assert!(matches!(events.last(), Some(AgentEvent::Done { total_turns: 2 })));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 6);
+        let tools = |i: usize| requests[i]["tools"].as_array().unwrap().iter().map(|t| t["name"].to_string()).collect::<Vec<_>>();
+        assert!(tools(1).contains(&format!("\"{}\"", DELEGATE_TOOL)));
+        assert!(!tools(2).contains(&format!("\"{}\"", DELEGATE_TOOL)));
+        assert!(requests[3].to_string().contains("Delegation not allowed at depth 2 (max 2)"));
+        assert!(requests[4].to_string().contains("The README describes a demo."));
+        assert!(requests[5].to_string().contains("Child summary: a demo project."));
+
+        // Grandchild events reach the parent wrapped once per level
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::SubAgent { event, .. }
+            if matches!(event.as_ref(), AgentEvent::SubAgent { event, .. }
+                if matches!(event.as_ref(), AgentEvent::ToolEnd { tool, success: false, .. } if tool == DELEGATE_TOOL)))));
+        assert_eq!(events.iter().filter(|e| matches!(e, AgentEvent::SubAgentStart { depth: 1, .. })).count(), 1);
+
+        // Parallel delegations run one at a time with a single slot
+        let server = MockServer::scripted(vec![
+            MockTurn::default()
+                .with_tool_call(DELEGATE_TOOL, delegate("Task A"))
+                .with_tool_call(DELEGATE_TOOL, delegate("Task B"))
+                .response(&format),
+            MockTurn::default().with_tool_call("read_file", serde_json::json!({"path": "README.md"})).response(&format),
+            MockTurn::text("Summary A").response(&format),
+            MockTurn::default().with_tool_call("read_file", serde_json::json!({"path": "README.md"})).response(&format),
+            MockTurn::text("Summary B").response(&format),
+            MockTurn::text("Both done.").response(&format),
+        ])
+        .await;
+        let (result, _) = run_agent(server.base_url(), "test-key", Some("anthropic"), model, config.clone(), "Do both").await;
+        assert!(result.is_ok());
+
+        let requests: Vec<String> = server.requests().iter().map(|r| r.to_string()).collect();
+        assert!(requests[1].contains("Task A") && !requests[1].contains("Task B"));
+        assert!(requests[2].contains("Task A") && requests[2].contains("A demo project."));
+        assert!(requests[3].contains("Task B") && !requests[3].contains("Task A"));
+        assert!(requests[5].contains("Summary A") && requests[5].contains("Summary B"));
+
+        // Sub-agents start on the model the parent fell back to
+        let primary = MockServer::scripted(vec![MockResponse::Status(
+            529,
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#.to_string(),
+        )])
+        .await;
+        let fallback = MockServer::scripted(vec![
+            MockTurn::default().with_tool_call(DELEGATE_TOOL, delegate("Read the README")).response(&format),
+            MockTurn::text("A demo project.").response(&format),
+            MockTurn::text("Done.").response(&format),
+        ])
+        .await;
+        let provider =
+            LLMClient::new("test-key".to_string(), Some(fallback.base_url().to_string()), Some("anthropic"), None)
+                .into_provider();
+        let agent = test_agent(primary.base_url(), "test-key", Some("anthropic"), model, config)
+            .with_fallbacks(vec![ModelRoute::new(provider, "claude-3-5-haiku-20241022".to_string())]);
+        let (result, _) = run_loop(agent, "What is this?").await;
+        assert!(result.is_ok());
+        assert_eq!(primary.requests().len(), 1);
+        let requests = fallback.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1]["model"], "claude-3-5-haiku-20241022");
+    }
+
+    #[tokio::test]
+    async fn test_error_paths() {
+        // HTTP error status
+        let server = MockServer::scripted(vec![MockResponse::Status(
+            401,
+            r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#.to_string(),
+        )])
+        .await;
+        let (result, events) =
+            run_agent(server.base_url(), "bad-key", Some("anthropic"), "claude-sonnet-4-20250514", test_config(), "Hi").await;
+        assert!(result.unwrap_err().contains("invalid x-api-key"));
+        assert!(matches!(events.last(), Some(AgentEvent::Error { .. })));
+
+        // Error event in the middle of a stream
+        let server = MockServer::scripted(vec![MockResponse::Stream(
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n"
+                .to_string(),
+        )])
+        .await;
+        let (result, _) =
+            run_agent(server.base_url(), "test-key", Some("anthropic"), "claude-sonnet-4-20250514", test_config(), "Hi").await;
+        assert!(result.unwrap_err().contains("Overloaded"));
+
+        // A tool that fails is reported to the model, not to the caller
+        let format = ApiFormat::Google;
+        let server = MockServer::scripted(vec![
+            MockTurn::default()
+                .with_tool_call("read_file", serde_json::json!({"path": "missing.md"}))
+                .response(&format),
+            MockTurn::text("That file does not exist.").response(&format),
+        ])
+        .await;
+        let (result, events) =
+            run_agent(server.base_url(), "test-key", Some("google"), "gemini-2.5-pro", test_config(), "Read").await;
+        assert!(result.is_ok());
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::ToolEnd { success: false, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let format = ApiFormat::OpenAI;
+        let upstream = MockServer::scripted(vec![
+            MockTurn::default()
+                .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                .response(&format),
+            MockTurn::text("It is a demo project.").response(&format),
+        ])
+        .await;
+
+        // Both runs share a project so their requests match
+        let config = test_config();
+        let recorder = MockServer::record(upstream.base_url()).await;
+        let (recorded, _) =
+            run_agent(recorder.base_url(), "test-key", Some("openai"), "gpt-4o", config.clone(), "What is this?").await;
+        let cassette = serde_json::to_string(&recorder.exchanges()).unwrap();
+
+        let replay = MockServer::replay(&cassette).await;
+        let (replayed, _) =
+            run_agent(replay.base_url(), "test-key", Some("openai"), "gpt-4o", config, "What is this?").await;
+
+        let recorded = serde_json::to_value(recorded.unwrap()).unwrap();
+        assert_eq!(recorded, serde_json::to_value(replayed.unwrap()).unwrap());
+        assert_eq!(replay.requests(), recorder.requests());
+    }
+
+    /// Replays a recorded Anthropic session; set `KUSE_RECORD_FIXTURES=1` and
+    /// `ANTHROPIC_API_KEY` to re-record it
+    #[tokio::test]
+    async fn test_anthropic_cassette() {
+        let server = MockServer::cassette("anthropic_read_file", "https://api.anthropic.com").await;
+        let api_key = if MockServer::recording() {
+            std::env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY is needed to record")
+        } else {
+            "test-key".to_string()
+        };
+
+        let (result, events) = run_agent(
+            server.base_url(),
+            &api_key,
+            Some("anthropic"),
+            "claude-sonnet-4-20250514",
+            test_config(),
+            "What is this project?",
+        )
+        .await;
+        server.finish();
+
+        assert_eq!(result.unwrap().len(), 4);
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::ToolStart { tool, .. } if tool == "read_file")));
+        assert!(matches!(events.last(), Some(AgentEvent::Done { total_turns: 2 })));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_chain() {
+        let overloaded = || {
+            MockResponse::Status(
+                529,
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#.to_string(),
+            )
+        };
+        let fallback_route = |server: &MockServer| {
+            let provider =
+                LLMClient::new("test-key".to_string(), Some(server.base_url().to_string()), Some("google"), None)
+                    .into_provider();
+            ModelRoute::new(provider, "gemini-2.5-pro".to_string())
+        };
+
+        // The primary fails after a tool call; the fallback finishes the task with the same history
+        let primary = MockServer::scripted(vec![
+            MockTurn::default()
+                .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                .response(&ApiFormat::Anthropic),
+            overloaded(),
+        ])
+        .await;
+        let fallback = MockServer::scripted(vec![MockTurn::text("A demo project.").response(&ApiFormat::Google)]).await;
+        let agent = test_agent(primary.base_url(), "test-key", Some("anthropic"), "claude-sonnet-4-20250514", test_config())
+            .with_fallbacks(vec![fallback_route(&fallback)]);
+
+        let (result, events) = run_loop(agent, "What is this?").await;
+        assert!(result.is_ok());
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::Warning { message }
+            if message.contains("anthropic/claude-sonnet-4-20250514 failed") && message.contains("switching to google/gemini-2.5-pro"))));
+        assert_eq!(primary.requests().len(), 2);
+
+        let requests = fallback.requests();
+        assert_eq!(requests.len(), 1);
+        let parts = &requests[0]["contents"][1]["parts"];
+        assert_eq!(parts[0]["functionCall"]["name"], "read_file");
+        assert_eq!(parts[0]["thoughtSignature"], "skip_thought_signature_validator");
+
+        // Errors that another model wouldn't fix are returned as they are
+        let primary = MockServer::scripted(vec![MockResponse::Status(
+            401,
+            r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#.to_string(),
+        )])
+        .await;
+        let fallback = MockServer::scripted(vec![MockTurn::text("Unused").response(&ApiFormat::Google)]).await;
+        let agent = test_agent(primary.base_url(), "bad-key", Some("anthropic"), "claude-sonnet-4-20250514", test_config())
+            .with_fallbacks(vec![fallback_route(&fallback)]);
+
+        let (result, _) = run_loop(agent, "Hi").await;
+        assert!(result.unwrap_err().contains("invalid x-api-key"));
+        assert!(fallback.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_azure_deployment() {
+        let format = ApiFormat::Azure;
+        let script = || {
+            vec![
+                MockTurn::default()
+                    .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                    .response(&format),
+                MockTurn::text("A demo project.").response(&format),
+            ]
+        };
+
+        // Resource endpoint: deployment in the path, api-version in the query, key in `api-key`
+        let server = MockServer::scripted(script()).await;
+        let (result, _) =
+            run_agent(server.base_url(), "azure-key", Some("azure"), "gpt4o-prod", test_config(), "What is this?").await;
+        assert!(result.is_ok());
+        for exchange in server.exchanges() {
+            assert_eq!(exchange.method, "POST");
+            assert_eq!(exchange.path, "/openai/deployments/gpt4o-prod/chat/completions?api-version=2024-10-21");
+            assert!(exchange.headers.contains(&("api-key".to_string(), "azure-key".to_string())));
+            assert!(!exchange.headers.iter().any(|(k, _)| k == "authorization" || k == "x-api-key"));
+            assert!(exchange.request.get("max_completion_tokens").is_some());
+        }
+
+        // A pinned api-version in the base URL is kept, and a pasted deployment URL is trimmed
+        let server = MockServer::scripted(script()).await;
+        let base_url = format!(
+            "{}/openai/deployments/old/chat/completions?api-version=2025-01-01-preview",
+            server.base_url()
+        );
+        let (result, _) = run_agent(&base_url, "azure-key", Some("azure"), "gpt4o-prod", test_config(), "Hi").await;
+        assert!(result.is_ok());
+        assert_eq!(
+            server.exchanges()[0].path,
+            "/openai/deployments/gpt4o-prod/chat/completions?api-version=2025-01-01-preview"
+        );
+
+        // The v1 API takes the deployment in the body
+        let server = MockServer::scripted(script()).await;
+        let base_url = format!("{}/openai/v1", server.base_url());
+        let (result, _) = run_agent(&base_url, "azure-key", Some("azure"), "gpt4o-prod", test_config(), "Hi").await;
+        assert!(result.is_ok());
+        let exchange = &server.exchanges()[0];
+        assert_eq!(exchange.path, "/openai/v1/chat/completions");
+        assert_eq!(exchange.request["model"], "gpt4o-prod");
+    }
+
+    #[tokio::test]
+    async fn test_bedrock_event_stream() {
+        let format = ApiFormat::Bedrock;
+        let server = MockServer::scripted(vec![
+            MockTurn::text("Let me check.")
+                .with_thinking("Need the README.")
+                .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                .response(&format),
+            MockTurn::text("A demo project.").response(&format),
+        ])
+        .await;
+        let model = "us.anthropic.claude-sonnet-4-20250514-v1:0";
+
+        let (result, events) =
+            run_agent(server.base_url(), "AKIDTEST:secret", Some("bedrock"), model, test_config(), "What is this?").await;
+        assert_eq!(result.unwrap().len(), 4);
+        assert!(events.iter().any(|e| matches!(e, AgentEvent::Thinking { content } if content == "Need the README.")));
+        assert!(matches!(events.last(), Some(AgentEvent::Done { total_turns: 2 })));
+
+        let exchanges = server.exchanges();
+        assert_eq!(exchanges.len(), 2);
+        for exchange in &exchanges {
+            assert_eq!(
+                exchange.path,
+                "/model/us.anthropic.claude-sonnet-4-20250514-v1%3A0/invoke-with-response-stream"
+            );
+            let header = |name: &str| exchange.headers.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+            let authorization = header("authorization").unwrap();
+            assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDTEST/"));
+            assert!(authorization.contains("/bedrock/aws4_request, SignedHeaders=content-type;host;x-amz-date,"));
+            assert!(header("x-amz-date").is_some());
+
+            // Model and version move out of the Anthropic body
+            assert_eq!(exchange.request["anthropic_version"], "bedrock-2023-05-31");
+            assert!(exchange.request.get("model").is_none());
+        }
+        // The signed thinking block went back with the tool result
+        assert_eq!(exchanges[1].request["messages"][1]["content"][0]["signature"], "sig_mock");
+    }
+
+    #[tokio::test]
+    async fn test_vertex_service_account() {
+        let token = || MockResponse::Stream(r#"{"access_token":"ya29.test","expires_in":3600}"#.to_string());
+        let server = MockServer::scripted(vec![
+            token(),
+            MockTurn::default()
+                .with_tool_call("read_file", serde_json::json!({"path": "README.md"}))
+                .response(&ApiFormat::Anthropic),
+            MockTurn::text("A demo project.").response(&ApiFormat::Anthropic),
+            token(),
+            MockTurn::text("Hello from Gemini.").response(&ApiFormat::Google),
+        ])
+        .await;
+
+        // The key's token endpoint is the stub, as is the regional API
+        let mut account: serde_json::Value =
+            serde_json::from_str(include_str!("../llm_client/fixtures/vertex_service_account.json")).unwrap();
+        account["token_uri"] = serde_json::json!(format!("{}/token", server.base_url()));
+        let key = account.to_string();
+        let base_url = format!("{}/v1/projects/kuse-test/locations/us-east5", server.base_url());
+
+        let (result, _) =
+            run_agent(&base_url, &key, Some("vertex"), "claude-sonnet-4@20250514", test_config(), "What is this?").await;
+        assert_eq!(result.unwrap().len(), 4);
+
+        // One token exchange covers the whole run
+        let exchanges = server.exchanges();
+        assert_eq!(exchanges[0].path, "/token");
+        let claude = "/v1/projects/kuse-test/locations/us-east5/publishers/anthropic/models/claude-sonnet-4@20250514:streamRawPredict";
+        for exchange in &exchanges[1..] {
+            assert_eq!(exchange.path, claude);
+            assert!(exchange.headers.contains(&("authorization".to_string(), "Bearer ya29.test".to_string())));
+            assert_eq!(exchange.request["anthropic_version"], "vertex-2023-10-16");
+            assert!(exchange.request.get("model").is_none());
+        }
+
+        // Gemini models go to Google's publisher endpoint
+        let (result, _) = run_agent(&base_url, &key, Some("vertex"), "gemini-2.5-pro", test_config(), "Hi").await;
+        assert!(result.is_ok());
+        let exchanges = server.exchanges();
+        assert_eq!(
+            exchanges.last().unwrap().path,
+            "/v1/projects/kuse-test/locations/us-east5/publishers/google/models/gemini-2.5-pro:streamGenerateContent?alt=sse"
+        );
+    }
 }
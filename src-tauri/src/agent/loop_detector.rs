@@ -0,0 +1,122 @@
+use crate::agent::{ToolResult, ToolUse};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+/// Number of recent tool calls kept for comparison
+const WINDOW: usize = 12;
+
+/// Identical calls within the window before the model gets a corrective note
+const WARN_THRESHOLD: usize = 3;
+
+/// Identical calls within the window before the run is stopped
+const STOP_THRESHOLD: usize = 5;
+
+/// Outcome of recording a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCheck {
+    Ok,
+    /// The same call and result has been seen this many times
+    Repeated(usize),
+    /// Repetition continued after the model was warned
+    Stuck(usize),
+}
+
+/// Detects the model repeating the same tool call with the same result
+#[derive(Debug, Default)]
+pub struct LoopDetector {
+    recent: VecDeque<u64>,
+}
+
+impl LoopDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a finished tool call and check it against recent calls
+    pub fn record(&mut self, tool_use: &ToolUse, result: &ToolResult) -> LoopCheck {
+        let fingerprint = fingerprint(tool_use, result);
+
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(fingerprint);
+
+        let count = self.recent.iter().filter(|f| **f == fingerprint).count();
+        if count >= STOP_THRESHOLD {
+            LoopCheck::Stuck(count)
+        } else if count >= WARN_THRESHOLD {
+            LoopCheck::Repeated(count)
+        } else {
+            LoopCheck::Ok
+        }
+    }
+}
+
+fn fingerprint(tool_use: &ToolUse, result: &ToolResult) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tool_use.name.hash(&mut hasher);
+    tool_use.input.to_string().hash(&mut hasher);
+    result.content.hash(&mut hasher);
+    result.is_error.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Note appended to a repeated tool result so the model changes course
+pub fn corrective_note(tool: &str, count: usize) -> String {
+    format!(
+        "\n\n[Loop detected] You have called `{}` {} times with the same input and got the same result. \
+         Repeating it will not change the outcome. Try a different approach, or stop and explain to the user what is blocking you.",
+        tool, count
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(command: &str) -> ToolUse {
+        ToolUse {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: "bash".to_string(),
+            input: json!({ "command": command }),
+            thought_signature: None,
+        }
+    }
+
+    #[test]
+    fn test_repeated_call_warns_then_stops() {
+        let mut detector = LoopDetector::new();
+        let failing = ToolResult::error("id".to_string(), "exit code 1".to_string());
+
+        assert_eq!(detector.record(&call("make"), &failing), LoopCheck::Ok);
+        assert_eq!(detector.record(&call("make"), &failing), LoopCheck::Ok);
+        assert_eq!(detector.record(&call("make"), &failing), LoopCheck::Repeated(3));
+        assert_eq!(detector.record(&call("make"), &failing), LoopCheck::Repeated(4));
+        assert_eq!(detector.record(&call("make"), &failing), LoopCheck::Stuck(5));
+    }
+
+    #[test]
+    fn test_different_results_are_not_a_loop() {
+        let mut detector = LoopDetector::new();
+
+        for i in 0..STOP_THRESHOLD + 2 {
+            let result = ToolResult::success("id".to_string(), format!("line {}", i));
+            assert_eq!(detector.record(&call("tail log"), &result), LoopCheck::Ok);
+        }
+    }
+
+    #[test]
+    fn test_old_calls_leave_window() {
+        let mut detector = LoopDetector::new();
+        let result = ToolResult::success("id".to_string(), "ok".to_string());
+
+        detector.record(&call("ls"), &result);
+        detector.record(&call("ls"), &result);
+        for i in 0..WINDOW {
+            detector.record(&call(&format!("cat {}", i)), &result);
+        }
+        assert_eq!(detector.record(&call("ls"), &result), LoopCheck::Ok);
+    }
+}
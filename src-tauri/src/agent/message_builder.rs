@@ -1,4 +1,6 @@
-use crate::agent::{AgentConfig, AgentContent, AgentMessage, ToolDefinition};
+use crate::agent::{plan, structured_output, sub_agent, AgentConfig, AgentContent, AgentMessage, ToolDefinition};
+use crate::llm_client::catalog::catalog;
+use crate::llm_client::ApiFormat;
 use crate::mcp::{MCPManager, MCPTool};
 use crate::tools;
 use serde::{Deserialize, Serialize};
@@ -14,7 +16,201 @@ pub struct ClaudeApiRequest {
     pub tools: Vec<ToolDefinition>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ThinkingConfig>,
     pub stream: bool,
+    /// Add Anthropic `cache_control` breakpoints in `to_anthropic_body`
+    #[serde(skip)]
+    pub prompt_caching: bool,
+    /// JSON Schema the answer must match, mapped to each provider's structured output option
+    #[serde(skip)]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+impl ClaudeApiRequest {
+    /// Plain chat request without a system prompt or tools
+    pub fn chat(model: &str, max_tokens: u32, temperature: Option<f32>, messages: Vec<ApiMessage>) -> Self {
+        Self {
+            model: model.to_string(),
+            max_tokens,
+            system: String::new(),
+            messages,
+            tools: Vec::new(),
+            temperature,
+            tool_choice: None,
+            thinking: None,
+            stream: true,
+            prompt_caching: false,
+            response_schema: None,
+        }
+    }
+
+    /// Copy of the request for another model, as used by fallback and routed models.
+    ///
+    /// Limits come from the new model's catalogue entry. History written by one
+    /// provider is adapted to what the next one accepts: Anthropic rejects unsigned
+    /// thinking blocks, and Gemini 3 wants a thought signature on every function
+    /// call, so calls made by other models get the documented placeholder.
+    pub fn retarget(&self, model: &str, api_format: &ApiFormat) -> Self {
+        let info = catalog().info(model);
+        let api_format = &api_format.wire_format(model);
+        let mut request = self.clone();
+        request.model = model.to_string();
+        request.prompt_caching = *api_format == ApiFormat::Anthropic;
+
+        for message in &mut request.messages {
+            let ApiContent::Blocks(blocks) = &mut message.content else {
+                continue;
+            };
+            for block in blocks.iter_mut().filter(|b| b["type"] == "tool_use") {
+                let Some(block) = block.as_object_mut() else {
+                    continue;
+                };
+                match api_format {
+                    ApiFormat::Anthropic => {
+                        block.remove("thought_signature");
+                    }
+                    ApiFormat::Google if !block.get("thought_signature").is_some_and(|s| s.is_string()) => {
+                        block.insert("thought_signature".into(), SKIP_THOUGHT_SIGNATURE.into());
+                    }
+                    _ => {}
+                }
+            }
+            if *api_format == ApiFormat::Anthropic {
+                blocks.retain(|b| b["type"] != "thinking" || b["signature"].is_string());
+            }
+        }
+
+        // With thinking on, Anthropic expects each tool call to follow a signed thinking block
+        let history_signed = *api_format != ApiFormat::Anthropic
+            || request.messages.iter().all(|m| match &m.content {
+                ApiContent::Blocks(blocks) if m.role == "assistant" => {
+                    !blocks.iter().any(|b| b["type"] == "tool_use") || blocks.iter().any(|b| b["type"] == "thinking")
+                }
+                _ => true,
+            });
+
+        request.thinking = self
+            .thinking
+            .filter(|_| info.reasoning && history_signed)
+            .map(|t| ThinkingConfig::enabled(t.budget_tokens.min(info.max_output_tokens.saturating_sub(MIN_THINKING_BUDGET))));
+        let (max_tokens, temperature) = match request.thinking {
+            Some(t) => (self.max_tokens.max(t.budget_tokens + MIN_THINKING_BUDGET), None),
+            None => (self.max_tokens, info.temperature(self.temperature)),
+        };
+        request.max_tokens = max_tokens.min(info.max_output_tokens);
+        request.temperature = temperature;
+
+        request
+    }
+
+    /// Request body for the Anthropic Messages API.
+    ///
+    /// A response schema becomes a tool the model is forced to call; forced tool
+    /// use doesn't allow extended thinking. With prompt caching enabled, breakpoints
+    /// are placed on the system prompt, the last tool definition and the last two
+    /// user messages. History only grows between turns, so each request reads the
+    /// prefix the previous one wrote.
+    pub fn to_anthropic_body(&self) -> serde_json::Value {
+        let mut body = serde_json::to_value(self).unwrap_or_default();
+        if let Some(schema) = &self.response_schema {
+            let mut tools = body["tools"].as_array().cloned().unwrap_or_default();
+            tools.push(serde_json::json!(structured_output::definition(schema)));
+            body["tools"] = serde_json::json!(tools);
+            body["tool_choice"] = serde_json::json!({ "type": "tool", "name": structured_output::STRUCTURED_OUTPUT_TOOL });
+            if let Some(body) = body.as_object_mut() {
+                body.remove("thinking");
+            }
+        }
+        if !self.prompt_caching {
+            return body;
+        }
+
+        let cache_control = serde_json::json!({ "type": "ephemeral" });
+
+        if !self.system.is_empty() {
+            body["system"] = serde_json::json!([{
+                "type": "text",
+                "text": self.system,
+                "cache_control": cache_control
+            }]);
+        }
+
+        if let Some(last_tool) = body
+            .get_mut("tools")
+            .and_then(|t| t.as_array_mut())
+            .and_then(|t| t.last_mut())
+        {
+            last_tool["cache_control"] = cache_control.clone();
+        }
+
+        if let Some(messages) = body.get_mut("messages").and_then(|m| m.as_array_mut()) {
+            for message in messages
+                .iter_mut()
+                .rev()
+                .filter(|m| m["role"] == "user")
+                .take(HISTORY_CACHE_BREAKPOINTS)
+            {
+                if let Some(text) = message["content"].as_str().map(|t| t.to_string()) {
+                    if text.is_empty() {
+                        continue;
+                    }
+                    message["content"] = serde_json::json!([{ "type": "text", "text": text }]);
+                }
+                if let Some(block) = message["content"].as_array_mut().and_then(|b| b.last_mut()) {
+                    block["cache_control"] = cache_control.clone();
+                }
+            }
+        }
+
+        body
+    }
+}
+
+/// Gemini's placeholder signature for function calls made by other models
+const SKIP_THOUGHT_SIGNATURE: &str = "skip_thought_signature_validator";
+
+/// Cache breakpoints placed in the history (Anthropic allows four in total)
+const HISTORY_CACHE_BREAKPOINTS: usize = 2;
+
+/// Smallest thinking budget accepted by Anthropic
+pub const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// Extended thinking parameters (serialized in Anthropic format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ThinkingConfig {
+    #[serde(rename = "type")]
+    pub thinking_type: &'static str,
+    pub budget_tokens: u32,
+}
+
+impl ThinkingConfig {
+    pub fn enabled(budget_tokens: u32) -> Self {
+        Self {
+            thinking_type: "enabled",
+            budget_tokens: budget_tokens.max(MIN_THINKING_BUDGET),
+        }
+    }
+
+    /// OpenAI `reasoning_effort` closest to the budget
+    pub fn reasoning_effort(&self) -> &'static str {
+        match self.budget_tokens {
+            0..=4095 => "low",
+            4096..=16383 => "medium",
+            _ => "high",
+        }
+    }
+}
+
+/// Whether the model may call tools (serialized in Anthropic format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ToolChoice {
+    Auto,
+    /// Tools stay defined (required once history contains tool calls) but can't be called
+    None,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +232,7 @@ pub struct MessageBuilder {
     max_tokens: u32,
     temperature: Option<f32>,
     mcp_manager: Option<Arc<MCPManager>>,
+    prompt_caching: bool,
 }
 
 impl MessageBuilder {
@@ -46,6 +243,7 @@ impl MessageBuilder {
             max_tokens,
             temperature,
             mcp_manager: None,
+            prompt_caching: false,
         }
     }
 
@@ -54,9 +252,22 @@ impl MessageBuilder {
         self
     }
 
+    /// Enable Anthropic prompt caching breakpoints
+    pub fn with_prompt_caching(mut self, enabled: bool) -> Self {
+        self.prompt_caching = enabled;
+        self
+    }
+
     pub async fn build_request(&self, messages: &[AgentMessage]) -> ClaudeApiRequest {
         let mut tools = tools::get_tools(&self.config.allowed_tools);
 
+        if self.config.allowed_tools.iter().any(|t| t == plan::PLAN_TOOL) {
+            tools.push(plan::definition());
+        }
+        if self.config.can_delegate() {
+            tools.push(sub_agent::definition());
+        }
+
         // Add MCP tools if available
         if let Some(mcp_manager) = &self.mcp_manager {
             let mcp_tools = self.get_mcp_tools(mcp_manager).await;
@@ -64,15 +275,37 @@ impl MessageBuilder {
         }
 
         let api_messages = self.convert_messages(messages);
+        let tool_choice = (!tools.is_empty()).then_some(ToolChoice::Auto);
+
+        // Output limit, reasoning support and temperature range come from the model catalogue
+        let model = catalog().info(&self.model);
+
+        // Thinking needs max_tokens above the budget and doesn't allow a custom temperature
+        let thinking = self
+            .config
+            .thinking_budget
+            .filter(|_| model.reasoning)
+            .map(|budget| {
+                ThinkingConfig::enabled(budget.min(model.max_output_tokens.saturating_sub(MIN_THINKING_BUDGET)))
+            });
+        let (max_tokens, temperature) = match thinking {
+            Some(t) => (self.max_tokens.max(t.budget_tokens + MIN_THINKING_BUDGET), None),
+            None => (self.max_tokens, model.temperature(self.temperature)),
+        };
+        let max_tokens = max_tokens.min(model.max_output_tokens);
 
         ClaudeApiRequest {
             model: self.model.clone(),
-            max_tokens: self.max_tokens,
+            max_tokens,
             system: self.config.system_prompt.clone(),
             messages: api_messages,
             tools,
-            temperature: self.temperature,
+            temperature,
+            tool_choice,
+            thinking,
             stream: true,
+            prompt_caching: self.prompt_caching,
+            response_schema: None,
         }
     }
 
@@ -132,3 +365,226 @@ impl MessageBuilder {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_thinking_request() {
+        let config = AgentConfig {
+            thinking_budget: Some(8000),
+            allowed_tools: vec![],
+            ..Default::default()
+        };
+        let builder = MessageBuilder::new(config, "claude-sonnet-4-20250514".to_string(), 4096, Some(0.7));
+        let request = builder.build_request(&[]).await;
+
+        assert_eq!(request.max_tokens, 8000 + MIN_THINKING_BUDGET);
+        assert_eq!(request.temperature, None);
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["thinking"], serde_json::json!({ "type": "enabled", "budget_tokens": 8000 }));
+        assert_eq!(ThinkingConfig::enabled(100).budget_tokens, MIN_THINKING_BUDGET);
+        assert_eq!(ThinkingConfig::enabled(8000).reasoning_effort(), "medium");
+    }
+
+    fn cached_request(prompt_caching: bool) -> ClaudeApiRequest {
+        ClaudeApiRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            max_tokens: 1024,
+            system: "You are helpful.".to_string(),
+            messages: vec![
+                ApiMessage {
+                    role: "user".to_string(),
+                    content: ApiContent::Text("Read README.md".to_string()),
+                },
+                ApiMessage {
+                    role: "assistant".to_string(),
+                    content: ApiContent::Blocks(vec![serde_json::json!({
+                        "type": "tool_use",
+                        "id": "toolu_1",
+                        "name": "read_file",
+                        "input": { "path": "README.md" }
+                    })]),
+                },
+                ApiMessage {
+                    role: "user".to_string(),
+                    content: ApiContent::Blocks(vec![serde_json::json!({
+                        "type": "tool_result",
+                        "tool_use_id": "toolu_1",
+                        "content": "# Kuse Cowork"
+                    })]),
+                },
+            ],
+            tools: vec![
+                ToolDefinition {
+                    name: "glob".to_string(),
+                    description: "Find files".to_string(),
+                    input_schema: serde_json::json!({ "type": "object" }),
+                },
+                ToolDefinition {
+                    name: "read_file".to_string(),
+                    description: "Read a file".to_string(),
+                    input_schema: serde_json::json!({ "type": "object" }),
+                },
+            ],
+            temperature: None,
+            tool_choice: Some(ToolChoice::Auto),
+            thinking: None,
+            stream: true,
+            prompt_caching,
+            response_schema: None,
+        }
+    }
+
+    #[test]
+    fn test_anthropic_body_cache_breakpoints() {
+        let body = cached_request(true).to_anthropic_body();
+
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "claude-sonnet-4-20250514",
+                "max_tokens": 1024,
+                "system": [{
+                    "type": "text",
+                    "text": "You are helpful.",
+                    "cache_control": { "type": "ephemeral" }
+                }],
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [{
+                            "type": "text",
+                            "text": "Read README.md",
+                            "cache_control": { "type": "ephemeral" }
+                        }]
+                    },
+                    {
+                        "role": "assistant",
+                        "content": [{
+                            "type": "tool_use",
+                            "id": "toolu_1",
+                            "name": "read_file",
+                            "input": { "path": "README.md" }
+                        }]
+                    },
+                    {
+                        "role": "user",
+                        "content": [{
+                            "type": "tool_result",
+                            "tool_use_id": "toolu_1",
+                            "content": "# Kuse Cowork",
+                            "cache_control": { "type": "ephemeral" }
+                        }]
+                    }
+                ],
+                "tools": [
+                    {
+                        "name": "glob",
+                        "description": "Find files",
+                        "input_schema": { "type": "object" }
+                    },
+                    {
+                        "name": "read_file",
+                        "description": "Read a file",
+                        "input_schema": { "type": "object" },
+                        "cache_control": { "type": "ephemeral" }
+                    }
+                ],
+                "tool_choice": { "type": "auto" },
+                "stream": true
+            })
+        );
+    }
+
+    #[test]
+    fn test_anthropic_body_without_caching() {
+        let body = cached_request(false).to_anthropic_body();
+
+        assert_eq!(body["system"], "You are helpful.");
+        assert_eq!(body["messages"][0]["content"], "Read README.md");
+        assert!(!body.to_string().contains("cache_control"));
+    }
+
+    #[tokio::test]
+    async fn test_catalog_limits() {
+        let config = AgentConfig {
+            thinking_budget: Some(8000),
+            allowed_tools: vec![],
+            ..Default::default()
+        };
+
+        // No thinking for models that don't reason; output clamped to the model's limit
+        let builder = MessageBuilder::new(config.clone(), "gpt-4o".to_string(), 100_000, Some(0.7));
+        let request = builder.build_request(&[]).await;
+        assert!(request.thinking.is_none());
+        assert_eq!(request.max_tokens, 16384);
+        assert_eq!(request.temperature, Some(0.7));
+
+        let config = AgentConfig {
+            thinking_budget: None,
+            ..config
+        };
+        let builder = MessageBuilder::new(config, "claude-sonnet-4-5-20250929".to_string(), 4096, Some(1.5));
+        assert_eq!(builder.build_request(&[]).await.temperature, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_builder_enables_caching() {
+        let config = AgentConfig {
+            allowed_tools: vec!["read_file".to_string()],
+            ..Default::default()
+        };
+        let builder = MessageBuilder::new(config, "claude-sonnet-4-20250514".to_string(), 4096, None)
+            .with_prompt_caching(true);
+        let messages = vec![AgentMessage {
+            role: "user".to_string(),
+            content: AgentContent::Text("Hello".to_string()),
+        }];
+
+        let body = builder.build_request(&messages).await.to_anthropic_body();
+        assert_eq!(body["system"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(body["tools"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(
+            body["messages"][0]["content"],
+            serde_json::json!([{ "type": "text", "text": "Hello", "cache_control": { "type": "ephemeral" } }])
+        );
+    }
+
+    #[test]
+    fn test_retarget() {
+        let mut request = cached_request(false);
+        request.thinking = Some(ThinkingConfig::enabled(8000));
+        request.max_tokens = 100_000;
+        if let ApiContent::Blocks(blocks) = &mut request.messages[1].content {
+            blocks.insert(0, serde_json::json!({ "type": "thinking", "thinking": "Look first" }));
+        }
+
+        // Gemini gets the placeholder signature on calls made by other models
+        let google = request.retarget("gemini-2.5-pro", &ApiFormat::Google);
+        assert_eq!(google.model, "gemini-2.5-pro");
+        assert!(google.thinking.is_some());
+        assert!(!google.prompt_caching);
+        let ApiContent::Blocks(blocks) = &google.messages[1].content else {
+            panic!("expected blocks");
+        };
+        assert_eq!(blocks[1]["thought_signature"], SKIP_THOUGHT_SIGNATURE);
+
+        // Anthropic rejects unsigned thinking and needs it before tool calls, so thinking is turned off
+        let anthropic = google.retarget("claude-sonnet-4-5-20250929", &ApiFormat::Anthropic);
+        assert!(anthropic.thinking.is_none());
+        assert!(anthropic.prompt_caching);
+        assert_eq!(anthropic.max_tokens, 64000);
+        assert_eq!(
+            serde_json::to_value(&anthropic.messages[1].content).unwrap(),
+            serde_json::json!([{ "type": "tool_use", "id": "toolu_1", "name": "read_file", "input": { "path": "README.md" } }])
+        );
+
+        // Models without reasoning drop thinking; output is clamped to the new model
+        let gpt = request.retarget("gpt-4o", &ApiFormat::OpenAI);
+        assert!(gpt.thinking.is_none());
+        assert_eq!(gpt.max_tokens, 16384);
+    }
+}
@@ -1,9 +1,15 @@
 pub mod agent_loop;
+pub mod loop_detector;
 pub mod message_builder;
+pub mod plan;
+pub mod project_config;
+pub mod structured_output;
+pub mod sub_agent;
 pub mod tool_executor;
 pub mod types;
 
 pub use agent_loop::AgentLoop;
 pub use message_builder::MessageBuilder;
+pub use project_config::{BashPolicy, DockerDefaults};
 pub use tool_executor::ToolExecutor;
 pub use types::*;
@@ -0,0 +1,156 @@
+use crate::agent::{PlanStepInfo, ToolDefinition};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Name of the structured planning tool
+pub const PLAN_TOOL: &str = "update_plan";
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: PLAN_TOOL.to_string(),
+        description: "Create or update your task plan. Always send the COMPLETE list of steps in order; steps you leave out are removed, new ones are inserted where you place them. Keep exactly one step in_progress while working, mark steps completed as you finish them and skipped if they are no longer needed.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "steps": {
+                    "type": "array",
+                    "description": "The full plan, in execution order",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "description": {
+                                "type": "string",
+                                "description": "Short description of the step"
+                            },
+                            "status": {
+                                "type": "string",
+                                "enum": ["pending", "in_progress", "completed", "skipped"]
+                            }
+                        },
+                        "required": ["description", "status"]
+                    }
+                },
+                "explanation": {
+                    "type": "string",
+                    "description": "Optional note on why the plan changed"
+                }
+            },
+            "required": ["steps"]
+        }),
+    }
+}
+
+/// Step status accepted by `update_plan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStepStatus {
+    Pending,
+    #[serde(alias = "running")]
+    InProgress,
+    #[serde(alias = "done")]
+    Completed,
+    Skipped,
+}
+
+impl PlanStepStatus {
+    /// Status string stored in `PlanStep` and shown by the frontend
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanStepStatus::Pending => "pending",
+            PlanStepStatus::InProgress => "running",
+            PlanStepStatus::Completed => "completed",
+            PlanStepStatus::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PlanStepInput {
+    description: String,
+    status: PlanStepStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlanInput {
+    steps: Vec<PlanStepInput>,
+}
+
+/// Parse `update_plan` input into numbered plan steps
+pub fn parse_update(input: &serde_json::Value) -> Result<Vec<PlanStepInfo>, String> {
+    let plan: PlanInput = serde_json::from_value(input.clone())
+        .map_err(|e| format!("Invalid plan: {}", e))?;
+
+    if plan.steps.is_empty() {
+        return Err("Plan must contain at least one step".to_string());
+    }
+
+    let in_progress = plan
+        .steps
+        .iter()
+        .filter(|s| s.status == PlanStepStatus::InProgress)
+        .count();
+    if in_progress > 1 {
+        return Err(format!(
+            "Only one step can be in_progress at a time ({} given)",
+            in_progress
+        ));
+    }
+
+    Ok(plan
+        .steps
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| PlanStepInfo {
+            step: i as i32 + 1,
+            description: s.description.trim().to_string(),
+            status: s.status.as_str().to_string(),
+        })
+        .collect())
+}
+
+/// Render the plan for the tool result
+pub fn render(steps: &[PlanStepInfo]) -> String {
+    let mut out = String::from("Plan updated:\n");
+    for step in steps {
+        out.push_str(&format!("{}. [{}] {}\n", step.step, step.status, step.description));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_update_renumbers_steps() {
+        let steps = parse_update(&json!({
+            "steps": [
+                { "description": "Read config", "status": "completed" },
+                { "description": "Add parser", "status": "in_progress" },
+                { "description": "Old idea", "status": "skipped" },
+                { "description": "Write tests", "status": "pending" }
+            ],
+            "explanation": "Inserted parser step"
+        }))
+        .unwrap();
+
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[1].step, 2);
+        assert_eq!(steps[1].status, "running");
+        assert_eq!(steps[2].status, "skipped");
+        assert_eq!(steps[3].description, "Write tests");
+    }
+
+    #[test]
+    fn test_parse_update_rejects_invalid() {
+        assert!(parse_update(&json!({ "steps": [] })).is_err());
+        assert!(parse_update(&json!({ "steps": [{ "description": "x", "status": "unknown" }] })).is_err());
+        assert!(parse_update(&json!({
+            "steps": [
+                { "description": "a", "status": "in_progress" },
+                { "description": "b", "status": "in_progress" }
+            ]
+        }))
+        .is_err());
+    }
+}
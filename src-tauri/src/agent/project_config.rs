@@ -0,0 +1,531 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Project configuration file, relative to the project root
+pub const PROJECT_CONFIG_FILE: &str = ".kuse-cowork/config.toml";
+
+/// Instruction files appended to the system prompt when present, in order
+pub const INSTRUCTION_FILES: &[&str] = &["AGENTS.md", "CLAUDE.md", ".kuse-cowork/instructions.md"];
+
+/// Maximum bytes read from a single instruction file
+const MAX_INSTRUCTION_FILE_BYTES: usize = 32 * 1024;
+
+/// Maximum bytes of instruction files appended to the system prompt in total
+const MAX_TOTAL_INSTRUCTION_BYTES: usize = 64 * 1024;
+
+/// Per-project settings loaded from `.kuse-cowork/config.toml`
+///
+/// ```toml
+/// model = "claude-sonnet-4-20250514"
+/// allowed_tools = ["read_file", "grep", "bash"]
+/// instructions = "Run `cargo fmt` before finishing."
+///
+/// [bash]
+/// allow = ["cargo *", "git status*"]
+/// deny = ["git push*"]
+///
+/// [docker]
+/// image = "rust:alpine"
+/// mounts = ["data:/data:ro"]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    /// Overrides the default tool list when set
+    pub allowed_tools: Option<Vec<String>>,
+    /// Overrides the model from settings when set
+    pub model: Option<String>,
+    /// Extra system prompt instructions
+    pub instructions: Option<String>,
+    pub bash: BashPolicy,
+    pub docker: DockerDefaults,
+}
+
+/// Allow/deny glob patterns applied to `bash` commands
+///
+/// Patterns are matched against each command of a shell line separately, so
+/// `cargo *` doesn't let `cargo test && rm -rf ~` through.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BashPolicy {
+    /// If non-empty, only commands matching one of these patterns may run
+    pub allow: Vec<String>,
+    /// Commands matching any of these patterns are always rejected
+    pub deny: Vec<String>,
+}
+
+impl BashPolicy {
+    /// Reject patterns that aren't valid globs
+    pub fn validate(&self) -> Result<(), String> {
+        for (list, patterns) in [("allow", &self.allow), ("deny", &self.deny)] {
+            for pattern in patterns {
+                glob::Pattern::new(pattern)
+                    .map_err(|e| format!("bash.{} pattern '{}' is not a valid glob: {}", list, pattern, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every command of a shell line against the deny and allow patterns
+    pub fn check(&self, command: &str) -> Result<(), String> {
+        // Substitutions and redirections run or write things no pattern sees
+        if !self.allow.is_empty() {
+            if let Some(construct) = unchecked_construct(command) {
+                return Err(format!(
+                    "Command blocked by project policy: {} is not allowed when an allow list is set",
+                    construct
+                ));
+            }
+        }
+
+        for segment in command_segments(command) {
+            if let Some(pattern) = self.deny.iter().find(|p| pattern_matches(p, segment)) {
+                return Err(format!(
+                    "Command blocked by project policy: '{}' matches deny pattern '{}'",
+                    segment, pattern
+                ));
+            }
+
+            if !self.allow.is_empty() && !self.allow.iter().any(|p| pattern_matches(p, segment)) {
+                return Err(format!(
+                    "Command blocked by project policy: '{}' does not match any allowed pattern ({})",
+                    segment,
+                    self.allow.join(", ")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn pattern_matches(pattern: &str, command: &str) -> bool {
+    // Invalid patterns are rejected when the config is loaded
+    glob::Pattern::new(pattern).is_ok_and(|p| p.matches(command))
+}
+
+/// Characters of a shell line the shell may treat specially: everything outside
+/// single quotes that isn't backslash-escaped, with whether it's in double quotes
+fn unquoted_chars(command: &str) -> impl Iterator<Item = (usize, char, bool)> + '_ {
+    let (mut single, mut double, mut escaped) = (false, false, false);
+    command.char_indices().filter_map(move |(i, c)| {
+        if escaped {
+            escaped = false;
+            return None;
+        }
+        match c {
+            '\\' if !single => escaped = true,
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            _ if !single => return Some((i, c, double)),
+            _ => {}
+        }
+        None
+    })
+}
+
+/// Command substitution or redirection in a shell line, if any
+fn unchecked_construct(command: &str) -> Option<&'static str> {
+    let mut chars = unquoted_chars(command).peekable();
+    while let Some((_, c, _)) = chars.next() {
+        match c {
+            '`' => return Some("command substitution"),
+            '$' if matches!(chars.peek(), Some((_, '(', _))) => return Some("command substitution"),
+            '<' | '>' => return Some("redirection"),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Commands a shell line runs: split on control operators (`;`, `&&`, `||`, `|`,
+/// `&`, newlines) and subshell or substitution boundaries outside quotes, without
+/// leading grouping, negation or variable assignments
+fn command_segments(command: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (i, c, in_double) in unquoted_chars(command) {
+        let boundary = match c {
+            '`' | '(' | ')' => true,
+            ';' | '&' | '|' | '\n' | '{' | '}' => !in_double,
+            _ => false,
+        };
+        if boundary {
+            segments.push(&command[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    segments.push(&command[start..]);
+
+    segments
+        .into_iter()
+        .map(|segment| {
+            let mut segment = segment.trim_start_matches(|c: char| c.is_whitespace() || c == '!' || c == '$');
+            while let Some((word, rest)) = segment.split_once(char::is_whitespace) {
+                if !is_assignment(word) {
+                    break;
+                }
+                segment = rest.trim_start();
+            }
+            segment.trim()
+        })
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// `NAME=value` prefix setting a variable for the command after it
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Defaults for the `docker_run` tool
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerDefaults {
+    /// Image used when the model doesn't specify one
+    pub image: Option<String>,
+    /// Extra volume mounts (`host_path:container_path[:ro]`), host paths relative to the project
+    /// and confined to it
+    pub mounts: Vec<String>,
+}
+
+/// Summary of an instruction file that was appended to the system prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstructionFileInfo {
+    pub path: String,
+    pub bytes: usize,
+    pub truncated: bool,
+}
+
+/// Files picked up from the project, reported to the frontend when the agent starts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectContextInfo {
+    pub config_file: Option<String>,
+    pub instruction_files: Vec<InstructionFileInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProjectContextInfo {
+    pub fn is_empty(&self) -> bool {
+        self.config_file.is_none() && self.instruction_files.is_empty() && self.error.is_none()
+    }
+}
+
+impl ProjectConfig {
+    /// Load `.kuse-cowork/config.toml` from the project, if present
+    pub fn load(project_path: &Path) -> Result<Option<Self>, String> {
+        let path = project_path.join(PROJECT_CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let config: Self = toml::from_str(&content)
+            .map_err(|e| format!("Invalid {}: {}", PROJECT_CONFIG_FILE, e))?;
+        config
+            .bash
+            .validate()
+            .map_err(|e| format!("Invalid {}: {}", PROJECT_CONFIG_FILE, e))?;
+
+        Ok(Some(config))
+    }
+
+    /// Resolve docker mount host paths against the project root, dropping any that
+    /// point outside it
+    pub fn resolve_mounts(&mut self, project_path: &Path) -> Result<(), String> {
+        let mut rejected = Vec::new();
+        self.docker.mounts.retain_mut(|mount| {
+            let Some((host, rest)) = mount.split_once(':') else {
+                rejected.push(mount.clone());
+                return false;
+            };
+            match confine_to_project(project_path, Path::new(host)) {
+                Some(host_path) => {
+                    *mount = format!("{}:{}", host_path.display(), rest);
+                    true
+                }
+                None => {
+                    rejected.push(mount.clone());
+                    false
+                }
+            }
+        });
+
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Ignored docker mounts outside the project: {}",
+                rejected.join(", ")
+            ))
+        }
+    }
+}
+
+/// Resolve `host` against the project root, or `None` if it leaves the project
+fn confine_to_project(project_path: &Path, host: &Path) -> Option<PathBuf> {
+    let resolved = normalize(&project_path.join(host));
+    let root = normalize(project_path);
+    if !resolved.starts_with(&root) {
+        return None;
+    }
+
+    // Symlinks inside the project can still point elsewhere
+    if let (Ok(real), Ok(real_root)) = (resolved.canonicalize(), root.canonicalize()) {
+        if !real.starts_with(real_root) {
+            return None;
+        }
+    }
+
+    Some(resolved)
+}
+
+/// Lexically resolve `.` and `..` components
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// An instruction file read from the project
+pub struct InstructionFile {
+    pub info: InstructionFileInfo,
+    pub content: String,
+}
+
+/// Read project instruction files, applying per-file and total size limits
+pub fn load_instruction_files(project_path: &Path) -> Vec<InstructionFile> {
+    let mut files = Vec::new();
+    let mut total = 0;
+
+    for name in INSTRUCTION_FILES {
+        let path: PathBuf = project_path.join(name);
+        if !path.is_file() {
+            continue;
+        }
+
+        let remaining = MAX_TOTAL_INSTRUCTION_BYTES.saturating_sub(total);
+        if remaining == 0 {
+            break;
+        }
+
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Failed to read instruction file {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        let limit = MAX_INSTRUCTION_FILE_BYTES.min(remaining);
+        let truncated = content.len() > limit;
+        let content = if truncated {
+            let mut end = limit;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            content[..end].to_string()
+        } else {
+            content
+        };
+
+        total += content.len();
+        files.push(InstructionFile {
+            info: InstructionFileInfo {
+                path: name.to_string(),
+                bytes: content.len(),
+                truncated,
+            },
+            content,
+        });
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kuse-project-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join(".kuse-cowork")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load_project_config() {
+        let dir = temp_project();
+        fs::write(
+            dir.join(PROJECT_CONFIG_FILE),
+            r#"
+model = "gpt-4o"
+allowed_tools = ["read_file", "bash"]
+
+[bash]
+deny = ["git push*"]
+
+[docker]
+image = "rust:alpine"
+mounts = ["data:/data:ro", "./cache/../out:/out"]
+"#,
+        )
+        .unwrap();
+
+        let mut config = ProjectConfig::load(&dir).unwrap().unwrap();
+        config.resolve_mounts(&dir).unwrap();
+
+        assert_eq!(config.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(config.allowed_tools.unwrap(), vec!["read_file", "bash"]);
+        assert_eq!(config.docker.image.as_deref(), Some("rust:alpine"));
+        assert_eq!(config.docker.mounts[0], format!("{}:/data:ro", dir.join("data").display()));
+        assert_eq!(config.docker.mounts[1], format!("{}:/out", dir.join("out").display()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_mounts_outside_project_rejected() {
+        let dir = temp_project();
+        let mut config = ProjectConfig::default();
+        config.docker.mounts = vec![
+            "src:/src".to_string(),
+            "/etc:/host-etc:ro".to_string(),
+            "../shared:/shared".to_string(),
+            "a/../../b:/b".to_string(),
+            format!("{}:/workspace", dir.display()),
+        ];
+
+        let err = config.resolve_mounts(&dir).unwrap_err();
+        assert!(err.contains("/etc:/host-etc:ro"), "{err}");
+        assert!(err.contains("../shared:/shared"), "{err}");
+        assert!(err.contains("a/../../b:/b"), "{err}");
+        assert_eq!(
+            config.docker.mounts,
+            vec![
+                format!("{}:/src", dir.join("src").display()),
+                format!("{}:/workspace", dir.display()),
+            ]
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.join("etc-link")).unwrap();
+            config.docker.mounts = vec!["etc-link:/etc-link".to_string()];
+            assert!(config.resolve_mounts(&dir).is_err());
+            assert!(config.docker.mounts.is_empty());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_and_invalid_config() {
+        let dir = temp_project();
+        assert!(ProjectConfig::load(&dir).unwrap().is_none());
+
+        fs::write(dir.join(PROJECT_CONFIG_FILE), "allowed_tools = 3").unwrap();
+        assert!(ProjectConfig::load(&dir).is_err());
+
+        fs::write(dir.join(PROJECT_CONFIG_FILE), "[bash]\nallow = [\"cargo [test\"]").unwrap();
+        let err = ProjectConfig::load(&dir).unwrap_err();
+        assert!(err.contains("bash.allow pattern 'cargo [test'"), "{err}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bash_policy() {
+        let policy = BashPolicy {
+            allow: vec!["cargo *".to_string(), "git status*".to_string()],
+            deny: vec!["cargo publish*".to_string()],
+        };
+
+        assert!(policy.check("cargo test").is_ok());
+        assert!(policy.check("git status --short").is_ok());
+        assert!(policy.check("cargo publish --dry-run").is_err());
+        assert!(policy.check("rm -rf target").is_err());
+        assert!(BashPolicy::default().check("anything").is_ok());
+    }
+
+    #[test]
+    fn test_bash_policy_chained_commands() {
+        let allow = BashPolicy {
+            allow: vec!["cargo *".to_string(), "git status*".to_string()],
+            deny: Vec::new(),
+        };
+        assert!(allow.check("cargo test && cargo clippy").is_ok());
+        assert!(allow.check("cargo test -- 'a;b|c' \"x && y\"").is_ok());
+        for bypass in [
+            "cargo test && rm -rf ~",
+            "cargo test; rm -rf ~",
+            "cargo test || rm -rf ~",
+            "cargo test | sh",
+            "cargo test & rm -rf ~",
+            "cargo test\nrm -rf ~",
+            "cargo test $(rm -rf ~)",
+            "cargo test `rm -rf ~`",
+            "cargo test > ~/.bashrc",
+            "cargo test < /etc/passwd",
+            "(rm -rf ~)",
+        ] {
+            assert!(allow.check(bypass).is_err(), "{bypass}");
+        }
+
+        let deny = BashPolicy {
+            allow: Vec::new(),
+            deny: vec!["git push*".to_string()],
+        };
+        assert!(deny.check("git status; git log").is_ok());
+        assert!(deny.check("echo 'git status; git push'").is_ok());
+        for bypass in [
+            "git status; git push --force",
+            "git status && git push",
+            "git status || git push",
+            "git status | git push",
+            "git status & git push",
+            "git status\ngit push",
+            "echo $(git push)",
+            "echo `git push`",
+            "echo \"$(git push)\"",
+            "(git push)",
+            "{ git push; }",
+            "! git push",
+            "GIT_TRACE=1 git push",
+        ] {
+            assert!(deny.check(bypass).is_err(), "{bypass}");
+        }
+    }
+
+    #[test]
+    fn test_instruction_files_truncated() {
+        let dir = temp_project();
+        fs::write(dir.join("AGENTS.md"), "a".repeat(MAX_INSTRUCTION_FILE_BYTES + 10)).unwrap();
+        fs::write(dir.join(".kuse-cowork/instructions.md"), "Use tabs.").unwrap();
+
+        let files = load_instruction_files(&dir);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].info.path, "AGENTS.md");
+        assert!(files[0].info.truncated);
+        assert_eq!(files[0].content.len(), MAX_INSTRUCTION_FILE_BYTES);
+        assert_eq!(files[1].content, "Use tabs.");
+        assert!(!files[1].info.truncated);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
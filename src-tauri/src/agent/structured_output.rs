@@ -0,0 +1,210 @@
+use crate::agent::message_builder::{ApiContent, ApiMessage, ClaudeApiRequest};
+use crate::agent::ToolDefinition;
+use crate::llm_client::LlmResponse;
+use serde_json::Value;
+use std::future::Future;
+
+/// Tool Anthropic models are forced to call with the structured answer
+pub const STRUCTURED_OUTPUT_TOOL: &str = "structured_output";
+
+/// Sent after the final answer when the run has a response schema
+pub const STRUCTURED_OUTPUT_PROMPT: &str = "Now give your final answer as a single JSON value that matches \
+the required schema. Reply with the JSON only, without any other text or code fences.";
+
+pub fn definition(schema: &Value) -> ToolDefinition {
+    ToolDefinition {
+        name: STRUCTURED_OUTPUT_TOOL.to_string(),
+        description: "Return your final answer. The input must match the required schema exactly.".to_string(),
+        input_schema: schema.clone(),
+    }
+}
+
+/// Check that a response schema is itself a valid JSON Schema
+pub fn check_schema(schema: &Value) -> Result<(), String> {
+    jsonschema::validator_for(schema)
+        .map(|_| ())
+        .map_err(|e| format!("Invalid response schema: {}", e))
+}
+
+/// Validate a value against the schema, listing every violation
+pub fn validate(schema: &Value, value: &Value) -> Result<(), Vec<String>> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| vec![format!("Invalid response schema: {}", e)])?;
+    let errors: Vec<String> = validator
+        .iter_errors(value)
+        .map(|e| match e.instance_path.to_string() {
+            path if path.is_empty() => e.to_string(),
+            path => format!("{}: {}", path, e),
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// JSON answer in a response: the forced tool's input, or else the text with any
+/// code fence removed
+pub fn parse_output(response: &LlmResponse) -> Result<Value, String> {
+    if let Some(tool_use) = response.tool_uses.iter().find(|tu| tu.name == STRUCTURED_OUTPUT_TOOL) {
+        return Ok(tool_use.input.clone());
+    }
+    parse_text(&response.text)
+}
+
+/// Parse a JSON answer from model text
+pub fn parse_text(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.strip_suffix("```"))
+        .unwrap_or(text)
+        .trim();
+    serde_json::from_str(text).map_err(|e| format!("Response is not valid JSON: {}", e))
+}
+
+/// The text as JSON, if it is an answer that already matches the schema
+pub fn valid_answer(schema: &Value, text: &str) -> Option<Value> {
+    parse_text(text).ok().filter(|value| validate(schema, value).is_ok())
+}
+
+/// Follow-up message asking the model to fix an answer that failed validation
+pub fn repair_prompt(errors: &[String]) -> String {
+    format!(
+        "Your answer does not match the required schema:\n{}\n\nReply again with corrected JSON only.",
+        errors.iter().map(|e| format!("- {}", e)).collect::<Vec<_>>().join("\n")
+    )
+}
+
+/// Send a schema-constrained request and validate the answer. An invalid answer
+/// gets one repair turn listing the violations before the request fails.
+pub async fn complete<F, Fut>(mut request: ClaudeApiRequest, schema: &Value, mut send: F) -> Result<Value, String>
+where
+    F: FnMut(ClaudeApiRequest) -> Fut,
+    Fut: Future<Output = Result<LlmResponse, String>>,
+{
+    request.response_schema = Some(schema.clone());
+
+    let response = send(request.clone()).await?;
+    let errors = match parse_output(&response) {
+        Ok(value) => match validate(schema, &value) {
+            Ok(()) => return Ok(value),
+            Err(errors) => errors,
+        },
+        Err(e) => vec![e],
+    };
+
+    // Show the model what it sent, as text so no tool result is needed
+    let answer = match response.tool_uses.iter().find(|tu| tu.name == STRUCTURED_OUTPUT_TOOL) {
+        Some(tool_use) => tool_use.input.to_string(),
+        None => response.text,
+    };
+    request.messages.push(ApiMessage {
+        role: "assistant".to_string(),
+        content: ApiContent::Text(answer),
+    });
+    request.messages.push(ApiMessage {
+        role: "user".to_string(),
+        content: ApiContent::Text(repair_prompt(&errors)),
+    });
+
+    let value = parse_output(&send(request).await?)?;
+    validate(schema, &value)
+        .map(|()| value)
+        .map_err(|errors| format!("Response does not match the schema: {}", errors.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::ToolUse;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string"},
+                "population": {"type": "integer", "minimum": 0}
+            },
+            "required": ["city", "population"],
+            "additionalProperties": false
+        })
+    }
+
+    fn text_response(text: &str) -> LlmResponse {
+        LlmResponse {
+            text: text.to_string(),
+            thinking: Vec::new(),
+            tool_uses: Vec::new(),
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_and_validate() {
+        let fenced = text_response("```json\n{\"city\": \"Oslo\", \"population\": 709000}\n```");
+        let value = parse_output(&fenced).unwrap();
+        assert_eq!(value["city"], "Oslo");
+        assert!(validate(&schema(), &value).is_ok());
+
+        let tool = LlmResponse {
+            tool_uses: vec![ToolUse {
+                id: "toolu_1".to_string(),
+                name: STRUCTURED_OUTPUT_TOOL.to_string(),
+                input: json!({"city": "Bergen", "population": 291000}),
+                thought_signature: None,
+            }],
+            ..text_response("")
+        };
+        assert_eq!(parse_output(&tool).unwrap()["city"], "Bergen");
+
+        let errors = validate(&schema(), &json!({"city": "Oslo", "population": -1, "extra": true})).unwrap_err();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(errors.iter().any(|e| e.starts_with("/population")), "{errors:?}");
+
+        assert!(parse_text("The answer is Oslo").is_err());
+        assert!(check_schema(&json!({"type": "object"})).is_ok());
+        assert!(check_schema(&json!({"type": 12})).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repair_turn() {
+        let request = ClaudeApiRequest::chat("gpt-4o", 1024, None, vec![ApiMessage {
+            role: "user".to_string(),
+            content: ApiContent::Text("Largest city in Norway?".to_string()),
+        }]);
+        let sent = Mutex::new(Vec::new());
+        let replies = Mutex::new(vec![
+            r#"{"city": "Oslo", "population": 709000}"#,
+            r#"{"city": "Oslo", "population": "709k"}"#,
+        ]);
+        let send = |request: ClaudeApiRequest| {
+            sent.lock().unwrap().push(request);
+            let reply = replies.lock().unwrap().pop().unwrap();
+            async move { Ok(text_response(reply)) }
+        };
+
+        let value = complete(request, &schema(), send).await.unwrap();
+        assert_eq!(value["population"], 709000);
+
+        let sent = sent.into_inner().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].response_schema, Some(schema()));
+        let repair = &sent[1].messages;
+        assert_eq!(repair.len(), 3);
+        assert_eq!(repair[1].role, "assistant");
+        let ApiContent::Text(prompt) = &repair[2].content else {
+            panic!("repair prompt should be text");
+        };
+        assert!(prompt.contains("/population"), "{prompt}");
+
+        // A second invalid answer fails the request
+        let still_wrong = |_| async { Ok(text_response("not json")) };
+        let request = ClaudeApiRequest::chat("gpt-4o", 1024, None, Vec::new());
+        let error = complete(request, &schema(), still_wrong).await.unwrap_err();
+        assert!(error.contains("not valid JSON"), "{error}");
+    }
+}
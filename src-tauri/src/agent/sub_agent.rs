@@ -0,0 +1,232 @@
+use crate::agent::plan::PLAN_TOOL;
+use crate::agent::{AgentConfig, AgentContent, AgentMessage, ContentBlock, ToolDefinition};
+use serde_json::json;
+
+/// Name of the delegation tool
+pub const DELEGATE_TOOL: &str = "delegate";
+
+pub const SUB_AGENT_SYSTEM_PROMPT: &str = r#"You are a sub-agent of Kuse Cowork, working on one focused task delegated by a parent agent.
+
+## Rules
+- Work only on the task you were given; do not start unrelated work
+- Use your tools to gather facts instead of guessing
+- You do not need to output a plan or step markers
+- The parent agent only sees your FINAL message, not your tool calls or intermediate text
+
+## Final Answer
+When you are done, reply without calling any tools. Your final message must be a concise, self-contained summary of:
+1. What you did
+2. What you found or changed (include file paths and key details)
+3. Anything left unresolved
+"#;
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: DELEGATE_TOOL.to_string(),
+        description: "Delegate a focused sub-task to a sub-agent with its own fresh context. The sub-agent works independently and returns only a summary of its result. Use this for large searches, investigations or self-contained changes that would otherwise fill your context. Several delegate calls in one turn run in parallel.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "task": {
+                    "type": "string",
+                    "description": "Clear, self-contained description of what the sub-agent should do and what it should report back"
+                },
+                "context": {
+                    "type": "string",
+                    "description": "Relevant background the sub-agent needs (file paths, constraints, findings so far). It cannot see your conversation."
+                },
+                "tools": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Tools the sub-agent may use (subset of your own tools, default: all of them)"
+                }
+            },
+            "required": ["task"]
+        }),
+    }
+}
+
+/// Parsed `delegate` tool input
+#[derive(Debug, Clone)]
+pub struct DelegateRequest {
+    pub task: String,
+    pub context: Option<String>,
+    pub tools: Option<Vec<String>>,
+}
+
+impl DelegateRequest {
+    pub fn from_input(input: &serde_json::Value) -> Result<Self, String> {
+        let task = input
+            .get("task")
+            .and_then(|v| v.as_str())
+            .filter(|t| !t.trim().is_empty())
+            .ok_or("Missing 'task' parameter")?
+            .to_string();
+
+        let context = input
+            .get("context")
+            .and_then(|v| v.as_str())
+            .filter(|c| !c.trim().is_empty())
+            .map(|c| c.to_string());
+
+        let tools = input.get("tools").and_then(|v| v.as_array()).map(|arr| {
+            arr.iter()
+                .filter_map(|t| t.as_str().map(|s| s.to_string()))
+                .collect()
+        });
+
+        Ok(Self { task, context, tools })
+    }
+
+    /// Initial user message for the sub-agent
+    pub fn prompt(&self) -> String {
+        match &self.context {
+            Some(context) => format!("## Task\n{}\n\n## Context\n{}", self.task, context),
+            None => format!("## Task\n{}", self.task),
+        }
+    }
+}
+
+/// Build the configuration for a sub-agent spawned by `parent`
+pub fn child_config(parent: &AgentConfig, request: &DelegateRequest) -> AgentConfig {
+    let depth = parent.delegation_depth + 1;
+
+    // Children may only use tools the parent has
+    let mut allowed_tools: Vec<String> = match &request.tools {
+        Some(requested) => parent
+            .allowed_tools
+            .iter()
+            .filter(|t| requested.contains(t))
+            .cloned()
+            .collect(),
+        None => parent.allowed_tools.clone(),
+    };
+
+    if depth >= parent.max_delegation_depth {
+        allowed_tools.retain(|t| t != DELEGATE_TOOL);
+    }
+    // Sub-agents don't own the task plan
+    allowed_tools.retain(|t| t != PLAN_TOOL);
+
+    let mut system_prompt = SUB_AGENT_SYSTEM_PROMPT.to_string();
+    if let Some(project_path) = &parent.project_path {
+        system_prompt.push_str(&format!("\nProject directory: {}\n", project_path));
+    }
+
+    AgentConfig {
+        system_prompt,
+        max_turns: parent.sub_agent_max_turns,
+        project_path: parent.project_path.clone(),
+        allowed_tools,
+        bash_policy: parent.bash_policy.clone(),
+        docker_defaults: parent.docker_defaults.clone(),
+        project_context: None,
+        delegation_depth: depth,
+        max_delegation_depth: parent.max_delegation_depth,
+        max_concurrent_sub_agents: parent.max_concurrent_sub_agents,
+        sub_agent_max_turns: parent.sub_agent_max_turns,
+        wrap_up_on_max_turns: parent.wrap_up_on_max_turns,
+        thinking_budget: parent.thinking_budget,
+        response_schema: None,
+    }
+}
+
+/// Extract the text of the last assistant message as the sub-agent's summary
+pub fn final_summary(messages: &[AgentMessage]) -> Option<String> {
+    let last = messages.iter().rev().find(|m| m.role == "assistant")?;
+
+    let text = match &last.content {
+        AgentContent::Text(text) => text.clone(),
+        AgentContent::Blocks(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        AgentContent::ToolResults(_) => String::new(),
+    };
+
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent() -> AgentConfig {
+        AgentConfig {
+            project_path: Some("/tmp/project".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_child_config_restricts_tools() {
+        let request = DelegateRequest::from_input(&json!({
+            "task": "Find all TODOs",
+            "tools": ["grep", "glob", "not_a_parent_tool"]
+        }))
+        .unwrap();
+
+        let child = child_config(&parent(), &request);
+        assert_eq!(child.allowed_tools, vec!["glob", "grep"]);
+        assert_eq!(child.delegation_depth, 1);
+        assert_eq!(child.project_path.as_deref(), Some("/tmp/project"));
+        assert!(child.system_prompt.contains("/tmp/project"));
+    }
+
+    #[test]
+    fn test_child_config_depth_limit() {
+        let request = DelegateRequest::from_input(&json!({ "task": "Investigate" })).unwrap();
+
+        let mut config = parent();
+        config.max_delegation_depth = 2;
+        assert!(config.can_delegate());
+
+        let child = child_config(&config, &request);
+        assert!(child.can_delegate());
+
+        let grandchild = child_config(&child, &request);
+        assert_eq!(grandchild.delegation_depth, 2);
+        assert!(!grandchild.allowed_tools.contains(&DELEGATE_TOOL.to_string()));
+        assert!(!grandchild.can_delegate());
+    }
+
+    #[test]
+    fn test_delegate_request_requires_task() {
+        assert!(DelegateRequest::from_input(&json!({})).is_err());
+        assert!(DelegateRequest::from_input(&json!({ "task": "  " })).is_err());
+
+        let request = DelegateRequest::from_input(&json!({
+            "task": "Summarise README",
+            "context": "Focus on install steps"
+        }))
+        .unwrap();
+        assert_eq!(request.prompt(), "## Task\nSummarise README\n\n## Context\nFocus on install steps");
+    }
+
+    #[test]
+    fn test_final_summary() {
+        let messages = vec![
+            AgentMessage {
+                role: "user".to_string(),
+                content: AgentContent::Text("task".to_string()),
+            },
+            AgentMessage {
+                role: "assistant".to_string(),
+                content: AgentContent::Blocks(vec![ContentBlock::Text {
+                    text: "Found 3 TODOs".to_string(),
+                }]),
+            },
+        ];
+        assert_eq!(final_summary(&messages).as_deref(), Some("Found 3 TODOs"));
+        assert_eq!(final_summary(&messages[..1]), None);
+    }
+}
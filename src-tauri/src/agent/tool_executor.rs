@@ -1,4 +1,4 @@
-use crate::agent::{ToolResult, ToolUse};
+use crate::agent::{BashPolicy, DockerDefaults, ToolResult, ToolUse};
 use crate::mcp::{MCPManager, MCPToolCall};
 use crate::tools;
 use std::sync::Arc;
@@ -6,6 +6,8 @@ use std::sync::Arc;
 pub struct ToolExecutor {
     project_path: Option<String>,
     mcp_manager: Option<Arc<MCPManager>>,
+    bash_policy: BashPolicy,
+    docker_defaults: DockerDefaults,
 }
 
 impl ToolExecutor {
@@ -13,9 +15,18 @@ impl ToolExecutor {
         Self {
             project_path,
             mcp_manager: None,
+            bash_policy: BashPolicy::default(),
+            docker_defaults: DockerDefaults::default(),
         }
     }
 
+    /// Apply project-level bash patterns and docker defaults
+    pub fn with_project_settings(mut self, bash_policy: BashPolicy, docker_defaults: DockerDefaults) -> Self {
+        self.bash_policy = bash_policy;
+        self.docker_defaults = docker_defaults;
+        self
+    }
+
     pub fn with_mcp_manager(mut self, mcp_manager: Arc<MCPManager>) -> Self {
         self.mcp_manager = Some(mcp_manager);
         self
@@ -76,14 +87,19 @@ impl ToolExecutor {
 
         // Docker tools have their own result handling
         if tool_use.name.starts_with("docker_") {
-            return tools::docker::execute_docker_tool(tool_use, &self.project_path);
+            return tools::docker::execute_docker_tool(tool_use, &self.project_path, &self.docker_defaults);
         }
 
         let result = match tool_use.name.as_str() {
             "read_file" => tools::file_read::execute(&tool_use.input, project_path),
             "write_file" => tools::file_write::execute(&tool_use.input, project_path),
             "edit_file" => tools::file_edit::execute(&tool_use.input, project_path),
-            "bash" => tools::bash::execute(&tool_use.input, project_path),
+            "bash" => {
+                let command = tool_use.input.get("command").and_then(|v| v.as_str()).unwrap_or("");
+                self.bash_policy
+                    .check(command)
+                    .and_then(|_| tools::bash::execute(&tool_use.input, project_path))
+            }
             "glob" => tools::glob::execute(&tool_use.input, project_path),
             "grep" => tools::grep::execute(&tool_use.input, project_path),
             "list_dir" => tools::list_dir::execute(&tool_use.input, project_path),
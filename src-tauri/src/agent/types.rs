@@ -1,5 +1,12 @@
 use serde::{Deserialize, Serialize};
+use crate::agent::project_config::{
+    load_instruction_files, BashPolicy, DockerDefaults, ProjectConfig, ProjectContextInfo,
+    PROJECT_CONFIG_FILE,
+};
+use crate::agent::plan::PLAN_TOOL;
+use crate::agent::sub_agent::DELEGATE_TOOL;
 use crate::skills::{get_available_skills, get_skills_directory_path};
+use std::path::Path;
 
 /// Tool definition sent to Claude API
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +69,16 @@ impl ToolResult {
 pub enum ContentBlock {
     #[serde(rename = "text")]
     Text { text: String },
+    /// Extended thinking; the signature must be sent back unchanged on Anthropic
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    /// Thinking the provider encrypted for safety reasons, sent back as-is
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
     #[serde(rename = "tool_use")]
     ToolUse {
         id: String,
@@ -95,6 +112,54 @@ pub struct AgentConfig {
     pub max_turns: u32,
     pub project_path: Option<String>,
     pub allowed_tools: Vec<String>,
+    /// Bash allow/deny patterns from the project config
+    #[serde(default)]
+    pub bash_policy: BashPolicy,
+    /// Docker defaults from the project config
+    #[serde(default)]
+    pub docker_defaults: DockerDefaults,
+    /// Project files that were loaded, reported when the agent starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_context: Option<ProjectContextInfo>,
+    /// Nesting level of this agent (0 for the top-level agent)
+    #[serde(default)]
+    pub delegation_depth: u32,
+    /// Sub-agents may be nested at most this many levels deep
+    #[serde(default = "default_max_delegation_depth")]
+    pub max_delegation_depth: u32,
+    /// Maximum sub-agents working at the same time across the whole agent tree;
+    /// one waiting on its own sub-agents doesn't count
+    #[serde(default = "default_max_concurrent_sub_agents")]
+    pub max_concurrent_sub_agents: usize,
+    /// Turn limit for each sub-agent
+    #[serde(default = "default_sub_agent_max_turns")]
+    pub sub_agent_max_turns: u32,
+    /// Ask for a tool-free summary when `max_turns` is reached. It is written by
+    /// the `RoutePurpose::Summary` route when settings set one, else the primary model.
+    #[serde(default = "default_wrap_up_on_max_turns")]
+    pub wrap_up_on_max_turns: bool,
+    /// Token budget for extended thinking / reasoning (disabled when unset)
+    #[serde(default)]
+    pub thinking_budget: Option<u32>,
+    /// JSON Schema the final answer must match (free-form text when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+fn default_max_delegation_depth() -> u32 {
+    2
+}
+
+fn default_max_concurrent_sub_agents() -> usize {
+    3
+}
+
+fn default_sub_agent_max_turns() -> u32 {
+    10
+}
+
+fn default_wrap_up_on_max_turns() -> bool {
+    true
 }
 
 impl Default for AgentConfig {
@@ -114,8 +179,91 @@ impl Default for AgentConfig {
                 "docker_run".to_string(),
                 "docker_list".to_string(),
                 "docker_images".to_string(),
+                DELEGATE_TOOL.to_string(),
+                PLAN_TOOL.to_string(),
             ],
+            bash_policy: BashPolicy::default(),
+            docker_defaults: DockerDefaults::default(),
+            project_context: None,
+            delegation_depth: 0,
+            max_delegation_depth: default_max_delegation_depth(),
+            max_concurrent_sub_agents: default_max_concurrent_sub_agents(),
+            sub_agent_max_turns: default_sub_agent_max_turns(),
+            wrap_up_on_max_turns: default_wrap_up_on_max_turns(),
+            thinking_budget: None,
+            response_schema: None,
+        }
+    }
+}
+
+impl AgentConfig {
+    /// Whether this agent may spawn sub-agents with the `delegate` tool
+    pub fn can_delegate(&self) -> bool {
+        self.delegation_depth < self.max_delegation_depth
+            && self.allowed_tools.iter().any(|t| t == DELEGATE_TOOL)
+    }
+
+    /// Apply `.kuse-cowork/config.toml` and instruction files from `project_path`.
+    ///
+    /// Returns the project config so callers can apply settings that live outside
+    /// `AgentConfig`, such as the model override.
+    pub fn load_project_settings(&mut self) -> ProjectConfig {
+        let Some(project_path) = self.project_path.clone() else {
+            return ProjectConfig::default();
+        };
+        let project_path = Path::new(&project_path);
+        let mut info = ProjectContextInfo::default();
+
+        let mut project_config = match ProjectConfig::load(project_path) {
+            Ok(Some(config)) => {
+                info.config_file = Some(PROJECT_CONFIG_FILE.to_string());
+                config
+            }
+            Ok(None) => ProjectConfig::default(),
+            Err(e) => {
+                eprintln!("[project_config] {}", e);
+                info.error = Some(e);
+                ProjectConfig::default()
+            }
+        };
+        if let Err(e) = project_config.resolve_mounts(project_path) {
+            eprintln!("[project_config] {}", e);
+            info.error = Some(e);
+        }
+
+        if let Some(tools) = &project_config.allowed_tools {
+            self.allowed_tools = tools.clone();
+        }
+        self.bash_policy = project_config.bash.clone();
+        self.docker_defaults = project_config.docker.clone();
+
+        if let Some(image) = &project_config.docker.image {
+            self.system_prompt.push_str(&format!("\nDefault Docker image for this project: {}\n", image));
+        }
+
+        if let Some(instructions) = &project_config.instructions {
+            if !instructions.trim().is_empty() {
+                self.system_prompt.push_str("\n\n## Project Instructions\n");
+                self.system_prompt.push_str(instructions.trim());
+                self.system_prompt.push('\n');
+            }
+        }
+
+        for file in load_instruction_files(project_path) {
+            self.system_prompt.push_str(&format!("\n\n## Project Instructions ({})\n", file.info.path));
+            self.system_prompt.push_str(file.content.trim());
+            if file.info.truncated {
+                self.system_prompt.push_str("\n[truncated]");
+            }
+            self.system_prompt.push('\n');
+            info.instruction_files.push(file.info);
         }
+
+        if !info.is_empty() {
+            self.project_context = Some(info);
+        }
+
+        project_config
     }
 }
 
@@ -125,7 +273,12 @@ You have access to tools that allow you to read and write files, execute command
 
 ## IMPORTANT: Always Create a Plan First
 
-Before starting ANY task, you MUST output a plan in this exact format:
+Before starting ANY task, call the `update_plan` tool with your steps. Keep the plan current as you work:
+- Mark exactly one step `in_progress` while you work on it
+- Mark steps `completed` as soon as they are done, or `skipped` if no longer needed
+- Insert, remove or reword steps whenever your understanding changes; always send the full list
+
+If the `update_plan` tool is not available, output the plan in this exact format instead:
 
 <plan>
 1. [First step description]
@@ -134,9 +287,7 @@ Before starting ANY task, you MUST output a plan in this exact format:
 ...
 </plan>
 
-After outputting the plan, immediately begin executing each step. As you work through each step, indicate progress with:
-- `[STEP 1 START]` when beginning a step
-- `[STEP 1 DONE]` when completing a step
+and indicate progress with `[STEP 1 START]` when beginning a step and `[STEP 1 DONE]` when completing it.
 
 ## Guidelines
 - Always read files before modifying them to understand the context
@@ -144,6 +295,7 @@ After outputting the plan, immediately begin executing each step. As you work th
 - Be careful with bash commands - prefer read-only operations when possible
 - Search with glob and grep before making assumptions about file locations
 - Explain what you're doing briefly
+- Delegate broad searches or independent sub-tasks to keep your own context focused
 
 ## Available Tools
 - `read_file` - Read file contents
@@ -156,6 +308,8 @@ After outputting the plan, immediately begin executing each step. As you work th
 - `docker_run` - Run commands in Docker containers
 - `docker_list` - List running containers
 - `docker_images` - List available images
+- `update_plan` - Create or update your task plan
+- `delegate` - Hand a self-contained sub-task to a sub-agent with a fresh context; only its final summary comes back
 
 ## Docker Integration
 The project_path (if provided) is automatically mounted to /workspace in containers.
@@ -163,8 +317,8 @@ Skills directory (~/.kuse-cowork/skills) is automatically mounted to /skills (re
 Default image: python:3.11-alpine. Also available: ubuntu:latest, node:20, rust:alpine
 
 ## Workflow
-1. Output your plan in <plan> tags
-2. Execute step by step, marking progress
+1. Create your plan with `update_plan`
+2. Execute step by step, updating the plan as you go
 3. Verify your changes work
 4. Summarize what was accomplished
 "#;
@@ -207,6 +361,12 @@ pub fn build_system_prompt() -> String {
 pub enum AgentEvent {
     #[serde(rename = "text")]
     Text { content: String },
+    /// Streamed model reasoning (accumulated for the current turn)
+    #[serde(rename = "thinking")]
+    Thinking { content: String },
+    /// Token usage of one model response
+    #[serde(rename = "usage")]
+    Usage(TokenUsage),
     #[serde(rename = "plan")]
     Plan { steps: Vec<PlanStepInfo> },
     #[serde(rename = "step_start")]
@@ -219,14 +379,83 @@ pub enum AgentEvent {
     ToolEnd { tool: String, result: String, success: bool },
     #[serde(rename = "turn_complete")]
     TurnComplete { turn: u32 },
+    /// Final answer of a run with a response schema, validated against it
+    #[serde(rename = "structured_output")]
+    StructuredOutput { output: serde_json::Value },
     #[serde(rename = "done")]
     Done { total_turns: u32 },
     #[serde(rename = "error")]
     Error { message: String },
+    #[serde(rename = "warning")]
+    Warning { message: String },
+    /// The run was ended early by the agent loop
+    #[serde(rename = "stopped")]
+    Stopped { reason: StopReason, message: String },
+    #[serde(rename = "project_context")]
+    ProjectContext {
+        config_file: Option<String>,
+        instruction_files: Vec<crate::agent::project_config::InstructionFileInfo>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    #[serde(rename = "sub_agent_start")]
+    SubAgentStart {
+        sub_agent_id: String,
+        depth: u32,
+        task: String,
+    },
+    /// Event emitted by a sub-agent, forwarded to the parent's listeners
+    #[serde(rename = "sub_agent")]
+    SubAgent {
+        sub_agent_id: String,
+        event: Box<AgentEvent>,
+    },
+}
+
+/// Token counts reported by the provider for one response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Prompt tokens written to the Anthropic prompt cache
+    pub cache_creation_input_tokens: u64,
+    /// Prompt tokens served from the Anthropic prompt cache
+    pub cache_read_input_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Merge counts from an Anthropic `usage` object (later events carry updated totals)
+    pub fn merge(&mut self, usage: &serde_json::Value) {
+        let get = |key: &str| usage.get(key).and_then(|v| v.as_u64());
+        if let Some(n) = get("input_tokens") {
+            self.input_tokens = n;
+        }
+        if let Some(n) = get("output_tokens") {
+            self.output_tokens = n;
+        }
+        if let Some(n) = get("cache_creation_input_tokens") {
+            self.cache_creation_input_tokens = n;
+        }
+        if let Some(n) = get("cache_read_input_tokens") {
+            self.cache_read_input_tokens = n;
+        }
+    }
+}
+
+/// Why the agent loop ended a run early
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model kept repeating the same tool call after being warned
+    LoopDetected,
+    /// `max_turns` was reached; the run can be continued with a fresh budget
+    TurnLimit,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct PlanStepInfo {
     pub step: i32,
     pub description: String,
+    /// "pending", "running", "completed" or "skipped"
+    pub status: String,
 }
@@ -1,184 +0,0 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
-use tokio::sync::mpsc;
-
-#[derive(Error, Debug)]
-pub enum ClaudeError {
-    #[error("HTTP error: {0}")]
-    Http(#[from] reqwest::Error),
-    #[error("API error: {0}")]
-    Api(String),
-    #[error("Parse error: {0}")]
-    #[allow(dead_code)]
-    Parse(String),
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Message {
-    pub role: String,
-    pub content: String,
-}
-
-#[derive(Debug, Serialize)]
-struct ClaudeRequest {
-    model: String,
-    max_tokens: u32,
-    messages: Vec<Message>,
-    stream: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f32>,
-}
-
-#[derive(Debug, Deserialize)]
-struct ClaudeResponse {
-    content: Vec<ContentBlock>,
-}
-
-#[derive(Debug, Deserialize)]
-struct ContentBlock {
-    #[serde(rename = "type")]
-    #[allow(dead_code)]
-    content_type: String,
-    text: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct StreamEvent {
-    #[serde(rename = "type")]
-    event_type: String,
-    delta: Option<Delta>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Delta {
-    #[serde(rename = "type")]
-    #[allow(dead_code)]
-    delta_type: Option<String>,
-    text: Option<String>,
-}
-
-pub struct ClaudeClient {
-    client: Client,
-    api_key: String,
-    base_url: String,
-}
-
-impl ClaudeClient {
-    pub fn new(api_key: String, base_url: Option<String>) -> Self {
-        Self {
-            client: Client::new(),
-            api_key,
-            base_url: base_url.unwrap_or_else(|| "https://api.anthropic.com".to_string()),
-        }
-    }
-
-    pub async fn send_message(
-        &self,
-        messages: Vec<Message>,
-        model: &str,
-        max_tokens: u32,
-        temperature: Option<f32>,
-    ) -> Result<String, ClaudeError> {
-        let request = ClaudeRequest {
-            model: model.to_string(),
-            max_tokens,
-            messages,
-            stream: false,
-            temperature,
-        };
-
-        let response = self
-            .client
-            .post(format!("{}/v1/messages", self.base_url))
-            .header("Content-Type", "application/json")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(ClaudeError::Api(error_text));
-        }
-
-        let claude_response: ClaudeResponse = response.json().await?;
-
-        let text = claude_response
-            .content
-            .into_iter()
-            .filter_map(|block| block.text)
-            .collect::<Vec<_>>()
-            .join("");
-
-        Ok(text)
-    }
-
-    pub async fn send_message_stream(
-        &self,
-        messages: Vec<Message>,
-        model: &str,
-        max_tokens: u32,
-        temperature: Option<f32>,
-        tx: mpsc::Sender<String>,
-    ) -> Result<String, ClaudeError> {
-        let request = ClaudeRequest {
-            model: model.to_string(),
-            max_tokens,
-            messages,
-            stream: true,
-            temperature,
-        };
-
-        let response = self
-            .client
-            .post(format!("{}/v1/messages", self.base_url))
-            .header("Content-Type", "application/json")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(ClaudeError::Api(error_text));
-        }
-
-        let mut full_text = String::new();
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-
-        use futures::StreamExt;
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            // Process complete SSE lines
-            while let Some(pos) = buffer.find('\n') {
-                let line = buffer[..pos].to_string();
-                buffer = buffer[pos + 1..].to_string();
-
-                if let Some(data) = line.strip_prefix("data: ") {
-                    if data == "[DONE]" {
-                        continue;
-                    }
-
-                    if let Ok(event) = serde_json::from_str::<StreamEvent>(data) {
-                        if event.event_type == "content_block_delta" {
-                            if let Some(delta) = event.delta {
-                                if let Some(text) = delta.text {
-                                    full_text.push_str(&text);
-                                    let _ = tx.send(full_text.clone()).await;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(full_text)
-    }
-}
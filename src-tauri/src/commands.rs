@@ -1,16 +1,24 @@
-use crate::agent::{AgentConfig, AgentContent, AgentEvent, AgentLoop, AgentMessage};
-use crate::claude::{ClaudeClient, Message as ClaudeMessage};
-use crate::database::{Conversation, Database, Message, PlanStep, Settings, Task, TaskMessage};
+use crate::agent::{AgentConfig, AgentContent, AgentEvent, AgentLoop, AgentMessage, StopReason};
+use crate::agent::message_builder::{ApiContent, ApiMessage, ClaudeApiRequest};
+use crate::agent::{structured_output, sub_agent};
+use crate::history::{ListQuery, Tag};
+use crate::http::HttpConfigError;
+use crate::database::{Conversation, Database, Message, PlanStep, SecretStoreStatus, Settings, Task, TaskMessage};
+use crate::llm_client::catalog::{catalog, DiscoveredModel, ModelInfo};
+use crate::llm_client::routing::{ModelRoute, ModelTarget, RoutePurpose};
+use crate::llm_client::{AuthType, LLMClient, LLMError, LlmProvider, OllamaProvider, ProviderConfig, PullProgress, StreamEvent};
+use crate::search::{SearchFilter, SearchHit};
+use crate::titles::{self, TitleChanged, TitleKind};
+use crate::transcript::{self, Export, ExportFormat, Imported, ToolCallRecord};
+use crate::trash::Trash;
 use crate::mcp::{MCPManager, MCPServerConfig, MCPServerStatus, MCPToolCall, MCPToolResult};
 use crate::skills::{SkillMetadata, get_available_skills};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tauri::{command, Emitter, State, Window};
-use tokio::sync::Mutex;
+use tauri::{command, Emitter, Runtime, State, Window};
 
 pub struct AppState {
     pub db: Arc<Database>,
-    pub claude_client: Mutex<Option<ClaudeClient>>,
     pub mcp_manager: Arc<MCPManager>,
 }
 
@@ -27,8 +35,16 @@ impl From<crate::database::DbError> for CommandError {
     }
 }
 
-impl From<crate::claude::ClaudeError> for CommandError {
-    fn from(e: crate::claude::ClaudeError) -> Self {
+impl From<HttpConfigError> for CommandError {
+    fn from(e: HttpConfigError) -> Self {
+        CommandError {
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<LLMError> for CommandError {
+    fn from(e: LLMError) -> Self {
         CommandError {
             message: e.to_string(),
         }
@@ -53,10 +69,14 @@ pub fn get_platform() -> String {
 
 // Settings commands
 #[command]
-pub fn get_settings(state: State<'_, Arc<AppState>>) -> Result<Settings, CommandError> {
+pub fn get_settings(state: State<'_, Arc<AppState>>, reveal: Option<bool>) -> Result<Settings, CommandError> {
     let settings = state.db.get_settings()?;
-    println!("[get_settings] api_key length from db: {}", settings.api_key.len());
-    Ok(settings)
+    // API keys only leave the backend in full when explicitly asked for
+    if reveal.unwrap_or(false) {
+        Ok(settings)
+    } else {
+        Ok(settings.masked())
+    }
 }
 
 #[command]
@@ -64,170 +84,353 @@ pub async fn save_settings(
     state: State<'_, Arc<AppState>>,
     settings: Settings,
 ) -> Result<(), CommandError> {
-    println!("[save_settings] model: {}", settings.model);
-    println!("[save_settings] base_url: {}", settings.base_url);
-    println!("[save_settings] api_key length: {}", settings.api_key.len());
-    // Show first and last 10 chars for debugging
-    if settings.api_key.len() > 20 {
-        println!("[save_settings] api_key preview: {}...{}",
-            &settings.api_key[..10],
-            &settings.api_key[settings.api_key.len()-10..]);
+    let network = state.db.unmask_network(&settings.network)?;
+    network.validate()?;
+    state.db.save_settings(&settings)?;
+    state.mcp_manager.set_network(network);
+
+    Ok(())
+}
+
+/// Whether the saved secrets could be unlocked at startup
+#[command]
+pub fn get_secret_store_status(state: State<'_, Arc<AppState>>) -> Result<SecretStoreStatus, CommandError> {
+    Ok(state.db.secret_store_status()?)
+}
+
+#[command]
+pub async fn unlock_secret_store(state: State<'_, Arc<AppState>>, passphrase: String) -> Result<(), CommandError> {
+    state.db.unlock_secrets(&passphrase)?;
+    state.mcp_manager.set_network(state.db.get_settings()?.network);
+    Ok(())
+}
+
+/// Delete the saved secrets so they can be entered again under a new key
+#[command]
+pub async fn reset_secret_store(
+    state: State<'_, Arc<AppState>>,
+    passphrase: Option<String>,
+) -> Result<(), CommandError> {
+    state.db.reset_secrets(passphrase.as_deref())?;
+    state.mcp_manager.set_network(state.db.get_settings()?.network);
+    Ok(())
+}
+
+/// Provider for the configured model and credentials
+fn provider_from_settings(settings: &Settings) -> Result<Arc<dyn LlmProvider>, CommandError> {
+    let provider_id = settings.get_provider();
+    Ok(LLMClient::new_with_openai_headers(
+        settings.api_key.clone(),
+        Some(settings.base_url.clone()),
+        Some(&provider_id),
+        Some(&settings.model),
+        settings.openai_organization.clone(),
+        settings.openai_project.clone(),
+    )
+    .with_http_client(settings.network.client(&provider_id)?)
+    .with_ollama_options(settings.ollama.clone())
+    .into_provider())
+}
+
+/// Provider for a preset: the current provider as configured, others with the key saved for them
+fn provider_for(settings: &Settings, provider_id: &str) -> Result<Arc<dyn LlmProvider>, CommandError> {
+    if provider_id == settings.get_provider() {
+        return provider_from_settings(settings);
     }
+    Ok(LLMClient::new_with_openai_headers(
+        settings.provider_keys.get(provider_id).cloned().unwrap_or_default(),
+        None,
+        Some(provider_id),
+        None,
+        settings.openai_organization.clone(),
+        settings.openai_project.clone(),
+    )
+    .with_http_client(settings.network.client(provider_id)?)
+    .with_ollama_options(settings.ollama.clone())
+    .into_provider())
+}
 
-    state.db.save_settings(&settings)?;
+/// Provider for a fallback or routed model, using the key saved for its provider
+fn model_route(settings: &Settings, target: &ModelTarget) -> Result<ModelRoute, CommandError> {
+    let api_key = settings.provider_keys.get(&target.provider).cloned().unwrap_or_default();
+    let provider = LLMClient::new_with_openai_headers(
+        api_key,
+        Some(target.base_url.clone()),
+        Some(&target.provider),
+        Some(&target.model),
+        settings.openai_organization.clone(),
+        settings.openai_project.clone(),
+    )
+    .with_http_client(settings.network.client(&target.provider)?)
+    .with_ollama_options(settings.ollama.clone())
+    .into_provider();
+    Ok(ModelRoute::new(provider, target.model.clone()))
+}
 
-    // Update Claude client with new settings
-    let mut client = state.claude_client.lock().await;
-    if !settings.api_key.is_empty() {
-        *client = Some(ClaudeClient::new(
-            settings.api_key.clone(),
-            Some(settings.base_url.clone()),
-        ));
-    } else {
-        *client = None;
+/// Primary model for a run: the project's model override on the provider that
+/// serves it, else the configured model
+fn primary_route(settings: &Settings, model: Option<String>) -> Result<ModelRoute, CommandError> {
+    let Some(model) = model.filter(|m| *m != settings.model) else {
+        return Ok(ModelRoute::new(provider_from_settings(settings)?, settings.model.clone()));
+    };
+
+    let provider = ProviderConfig::from_model(&model);
+    if provider.id == settings.get_provider() {
+        return Ok(ModelRoute::new(provider_from_settings(settings)?, model));
+    }
+
+    let has_key = settings.provider_keys.get(&provider.id).is_some_and(|k| !k.is_empty());
+    if provider.auth_type != AuthType::None && !has_key {
+        return Err(CommandError {
+            message: format!(
+                "Project model '{}' is served by {}, but no API key is saved for it",
+                model, provider.name
+            ),
+        });
+    }
+    model_route(settings, &ModelTarget { provider: provider.id, model, base_url: String::new() })
+}
+
+/// Add the fallback chain and routing rules from settings to an agent
+fn with_model_routing(agent: AgentLoop, settings: &Settings) -> Result<AgentLoop, CommandError> {
+    let fallbacks = settings
+        .fallbacks
+        .iter()
+        .map(|t| model_route(settings, t))
+        .collect::<Result<_, _>>()?;
+    settings
+        .routes
+        .iter()
+        .try_fold(agent.with_fallbacks(fallbacks), |agent, rule| {
+            Ok(agent.with_route(rule.purpose, model_route(settings, &rule.target)?))
+        })
+}
+
+/// Model for generated titles: the `title` routing rule, else the primary model
+fn title_route(settings: &Settings) -> Result<ModelRoute, CommandError> {
+    match settings.routes.iter().find(|rule| rule.purpose == RoutePurpose::Title) {
+        Some(rule) => model_route(settings, &rule.target),
+        None => Ok(ModelRoute::new(provider_from_settings(settings)?, settings.model.clone())),
     }
+}
 
+/// Set a generated title, unless the user renamed the conversation or task, and
+/// tell the frontend when it changed
+fn set_title<R: Runtime>(window: &Window<R>, db: &Database, kind: TitleKind, id: &str, title: String) -> Result<(), CommandError> {
+    let changed = match kind {
+        TitleKind::Conversation => db.update_conversation_title(id, &title)?,
+        TitleKind::Task => db.update_task_title(id, &title)?,
+    };
+    if changed {
+        let _ = window.emit("title-changed", TitleChanged { kind, id: id.to_string(), title });
+    }
     Ok(())
 }
 
+/// Title a new conversation or task after its first exchange. The start of the
+/// prompt is used right away and replaced by the model's title in the background.
+fn title_first_exchange<R: Runtime>(
+    window: &Window<R>,
+    db: &Arc<Database>,
+    settings: &Settings,
+    kind: TitleKind,
+    id: &str,
+    prompt: &str,
+    reply: &str,
+) -> Result<(), CommandError> {
+    set_title(window, db, kind, id, titles::placeholder(prompt))?;
+
+    let route = match title_route(settings) {
+        Ok(route) => route,
+        Err(e) => {
+            eprintln!("[titles] no model for {}: {}", id, e.message);
+            return Ok(());
+        }
+    };
+    let (window, db, id) = (window.clone(), db.clone(), id.to_string());
+    let (prompt, reply) = (prompt.to_string(), reply.to_string());
+    tokio::spawn(async move {
+        let result = match titles::generate(&route, &prompt, &reply).await {
+            Ok(title) => set_title(&window, &db, kind, &id, title),
+            Err(message) => Err(CommandError { message }),
+        };
+        if let Err(e) = result {
+            eprintln!("[titles] {}: {}", id, e.message);
+        }
+    });
+    Ok(())
+}
+
+/// Tool-free request for a conversation's stored messages
+fn plain_chat_request(settings: &Settings, db_messages: &[Message]) -> ClaudeApiRequest {
+    let messages = db_messages
+        .iter()
+        .map(|m| ApiMessage {
+            role: m.role.clone(),
+            content: ApiContent::Text(m.content.clone()),
+        })
+        .collect();
+
+    let model = catalog().info(&settings.model);
+    ClaudeApiRequest::chat(
+        &settings.model,
+        settings.max_tokens.min(model.max_output_tokens),
+        model.temperature(Some(settings.temperature)),
+        messages,
+    )
+}
+
+/// Answer matching `schema`, streaming each attempt's text to `tx`
+async fn structured_chat(
+    provider: &dyn LlmProvider,
+    request: ClaudeApiRequest,
+    schema: &serde_json::Value,
+    tx: tokio::sync::mpsc::Sender<StreamEvent>,
+) -> Result<String, CommandError> {
+    structured_output::complete(request, schema, |request| {
+        let tx = tx.clone();
+        async move { provider.stream(&request, tx).await.map_err(|e| e.to_string()) }
+    })
+    .await
+    .map(|value| value.to_string())
+    .map_err(|message| CommandError { message })
+}
+
+/// Known models with their limits, capabilities and prices
 #[command]
-pub async fn test_connection(state: State<'_, Arc<AppState>>) -> Result<String, CommandError> {
-    use crate::llm_client::{LLMClient, Message};
+pub fn get_model_catalog() -> Vec<ModelInfo> {
+    catalog().models().to_vec()
+}
 
+/// Models offered by a provider (the current one by default), annotated with
+/// catalogue data. Lists are cached per endpoint; `refresh` skips the cache.
+#[command]
+pub async fn list_models(
+    state: State<'_, Arc<AppState>>,
+    provider: Option<String>,
+    refresh: Option<bool>,
+) -> Result<Vec<DiscoveredModel>, CommandError> {
     let settings = state.db.get_settings()?;
+    let provider_id = provider.filter(|p| !p.is_empty()).unwrap_or_else(|| settings.get_provider());
+    let provider = provider_for(&settings, &provider_id)?;
+    let base_url = provider.config().base_url.clone();
+
+    let cached = state.db.get_cached_models(&provider_id, &base_url)?;
+    let now = chrono::Utc::now().timestamp_millis();
+    if let Some(fresh) = cached.as_ref().filter(|c| c.is_fresh(now) && !refresh.unwrap_or(false)) {
+        return Ok(catalog().annotate(fresh.models.clone()));
+    }
 
-    // Debug logging
-    println!("[test_connection] model: {}", settings.model);
-    println!("[test_connection] base_url: {}", settings.base_url);
-    println!("[test_connection] api_key length: {}", settings.api_key.len());
-    println!("[test_connection] provider: {}", settings.get_provider());
-    println!("[test_connection] is_local_provider: {}, allows_empty_api_key: {}",
-        settings.is_local_provider(), settings.allows_empty_api_key());
+    let models = match provider.list_models().await {
+        Ok(mut models) => {
+            models.sort();
+            models.dedup();
+            state.db.cache_models(&provider_id, &base_url, &models)?;
+            models
+        }
+        // An outdated list beats none when the provider can't be reached
+        Err(e) => match cached {
+            Some(stale) => {
+                eprintln!("[list_models] {}: {}; using cached list", provider_id, e);
+                stale.models
+            }
+            None => return Err(e.into()),
+        },
+    };
+    Ok(catalog().annotate(models))
+}
+
+#[command]
+pub async fn test_connection(state: State<'_, Arc<AppState>>) -> Result<String, CommandError> {
+    let settings = state.db.get_settings()?;
 
     if settings.api_key.is_empty() && !settings.allows_empty_api_key() {
         return Ok("No API key configured".to_string());
     }
 
-    // Choose test method based on provider type
-    if settings.is_local_provider() {
-        // Local service - use LLMClient to check connection
-        let llm_client = LLMClient::new(
-            String::new(), // Local services don't need API key
-            Some(settings.base_url.clone()),
-            None,
-            Some(&settings.model),
-        );
-
-        match llm_client.check_connection().await {
-            Ok(true) => Ok("success".to_string()),
-            Ok(false) => Ok("Error: Cannot connect to local service, please ensure it is running".to_string()),
-            Err(e) => Ok(format!("Error: {}", e)),
-        }
-    } else {
-        // Cloud service - check provider type
-        let provider = settings.get_provider();
-
-        match provider.as_str() {
-            "anthropic" => {
-                // Anthropic - use ClaudeClient
-                let client = ClaudeClient::new(settings.api_key, Some(settings.base_url));
-                let messages = vec![ClaudeMessage {
-                    role: "user".to_string(),
-                    content: "Hi".to_string(),
-                }];
-
-                match client.send_message(messages, &settings.model, 10, None).await {
-                    Ok(_) => Ok("success".to_string()),
-                    Err(e) => Ok(format!("Error: {}", e)),
-                }
-            }
-            "openai" => {
-                // OpenAI - test with actual API request using LLMClient
-                let llm_client = LLMClient::new_with_openai_headers(
-                    settings.api_key.clone(),
-                    Some(settings.base_url.clone()),
-                    Some("openai"),
-                    Some(&settings.model),
-                    settings.openai_organization.clone(),
-                    settings.openai_project.clone(),
-                );
-
-                let test_messages = vec![Message {
-                    role: "user".to_string(),
-                    content: "Hi".to_string(),
-                }];
-
-                // Send a minimal test request
-                match llm_client.send_message(test_messages, &settings.model, 10, None).await {
-                    Ok(_) => Ok("success".to_string()),
-                    Err(e) => Ok(format!("Error: {}", e)),
-                }
-            }
-            "google" => {
-                // Google Gemini - test with actual API request
-                let llm_client = LLMClient::new(
-                    settings.api_key.clone(),
-                    Some(settings.base_url.clone()),
-                    Some("google"),
-                    Some(&settings.model),
-                );
-
-                let test_messages = vec![Message {
-                    role: "user".to_string(),
-                    content: "Hi".to_string(),
-                }];
-
-                match llm_client.send_message(test_messages, &settings.model, 10, None).await {
-                    Ok(_) => Ok("success".to_string()),
-                    Err(e) => Ok(format!("Error: {}", e)),
-                }
-            }
-            _ => {
-                // Other cloud services - try sending a test message
-                let llm_client = LLMClient::new(
-                    settings.api_key.clone(),
-                    Some(settings.base_url.clone()),
-                    None,
-                    Some(&settings.model),
-                );
-
-                let test_messages = vec![Message {
-                    role: "user".to_string(),
-                    content: "Hi".to_string(),
-                }];
-
-                // Try to send a minimal test request
-                match llm_client.send_message(test_messages, &settings.model, 10, None).await {
-                    Ok(_) => Ok("success".to_string()),
-                    Err(e) => {
-                        // If sending fails, try simple connection check (for services that support it)
-                        match llm_client.check_connection().await {
-                            Ok(true) => Ok("success".to_string()),
-                            Ok(false) => Ok(format!("Error: {}", e)),
-                            Err(conn_e) => Ok(format!("Error: {}", conn_e)),
-                        }
-                    }
-                }
-            }
-        }
+    // Local services only need to be reachable; cloud providers get a minimal request
+    let provider = match provider_from_settings(&settings) {
+        Ok(provider) => provider,
+        Err(e) => return Ok(format!("Error: {}", e.message)),
+    };
+    match provider.check_connection(&settings.model).await {
+        Ok(()) => Ok("success".to_string()),
+        Err(e) => Ok(format!("Error: {}", e)),
     }
 }
 
+/// Download a model into the Ollama server, emitting `ollama-pull-progress` events
+#[command]
+pub async fn pull_ollama_model(
+    window: Window,
+    state: State<'_, Arc<AppState>>,
+    model: String,
+) -> Result<(), CommandError> {
+    let settings = state.db.get_settings()?;
+    // Use the configured server when Ollama is the current provider, else the default one
+    let base_url = (settings.get_provider() == "ollama").then(|| settings.base_url.clone());
+    let client = LLMClient::new(String::new(), base_url, Some("ollama"), None)
+        .with_http_client(settings.network.client("ollama")?);
+    let provider = OllamaProvider::new(client);
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<PullProgress>(100);
+    let window_clone = window.clone();
+    let emit_task = tokio::spawn(async move {
+        while let Some(progress) = rx.recv().await {
+            let _ = window_clone.emit("ollama-pull-progress", &progress);
+        }
+    });
+
+    let result = provider.pull_model(&model, tx).await;
+    let _ = emit_task.await;
+    result?;
+
+    // The new model should show up in the next listing
+    state.db.clear_model_cache("ollama")?;
+    Ok(())
+}
+
 // Conversation commands
 #[command]
 pub fn list_conversations(
     state: State<'_, Arc<AppState>>,
+    query: Option<ListQuery>,
 ) -> Result<Vec<Conversation>, CommandError> {
-    state.db.list_conversations().map_err(Into::into)
+    state.db.list_conversations(&query.unwrap_or_default()).map_err(Into::into)
 }
 
 #[command]
 pub fn create_conversation(
     state: State<'_, Arc<AppState>>,
     title: String,
+    project_path: Option<String>,
 ) -> Result<Conversation, CommandError> {
     let id = uuid::Uuid::new_v4().to_string();
-    state.db.create_conversation(&id, &title).map_err(Into::into)
+    state.db.create_conversation(&id, &title, project_path.as_deref()).map_err(Into::into)
+}
+
+#[command]
+pub fn set_conversation_pinned(state: State<'_, Arc<AppState>>, id: String, pinned: bool) -> Result<(), CommandError> {
+    state.db.set_conversation_pinned(&id, pinned).map_err(Into::into)
+}
+
+#[command]
+pub fn set_conversation_archived(state: State<'_, Arc<AppState>>, id: String, archived: bool) -> Result<(), CommandError> {
+    state.db.set_conversation_archived(&id, archived).map_err(Into::into)
+}
+
+#[command]
+pub fn set_conversation_project(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    project_path: Option<String>,
+) -> Result<(), CommandError> {
+    state.db.set_conversation_project(&id, project_path.as_deref()).map_err(Into::into)
+}
+
+#[command]
+pub fn set_conversation_tags(state: State<'_, Arc<AppState>>, id: String, tag_ids: Vec<String>) -> Result<(), CommandError> {
+    state.db.set_conversation_tags(&id, &tag_ids).map_err(Into::into)
 }
 
 #[command]
@@ -236,7 +439,7 @@ pub fn update_conversation_title(
     id: String,
     title: String,
 ) -> Result<(), CommandError> {
-    state.db.update_conversation_title(&id, &title).map_err(Into::into)
+    state.db.rename_conversation(&id, &title).map_err(Into::into)
 }
 
 #[command]
@@ -283,10 +486,12 @@ pub async fn send_chat_message(
     state: State<'_, Arc<AppState>>,
     conversation_id: String,
     content: String,
+    response_schema: Option<serde_json::Value>,
 ) -> Result<String, CommandError> {
-    use crate::llm_client::{LLMClient, Message as LLMMessage};
-
     let settings = state.db.get_settings()?;
+    if let Some(schema) = &response_schema {
+        structured_output::check_schema(schema).map_err(|message| CommandError { message })?;
+    }
 
     if settings.api_key.is_empty() && !settings.allows_empty_api_key() {
         return Err(CommandError {
@@ -304,67 +509,23 @@ pub async fn send_chat_message(
     let db_messages = state.db.get_messages(&conversation_id)?;
 
     // Create channel for streaming
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<StreamEvent>(100);
 
     // Spawn task to emit events
     let window_clone = window.clone();
     let emit_task = tokio::spawn(async move {
-        while let Some(text) = rx.recv().await {
-            let _ = window_clone.emit("chat-stream", StreamPayload { text, done: false });
+        while let Some(event) = rx.recv().await {
+            if let StreamEvent::Text(text) = event {
+                let _ = window_clone.emit("chat-stream", StreamPayload { text, done: false });
+            }
         }
     });
 
-    // Choose client based on provider
-    let provider = settings.get_provider();
-    let response = match provider.as_str() {
-        "anthropic" => {
-            // Use ClaudeClient for Anthropic
-            let claude_messages: Vec<ClaudeMessage> = db_messages
-                .iter()
-                .map(|m| ClaudeMessage {
-                    role: m.role.clone(),
-                    content: m.content.clone(),
-                })
-                .collect();
-            let client = ClaudeClient::new(settings.api_key, Some(settings.base_url));
-            client
-                .send_message_stream(
-                    claude_messages,
-                    &settings.model,
-                    settings.max_tokens,
-                    Some(settings.temperature),
-                    tx,
-                )
-                .await?
-        }
-        _ => {
-            // Use LLMClient for OpenAI and other providers
-            let llm_messages: Vec<LLMMessage> = db_messages
-                .iter()
-                .map(|m| LLMMessage {
-                    role: m.role.clone(),
-                    content: m.content.clone(),
-                })
-                .collect();
-            let llm_client = LLMClient::new_with_openai_headers(
-                settings.api_key.clone(),
-                Some(settings.base_url.clone()),
-                Some(&provider),
-                Some(&settings.model),
-                settings.openai_organization.clone(),
-                settings.openai_project.clone(),
-            );
-            llm_client
-                .send_message_stream(
-                    llm_messages,
-                    &settings.model,
-                    settings.max_tokens,
-                    Some(settings.temperature),
-                    tx,
-                )
-                .await
-                .map_err(|e| CommandError { message: e.to_string() })?
-        }
+    let provider = provider_from_settings(&settings)?;
+    let chat_request = plain_chat_request(&settings, &db_messages);
+    let response = match &response_schema {
+        Some(schema) => structured_chat(provider.as_ref(), chat_request, schema, tx).await?,
+        None => provider.stream(&chat_request, tx).await?.text,
     };
 
     // Wait for emit task to finish
@@ -385,14 +546,9 @@ pub async fn send_chat_message(
         .db
         .add_message(&assistant_msg_id, &conversation_id, "assistant", &response)?;
 
-    // Update conversation title if this is the first message
+    // Title the conversation after its first exchange
     if db_messages.len() == 1 {
-        let title = if content.len() > 30 {
-            format!("{}...", &content[..30])
-        } else {
-            content.clone()
-        };
-        state.db.update_conversation_title(&conversation_id, &title)?;
+        title_first_exchange(&window, &state.db, &settings, TitleKind::Conversation, &conversation_id, &content, &response)?;
     }
 
     Ok(response)
@@ -408,6 +564,9 @@ pub enum ChatEvent {
     ToolStart { tool: String, input: serde_json::Value },
     #[serde(rename = "tool_end")]
     ToolEnd { tool: String, result: String, success: bool },
+    /// Something the user should know about that didn't stop the reply
+    #[serde(rename = "warning")]
+    Warning { message: String },
     #[serde(rename = "done")]
     Done { final_text: String },
 }
@@ -419,6 +578,11 @@ pub struct AgentRequest {
     pub project_path: Option<String>,
     pub system_prompt: Option<String>,
     pub max_turns: Option<u32>,
+    /// Extended thinking budget in tokens
+    pub thinking_budget: Option<u32>,
+    /// JSON Schema the final answer must match
+    #[serde(default)]
+    pub response_schema: Option<serde_json::Value>,
 }
 
 #[command]
@@ -463,22 +627,26 @@ pub async fn run_agent(
     if let Some(turns) = request.max_turns {
         config.max_turns = turns;
     }
+    config.thinking_budget = request.thinking_budget;
     config.project_path = request.project_path;
-
-    // Get provider info
-    let provider_id = settings.get_provider();
+    if let Some(schema) = &request.response_schema {
+        structured_output::check_schema(schema).map_err(|message| CommandError { message })?;
+    }
+    let structured = request.response_schema.is_some();
+    config.response_schema = request.response_schema;
+    let project_config = config.load_project_settings();
 
     // Create agent loop with provider
-    let agent = AgentLoop::new_with_provider(
-        settings.api_key,
-        settings.base_url,
+    let route = primary_route(&settings, project_config.model)?;
+    let agent = AgentLoop::from_provider(
+        route.provider,
         config,
-        settings.model,
+        route.model,
         settings.max_tokens,
         Some(settings.temperature),
         state.mcp_manager.clone(),
-        Some(&provider_id),
     );
+    let agent = with_model_routing(agent, &settings)?;
 
     // Create channel for events
     let (tx, mut rx) = tokio::sync::mpsc::channel::<AgentEvent>(100);
@@ -498,6 +666,11 @@ pub async fn run_agent(
     let _ = emit_task.await;
 
     match result {
+        // The validated JSON is the last message of a structured run
+        Ok(messages) if structured => match messages.last().map(|m| &m.content) {
+            Some(AgentContent::Text(text)) => Ok(text.clone()),
+            _ => Ok(String::new()),
+        },
         Ok(_messages) => Ok("Agent completed successfully".to_string()),
         Err(e) => Err(CommandError { message: e }),
     }
@@ -510,6 +683,9 @@ pub struct EnhancedChatRequest {
     pub content: String,
     pub project_path: Option<String>,
     pub enable_tools: bool,
+    /// JSON Schema the reply must match
+    #[serde(default)]
+    pub response_schema: Option<serde_json::Value>,
 }
 
 #[command]
@@ -518,11 +694,74 @@ pub async fn send_chat_with_tools(
     state: State<'_, Arc<AppState>>,
     request: EnhancedChatRequest,
 ) -> Result<String, CommandError> {
-    use crate::agent::{
-        AgentConfig, AgentContent, AgentMessage, ContentBlock, MessageBuilder, ToolExecutor, ToolUse,
-    };
-    use futures::StreamExt;
+    chat_with_tools(window, &state, request, ReplyTo::NewPrompt).await
+}
+
+/// Edit an earlier prompt: the new text starts a branch beside it, which is answered
+#[command]
+pub async fn edit_and_resend(
+    window: Window,
+    state: State<'_, Arc<AppState>>,
+    message_id: String,
+    request: EnhancedChatRequest,
+) -> Result<String, CommandError> {
+    let message = branch_point(&state.db, &request.conversation_id, &message_id, "user")?;
+    chat_with_tools(window, &state, request, ReplyTo::EditedPrompt(message.parent_id)).await
+}
+
+/// Answer the prompt before `message_id` again, as a branch beside that reply
+#[command]
+pub async fn regenerate_reply(
+    window: Window,
+    state: State<'_, Arc<AppState>>,
+    message_id: String,
+    request: EnhancedChatRequest,
+) -> Result<String, CommandError> {
+    let message = branch_point(&state.db, &request.conversation_id, &message_id, "assistant")?;
+    let prompt_id = message.parent_id.ok_or_else(|| CommandError {
+        message: format!("Message {} has no prompt to answer again", message_id),
+    })?;
+    chat_with_tools(window, &state, request, ReplyTo::Prompt(prompt_id)).await
+}
+
+/// Make the branch through `message_id` active; returns the new active path
+#[command]
+pub fn switch_branch(
+    state: State<'_, Arc<AppState>>,
+    conversation_id: String,
+    message_id: String,
+) -> Result<Vec<Message>, CommandError> {
+    state.db.switch_branch(&conversation_id, &message_id)?;
+    state.db.get_messages(&conversation_id).map_err(Into::into)
+}
+
+/// Message a new branch starts beside, checked to be a `role` message of the conversation
+fn branch_point(db: &Database, conversation_id: &str, message_id: &str, role: &str) -> Result<Message, CommandError> {
+    db.get_message(message_id)?
+        .filter(|m| m.conversation_id == conversation_id && m.role == role)
+        .ok_or_else(|| CommandError {
+            message: format!("No {} message {} in this conversation", role, message_id),
+        })
+}
 
+/// Where a chat reply goes in the conversation's message tree
+enum ReplyTo {
+    /// After `request.content`, added to the end of the active branch
+    NewPrompt,
+    /// After `request.content`, as a new branch from this message (`None` for a new first one)
+    EditedPrompt(Option<String>),
+    /// As another answer to this stored prompt
+    Prompt(String),
+}
+
+/// Reply to a prompt in the conversation; the active branch moves to the reply
+/// once it is saved
+async fn chat_with_tools<R: Runtime>(
+    window: Window<R>,
+    state: &AppState,
+    request: EnhancedChatRequest,
+    reply_to: ReplyTo,
+) -> Result<String, CommandError> {
     let settings = state.db.get_settings()?;
 
     if settings.api_key.is_empty() && !settings.allows_empty_api_key() {
@@ -531,101 +770,133 @@ pub async fn send_chat_with_tools(
         });
     }
 
-    // Add user message to database
-    let user_msg_id = uuid::Uuid::new_v4().to_string();
-    state
-        .db
-        .add_message(&user_msg_id, &request.conversation_id, "user", &request.content)?;
+    if let Some(schema) = &request.response_schema {
+        structured_output::check_schema(schema).map_err(|message| CommandError { message })?;
+    }
 
-    // Get conversation history
-    let db_messages = state.db.get_messages(&request.conversation_id)?;
+    // Get the history ending in the prompt. An edited prompt is only saved with its
+    // reply, so a failed reply leaves the conversation as it was.
+    let db_messages = match &reply_to {
+        ReplyTo::NewPrompt => {
+            let user_msg_id = uuid::Uuid::new_v4().to_string();
+            state
+                .db
+                .add_message(&user_msg_id, &request.conversation_id, "user", &request.content)?;
+            state.db.get_messages(&request.conversation_id)?
+        }
+        ReplyTo::EditedPrompt(parent_id) => {
+            let mut messages = state.db.get_branch(&request.conversation_id, parent_id.as_deref())?;
+            messages.push(Message {
+                id: uuid::Uuid::new_v4().to_string(),
+                conversation_id: request.conversation_id.clone(),
+                role: "user".to_string(),
+                content: request.content.clone(),
+                timestamp: chrono::Utc::now().timestamp_millis(),
+                tool_calls: Vec::new(),
+                parent_id: parent_id.clone(),
+                branches: Vec::new(),
+            });
+            messages
+        }
+        ReplyTo::Prompt(prompt_id) => state.db.get_branch(&request.conversation_id, Some(prompt_id))?,
+    };
+    let Some(prompt) = db_messages.last() else {
+        return Err(CommandError {
+            message: "No prompt to reply to".to_string(),
+        });
+    };
 
-    // If tools are not enabled, fall back to simple chat
-    if !request.enable_tools {
-        use crate::llm_client::{LLMClient, Message as LLMMessage};
+    // Reply without tools, or through the agent loop with them
+    let (final_text, tool_calls) = if request.enable_tools {
+        tool_reply(&window, state, &settings, request.project_path, request.response_schema, &db_messages).await?
+    } else {
+        let reply = plain_reply(&window, &settings, request.response_schema.as_ref(), &db_messages).await?;
+        (reply, Vec::new())
+    };
 
-        let provider = settings.get_provider();
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+    // Emit done
+    let _ = window.emit("chat-event", ChatEvent::Done { final_text: final_text.clone() });
 
-        let window_clone = window.clone();
-        let emit_task = tokio::spawn(async move {
-            while let Some(text) = rx.recv().await {
-                let _ = window_clone.emit("chat-event", ChatEvent::Text { content: text });
-            }
-        });
+    // Save the edited prompt and the final assistant response, with the tool calls behind it
+    if let ReplyTo::EditedPrompt(parent_id) = &reply_to {
+        state.db.add_message_after(
+            &prompt.id,
+            &request.conversation_id,
+            "user",
+            &prompt.content,
+            parent_id.as_deref(),
+        )?;
+    }
+    let assistant_msg_id = uuid::Uuid::new_v4().to_string();
+    state.db.add_message_after(
+        &assistant_msg_id,
+        &request.conversation_id,
+        "assistant",
+        &final_text,
+        Some(&prompt.id),
+    )?;
+    if !tool_calls.is_empty() {
+        state.db.set_message_tool_calls(&assistant_msg_id, &tool_calls)?;
+    }
 
-        let response = match provider.as_str() {
-            "anthropic" => {
-                // Use ClaudeClient for Anthropic
-                let claude_messages: Vec<ClaudeMessage> = db_messages
-                    .iter()
-                    .map(|m| ClaudeMessage {
-                        role: m.role.clone(),
-                        content: m.content.clone(),
-                    })
-                    .collect();
-                let client = ClaudeClient::new(settings.api_key.clone(), Some(settings.base_url.clone()));
-                client
-                    .send_message_stream(
-                        claude_messages,
-                        &settings.model,
-                        settings.max_tokens,
-                        Some(settings.temperature),
-                        tx,
-                    )
-                    .await?
-            }
-            _ => {
-                // Use LLMClient for OpenAI and other providers
-                let llm_messages: Vec<LLMMessage> = db_messages
-                    .iter()
-                    .map(|m| LLMMessage {
-                        role: m.role.clone(),
-                        content: m.content.clone(),
-                    })
-                    .collect();
-                let llm_client = LLMClient::new_with_openai_headers(
-                    settings.api_key.clone(),
-                    Some(settings.base_url.clone()),
-                    Some(&provider),
-                    Some(&settings.model),
-                    settings.openai_organization.clone(),
-                    settings.openai_project.clone(),
-                );
-                llm_client
-                    .send_message_stream(
-                        llm_messages,
-                        &settings.model,
-                        settings.max_tokens,
-                        Some(settings.temperature),
-                        tx,
-                    )
-                    .await
-                    .map_err(|e| CommandError { message: e.to_string() })?
-            }
-        };
+    // Title the conversation after its first exchange
+    if !matches!(reply_to, ReplyTo::Prompt(_)) && db_messages.len() == 1 {
+        title_first_exchange(
+            &window,
+            &state.db,
+            &settings,
+            TitleKind::Conversation,
+            &request.conversation_id,
+            &request.content,
+            &final_text,
+        )?;
+    }
 
-        let _ = emit_task.await;
-        let _ = window.emit("chat-event", ChatEvent::Done { final_text: response.clone() });
+    Ok(final_text)
+}
 
-        // Save assistant response
-        let assistant_msg_id = uuid::Uuid::new_v4().to_string();
-        state
-            .db
-            .add_message(&assistant_msg_id, &request.conversation_id, "assistant", &response)?;
+/// Tool-free reply to the stored messages, streamed to the chat view
+async fn plain_reply<R: Runtime>(
+    window: &Window<R>,
+    settings: &Settings,
+    response_schema: Option<&serde_json::Value>,
+    db_messages: &[Message],
+) -> Result<String, CommandError> {
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<StreamEvent>(100);
 
-        return Ok(response);
-    }
+    let window_clone = window.clone();
+    let emit_task = tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if let StreamEvent::Text(content) = event {
+                let _ = window_clone.emit("chat-event", ChatEvent::Text { content });
+            }
+        }
+    });
 
-    // Enhanced chat with tools - use AgentLoop which supports multiple providers
-    use crate::llm_client::ProviderConfig;
+    let provider = provider_from_settings(settings)?;
+    let chat_request = plain_chat_request(settings, db_messages);
+    let response = match response_schema {
+        Some(schema) => structured_chat(provider.as_ref(), chat_request, schema, tx).await?,
+        None => provider.stream(&chat_request, tx).await?.text,
+    };
 
-    let tool_executor = ToolExecutor::new(request.project_path.clone())
-        .with_mcp_manager(state.mcp_manager.clone());
+    let _ = emit_task.await;
+    Ok(response)
+}
 
+/// Reply through the agent loop with tools, streamed to the chat view; returns the
+/// reply and the tool calls behind it
+async fn tool_reply<R: Runtime>(
+    window: &Window<R>,
+    state: &AppState,
+    settings: &Settings,
+    project_path: Option<String>,
+    response_schema: Option<serde_json::Value>,
+    db_messages: &[Message],
+) -> Result<(String, Vec<ToolCallRecord>), CommandError> {
     // Build agent-style config for tools
     let mut config = AgentConfig {
-        project_path: request.project_path,
+        project_path,
         max_turns: 10, // Limit turns in chat mode
         ..Default::default()
     };
@@ -655,15 +926,27 @@ For simple questions or conversations, respond directly without using tools.
 
 Be concise and helpful. Explain what you're doing when using tools.{}"#, mcp_info);
 
-    let message_builder = MessageBuilder::new(
-        config.clone(),
-        settings.model.clone(),
+    // Apply project config and instruction files
+    let project_config = config.load_project_settings();
+    let route = primary_route(settings, project_config.model)?;
+
+    // Chat mode runs without sub-agent or plan support
+    config.max_delegation_depth = 0;
+    config.allowed_tools.retain(|t| t != crate::agent::plan::PLAN_TOOL);
+    config.response_schema = response_schema;
+
+    let agent = AgentLoop::from_provider(
+        route.provider,
+        config,
+        route.model,
         settings.max_tokens,
         Some(settings.temperature),
+        state.mcp_manager.clone(),
     );
+    let agent = with_model_routing(agent, settings)?;
 
     // Convert DB messages to agent messages
-    let mut agent_messages: Vec<AgentMessage> = db_messages
+    let agent_messages: Vec<AgentMessage> = db_messages
         .iter()
         .map(|m| AgentMessage {
             role: m.role.clone(),
@@ -671,437 +954,42 @@ Be concise and helpful. Explain what you're doing when using tools.{}"#, mcp_inf
         })
         .collect();
 
-    let client = reqwest::Client::new();
-    let mut final_text = String::new();
-    let mut turn = 0;
-    let max_turns = config.max_turns;
-
-    // Get provider config for determining API format
-    let provider_id = settings.get_provider();
-    let mut provider_config = ProviderConfig::from_preset(&provider_id);
-    if !settings.base_url.is_empty() {
-        provider_config.base_url = settings.base_url.clone();
-    }
-
-    // Determine API format
-    let use_openai_format = matches!(
-        provider_config.api_format,
-        crate::llm_client::ApiFormat::OpenAI | crate::llm_client::ApiFormat::OpenAICompatible
-    );
-    let use_google_format = matches!(
-        provider_config.api_format,
-        crate::llm_client::ApiFormat::Google
-    );
-
-    // For Google: track thoughtSignature per function call across iterations (required for Gemini 3)
-    let mut google_thought_signatures: std::collections::HashMap<String, String> = std::collections::HashMap::new();
-
-    loop {
-        turn += 1;
-        if turn > max_turns {
-            break;
-        }
-
-        // Build and send request
-        let api_request = message_builder.build_request(&agent_messages).await;
-
-        let response = if use_google_format {
-            // Google Gemini format request (pass thought signatures for Gemini 3 function calling)
-            let google_request = convert_to_google_format(&api_request, &settings.model, settings.max_tokens, &google_thought_signatures);
-            let base = provider_config.base_url.trim_end_matches('/');
-            let url = format!("{}/v1beta/models/{}:streamGenerateContent?alt=sse", base, settings.model);
-
-            client.post(&url)
-                .header("Content-Type", "application/json")
-                .header("x-goog-api-key", &settings.api_key)
-                .json(&google_request)
-                .send()
-                .await
-                .map_err(|e| CommandError { message: format!("HTTP error: {}", e) })?
-        } else if use_openai_format {
-            // OpenAI format request
-            let openai_request = convert_to_openai_format(&api_request, &settings.model);
-            let base = provider_config.base_url.trim_end_matches('/');
-            let url = if base.ends_with("/v1") {
-                format!("{}/chat/completions", base)
-            } else {
-                format!("{}/v1/chat/completions", base)
-            };
-
-            let mut req = client.post(&url)
-                .header("Content-Type", "application/json");
-
-            if !settings.api_key.is_empty() {
-                req = req.header("Authorization", format!("Bearer {}", settings.api_key));
-            }
-            // Add optional OpenAI headers
-            if let Some(ref org) = settings.openai_organization {
-                if !org.is_empty() {
-                    req = req.header("OpenAI-Organization", org);
-                }
-            }
-            if let Some(ref proj) = settings.openai_project {
-                if !proj.is_empty() {
-                    req = req.header("OpenAI-Project", proj);
-                }
-            }
-
-            req.json(&openai_request)
-                .send()
-                .await
-                .map_err(|e| CommandError { message: format!("HTTP error: {}", e) })?
-        } else {
-            // Anthropic format request
-            client
-                .post(format!("{}/v1/messages", provider_config.base_url.trim_end_matches('/')))
-                .header("Content-Type", "application/json")
-                .header("x-api-key", &settings.api_key)
-                .header("anthropic-version", "2023-06-01")
-                .json(&api_request)
-                .send()
-                .await
-                .map_err(|e| CommandError { message: format!("HTTP error: {}", e) })?
-        };
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(CommandError { message: format!("API error: {}", error_text) });
-        }
-
-        // Handle streaming response based on provider format
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-        let mut accumulated_text = String::new();
-        let mut tool_uses: Vec<ToolUse> = Vec::new();
-
-        if use_google_format {
-            // Google Gemini streaming format (SSE with alt=sse)
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk.map_err(|e| CommandError { message: format!("Stream error: {}", e) })?;
-                buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                while let Some(pos) = buffer.find('\n') {
-                    let line = buffer[..pos].trim().to_string();
-                    buffer = buffer[pos + 1..].to_string();
-
-                    if line.is_empty() {
-                        continue;
-                    }
-
-                    // Parse SSE data: prefix
-                    let json_str = if let Some(data) = line.strip_prefix("data: ") {
-                        data
-                    } else {
-                        continue;
-                    };
-
-                    if let Ok(event) = serde_json::from_str::<serde_json::Value>(json_str) {
-                        // Extract text and function calls from candidates
-                        if let Some(candidates) = event.get("candidates").and_then(|v| v.as_array()) {
-                            for candidate in candidates {
-                                if let Some(parts) = candidate.get("content")
-                                    .and_then(|c| c.get("parts"))
-                                    .and_then(|p| p.as_array())
-                                {
-                                    for part in parts {
-                                        // Handle text
-                                        if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-                                            if !text.is_empty() {
-                                                accumulated_text.push_str(text);
-                                                let _ = window.emit("chat-event", ChatEvent::Text {
-                                                    content: accumulated_text.clone(),
-                                                });
-                                            }
-                                        }
-                                        // Handle function calls (with thoughtSignature for Gemini 3)
-                                        if let Some(fc) = part.get("functionCall") {
-                                            let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string();
-                                            let args = fc.get("args").cloned().unwrap_or(serde_json::json!({}));
-                                            let id = format!("fc_{}", uuid::Uuid::new_v4());
-
-                                            // Capture thoughtSignature from the same part (required for Gemini 3)
-                                            let thought_signature = part.get("thoughtSignature")
-                                                .and_then(|v| v.as_str())
-                                                .map(|s| s.to_string());
-
-                                            // Also store in map for lookup when building functionResponse
-                                            if let Some(ref sig) = thought_signature {
-                                                google_thought_signatures.insert(id.clone(), sig.clone());
-                                            }
-
-                                            tool_uses.push(ToolUse {
-                                                id: id.clone(),
-                                                name: name.clone(),
-                                                input: args.clone(),
-                                                thought_signature,
-                                            });
-
-                                            let _ = window.emit("chat-event", ChatEvent::ToolStart {
-                                                tool: name,
-                                                input: args,
-                                            });
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        } else if use_openai_format {
-            // OpenAI streaming format
-            let mut current_tool_calls: std::collections::HashMap<i64, (String, String, String)> = std::collections::HashMap::new();
-
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk.map_err(|e| CommandError { message: format!("Stream error: {}", e) })?;
-                buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                while let Some(pos) = buffer.find('\n') {
-                    let line = buffer[..pos].to_string();
-                    buffer = buffer[pos + 1..].to_string();
-
-                    if let Some(data) = line.strip_prefix("data: ") {
-                        if data.trim() == "[DONE]" {
-                            continue;
-                        }
-
-                        if let Ok(event) = serde_json::from_str::<serde_json::Value>(data) {
-                            if let Some(choices) = event.get("choices").and_then(|v| v.as_array()) {
-                                for choice in choices {
-                                    if let Some(delta) = choice.get("delta") {
-                                        // Handle text content
-                                        if let Some(content) = delta.get("content").and_then(|v| v.as_str()) {
-                                            accumulated_text.push_str(content);
-                                            let _ = window.emit("chat-event", ChatEvent::Text {
-                                                content: accumulated_text.clone(),
-                                            });
-                                        }
-
-                                        // Handle tool_calls
-                                        if let Some(tcs) = delta.get("tool_calls").and_then(|v| v.as_array()) {
-                                            for tc in tcs {
-                                                let index = tc.get("index").and_then(|v| v.as_i64()).unwrap_or(0);
-
-                                                let entry = current_tool_calls.entry(index).or_insert_with(|| {
-                                                    (String::new(), String::new(), String::new())
-                                                });
-
-                                                if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
-                                                    entry.0 = id.to_string();
-                                                }
-                                                if let Some(func) = tc.get("function") {
-                                                    if let Some(name) = func.get("name").and_then(|v| v.as_str()) {
-                                                        entry.1 = name.to_string();
-                                                    }
-                                                    if let Some(args) = func.get("arguments").and_then(|v| v.as_str()) {
-                                                        entry.2.push_str(args);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-
-                                    // Check if finished
-                                    if choice.get("finish_reason").and_then(|v| v.as_str()).is_some() {
-                                        // Convert collected tool_calls to ToolUse
-                                        for (id, name, args) in current_tool_calls.values() {
-                                            if !id.is_empty() && !name.is_empty() {
-                                                let input: serde_json::Value = serde_json::from_str(args)
-                                                    .unwrap_or(serde_json::json!({}));
-
-                                                tool_uses.push(ToolUse {
-                                                    id: id.clone(),
-                                                    name: name.clone(),
-                                                    input: input.clone(),
-                                                    thought_signature: None, // OpenAI doesn't use thought signatures
-                                                });
-
-                                                // Emit tool start
-                                                let _ = window.emit("chat-event", ChatEvent::ToolStart {
-                                                    tool: name.clone(),
-                                                    input,
-                                                });
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        } else {
-            // Anthropic streaming format
-            let mut current_tool_input = String::new();
-            let mut current_tool_id = String::new();
-            let mut current_tool_name = String::new();
-
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk.map_err(|e| CommandError { message: format!("Stream error: {}", e) })?;
-                buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                while let Some(pos) = buffer.find('\n') {
-                    let line = buffer[..pos].to_string();
-                    buffer = buffer[pos + 1..].to_string();
-
-                    if let Some(data) = line.strip_prefix("data: ") {
-                        if data == "[DONE]" {
-                            continue;
-                        }
-
-                        if let Ok(event) = serde_json::from_str::<serde_json::Value>(data) {
-                            let event_type = event.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                            match event_type {
-                                "content_block_start" => {
-                                    if let Some(block) = event.get("content_block") {
-                                        if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
-                                            current_tool_id = block
-                                                .get("id")
-                                                .and_then(|v| v.as_str())
-                                                .unwrap_or("")
-                                                .to_string();
-                                            current_tool_name = block
-                                                .get("name")
-                                                .and_then(|v| v.as_str())
-                                                .unwrap_or("")
-                                                .to_string();
-                                            current_tool_input.clear();
-                                        }
-                                    }
-                                }
-                                "content_block_delta" => {
-                                    if let Some(delta) = event.get("delta") {
-                                        let delta_type = delta.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                                        if delta_type == "text_delta" {
-                                            if let Some(text) = delta.get("text").and_then(|v| v.as_str()) {
-                                                accumulated_text.push_str(text);
-                                                let _ = window.emit("chat-event", ChatEvent::Text {
-                                                    content: accumulated_text.clone(),
-                                                });
-                                            }
-                                        } else if delta_type == "input_json_delta" {
-                                            if let Some(partial) = delta.get("partial_json").and_then(|v| v.as_str()) {
-                                                current_tool_input.push_str(partial);
-                                            }
-                                        }
-                                    }
-                                }
-                                "content_block_stop" => {
-                                    if !current_tool_id.is_empty() {
-                                        let input: serde_json::Value = serde_json::from_str(&current_tool_input)
-                                            .unwrap_or(serde_json::json!({}));
-
-                                        tool_uses.push(ToolUse {
-                                            id: current_tool_id.clone(),
-                                            name: current_tool_name.clone(),
-                                            input: input.clone(),
-                                            thought_signature: None, // Anthropic doesn't use thought signatures
-                                        });
-
-                                        // Emit tool start
-                                        let _ = window.emit("chat-event", ChatEvent::ToolStart {
-                                            tool: current_tool_name.clone(),
-                                            input,
-                                        });
-
-                                        current_tool_id.clear();
-                                        current_tool_name.clear();
-                                        current_tool_input.clear();
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+    // Forward agent events the chat view shows, keeping the validated answer of a structured reply
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<AgentEvent>(100);
+    let forward = async {
+        let mut structured = None;
+        while let Some(event) = rx.recv().await {
+            let event = match event {
+                AgentEvent::Text { content } => ChatEvent::Text { content },
+                AgentEvent::ToolStart { tool, input } => ChatEvent::ToolStart { tool, input },
+                AgentEvent::ToolEnd { tool, result, success } => ChatEvent::ToolEnd { tool, result, success },
+                AgentEvent::Warning { message } | AgentEvent::Stopped { message, .. } => ChatEvent::Warning { message },
+                AgentEvent::StructuredOutput { output } => {
+                    structured = Some(output);
+                    continue;
                 }
-            }
-        }
-
-        // Update final text
-        if !accumulated_text.is_empty() {
-            final_text = accumulated_text.clone();
-        }
-
-        // Add assistant message to history
-        let assistant_content = if tool_uses.is_empty() {
-            AgentContent::Text(accumulated_text)
-        } else {
-            let mut blocks = Vec::new();
-            if !accumulated_text.is_empty() {
-                blocks.push(ContentBlock::Text { text: accumulated_text });
-            }
-            for tu in &tool_uses {
-                blocks.push(ContentBlock::ToolUse {
-                    id: tu.id.clone(),
-                    name: tu.name.clone(),
-                    input: tu.input.clone(),
-                    thought_signature: tu.thought_signature.clone(),
-                });
-            }
-            AgentContent::Blocks(blocks)
-        };
-
-        agent_messages.push(AgentMessage {
-            role: "assistant".to_string(),
-            content: assistant_content,
-        });
-
-        // If no tool uses, we're done
-        if tool_uses.is_empty() {
-            break;
-        }
-
-        // Execute tools
-        let mut tool_results = Vec::new();
-
-        for tool_use in &tool_uses {
-            let result = tool_executor.execute(tool_use).await;
-
-            // Emit tool end
-            let _ = window.emit("chat-event", ChatEvent::ToolEnd {
-                tool: tool_use.name.clone(),
-                result: result.content.clone(),
-                success: result.is_error.is_none(),
-            });
-
-            tool_results.push(result);
+                _ => continue,
+            };
+            let _ = window.emit("chat-event", event);
         }
+        structured
+    };
+    let (result, structured) = tokio::join!(agent.run_with_history(agent_messages, tx), forward);
+    let agent_messages = result.map_err(|message| CommandError { message })?;
 
-        // Add tool results as user message
-        agent_messages.push(AgentMessage {
-            role: "user".to_string(),
-            content: AgentContent::ToolResults(tool_results),
-        });
-    }
-
-    // Emit done
-    let _ = window.emit("chat-event", ChatEvent::Done { final_text: final_text.clone() });
-
-    // Save final assistant response to database
-    let assistant_msg_id = uuid::Uuid::new_v4().to_string();
-    state
-        .db
-        .add_message(&assistant_msg_id, &request.conversation_id, "assistant", &final_text)?;
-
-    // Update conversation title if this is the first exchange
-    if db_messages.len() == 1 {
-        let title = if request.content.len() > 30 {
-            format!("{}...", &request.content[..30])
-        } else {
-            request.content.clone()
-        };
-        state.db.update_conversation_title(&request.conversation_id, &title)?;
-    }
+    let new_messages = &agent_messages[db_messages.len()..];
+    let final_text = match structured {
+        Some(output) => output.to_string(),
+        None => sub_agent::final_summary(new_messages).unwrap_or_default(),
+    };
+    Ok((final_text, transcript::tool_calls(new_messages)))
 
-    Ok(final_text)
 }
 
 // Task commands
 #[command]
-pub fn list_tasks(state: State<'_, Arc<AppState>>) -> Result<Vec<Task>, CommandError> {
-    state.db.list_tasks().map_err(Into::into)
+pub fn list_tasks(state: State<'_, Arc<AppState>>, query: Option<ListQuery>) -> Result<Vec<Task>, CommandError> {
+    state.db.list_tasks(&query.unwrap_or_default()).map_err(Into::into)
 }
 
 #[command]
@@ -1120,11 +1008,57 @@ pub fn create_task(
     state.db.create_task(&id, &title, &description, project_path.as_deref()).map_err(Into::into)
 }
 
+#[command]
+pub fn update_task_title(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    title: String,
+) -> Result<(), CommandError> {
+    state.db.rename_task(&id, &title).map_err(Into::into)
+}
+
+#[command]
+pub fn set_task_pinned(state: State<'_, Arc<AppState>>, id: String, pinned: bool) -> Result<(), CommandError> {
+    state.db.set_task_pinned(&id, pinned).map_err(Into::into)
+}
+
+#[command]
+pub fn set_task_archived(state: State<'_, Arc<AppState>>, id: String, archived: bool) -> Result<(), CommandError> {
+    state.db.set_task_archived(&id, archived).map_err(Into::into)
+}
+
+#[command]
+pub fn set_task_tags(state: State<'_, Arc<AppState>>, id: String, tag_ids: Vec<String>) -> Result<(), CommandError> {
+    state.db.set_task_tags(&id, &tag_ids).map_err(Into::into)
+}
+
 #[command]
 pub fn delete_task(state: State<'_, Arc<AppState>>, id: String) -> Result<(), CommandError> {
     state.db.delete_task(&id).map_err(Into::into)
 }
 
+// Trash commands
+#[command]
+pub fn list_trash(state: State<'_, Arc<AppState>>) -> Result<Trash, CommandError> {
+    state.db.list_trash().map_err(Into::into)
+}
+
+#[command]
+pub fn restore_conversation(state: State<'_, Arc<AppState>>, id: String) -> Result<(), CommandError> {
+    state.db.restore_conversation(&id).map_err(Into::into)
+}
+
+#[command]
+pub fn restore_task(state: State<'_, Arc<AppState>>, id: String) -> Result<(), CommandError> {
+    state.db.restore_task(&id).map_err(Into::into)
+}
+
+/// Permanently delete everything in the trash; returns how many items were removed
+#[command]
+pub fn empty_trash(state: State<'_, Arc<AppState>>) -> Result<usize, CommandError> {
+    state.db.purge_trash(None).map_err(Into::into)
+}
+
 // Run agent with task tracking
 #[derive(Debug, Deserialize)]
 pub struct TaskAgentRequest {
@@ -1132,6 +1066,11 @@ pub struct TaskAgentRequest {
     pub message: String,
     pub project_path: Option<String>,
     pub max_turns: Option<u32>,
+    /// Extended thinking budget in tokens
+    pub thinking_budget: Option<u32>,
+    /// JSON Schema the final answer must match
+    #[serde(default)]
+    pub response_schema: Option<serde_json::Value>,
 }
 
 #[command]
@@ -1139,6 +1078,14 @@ pub async fn run_task_agent(
     window: Window,
     state: State<'_, Arc<AppState>>,
     request: TaskAgentRequest,
+) -> Result<String, CommandError> {
+    task_agent(window, &state, request).await
+}
+
+async fn task_agent<R: Runtime>(
+    window: Window<R>,
+    state: &AppState,
+    request: TaskAgentRequest,
 ) -> Result<String, CommandError> {
     let settings = state.db.get_settings()?;
 
@@ -1149,6 +1096,13 @@ pub async fn run_task_agent(
         });
     }
 
+    if let Some(schema) = &request.response_schema {
+        structured_output::check_schema(schema).map_err(|message| CommandError { message })?;
+    }
+
+    // Fail before the task is marked running if no HTTP client can be built
+    settings.network.validate()?;
+
     // Load existing conversation history
     let existing_messages = state.db.get_task_messages(&request.task_id)?;
 
@@ -1184,22 +1138,22 @@ pub async fn run_task_agent(
     if let Some(turns) = request.max_turns {
         config.max_turns = turns;
     }
+    config.thinking_budget = request.thinking_budget;
     config.project_path = request.project_path;
-
-    // Get provider info
-    let provider_id = settings.get_provider();
+    config.response_schema = request.response_schema;
+    let project_config = config.load_project_settings();
 
     // Create agent loop with provider
-    let agent = AgentLoop::new_with_provider(
-        settings.api_key,
-        settings.base_url,
+    let route = primary_route(&settings, project_config.model)?;
+    let agent = AgentLoop::from_provider(
+        route.provider,
         config,
-        settings.model,
+        route.model,
         settings.max_tokens,
         Some(settings.temperature),
         state.mcp_manager.clone(),
-        Some(&provider_id),
     );
+    let agent = with_model_routing(agent, &settings)?;
 
     // Build conversation history from existing messages
     let mut agent_messages: Vec<AgentMessage> = existing_messages
@@ -1232,6 +1186,8 @@ pub async fn run_task_agent(
     // Spawn event emitter with task tracking
     let window_clone = window.clone();
     let emit_task = tokio::spawn(async move {
+        // Reason and message of a Stopped event, if the loop ended the run early
+        let mut stopped = None;
         while let Some(event) = rx.recv().await {
             // Track plan and step updates in database
             match &event {
@@ -1241,11 +1197,17 @@ pub async fn run_task_agent(
                         *text = content.clone();
                     }
                 }
+                AgentEvent::StructuredOutput { output } => {
+                    // The validated JSON is saved as the task's answer
+                    if let Ok(mut text) = accumulated_text_clone.lock() {
+                        *text = output.to_string();
+                    }
+                }
                 AgentEvent::Plan { steps } => {
                     let plan_steps: Vec<PlanStep> = steps.iter().map(|s| PlanStep {
                         step: s.step,
                         description: s.description.clone(),
-                        status: "pending".to_string(),
+                        status: s.status.clone(),
                     }).collect();
                     let _ = db.update_task_plan(&task_id, &plan_steps);
                 }
@@ -1261,30 +1223,66 @@ pub async fn run_task_agent(
                 AgentEvent::Error { .. } => {
                     let _ = db.update_task_status(&task_id, "failed");
                 }
+                AgentEvent::Stopped { reason, message } => {
+                    // Turn-limited tasks can be continued with a follow-up message
+                    let status = match reason {
+                        StopReason::TurnLimit => "paused",
+                        StopReason::LoopDetected => "stopped",
+                    };
+                    let _ = db.update_task_status(&task_id, status);
+                    stopped = Some((*reason, message.clone()));
+                }
                 _ => {}
             }
 
             // Emit to frontend
             let _ = window_clone.emit("agent-event", &event);
         }
+        stopped
     });
 
     // Run agent with conversation history
+    let history_len = agent_messages.len();
     let result = agent.run_with_history(agent_messages, tx).await;
 
     // Wait for emitter to finish
-    let _ = emit_task.await;
+    let stopped = emit_task.await.ok().flatten();
 
     // Save assistant message with accumulated text
     let final_text = accumulated_text.lock().map(|t| t.clone()).unwrap_or_default();
     if !final_text.is_empty() {
         let assistant_msg_id = uuid::Uuid::new_v4().to_string();
         let _ = db_for_msg.add_task_message(&assistant_msg_id, &task_id_for_msg, "assistant", &final_text);
+        if let Ok(messages) = &result {
+            let tool_calls = transcript::tool_calls(messages.get(history_len..).unwrap_or_default());
+            if !tool_calls.is_empty() {
+                let _ = db_for_msg.set_task_message_tool_calls(&assistant_msg_id, &tool_calls);
+            }
+        }
+
+        // Title the task after its first exchange
+        if existing_messages.is_empty() {
+            let _ = title_first_exchange(
+                &window,
+                &db_for_msg,
+                &settings,
+                TitleKind::Task,
+                &task_id_for_msg,
+                &request.message,
+                &final_text,
+            );
+        }
     }
 
     // Always ensure task status is updated at the end
     match result {
         Ok(_messages) => {
+            // Stopped runs keep their paused/stopped status
+            match stopped {
+                Some((StopReason::TurnLimit, message)) => return Ok(format!("Task paused: {}", message)),
+                Some((StopReason::LoopDetected, message)) => return Err(CommandError { message }),
+                None => {}
+            }
             // Explicitly update to completed (in case event was missed)
             let _ = state.db.update_task_status(&request.task_id, "completed");
             Ok("Task completed successfully".to_string())
@@ -1305,6 +1303,92 @@ pub fn get_task_messages(
     state.db.get_task_messages(&task_id).map_err(Into::into)
 }
 
+// Tag commands
+#[command]
+pub fn list_tags(state: State<'_, Arc<AppState>>) -> Result<Vec<Tag>, CommandError> {
+    state.db.list_tags().map_err(Into::into)
+}
+
+#[command]
+pub fn create_tag(state: State<'_, Arc<AppState>>, name: String, color: Option<String>) -> Result<Tag, CommandError> {
+    let id = uuid::Uuid::new_v4().to_string();
+    state.db.create_tag(&id, name.trim(), color.as_deref()).map_err(Into::into)
+}
+
+#[command]
+pub fn update_tag(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    name: String,
+    color: Option<String>,
+) -> Result<(), CommandError> {
+    state.db.update_tag(&id, name.trim(), color.as_deref()).map_err(Into::into)
+}
+
+#[command]
+pub fn delete_tag(state: State<'_, Arc<AppState>>, id: String) -> Result<(), CommandError> {
+    state.db.delete_tag(&id).map_err(Into::into)
+}
+
+// Search command
+#[command]
+pub fn search_history(
+    state: State<'_, Arc<AppState>>,
+    query: String,
+    filter: Option<SearchFilter>,
+) -> Result<Vec<SearchHit>, CommandError> {
+    state
+        .db
+        .search_history(&query, &filter.unwrap_or_default())
+        .map_err(Into::into)
+}
+
+// Export and import commands
+
+/// Write an export to `path` when given; the rendered text is returned either way
+fn write_export(export: Option<Export>, what: &str, format: ExportFormat, path: Option<String>) -> Result<String, CommandError> {
+    let export = export.ok_or_else(|| CommandError {
+        message: format!("{} not found", what),
+    })?;
+    let content = export.render(format);
+    if let Some(path) = path {
+        std::fs::write(&path, &content).map_err(|e| CommandError {
+            message: format!("Failed to write {}: {}", path, e),
+        })?;
+    }
+    Ok(content)
+}
+
+#[command]
+pub fn export_conversation(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    format: ExportFormat,
+    path: Option<String>,
+) -> Result<String, CommandError> {
+    write_export(state.db.export_conversation(&id)?, "Conversation", format, path)
+}
+
+#[command]
+pub fn export_task(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    format: ExportFormat,
+    path: Option<String>,
+) -> Result<String, CommandError> {
+    write_export(state.db.export_task(&id)?, "Task", format, path)
+}
+
+/// Restore a JSON export from `path` into a new conversation or task
+#[command]
+pub fn import_history(state: State<'_, Arc<AppState>>, path: String) -> Result<Imported, CommandError> {
+    let json = std::fs::read_to_string(&path).map_err(|e| CommandError {
+        message: format!("Failed to read {}: {}", path, e),
+    })?;
+    let export = Export::from_json(&json).map_err(|message| CommandError { message })?;
+    state.db.import(&export).map_err(Into::into)
+}
+
 // Skills commands
 #[command]
 pub fn get_skills_list() -> Vec<SkillMetadata> {
@@ -1314,9 +1398,10 @@ pub fn get_skills_list() -> Vec<SkillMetadata> {
 // MCP commands
 #[command]
 pub fn list_mcp_servers(state: State<'_, Arc<AppState>>) -> Result<Vec<MCPServerConfig>, CommandError> {
-    state.db.get_mcp_servers().map_err(|e| CommandError {
+    let servers = state.db.get_mcp_servers().map_err(|e| CommandError {
         message: format!("Failed to get MCP servers: {}", e)
-    })
+    })?;
+    Ok(servers.into_iter().map(MCPServerConfig::masked).collect())
 }
 
 #[command]
@@ -1394,252 +1479,187 @@ pub async fn execute_mcp_tool(
     Ok(state.mcp_manager.execute_tool(&call).await)
 }
 
-/// Convert Claude API request format to OpenAI format
-fn convert_to_openai_format(
-    request: &crate::agent::message_builder::ClaudeApiRequest,
-    model: &str,
-) -> serde_json::Value {
-    use crate::agent::message_builder::ApiContent;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_client::mock::{MockResponse, MockServer, MockTurn};
+    use crate::llm_client::ApiFormat;
 
-    // Build messages, including system prompt
-    let mut messages: Vec<serde_json::Value> = Vec::new();
-
-    // Add system message
-    if !request.system.is_empty() {
-        messages.push(serde_json::json!({
-            "role": "system",
-            "content": request.system
-        }));
+    fn test_state(server: &MockServer) -> AppState {
+        let state = AppState {
+            db: Arc::new(Database::in_memory()),
+            mcp_manager: Arc::new(MCPManager::new()),
+        };
+        let settings = Settings {
+            api_key: "test-key".to_string(),
+            base_url: server.base_url().to_string(),
+            provider: "anthropic".to_string(),
+            model: "claude-sonnet-4-20250514".to_string(),
+            ..Settings::default()
+        };
+        state.db.save_settings(&settings).unwrap();
+        state
     }
 
-    // Convert conversation messages
-    for msg in &request.messages {
-        let role = &msg.role;
-
-        match &msg.content {
-            ApiContent::Text(text) => {
-                messages.push(serde_json::json!({
-                    "role": role,
-                    "content": text
-                }));
-            }
-            ApiContent::Blocks(blocks) => {
-                // Handle content blocks (text, tool_use, tool_result)
-                let mut text_parts: Vec<String> = Vec::new();
-                let mut tool_calls: Vec<serde_json::Value> = Vec::new();
-
-                for block in blocks {
-                    let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                    match block_type {
-                        "text" => {
-                            if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
-                                text_parts.push(text.to_string());
-                            }
-                        }
-                        "tool_use" => {
-                            tool_calls.push(serde_json::json!({
-                                "id": block.get("id"),
-                                "type": "function",
-                                "function": {
-                                    "name": block.get("name"),
-                                    "arguments": serde_json::to_string(block.get("input").unwrap_or(&serde_json::json!({}))).unwrap_or_default()
-                                }
-                            }));
-                        }
-                        "tool_result" => {
-                            // OpenAI uses tool role to represent tool results
-                            messages.push(serde_json::json!({
-                                "role": "tool",
-                                "tool_call_id": block.get("tool_use_id"),
-                                "content": block.get("content")
-                            }));
-                        }
-                        _ => {}
-                    }
-                }
-
-                // If there's text content
-                if !text_parts.is_empty() {
-                    let mut msg_obj = serde_json::json!({
-                        "role": role,
-                        "content": text_parts.join("\n")
-                    });
-
-                    // If there are tool_calls
-                    if !tool_calls.is_empty() {
-                        msg_obj["tool_calls"] = serde_json::json!(tool_calls);
-                    }
-
-                    messages.push(msg_obj);
-                } else if !tool_calls.is_empty() {
-                    // Only tool_calls, no text
-                    messages.push(serde_json::json!({
-                        "role": role,
-                        "content": serde_json::Value::Null,
-                        "tool_calls": tool_calls
-                    }));
-                }
-            }
-        }
+    fn temp_project() -> Option<String> {
+        let dir = std::env::temp_dir().join(format!("kuse-task-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("README.md"), "# Demo\n").unwrap();
+        Some(dir.to_string_lossy().to_string())
     }
 
-    // Convert tools definition
-    let tools: Vec<serde_json::Value> = request.tools.iter().map(|tool| {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": tool.name,
-                "description": tool.description,
-                "parameters": tool.input_schema
+    #[tokio::test]
+    async fn test_plain_chat_titles_first_exchange() {
+        let format = ApiFormat::Anthropic;
+        let server = MockServer::scripted(vec![
+            MockTurn::text("Paris.").response(&format),
+            MockTurn::text("Capital of France").response(&format),
+        ])
+        .await;
+
+        let state = test_state(&server);
+        state.db.create_conversation("c1", "New Chat", None).unwrap();
+
+        let app = tauri::test::mock_app();
+        let webview = tauri::WebviewWindowBuilder::new(&app, "main", Default::default()).build().unwrap();
+        let request = EnhancedChatRequest {
+            conversation_id: "c1".to_string(),
+            content: "What is the capital of France?".to_string(),
+            project_path: None,
+            enable_tools: false,
+            response_schema: None,
+        };
+        let reply = chat_with_tools(webview.as_ref().window(), &state, request, ReplyTo::NewPrompt).await.unwrap();
+        assert_eq!(reply, "Paris.");
+
+        // The placeholder is replaced by the generated title in the background
+        let mut title = String::new();
+        for _ in 0..50 {
+            title = state.db.get_conversation("c1").unwrap().unwrap().title;
+            if title == "Capital of France" {
+                break;
             }
-        })
-    }).collect();
-
-    let mut openai_request = serde_json::json!({
-        "model": request.model,
-        "stream": request.stream,
-        "messages": messages
-    });
-
-    // Use correct max tokens parameter based on model
-    let model_lower = model.to_lowercase();
-    let is_legacy = model_lower.contains("gpt-3.5")
-        || (model_lower.contains("gpt-4") && !model_lower.contains("gpt-4o") && !model_lower.contains("gpt-4-turbo"));
-
-    if is_legacy {
-        openai_request["max_tokens"] = serde_json::json!(request.max_tokens);
-    } else {
-        openai_request["max_completion_tokens"] = serde_json::json!(request.max_tokens);
-    }
-
-    // Only add temperature for non-reasoning models (o1, o3, gpt-5 don't support custom temperature)
-    let is_reasoning = model_lower.starts_with("o1") || model_lower.starts_with("o3") || model_lower.starts_with("gpt-5")
-        || model_lower.contains("-o1") || model_lower.contains("-o3")
-        || model_lower.contains("o1-") || model_lower.contains("o3-");
-
-    if !is_reasoning {
-        if let Some(temp) = request.temperature {
-            openai_request["temperature"] = serde_json::json!(temp);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
         }
+        assert_eq!(title, "Capital of France");
+        assert_eq!(server.requests().len(), 2);
     }
 
-    if !tools.is_empty() {
-        openai_request["tools"] = serde_json::json!(tools);
-        openai_request["tool_choice"] = serde_json::json!("auto");
+    #[tokio::test]
+    async fn test_task_turn_limit_pauses_with_summary() {
+        let format = ApiFormat::Anthropic;
+        let server = MockServer::scripted(vec![
+            MockTurn::text("Let me look around.")
+                .with_tool_call("list_dir", serde_json::json!({"path": "."}))
+                .response(&format),
+            MockTurn::text("Listed the project. Still to do: write the report.").response(&format),
+            MockTurn::text("Project report").response(&format),
+        ])
+        .await;
+        let state = test_state(&server);
+        state.db.create_task("t1", "New Task", "Write a report", None).unwrap();
+
+        let app = tauri::test::mock_app();
+        let webview = tauri::WebviewWindowBuilder::new(&app, "main", Default::default()).build().unwrap();
+        let request = TaskAgentRequest {
+            task_id: "t1".to_string(),
+            message: "Write a report".to_string(),
+            project_path: temp_project(),
+            max_turns: Some(1),
+            thinking_budget: None,
+            response_schema: None,
+        };
+        let reply = task_agent(webview.as_ref().window(), &state, request).await.unwrap();
+        assert!(reply.starts_with("Task paused"), "{reply}");
+
+        // The wrap-up is the task's answer and the task can be continued
+        assert_eq!(state.db.get_task("t1").unwrap().unwrap().status, "paused");
+        let messages = state.db.get_task_messages("t1").unwrap();
+        assert_eq!(messages.last().unwrap().content, "Listed the project. Still to do: write the report.");
+        assert_eq!(server.requests()[1]["tool_choice"], serde_json::json!({"type": "none"}));
     }
 
-    openai_request
-}
-
-/// Convert Claude API request format to Google Gemini format
-fn convert_to_google_format(
-    request: &crate::agent::message_builder::ClaudeApiRequest,
-    _model: &str,
-    max_tokens: u32,
-    thought_signatures: &std::collections::HashMap<String, String>,
-) -> serde_json::Value {
-    use crate::agent::message_builder::ApiContent;
-
-    // Build contents array
-    let mut contents: Vec<serde_json::Value> = Vec::new();
-
-    // Convert messages to Google format
-    for msg in &request.messages {
-        // Google uses "user" and "model" instead of "user" and "assistant"
-        let role = if msg.role == "assistant" { "model" } else { &msg.role };
-
-        let parts = match &msg.content {
-            ApiContent::Text(text) => {
-                vec![serde_json::json!({"text": text})]
-            }
-            ApiContent::Blocks(blocks) => {
-                let mut parts_list = Vec::new();
-                for block in blocks {
-                    let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                    match block_type {
-                        "text" => {
-                            if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
-                                parts_list.push(serde_json::json!({"text": text}));
-                            }
-                        }
-                        "tool_use" => {
-                            // Convert to functionCall format with thoughtSignature if present (for Gemini 3)
-                            let tool_id = block.get("id").and_then(|v| v.as_str()).unwrap_or("");
-                            let mut fc_part = serde_json::json!({
-                                "functionCall": {
-                                    "name": block.get("name"),
-                                    "args": block.get("input")
-                                }
-                            });
-                            // Include thoughtSignature if we have it for this tool
-                            if let Some(sig) = thought_signatures.get(tool_id) {
-                                fc_part["thoughtSignature"] = serde_json::json!(sig);
-                            }
-                            parts_list.push(fc_part);
-                        }
-                        "tool_result" => {
-                            // Convert to functionResponse format with thoughtSignature (required for Gemini 3)
-                            let tool_use_id = block.get("tool_use_id").and_then(|v| v.as_str()).unwrap_or("unknown");
-                            let mut fr_part = serde_json::json!({
-                                "functionResponse": {
-                                    "name": tool_use_id,
-                                    "response": {
-                                        "content": block.get("content")
-                                    }
-                                }
-                            });
-                            // Include thoughtSignature from matching tool_use (required for Gemini 3)
-                            if let Some(sig) = thought_signatures.get(tool_use_id) {
-                                fr_part["thoughtSignature"] = serde_json::json!(sig);
-                            }
-                            parts_list.push(fr_part);
-                        }
-                        _ => {}
-                    }
-                }
-                parts_list
-            }
+    #[tokio::test]
+    async fn test_task_loop_is_stopped() {
+        let format = ApiFormat::Anthropic;
+        let list = || {
+            MockTurn::default()
+                .with_tool_call("list_dir", serde_json::json!({"path": "."}))
+                .response(&format)
         };
-
-        if !parts.is_empty() {
-            contents.push(serde_json::json!({
-                "role": role,
-                "parts": parts
-            }));
-        }
+        let server = MockServer::scripted((0..5).map(|_| list()).collect()).await;
+        let state = test_state(&server);
+        state.db.create_task("t1", "New Task", "Explore", None).unwrap();
+
+        let app = tauri::test::mock_app();
+        let webview = tauri::WebviewWindowBuilder::new(&app, "main", Default::default()).build().unwrap();
+        let request = TaskAgentRequest {
+            task_id: "t1".to_string(),
+            message: "Explore".to_string(),
+            project_path: temp_project(),
+            max_turns: None,
+            thinking_budget: None,
+            response_schema: None,
+        };
+        let err = task_agent(webview.as_ref().window(), &state, request).await.unwrap_err();
+        assert!(err.message.contains("called 5 times"), "{}", err.message);
+        assert_eq!(state.db.get_task("t1").unwrap().unwrap().status, "stopped");
     }
 
-    // Convert tools to Google functionDeclarations format
-    let function_declarations: Vec<serde_json::Value> = request.tools.iter().map(|tool| {
-        serde_json::json!({
-            "name": tool.name,
-            "description": tool.description,
-            "parameters": tool.input_schema
-        })
-    }).collect();
-
-    let mut google_request = serde_json::json!({
-        "contents": contents,
-        "generationConfig": {
-            "maxOutputTokens": max_tokens
+    #[tokio::test]
+    async fn test_failed_branch_reply_keeps_active_branch() {
+        let format = ApiFormat::Anthropic;
+        let unauthorized = || {
+            MockResponse::Status(
+                401,
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#.to_string(),
+            )
+        };
+        let server = MockServer::scripted(vec![
+            unauthorized(),
+            unauthorized(),
+            MockTurn::text("Apple").response(&format),
+            MockTurn::text("Pear").response(&format),
+        ])
+        .await;
+        let state = test_state(&server);
+        state.db.create_conversation("c1", "Colours", None).unwrap();
+        for (id, role, content) in [("u1", "user", "Hi"), ("a1", "assistant", "Hello"), ("u2", "user", "Name a colour"), ("a2", "assistant", "Red")] {
+            state.db.add_message(id, "c1", role, content).unwrap();
         }
-    });
-
-    // Add system instruction if present
-    if !request.system.is_empty() {
-        google_request["systemInstruction"] = serde_json::json!({
-            "parts": [{"text": request.system}]
-        });
-    }
-
-    // Add tools if present
-    if !function_declarations.is_empty() {
-        google_request["tools"] = serde_json::json!([{
-            "functionDeclarations": function_declarations
-        }]);
+        let path = || state.db.get_messages("c1").unwrap();
+        let ids = |messages: &[Message]| messages.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+
+        let app = tauri::test::mock_app();
+        let webview = tauri::WebviewWindowBuilder::new(&app, "main", Default::default()).build().unwrap();
+        let request = |content: &str| EnhancedChatRequest {
+            conversation_id: "c1".to_string(),
+            content: content.to_string(),
+            project_path: None,
+            enable_tools: false,
+            response_schema: None,
+        };
+        let window = || webview.as_ref().window();
+        let edit = || ReplyTo::EditedPrompt(Some("a1".to_string()));
+
+        // Failed edits and regenerations leave the conversation as it was
+        assert!(chat_with_tools(window(), &state, request("Name a fruit"), edit()).await.is_err());
+        assert!(chat_with_tools(window(), &state, request(""), ReplyTo::Prompt("u2".to_string())).await.is_err());
+        let messages = path();
+        assert_eq!(ids(&messages), ["u1", "a1", "u2", "a2"]);
+        assert!(messages.iter().all(|m| m.branches.is_empty()));
+
+        // Successful ones become the active branch
+        assert_eq!(chat_with_tools(window(), &state, request("Name a fruit"), edit()).await.unwrap(), "Apple");
+        let messages = path();
+        assert_eq!(messages[2].content, "Name a fruit");
+        assert_eq!(messages[2].branches, ["u2", messages[2].id.as_str()]);
+        assert_eq!(messages[3].content, "Apple");
+
+        let prompt = ReplyTo::Prompt(messages[2].id.clone());
+        assert_eq!(chat_with_tools(window(), &state, request(""), prompt).await.unwrap(), "Pear");
+        let messages = path();
+        assert_eq!(messages[3].content, "Pear");
+        assert_eq!(messages[3].branches.len(), 2);
     }
-
-    google_request
 }
@@ -0,0 +1,307 @@
+//! Organising conversations and tasks: tags, pinning, archiving and list queries
+
+use crate::database::{Database, DbError};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Updated,
+    Created,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+/// Filters, order and page of a conversation or task list. Pinned items come
+/// first whatever the order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListQuery {
+    /// List the archived items instead of the others
+    #[serde(default)]
+    pub archived: bool,
+    /// Only pinned, or only unpinned, items
+    #[serde(default)]
+    pub pinned: Option<bool>,
+    /// Only items with this tag id
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub project_path: Option<String>,
+    /// Only tasks with this status; ignored for conversations
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub sort: SortField,
+    /// Defaults to newest first for dates and A to Z for titles
+    #[serde(default)]
+    pub direction: Option<SortDirection>,
+    /// Page size; everything when not set
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub offset: u32,
+}
+
+impl ListQuery {
+    /// `ORDER BY` terms after the pinned ones, for a table aliased `alias`
+    pub(crate) fn order_by(&self, alias: &str) -> String {
+        let (column, default) = match self.sort {
+            SortField::Updated => ("updated_at", SortDirection::Desc),
+            SortField::Created => ("created_at", SortDirection::Desc),
+            SortField::Title => ("title COLLATE NOCASE", SortDirection::Asc),
+        };
+        let direction = match self.direction.unwrap_or(default) {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        };
+        format!("{alias}.{column} {direction}, {alias}.id")
+    }
+
+    /// `LIMIT` value; SQLite reads a negative one as no limit
+    pub(crate) fn sql_limit(&self) -> i64 {
+        self.limit.map(i64::from).unwrap_or(-1)
+    }
+}
+
+/// Label grouping conversations and tasks; an item can have several
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    /// CSS colour shown with the tag
+    #[serde(default)]
+    pub color: Option<String>,
+    pub created_at: i64,
+}
+
+/// Tables holding a kind of history item and its tag links
+#[derive(Debug, Clone, Copy)]
+enum Items {
+    Conversations,
+    Tasks,
+}
+
+impl Items {
+    fn table(self) -> &'static str {
+        match self {
+            Items::Conversations => "conversations",
+            Items::Tasks => "tasks",
+        }
+    }
+
+    /// Link table and its item id column
+    fn tag_links(self) -> (&'static str, &'static str) {
+        match self {
+            Items::Conversations => ("conversation_tags", "conversation_id"),
+            Items::Tasks => ("task_tags", "task_id"),
+        }
+    }
+}
+
+impl Database {
+    pub fn list_tags(&self) -> Result<Vec<Tag>, DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare("SELECT id, name, color, created_at FROM tags ORDER BY name COLLATE NOCASE")?;
+        let tags = stmt.query_map([], |row| {
+            Ok(Tag {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                color: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?;
+        Ok(tags.collect::<Result<_, _>>()?)
+    }
+
+    pub fn create_tag(&self, id: &str, name: &str, color: Option<&str>) -> Result<Tag, DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let now = chrono::Utc::now().timestamp_millis();
+        conn.execute(
+            "INSERT INTO tags (id, name, color, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![id, name, color, now],
+        )?;
+        Ok(Tag {
+            id: id.to_string(),
+            name: name.to_string(),
+            color: color.map(|c| c.to_string()),
+            created_at: now,
+        })
+    }
+
+    pub fn update_tag(&self, id: &str, name: &str, color: Option<&str>) -> Result<(), DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute("UPDATE tags SET name = ?1, color = ?2 WHERE id = ?3", params![name, color, id])?;
+        Ok(())
+    }
+
+    /// Delete a tag, removing it from every conversation and task
+    pub fn delete_tag(&self, id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM conversation_tags WHERE tag_id = ?1", [id])?;
+        tx.execute("DELETE FROM task_tags WHERE tag_id = ?1", [id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?1", [id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace a conversation's tags
+    pub fn set_conversation_tags(&self, id: &str, tag_ids: &[String]) -> Result<(), DbError> {
+        self.set_tags(Items::Conversations, id, tag_ids)
+    }
+
+    /// Replace a task's tags
+    pub fn set_task_tags(&self, id: &str, tag_ids: &[String]) -> Result<(), DbError> {
+        self.set_tags(Items::Tasks, id, tag_ids)
+    }
+
+    pub fn set_conversation_pinned(&self, id: &str, pinned: bool) -> Result<(), DbError> {
+        self.set_flag(Items::Conversations, "pinned", id, pinned)
+    }
+
+    pub fn set_conversation_archived(&self, id: &str, archived: bool) -> Result<(), DbError> {
+        self.set_flag(Items::Conversations, "archived", id, archived)
+    }
+
+    pub fn set_task_pinned(&self, id: &str, pinned: bool) -> Result<(), DbError> {
+        self.set_flag(Items::Tasks, "pinned", id, pinned)
+    }
+
+    pub fn set_task_archived(&self, id: &str, archived: bool) -> Result<(), DbError> {
+        self.set_flag(Items::Tasks, "archived", id, archived)
+    }
+
+    pub fn set_conversation_project(&self, id: &str, project_path: Option<&str>) -> Result<(), DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute(
+            "UPDATE conversations SET project_path = ?1 WHERE id = ?2",
+            params![project_path, id],
+        )?;
+        Ok(())
+    }
+
+    fn set_tags(&self, items: Items, id: &str, tag_ids: &[String]) -> Result<(), DbError> {
+        let (links, item_column) = items.tag_links();
+        let mut conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let tx = conn.transaction()?;
+        tx.execute(&format!("DELETE FROM {links} WHERE {item_column} = ?1"), [id])?;
+        // Unknown tags and items are skipped rather than linked
+        let mut insert = tx.prepare(&format!(
+            "INSERT OR IGNORE INTO {links} ({item_column}, tag_id)
+             SELECT ?1, id FROM tags WHERE id = ?2 AND EXISTS (SELECT 1 FROM {} WHERE id = ?1)",
+            items.table()
+        ))?;
+        for tag_id in tag_ids {
+            insert.execute([id, tag_id])?;
+        }
+        drop(insert);
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Pinning and archiving leave `updated_at` alone, so items keep their place
+    fn set_flag(&self, items: Items, column: &str, id: &str, value: bool) -> Result<(), DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute(
+            &format!("UPDATE {} SET {column} = ?1 WHERE id = ?2", items.table()),
+            params![value, id],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids<T>(items: Vec<T>, id: impl Fn(&T) -> &str) -> Vec<String> {
+        items.iter().map(|item| id(item).to_string()).collect()
+    }
+
+    #[test]
+    fn test_list_conversations() {
+        let db = Database::in_memory();
+        for (id, title) in [("c1", "beta"), ("c2", "Alpha"), ("c3", "gamma"), ("c4", "delta")] {
+            db.create_conversation(id, title, (id == "c4").then_some("/work/app")).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let list = |query: ListQuery| ids(db.list_conversations(&query).unwrap(), |c| &c.id);
+
+        assert_eq!(list(ListQuery::default()), ["c4", "c3", "c2", "c1"]);
+        db.set_conversation_pinned("c2", true).unwrap();
+        db.set_conversation_archived("c3", true).unwrap();
+        assert_eq!(list(ListQuery::default()), ["c2", "c4", "c1"]);
+        assert_eq!(list(ListQuery { archived: true, ..Default::default() }), ["c3"]);
+        assert_eq!(list(ListQuery { pinned: Some(false), ..Default::default() }), ["c4", "c1"]);
+
+        // Sorting, with pinned items still first, and pages
+        let by_title = ListQuery { sort: SortField::Title, ..Default::default() };
+        assert_eq!(list(by_title.clone()), ["c2", "c1", "c4"]);
+        assert_eq!(list(ListQuery { direction: Some(SortDirection::Desc), ..by_title.clone() }), ["c2", "c4", "c1"]);
+        let created = ListQuery { sort: SortField::Created, direction: Some(SortDirection::Asc), ..Default::default() };
+        assert_eq!(list(ListQuery { limit: Some(2), ..created.clone() }), ["c2", "c1"]);
+        assert_eq!(list(ListQuery { limit: Some(2), offset: 2, ..created }), ["c4"]);
+
+        // Projects
+        let project = ListQuery { project_path: Some("/work/app".to_string()), ..Default::default() };
+        assert_eq!(list(project.clone()), ["c4"]);
+        db.set_conversation_project("c1", Some("/work/app")).unwrap();
+        assert_eq!(list(project), ["c4", "c1"]);
+        assert_eq!(db.get_conversation("c1").unwrap().unwrap().project_path.as_deref(), Some("/work/app"));
+    }
+
+    #[test]
+    fn test_tags() {
+        let db = Database::in_memory();
+        db.create_conversation("c1", "Chat", None).unwrap();
+        db.create_task("t1", "Task", "Do it", None).unwrap();
+        db.create_task("t2", "Other task", "Do that", None).unwrap();
+        let work = db.create_tag("tag-work", "Work", Some("#3b82f6")).unwrap();
+        db.create_tag("tag-home", "home", None).unwrap();
+        assert!(db.create_tag("tag-dup", "WORK", None).is_err());
+        assert_eq!(ids(db.list_tags().unwrap(), |t| &t.name), ["home", "Work"]);
+
+        let tags = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        db.set_conversation_tags("c1", &tags(&["tag-work", "missing"])).unwrap();
+        db.set_task_tags("t1", &tags(&["tag-work", "tag-home"])).unwrap();
+        db.set_task_tags("nope", &tags(&["tag-work"])).unwrap();
+        assert_eq!(db.get_conversation("c1").unwrap().unwrap().tags, ["tag-work"]);
+
+        let tagged = |tag: &str| ListQuery { tag: Some(tag.to_string()), ..Default::default() };
+        assert_eq!(ids(db.list_tasks(&tagged("tag-home")).unwrap(), |t| &t.id), ["t1"]);
+        assert_eq!(db.list_conversations(&tagged(&work.id)).unwrap().len(), 1);
+
+        db.set_task_tags("t1", &tags(&["tag-home"])).unwrap();
+        assert!(db.list_tasks(&tagged("tag-work")).unwrap().is_empty());
+
+        db.update_tag("tag-home", "Home", Some("#22c55e")).unwrap();
+        db.delete_tag("tag-work").unwrap();
+        assert_eq!(ids(db.list_tags().unwrap(), |t| &t.name), ["Home"]);
+        assert!(db.get_conversation("c1").unwrap().unwrap().tags.is_empty());
+
+        db.delete_task("t1").unwrap();
+        db.purge_trash(None).unwrap();
+        let links: u32 = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT count(*) FROM task_tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(links, 0);
+
+        let planning = ListQuery { status: Some("planning".to_string()), ..Default::default() };
+        db.set_task_archived("t2", true).unwrap();
+        assert!(db.list_tasks(&planning).unwrap().is_empty());
+        db.set_task_archived("t2", false).unwrap();
+        db.set_task_pinned("t2", true).unwrap();
+        assert!(db.list_tasks(&planning).unwrap()[0].pinned);
+    }
+}
@@ -1,35 +1,50 @@
 mod agent;
-mod claude;
 mod commands;
 mod database;
+mod history;
+mod http;
 mod llm_client;
 mod mcp;
+mod migrations;
+mod search;
+mod secrets;
 mod skills;
+mod titles;
+mod transcript;
+mod trash;
 mod tools;
 
 use commands::AppState;
 use mcp::MCPManager;
 use std::sync::Arc;
 use tauri::Manager;
-use tokio::sync::Mutex;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize database
-    let db = database::Database::new().expect("Failed to initialize database");
-
-    // Initialize MCP tables
-    db.create_mcp_tables().expect("Failed to create MCP tables");
+    // A locked secret store doesn't fail here; the settings offer to unlock or reset it
+    let db = match database::Database::new() {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to initialize database: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = db.run_maintenance() {
+        eprintln!("Database maintenance failed: {}", e);
+    }
 
     // Initialize MCP manager
     let mcp_manager = Arc::new(MCPManager::new());
+    if let Ok(settings) = db.get_settings() {
+        mcp_manager.set_network(settings.network);
+    }
     let db_arc = Arc::new(db);
 
     // Auto-connect enabled MCP servers will be done in the tauri app setup
 
     let app_state = Arc::new(AppState {
         db: db_arc,
-        claude_client: Mutex::new(None),
         mcp_manager,
     });
 
@@ -41,22 +56,51 @@ pub fn run() {
             commands::get_platform,
             commands::get_settings,
             commands::save_settings,
+            commands::get_secret_store_status,
+            commands::unlock_secret_store,
+            commands::reset_secret_store,
             commands::test_connection,
+            commands::pull_ollama_model,
+            commands::get_model_catalog,
+            commands::list_models,
             commands::list_conversations,
             commands::create_conversation,
             commands::update_conversation_title,
+            commands::set_conversation_pinned,
+            commands::set_conversation_archived,
+            commands::set_conversation_project,
+            commands::set_conversation_tags,
             commands::delete_conversation,
             commands::get_messages,
             commands::add_message,
             commands::send_chat_message,
             commands::send_chat_with_tools,
+            commands::edit_and_resend,
+            commands::regenerate_reply,
+            commands::switch_branch,
             commands::run_agent,
             commands::list_tasks,
             commands::get_task,
             commands::create_task,
+            commands::update_task_title,
+            commands::set_task_pinned,
+            commands::set_task_archived,
+            commands::set_task_tags,
             commands::delete_task,
+            commands::list_trash,
+            commands::restore_conversation,
+            commands::restore_task,
+            commands::empty_trash,
             commands::run_task_agent,
             commands::get_task_messages,
+            commands::list_tags,
+            commands::create_tag,
+            commands::update_tag,
+            commands::delete_tag,
+            commands::search_history,
+            commands::export_conversation,
+            commands::export_task,
+            commands::import_history,
             commands::get_skills_list,
             commands::list_mcp_servers,
             commands::save_mcp_server,
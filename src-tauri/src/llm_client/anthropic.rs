@@ -0,0 +1,184 @@
+use super::sse::{read_sse, ResponseAccumulator, SseParser};
+use super::{LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::ClaudeApiRequest;
+use crate::agent::{ContentBlock, TokenUsage};
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic Messages API
+pub struct AnthropicProvider {
+    client: LLMClient,
+}
+
+impl AnthropicProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self { client }
+    }
+}
+
+impl LlmProvider for AnthropicProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            let mut body = request.to_anthropic_body();
+            body["stream"] = serde_json::json!(true);
+
+            let response = self
+                .client
+                .post_json(
+                    &self.client.v1_url("messages"),
+                    &body,
+                    &[("anthropic-version", ANTHROPIC_VERSION)],
+                )
+                .await?;
+
+            read_sse(response, AnthropicStream::default(), &tx).await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(async move {
+            let data = self
+                .client
+                .get_json(&self.client.v1_url("models"), &[("anthropic-version", ANTHROPIC_VERSION)])
+                .await?;
+
+            Ok(data["data"]
+                .as_array()
+                .map(|models| {
+                    models
+                        .iter()
+                        .filter_map(|m| m["id"].as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+}
+
+/// Parser for Anthropic `message_*` and `content_block_*` stream events
+#[derive(Debug, Default)]
+pub struct AnthropicStream {
+    acc: ResponseAccumulator,
+    usage: TokenUsage,
+    /// Id, name and partial JSON input of the tool call being streamed
+    current_tool: Option<(String, String, String)>,
+    /// Text and signature of the thinking block being streamed
+    current_thinking: Option<(String, String)>,
+    stopped: bool,
+    error: Option<String>,
+}
+
+impl SseParser for AnthropicStream {
+    fn on_event(&mut self, event: &serde_json::Value, out: &mut Vec<StreamEvent>) {
+        let event_type = event.get("type").and_then(|v| v.as_str()).unwrap_or("");
+
+        match event_type {
+            "message_start" => {
+                if let Some(u) = event.get("message").and_then(|m| m.get("usage")) {
+                    self.usage.merge(u);
+                }
+            }
+            "message_delta" => {
+                if let Some(u) = event.get("usage") {
+                    self.usage.merge(u);
+                }
+            }
+            "content_block_start" => {
+                if let Some(block) = event.get("content_block") {
+                    match block.get("type").and_then(|v| v.as_str()) {
+                        Some("thinking") => {
+                            self.current_thinking = Some((String::new(), String::new()));
+                        }
+                        Some("redacted_thinking") => {
+                            if let Ok(block) = serde_json::from_value::<ContentBlock>(block.clone()) {
+                                self.acc.thinking.push(block);
+                            }
+                        }
+                        Some("tool_use") => {
+                            let field = |key: &str| block.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+                            self.current_tool = Some((field("id"), field("name"), String::new()));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "content_block_delta" => {
+                let Some(delta) = event.get("delta") else {
+                    return;
+                };
+                let field = |key: &str| delta.get(key).and_then(|v| v.as_str());
+
+                match field("type").unwrap_or("") {
+                    "text_delta" => {
+                        if let Some(text) = field("text") {
+                            self.acc.push_text(text, out);
+                        }
+                    }
+                    "input_json_delta" => {
+                        if let (Some(partial), Some((_, _, input))) = (field("partial_json"), self.current_tool.as_mut()) {
+                            input.push_str(partial);
+                        }
+                    }
+                    "thinking_delta" => {
+                        if let (Some(text), Some((thinking, _))) = (field("thinking"), self.current_thinking.as_mut()) {
+                            thinking.push_str(text);
+                            self.acc.push_thinking(text, out);
+                        }
+                    }
+                    "signature_delta" => {
+                        if let (Some(sig), Some((_, signature))) = (field("signature"), self.current_thinking.as_mut()) {
+                            signature.push_str(sig);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            "content_block_stop" => {
+                if let Some((id, name, input)) = self.current_tool.take() {
+                    self.acc.push_tool_use(id, name, &input, None);
+                } else if let Some((thinking, signature)) = self.current_thinking.take() {
+                    self.acc.thinking.push(ContentBlock::Thinking {
+                        thinking,
+                        signature: Some(signature),
+                    });
+                }
+            }
+            "message_stop" => {
+                self.stopped = true;
+            }
+            "error" => {
+                self.error = Some(
+                    event
+                        .get("error")
+                        .and_then(|e| e.get("message"))
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("Unknown stream error")
+                        .to_string(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Result<LlmResponse, LLMError> {
+        if let Some(error) = self.error {
+            return Err(LLMError::Api(error));
+        }
+        if !self.stopped {
+            return Err(LLMError::Parse("No response received".to_string()));
+        }
+
+        self.acc.usage = Some(self.usage);
+        Ok(self.acc.into_response())
+    }
+}
@@ -0,0 +1,404 @@
+use super::anthropic::AnthropicStream;
+use super::sigv4::{self, AwsCredentials};
+use super::sse::SseParser;
+use super::{LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::ClaudeApiRequest;
+use base64::Engine;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use reqwest::Url;
+use tokio::sync::mpsc;
+
+const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// How Bedrock requests are authorised
+enum BedrockAuth {
+    SigV4(AwsCredentials),
+    /// Bedrock API key
+    Bearer(String),
+}
+
+/// Amazon Bedrock, running Anthropic models through `InvokeModelWithResponseStream`
+pub struct BedrockProvider {
+    client: LLMClient,
+}
+
+impl BedrockProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self { client }
+    }
+
+    /// Credentials from the API key field, falling back to the AWS environment variables
+    fn auth(&self) -> Result<BedrockAuth, LLMError> {
+        let key = self.client.api_key.trim();
+        if !key.is_empty() {
+            return Ok(match AwsCredentials::parse(key) {
+                Some(credentials) => BedrockAuth::SigV4(credentials),
+                None => BedrockAuth::Bearer(key.to_string()),
+            });
+        }
+
+        if let Some(credentials) = AwsCredentials::from_env() {
+            return Ok(BedrockAuth::SigV4(credentials));
+        }
+        std::env::var("AWS_BEARER_TOKEN_BEDROCK")
+            .ok()
+            .filter(|t| !t.is_empty())
+            .map(BedrockAuth::Bearer)
+            .ok_or_else(|| {
+                LLMError::Api(
+                    "No AWS credentials: enter ACCESS_KEY_ID:SECRET_ACCESS_KEY or a Bedrock API key".to_string(),
+                )
+            })
+    }
+
+    /// Region from a `bedrock-runtime.<region>.amazonaws.com` URL, else from the environment
+    fn region(&self) -> String {
+        region_from_url(&self.client.base_url)
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .filter(|r| !r.is_empty())
+            .unwrap_or_else(|| DEFAULT_REGION.to_string())
+    }
+
+    /// Send a signed request and fail on non-success status
+    async fn send(&self, method: reqwest::Method, url: &str, body: Vec<u8>) -> Result<reqwest::Response, LLMError> {
+        let parsed = Url::parse(url).map_err(|e| LLMError::Api(format!("Invalid Bedrock URL {}: {}", url, e)))?;
+        let mut request = self
+            .client
+            .client
+            .request(method.clone(), parsed.clone())
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json");
+
+        match self.auth()? {
+            BedrockAuth::SigV4(credentials) => {
+                let headers = sigv4::sign(
+                    &credentials,
+                    &self.region(),
+                    "bedrock",
+                    method.as_str(),
+                    &parsed,
+                    &[("content-type", "application/json")],
+                    &body,
+                    chrono::Utc::now(),
+                );
+                for (key, value) in headers {
+                    request = request.header(key, value);
+                }
+            }
+            BedrockAuth::Bearer(token) => {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+        }
+
+        LLMClient::check_status(request.body(body).send().await?).await
+    }
+}
+
+impl LlmProvider for BedrockProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            // The model goes in the path and the API version in the body
+            let mut body = request.to_anthropic_body();
+            if let Some(body) = body.as_object_mut() {
+                body.remove("model");
+                body.remove("stream");
+                body.insert("anthropic_version".to_string(), BEDROCK_ANTHROPIC_VERSION.into());
+            }
+
+            let url = format!(
+                "{}/model/{}/invoke-with-response-stream",
+                self.client.base_url.trim_end_matches('/'),
+                sigv4::uri_encode(&request.model)
+            );
+            let body = serde_json::to_vec(&body).map_err(|e| LLMError::Parse(e.to_string()))?;
+            let response = self.send(reqwest::Method::POST, &url, body).await?;
+            read_event_stream(response, AnthropicStream::default(), &tx).await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(async move {
+            // Models are listed by the control plane, `bedrock.<region>` rather than `bedrock-runtime.<region>`
+            let base = self.client.base_url.trim_end_matches('/').replacen("bedrock-runtime.", "bedrock.", 1);
+            let url = format!("{}/foundation-models?byOutputModality=TEXT", base);
+            let data: serde_json::Value = self.send(reqwest::Method::GET, &url, Vec::new()).await?.json().await?;
+
+            Ok(data["modelSummaries"]
+                .as_array()
+                .map(|models| {
+                    models
+                        .iter()
+                        .filter_map(|m| m["modelId"].as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+}
+
+fn region_from_url(url: &str) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_string();
+    let rest = host.strip_prefix("bedrock-runtime.").or_else(|| host.strip_prefix("bedrock."))?;
+    rest.split('.').next().filter(|r| !r.is_empty()).map(|r| r.to_string())
+}
+
+/// One message of an `application/vnd.amazon.eventstream` body
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventMessage {
+    /// String headers; other header types are skipped
+    pub headers: Vec<(String, String)>,
+    pub payload: Vec<u8>,
+}
+
+impl EventMessage {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Splits an AWS event stream into messages, keeping partial messages between chunks.
+///
+/// Each message is a prelude (total length, headers length, CRC), headers, a payload
+/// and a CRC of everything before it; all integers are big-endian.
+#[derive(Debug, Default)]
+pub struct EventStreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamDecoder {
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<EventMessage>, LLMError> {
+        self.buffer.extend_from_slice(chunk);
+        let mut messages = Vec::new();
+
+        while self.buffer.len() >= 12 {
+            let total = read_u32(&self.buffer, 0) as usize;
+            let headers_len = read_u32(&self.buffer, 4) as usize;
+            if read_u32(&self.buffer, 8) != crc32fast::hash(&self.buffer[..8]) {
+                return Err(LLMError::Parse("Event stream prelude checksum mismatch".to_string()));
+            }
+            if total < 16 + headers_len {
+                return Err(LLMError::Parse("Invalid event stream message length".to_string()));
+            }
+            if self.buffer.len() < total {
+                break;
+            }
+            if read_u32(&self.buffer, total - 4) != crc32fast::hash(&self.buffer[..total - 4]) {
+                return Err(LLMError::Parse("Event stream message checksum mismatch".to_string()));
+            }
+
+            let message: Vec<u8> = self.buffer.drain(..total).collect();
+            messages.push(EventMessage {
+                headers: parse_headers(&message[12..12 + headers_len])?,
+                payload: message[12 + headers_len..total - 4].to_vec(),
+            });
+        }
+
+        Ok(messages)
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn parse_headers(mut bytes: &[u8]) -> Result<Vec<(String, String)>, LLMError> {
+    let truncated = || LLMError::Parse("Truncated event stream header".to_string());
+    let mut headers = Vec::new();
+
+    while !bytes.is_empty() {
+        let name_len = bytes[0] as usize;
+        let name = bytes.get(1..1 + name_len).ok_or_else(truncated)?;
+        let name = String::from_utf8_lossy(name).to_string();
+        let value_type = *bytes.get(1 + name_len).ok_or_else(truncated)?;
+        let rest = &bytes[2 + name_len..];
+
+        // Value sizes by type: bools carry none, 6 and 7 are length-prefixed bytes and strings
+        let (value_len, skip) = match value_type {
+            0 | 1 => (0, 0),
+            2 => (1, 0),
+            3 => (2, 0),
+            4 => (4, 0),
+            5 | 8 => (8, 0),
+            9 => (16, 0),
+            6 | 7 => {
+                let len = rest.get(..2).ok_or_else(truncated)?;
+                (u16::from_be_bytes([len[0], len[1]]) as usize, 2)
+            }
+            other => return Err(LLMError::Parse(format!("Unknown event stream header type {}", other))),
+        };
+        let value = rest.get(skip..skip + value_len).ok_or_else(truncated)?;
+        if value_type == 7 {
+            headers.push((name, String::from_utf8_lossy(value).to_string()));
+        }
+        bytes = &rest[skip + value_len..];
+    }
+
+    Ok(headers)
+}
+
+/// Error for an `exception` or `error` message, keeping throttling and server
+/// errors retryable
+fn event_error(message: &EventMessage) -> LLMError {
+    if message.header(":message-type") == Some("error") {
+        return LLMError::Api(format!(
+            "{}: {}",
+            message.header(":error-code").unwrap_or("error"),
+            message.header(":error-message").unwrap_or("")
+        ));
+    }
+
+    let kind = message.header(":exception-type").unwrap_or("exception");
+    let text = serde_json::from_slice::<serde_json::Value>(&message.payload)
+        .ok()
+        .and_then(|p| p["message"].as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| String::from_utf8_lossy(&message.payload).to_string());
+    let status = match kind {
+        "throttlingException" => 429,
+        "serviceUnavailableException" | "modelNotReadyException" => 503,
+        "internalServerException" => 500,
+        _ => return LLMError::Api(format!("{}: {}", kind, text)),
+    };
+    LLMError::Status {
+        status,
+        message: format!("{}: {}", kind, text),
+    }
+}
+
+/// Feed a `chunk` event's payload, a base64-encoded Anthropic stream event, to the parser
+fn feed<P: SseParser>(parser: &mut P, message: &EventMessage, out: &mut Vec<StreamEvent>) -> Result<(), LLMError> {
+    match message.header(":message-type") {
+        Some("event") if message.header(":event-type") == Some("chunk") => {
+            let payload: serde_json::Value =
+                serde_json::from_slice(&message.payload).map_err(|e| LLMError::Parse(e.to_string()))?;
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(payload["bytes"].as_str().unwrap_or_default())
+                .map_err(|e| LLMError::Parse(e.to_string()))?;
+            let event = serde_json::from_slice(&bytes).map_err(|e| LLMError::Parse(e.to_string()))?;
+            parser.on_event(&event, out);
+            Ok(())
+        }
+        Some("event") => Ok(()),
+        _ => Err(event_error(message)),
+    }
+}
+
+/// Read an event stream response to the end, forwarding stream events to `tx`
+async fn read_event_stream<P: SseParser>(
+    response: reqwest::Response,
+    mut parser: P,
+    tx: &mpsc::Sender<StreamEvent>,
+) -> Result<LlmResponse, LLMError> {
+    let mut stream = response.bytes_stream();
+    let mut decoder = EventStreamDecoder::default();
+    let mut events = Vec::new();
+
+    while let Some(chunk) = stream.next().await {
+        for message in decoder.push(&chunk?)? {
+            feed(&mut parser, &message, &mut events)?;
+            for event in events.drain(..) {
+                let _ = tx.send(event).await;
+            }
+        }
+    }
+
+    parser.finish()
+}
+
+/// Encode one event stream message with string headers
+#[cfg(test)]
+pub fn encode_message(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut header_bytes = Vec::new();
+    for (name, value) in headers {
+        header_bytes.push(name.len() as u8);
+        header_bytes.extend_from_slice(name.as_bytes());
+        header_bytes.push(7);
+        header_bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        header_bytes.extend_from_slice(value.as_bytes());
+    }
+
+    let total = (16 + header_bytes.len() + payload.len()) as u32;
+    let mut message = Vec::with_capacity(total as usize);
+    message.extend_from_slice(&total.to_be_bytes());
+    message.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+    message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+    message.extend_from_slice(&header_bytes);
+    message.extend_from_slice(payload);
+    message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+    message
+}
+
+/// Bedrock `chunk` event wrapping one Anthropic stream event
+#[cfg(test)]
+pub fn encode_chunk(event: &serde_json::Value) -> Vec<u8> {
+    let bytes = base64::engine::general_purpose::STANDARD.encode(event.to_string());
+    encode_message(
+        &[(":event-type", "chunk"), (":content-type", "application/json"), (":message-type", "event")],
+        serde_json::json!({ "bytes": bytes }).to_string().as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_stream_decoding() {
+        let mut stream = Vec::new();
+        for event in [
+            serde_json::json!({"type": "message_start", "message": {"usage": {"input_tokens": 12}}}),
+            serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}}),
+            serde_json::json!({"type": "content_block_stop", "index": 0}),
+            serde_json::json!({"type": "message_stop"}),
+        ] {
+            stream.extend(encode_chunk(&event));
+        }
+
+        // Messages split across chunks are reassembled
+        let mut decoder = EventStreamDecoder::default();
+        let mut parser = AnthropicStream::default();
+        let mut events = Vec::new();
+        for piece in stream.chunks(7) {
+            for message in decoder.push(piece).unwrap() {
+                feed(&mut parser, &message, &mut events).unwrap();
+            }
+        }
+        assert_eq!(events, vec![StreamEvent::Text("Hello".to_string())]);
+        let response = parser.finish().unwrap();
+        assert_eq!(response.text, "Hello");
+        assert_eq!(response.usage.unwrap().input_tokens, 12);
+
+        // Corrupted messages are rejected
+        let mut corrupt = encode_chunk(&serde_json::json!({"type": "message_stop"}));
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        assert!(EventStreamDecoder::default().push(&corrupt).is_err());
+
+        // Throttling arrives as an exception message and can be retried elsewhere
+        let throttled = encode_message(
+            &[(":exception-type", "throttlingException"), (":message-type", "exception")],
+            br#"{"message":"Too many requests"}"#,
+        );
+        let message = EventStreamDecoder::default().push(&throttled).unwrap().remove(0);
+        let error = feed(&mut AnthropicStream::default(), &message, &mut Vec::new()).unwrap_err();
+        assert!(error.is_retryable());
+        assert!(error.to_string().contains("Too many requests"));
+    }
+
+    #[test]
+    fn test_region_from_url() {
+        assert_eq!(region_from_url("https://bedrock-runtime.eu-west-1.amazonaws.com").as_deref(), Some("eu-west-1"));
+        assert_eq!(region_from_url("http://127.0.0.1:9000"), None);
+    }
+}
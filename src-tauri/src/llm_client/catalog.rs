@@ -0,0 +1,308 @@
+//! Model catalogue: provider, limits, capabilities and prices of known models.
+//!
+//! The bundled list lives in `models.json`. Entries in
+//! `<data dir>/kuse-cowork/models.json` are merged over it at startup, replacing
+//! bundled models with the same id, so new models can be added without a release.
+
+use super::ApiFormat;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const BUNDLED_MODELS: &str = include_str!("models.json");
+
+/// Prices in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Provider preset id, as used by `ProviderConfig::from_preset`
+    pub provider: String,
+    /// Overrides the provider's API format (the GPT-5 series uses the Responses API)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_format: Option<ApiFormat>,
+    /// Further id prefixes this entry covers, e.g. dated snapshots of the model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefixes: Vec<String>,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    #[serde(default)]
+    pub vision: bool,
+    #[serde(default = "default_true")]
+    pub tools: bool,
+    /// Reasons before answering and accepts a thinking budget or reasoning effort
+    #[serde(default)]
+    pub reasoning: bool,
+    /// Highest temperature accepted; `null` for models that only take their default
+    #[serde(default = "default_max_temperature")]
+    pub max_temperature: Option<f32>,
+    /// Chat Completions takes `max_tokens` instead of `max_completion_tokens`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub legacy_max_tokens: bool,
+    /// Known to the catalogue but not offered in the model picker
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub pricing: Option<ModelPricing>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_temperature() -> Option<f32> {
+    Some(2.0)
+}
+
+impl ModelInfo {
+    /// Permissive defaults for models missing from the catalogue, which are
+    /// usually newer than the catalogue
+    pub fn unknown(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            provider: String::new(),
+            api_format: None,
+            prefixes: Vec::new(),
+            context_window: 128_000,
+            max_output_tokens: 8192,
+            vision: false,
+            tools: true,
+            reasoning: true,
+            max_temperature: default_max_temperature(),
+            legacy_max_tokens: false,
+            hidden: true,
+            pricing: None,
+        }
+    }
+
+    pub fn supports_temperature(&self) -> bool {
+        self.max_temperature.is_some()
+    }
+
+    /// Temperature to send for a requested value, clamped to the model's range
+    pub fn temperature(&self, requested: Option<f32>) -> Option<f32> {
+        let max = self.max_temperature?;
+        requested.map(|t| t.min(max))
+    }
+
+    /// Length of the longest id or prefix of this entry that `model` starts with
+    fn match_len(&self, model: &str) -> Option<usize> {
+        std::iter::once(&self.id)
+            .chain(&self.prefixes)
+            .map(|p| p.to_lowercase())
+            .filter(|p| model.starts_with(p.as_str()))
+            .map(|p| p.len())
+            .max()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModelCatalog {
+    models: Vec<ModelInfo>,
+}
+
+impl ModelCatalog {
+    /// The catalogue shipped with the app
+    pub fn bundled() -> Self {
+        Self {
+            models: serde_json::from_str(BUNDLED_MODELS).expect("bundled models.json is invalid"),
+        }
+    }
+
+    /// Bundled catalogue with the user's overrides merged in
+    pub fn load() -> Self {
+        let mut catalog = Self::bundled();
+
+        if let Some(path) = user_catalog_path().filter(|p| p.exists()) {
+            match std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| serde_json::from_str::<Vec<ModelInfo>>(&s).map_err(|e| e.to_string()))
+            {
+                Ok(models) => catalog.merge(models),
+                Err(e) => eprintln!("Ignoring model catalogue {}: {}", path.display(), e),
+            }
+        }
+
+        catalog
+    }
+
+    /// Add models, replacing existing entries with the same id
+    pub fn merge(&mut self, models: Vec<ModelInfo>) {
+        for model in models {
+            match self.models.iter_mut().find(|m| m.id == model.id) {
+                Some(existing) => *existing = model,
+                None => self.models.push(model),
+            }
+        }
+    }
+
+    pub fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    /// Entry for a model id: an exact id, or else the longest matching id or prefix
+    pub fn find(&self, model: &str) -> Option<&ModelInfo> {
+        let lower = model.to_lowercase();
+        self.models
+            .iter()
+            .find(|m| m.id.to_lowercase() == lower)
+            .or_else(|| {
+                self.models
+                    .iter()
+                    .filter_map(|m| m.match_len(&lower).map(|len| (len, m)))
+                    .max_by_key(|(len, _)| *len)
+                    .map(|(_, m)| m)
+            })
+    }
+
+    /// Capabilities of a model. Aggregator ids such as `openai/o3` and cloud
+    /// platform ids such as Bedrock's `us.anthropic.claude-sonnet-4-20250514-v1:0`
+    /// or Vertex's `claude-sonnet-4@20250514` fall back to the upstream model;
+    /// unknown models get permissive defaults.
+    pub fn info(&self, model: &str) -> ModelInfo {
+        self.lookup(model).cloned().unwrap_or_else(|| ModelInfo::unknown(model))
+    }
+
+    /// Catalogue entry for a model, including the upstream fallbacks of `info`
+    pub fn lookup(&self, model: &str) -> Option<&ModelInfo> {
+        self.find(model)
+            .or_else(|| model.rsplit_once('/').and_then(|(_, upstream)| self.find(upstream)))
+            .or_else(|| self.find(&platform_upstream_id(model)))
+    }
+
+    /// Model ids reported by a provider, with what the catalogue knows about them
+    pub fn annotate(&self, ids: Vec<String>) -> Vec<DiscoveredModel> {
+        ids.into_iter()
+            .map(|id| DiscoveredModel {
+                info: self.lookup(&id).cloned(),
+                id,
+            })
+            .collect()
+    }
+}
+
+/// A model offered by a provider
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredModel {
+    pub id: String,
+    /// Limits and capabilities, for models the catalogue knows
+    pub info: Option<ModelInfo>,
+}
+
+/// Model id without a Bedrock `[region.]vendor.` prefix, with Vertex's `@` version separator as `-`
+fn platform_upstream_id(model: &str) -> String {
+    let model = ["anthropic.", "google.", "meta.", "mistral.", "amazon."]
+        .iter()
+        .find_map(|vendor| model.find(vendor).map(|i| &model[i + vendor.len()..]))
+        .unwrap_or(model);
+    model.replace('@', "-")
+}
+
+/// Optional user catalogue merged over the bundled one
+pub fn user_catalog_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("kuse-cowork").join("models.json"))
+}
+
+static CATALOG: OnceLock<ModelCatalog> = OnceLock::new();
+
+/// The catalogue, loaded on first use
+pub fn catalog() -> &'static ModelCatalog {
+    CATALOG.get_or_init(ModelCatalog::load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_model() {
+        let catalog = ModelCatalog::bundled();
+
+        assert_eq!(catalog.find("gpt-4o").unwrap().id, "gpt-4o");
+        // Dated snapshots resolve to their family, preferring the longest prefix
+        assert_eq!(catalog.find("gpt-4o-2024-08-06").unwrap().id, "gpt-4o");
+        assert_eq!(catalog.find("gpt-4o-mini-2024-07-18").unwrap().id, "gpt-4o-mini");
+        assert_eq!(catalog.find("claude-sonnet-4-5").unwrap().id, "claude-sonnet-4-5-20250929");
+        assert_eq!(catalog.find("claude-sonnet-4-0").unwrap().id, "claude-sonnet-4-20250514");
+        assert!(catalog.find("my-finetune").is_none());
+
+        // Aggregator ids only borrow capabilities of the upstream model
+        assert!(catalog.find("openrouter-only/o3").is_none());
+        let info = catalog.info("openrouter-only/o3");
+        assert!(info.reasoning);
+        assert!(!info.supports_temperature());
+
+        // Cloud platform ids borrow capabilities too
+        let bedrock = catalog.info("us.anthropic.claude-sonnet-4-20250514-v1:0");
+        assert_eq!(bedrock.id, "claude-sonnet-4-20250514");
+        assert_eq!(catalog.info("claude-sonnet-4@20250514").id, "claude-sonnet-4-20250514");
+
+        let unknown = catalog.info("my-finetune");
+        assert_eq!(unknown.temperature(Some(0.7)), Some(0.7));
+    }
+
+    #[test]
+    fn test_temperature_rules() {
+        let catalog = ModelCatalog::bundled();
+
+        assert_eq!(catalog.info("gpt-5-mini").temperature(Some(0.7)), None);
+        assert_eq!(catalog.info("claude-sonnet-4-5-20250929").temperature(Some(1.5)), Some(1.0));
+        assert_eq!(catalog.info("gpt-4o").temperature(Some(1.5)), Some(1.5));
+        assert!(catalog.info("gpt-4-0613").legacy_max_tokens);
+        assert!(!catalog.info("gpt-4-turbo").legacy_max_tokens);
+        assert!(!catalog.info("gpt-4.1-mini").legacy_max_tokens);
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let mut catalog = ModelCatalog::bundled();
+        let count = catalog.models().len();
+
+        let overrides: Vec<ModelInfo> = serde_json::from_str(
+            r#"[
+                {"id": "gpt-4o", "provider": "openai", "context_window": 64000, "max_output_tokens": 4096},
+                {"id": "acme-1", "provider": "custom", "context_window": 32000, "max_output_tokens": 2048}
+            ]"#,
+        )
+        .unwrap();
+        catalog.merge(overrides);
+
+        assert_eq!(catalog.models().len(), count + 1);
+        let gpt = catalog.find("gpt-4o").unwrap();
+        assert_eq!(gpt.context_window, 64000);
+        assert!(gpt.tools);
+        assert_eq!(gpt.max_temperature, Some(2.0));
+        assert_eq!(catalog.find("acme-1").unwrap().provider, "custom");
+    }
+
+    #[test]
+    fn test_annotate_listing() {
+        let catalog = ModelCatalog::bundled();
+        let models = catalog.annotate(vec![
+            "gpt-4o-2024-08-06".to_string(),
+            "qwen2.5:latest".to_string(),
+            "my-finetune".to_string(),
+        ]);
+
+        assert_eq!(models[0].id, "gpt-4o-2024-08-06");
+        assert_eq!(models[0].info.as_ref().unwrap().id, "gpt-4o");
+        assert!(models[1].info.as_ref().unwrap().tools);
+        // Unknown models carry no made-up limits
+        assert!(models[2].info.is_none());
+    }
+}
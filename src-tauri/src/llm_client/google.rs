@@ -0,0 +1,332 @@
+use super::sse::{read_sse, ResponseAccumulator, SseParser};
+use super::{LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::{ApiContent, ClaudeApiRequest, ToolChoice};
+use crate::agent::{TokenUsage, ToolUse};
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Google Gemini `generateContent` API
+pub struct GoogleProvider {
+    client: LLMClient,
+}
+
+impl GoogleProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self { client }
+    }
+}
+
+impl LlmProvider for GoogleProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            // alt=sse returns Server-Sent Events instead of a JSON array
+            let url = format!(
+                "{}/v1beta/models/{}:streamGenerateContent?alt=sse",
+                self.client.base_url.trim_end_matches('/'),
+                request.model
+            );
+            let body = to_google_body(request);
+            let response = self.client.post_json(&url, &body, &[]).await?;
+            read_sse(response, GoogleStream::default(), &tx).await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(async move {
+            let url = format!("{}/v1beta/models", self.client.base_url.trim_end_matches('/'));
+            let data = self.client.get_json(&url, &[]).await?;
+
+            Ok(data["models"]
+                .as_array()
+                .map(|models| {
+                    models
+                        .iter()
+                        .filter_map(|m| m["name"].as_str())
+                        .map(|name| name.trim_start_matches("models/").to_string())
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+}
+
+/// Convert a request in the agent's (Anthropic) format to a Gemini body
+pub fn to_google_body(request: &ClaudeApiRequest) -> serde_json::Value {
+    // Function responses need the call's name and thoughtSignature, looked up by tool_use id
+    let mut calls: HashMap<&str, (&serde_json::Value, Option<&str>)> = HashMap::new();
+    for msg in &request.messages {
+        if let ApiContent::Blocks(blocks) = &msg.content {
+            for block in blocks {
+                if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
+                    if let Some(id) = block.get("id").and_then(|v| v.as_str()) {
+                        let signature = block.get("thought_signature").and_then(|v| v.as_str());
+                        calls.insert(id, (&block["name"], signature));
+                    }
+                }
+            }
+        }
+    }
+
+    // Build contents array
+    let mut contents: Vec<serde_json::Value> = Vec::new();
+
+    // Convert messages to Google format
+    for msg in &request.messages {
+        // Google uses "user" and "model" instead of "user" and "assistant"
+        let role = if msg.role == "assistant" { "model" } else { &msg.role };
+
+        let parts = match &msg.content {
+            ApiContent::Text(text) => {
+                vec![serde_json::json!({"text": text})]
+            }
+            ApiContent::Blocks(blocks) => {
+                let mut parts_list = Vec::new();
+                for block in blocks {
+                    let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
+
+                    match block_type {
+                        "text" => {
+                            if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
+                                parts_list.push(serde_json::json!({"text": text}));
+                            }
+                        }
+                        "tool_use" => {
+                            // Convert to functionCall format with thoughtSignature if present
+                            let mut fc_part = serde_json::json!({
+                                "functionCall": {
+                                    "name": block.get("name"),
+                                    "args": block.get("input")
+                                }
+                            });
+                            // Include thoughtSignature if present (for Gemini 3)
+                            if let Some(sig) = block.get("thought_signature").and_then(|v| v.as_str()) {
+                                fc_part["thoughtSignature"] = serde_json::json!(sig);
+                            }
+                            parts_list.push(fc_part);
+                        }
+                        "tool_result" => {
+                            let tool_use_id = block.get("tool_use_id").and_then(|v| v.as_str()).unwrap_or("unknown");
+                            let (name, signature) = match calls.get(tool_use_id) {
+                                Some((name, signature)) => ((*name).clone(), *signature),
+                                None => (serde_json::json!(tool_use_id), None),
+                            };
+
+                            let mut fr_part = serde_json::json!({
+                                "functionResponse": {
+                                    "name": name,
+                                    "response": {
+                                        "content": block.get("content")
+                                    }
+                                }
+                            });
+
+                            // Include thoughtSignature from matching tool_use (required for Gemini 3)
+                            if let Some(sig) = signature {
+                                fr_part["thoughtSignature"] = serde_json::json!(sig);
+                            }
+
+                            parts_list.push(fr_part);
+                        }
+                        _ => {}
+                    }
+                }
+                parts_list
+            }
+        };
+
+        if !parts.is_empty() {
+            contents.push(serde_json::json!({
+                "role": role,
+                "parts": parts
+            }));
+        }
+    }
+
+    // Gemini 3 recommends keeping temperature at its default of 1.0
+    let mut body = serde_json::json!({
+        "contents": contents,
+        "generationConfig": {
+            "maxOutputTokens": request.max_tokens
+        }
+    });
+
+    if let Some(thinking) = &request.thinking {
+        body["generationConfig"]["thinkingConfig"] = serde_json::json!({
+            "thinkingBudget": thinking.budget_tokens,
+            "includeThoughts": true
+        });
+    }
+
+    // Add system instruction if present
+    if !request.system.is_empty() {
+        body["systemInstruction"] = serde_json::json!({
+            "parts": [{"text": request.system}]
+        });
+    }
+
+    // Convert tools to Google functionDeclarations format
+    if !request.tools.is_empty() {
+        let function_declarations: Vec<serde_json::Value> = request.tools.iter().map(|tool| {
+            serde_json::json!({
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.input_schema
+            })
+        }).collect();
+
+        body["tools"] = serde_json::json!([{
+            "functionDeclarations": function_declarations
+        }]);
+
+        if request.tool_choice == Some(ToolChoice::None) {
+            body["toolConfig"] = serde_json::json!({
+                "functionCallingConfig": { "mode": "NONE" }
+            });
+        }
+    }
+
+    // JSON mode can't be combined with function calling
+    if let Some(schema) = &request.response_schema {
+        body["generationConfig"]["responseMimeType"] = serde_json::json!("application/json");
+        body["generationConfig"]["responseSchema"] = gemini_schema(schema);
+        if let Some(body) = body.as_object_mut() {
+            body.remove("tools");
+            body.remove("toolConfig");
+        }
+    }
+
+    body
+}
+
+/// Keywords of the OpenAPI schema subset accepted by `responseSchema`
+const GEMINI_SCHEMA_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "description",
+    "nullable",
+    "enum",
+    "items",
+    "minItems",
+    "maxItems",
+    "properties",
+    "required",
+    "propertyOrdering",
+    "anyOf",
+    "minimum",
+    "maximum",
+    "minLength",
+    "maxLength",
+    "pattern",
+];
+
+/// JSON Schema reduced to the keywords Gemini accepts
+fn gemini_schema(schema: &serde_json::Value) -> serde_json::Value {
+    match schema {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| GEMINI_SCHEMA_KEYWORDS.contains(&key.as_str()))
+            .map(|(key, value)| {
+                let value = match key.as_str() {
+                    // Property names are not keywords, so only their schemas are reduced
+                    "properties" => serde_json::Value::Object(
+                        value
+                            .as_object()
+                            .into_iter()
+                            .flatten()
+                            .map(|(name, schema)| (name.clone(), gemini_schema(schema)))
+                            .collect(),
+                    ),
+                    "items" => gemini_schema(value),
+                    "anyOf" => serde_json::Value::Array(value.as_array().into_iter().flatten().map(gemini_schema).collect()),
+                    _ => value.clone(),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+/// Parser for Gemini `GenerateContentResponse` chunks
+#[derive(Debug, Default)]
+pub struct GoogleStream {
+    acc: ResponseAccumulator,
+    error: Option<String>,
+}
+
+impl SseParser for GoogleStream {
+    fn on_event(&mut self, event: &serde_json::Value, out: &mut Vec<StreamEvent>) {
+        if let Some(message) = event.pointer("/error/message").and_then(|m| m.as_str()) {
+            self.error = Some(message.to_string());
+            return;
+        }
+
+        // Every chunk carries the running totals
+        if let Some(usage) = event.get("usageMetadata") {
+            let get = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+            let cached = get("cachedContentTokenCount");
+            self.acc.usage = Some(TokenUsage {
+                input_tokens: get("promptTokenCount").saturating_sub(cached),
+                output_tokens: get("candidatesTokenCount") + get("thoughtsTokenCount"),
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: cached,
+            });
+        }
+
+        let parts = event
+            .get("candidates")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c.pointer("/content/parts").and_then(|p| p.as_array()))
+            .flatten();
+
+        for part in parts {
+            let text = part.get("text").and_then(|v| v.as_str());
+
+            // Thought summaries are display-only; the signatures Gemini needs back
+            // travel on the function calls
+            if part.get("thought").and_then(|v| v.as_bool()).unwrap_or(false) {
+                if let Some(text) = text {
+                    self.acc.push_thinking(text, out);
+                }
+                continue;
+            }
+
+            if let Some(text) = text {
+                self.acc.push_text(text, out);
+            }
+
+            // Handle function calls (with thoughtSignature for Gemini 3)
+            if let Some(fc) = part.get("functionCall") {
+                self.acc.tool_uses.push(ToolUse {
+                    // Gemini doesn't assign call ids
+                    id: format!("fc_{}", uuid::Uuid::new_v4()),
+                    name: fc.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                    input: fc.get("args").cloned().unwrap_or(serde_json::json!({})),
+                    thought_signature: part
+                        .get("thoughtSignature")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                });
+            }
+        }
+    }
+
+    fn finish(self) -> Result<LlmResponse, LLMError> {
+        match self.error {
+            Some(error) => Err(LLMError::Api(error)),
+            None => Ok(self.acc.into_response()),
+        }
+    }
+}
@@ -0,0 +1,530 @@
+//! In-process mock LLM server for offline tests.
+//!
+//! `MockServer` listens on a local port and answers provider requests in one of
+//! three modes:
+//! - scripted: canned SSE streams built from `MockTurn`s, or error statuses
+//! - replay: the exchanges of a cassette file, in order
+//! - record: proxied to a real upstream, saving every exchange to a cassette
+//!
+//! Cassettes live in `fixtures/sessions/`. Set `KUSE_RECORD_FIXTURES=1` to
+//! re-record them against the real APIs with `MockServer::cassette`.
+
+use super::bedrock::encode_chunk;
+use super::ApiFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Environment variable that switches `MockServer::cassette` to record mode
+pub const RECORD_ENV: &str = "KUSE_RECORD_FIXTURES";
+
+/// One scripted assistant turn, rendered into any provider's stream format
+#[derive(Debug, Clone, Default)]
+pub struct MockTurn {
+    pub thinking: Option<String>,
+    pub text: String,
+    /// Tool name and input of each call
+    pub tool_calls: Vec<(String, serde_json::Value)>,
+}
+
+impl MockTurn {
+    pub fn text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_thinking(mut self, thinking: &str) -> Self {
+        self.thinking = Some(thinking.to_string());
+        self
+    }
+
+    pub fn with_tool_call(mut self, name: &str, input: serde_json::Value) -> Self {
+        self.tool_calls.push((name.to_string(), input));
+        self
+    }
+
+    /// Render the turn as a stream body in the given API format (SSE, or NDJSON for Ollama)
+    pub fn to_sse(&self, format: &ApiFormat) -> String {
+        match format {
+            // Cloud platforms serving Claude stream Anthropic events (Bedrock wraps them, see `response`)
+            ApiFormat::Anthropic | ApiFormat::Bedrock | ApiFormat::Vertex => self.anthropic_sse(),
+            ApiFormat::OpenAI | ApiFormat::OpenAICompatible | ApiFormat::Minimax | ApiFormat::Azure => {
+                self.openai_sse()
+            }
+            ApiFormat::OpenAIResponses => self.responses_sse(),
+            ApiFormat::Google => self.google_sse(),
+            ApiFormat::Ollama => self.ollama_ndjson(),
+        }
+    }
+
+    /// Scripted response streaming this turn
+    pub fn response(&self, format: &ApiFormat) -> MockResponse {
+        match format {
+            ApiFormat::Bedrock => MockResponse::Bytes(self.anthropic_events().iter().flat_map(encode_chunk).collect()),
+            _ => MockResponse::Stream(self.to_sse(format)),
+        }
+    }
+
+    fn anthropic_sse(&self) -> String {
+        self.anthropic_events()
+            .iter()
+            .map(|event| format!("event: {}\ndata: {}\n\n", event["type"].as_str().unwrap_or(""), event))
+            .collect()
+    }
+
+    fn anthropic_events(&self) -> Vec<serde_json::Value> {
+        let mut events = vec![serde_json::json!({
+            "type": "message_start",
+            "message": {"id": "msg_mock", "role": "assistant", "content": [], "usage": {"input_tokens": 10, "output_tokens": 1}}
+        })];
+        let mut index = 0;
+
+        if let Some(thinking) = &self.thinking {
+            events.push(serde_json::json!({"type": "content_block_start", "index": index, "content_block": {"type": "thinking", "thinking": ""}}));
+            events.push(serde_json::json!({"type": "content_block_delta", "index": index, "delta": {"type": "thinking_delta", "thinking": thinking}}));
+            events.push(serde_json::json!({"type": "content_block_delta", "index": index, "delta": {"type": "signature_delta", "signature": "sig_mock"}}));
+            events.push(serde_json::json!({"type": "content_block_stop", "index": index}));
+            index += 1;
+        }
+        if !self.text.is_empty() {
+            events.push(serde_json::json!({"type": "content_block_start", "index": index, "content_block": {"type": "text", "text": ""}}));
+            events.push(serde_json::json!({"type": "content_block_delta", "index": index, "delta": {"type": "text_delta", "text": self.text}}));
+            events.push(serde_json::json!({"type": "content_block_stop", "index": index}));
+            index += 1;
+        }
+        for (i, (name, input)) in self.tool_calls.iter().enumerate() {
+            events.push(serde_json::json!({"type": "content_block_start", "index": index, "content_block": {"type": "tool_use", "id": format!("toolu_mock_{}", i), "name": name, "input": {}}}));
+            events.push(serde_json::json!({"type": "content_block_delta", "index": index, "delta": {"type": "input_json_delta", "partial_json": input.to_string()}}));
+            events.push(serde_json::json!({"type": "content_block_stop", "index": index}));
+            index += 1;
+        }
+
+        let stop_reason = if self.tool_calls.is_empty() { "end_turn" } else { "tool_use" };
+        events.push(serde_json::json!({"type": "message_delta", "delta": {"stop_reason": stop_reason}, "usage": {"output_tokens": 5}}));
+        events.push(serde_json::json!({"type": "message_stop"}));
+        events
+    }
+
+    fn openai_sse(&self) -> String {
+        let chunk = |delta: serde_json::Value, finish_reason: Option<&str>| {
+            serde_json::json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+            })
+        };
+
+        let mut events = Vec::new();
+        if let Some(thinking) = &self.thinking {
+            events.push(chunk(serde_json::json!({"reasoning_content": thinking}), None));
+        }
+        if !self.text.is_empty() {
+            events.push(chunk(serde_json::json!({"content": self.text}), None));
+        }
+        for (i, (name, input)) in self.tool_calls.iter().enumerate() {
+            events.push(chunk(
+                serde_json::json!({"tool_calls": [{
+                    "index": i,
+                    "id": format!("call_mock_{}", i),
+                    "type": "function",
+                    "function": {"name": name, "arguments": input.to_string()}
+                }]}),
+                None,
+            ));
+        }
+
+        let finish_reason = if self.tool_calls.is_empty() { "stop" } else { "tool_calls" };
+        events.push(chunk(serde_json::json!({}), Some(finish_reason)));
+        events.push(serde_json::json!({
+            "id": "chatcmpl-mock",
+            "choices": [],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5}
+        }));
+
+        let mut sse: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        sse.push_str("data: [DONE]\n\n");
+        sse
+    }
+
+    fn responses_sse(&self) -> String {
+        let mut events = Vec::new();
+        if let Some(thinking) = &self.thinking {
+            events.push(serde_json::json!({"type": "response.reasoning_summary_text.delta", "delta": thinking}));
+        }
+        if !self.text.is_empty() {
+            events.push(serde_json::json!({"type": "response.output_text.delta", "delta": self.text}));
+        }
+        for (i, (name, input)) in self.tool_calls.iter().enumerate() {
+            events.push(serde_json::json!({
+                "type": "response.output_item.done",
+                "item": {"type": "function_call", "call_id": format!("call_mock_{}", i), "name": name, "arguments": input.to_string()}
+            }));
+        }
+        events.push(serde_json::json!({
+            "type": "response.completed",
+            "response": {"status": "completed", "usage": {"input_tokens": 10, "output_tokens": 5}}
+        }));
+
+        events
+            .iter()
+            .map(|event| format!("event: {}\ndata: {}\n\n", event["type"].as_str().unwrap_or(""), event))
+            .collect()
+    }
+
+    fn google_sse(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(thinking) = &self.thinking {
+            parts.push(serde_json::json!({"text": thinking, "thought": true}));
+        }
+        if !self.text.is_empty() {
+            parts.push(serde_json::json!({"text": self.text}));
+        }
+        for (name, input) in &self.tool_calls {
+            parts.push(serde_json::json!({"functionCall": {"name": name, "args": input}, "thoughtSignature": "sig_mock"}));
+        }
+
+        // One chunk per part, the last one carrying the finish reason
+        let count = parts.len();
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let mut candidate = serde_json::json!({"content": {"role": "model", "parts": [part]}});
+                if i + 1 == count {
+                    candidate["finishReason"] = serde_json::json!("STOP");
+                }
+                let event = serde_json::json!({
+                    "candidates": [candidate],
+                    "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 5}
+                });
+                format!("data: {}\n\n", event)
+            })
+            .collect()
+    }
+
+    fn ollama_ndjson(&self) -> String {
+        let line = |message: serde_json::Value| {
+            serde_json::json!({"model": "mock", "message": message, "done": false})
+        };
+
+        let mut lines = Vec::new();
+        if let Some(thinking) = &self.thinking {
+            lines.push(line(serde_json::json!({"role": "assistant", "content": "", "thinking": thinking})));
+        }
+        if !self.text.is_empty() {
+            lines.push(line(serde_json::json!({"role": "assistant", "content": self.text})));
+        }
+        if !self.tool_calls.is_empty() {
+            let calls: Vec<_> = self
+                .tool_calls
+                .iter()
+                .map(|(name, input)| serde_json::json!({"function": {"name": name, "arguments": input}}))
+                .collect();
+            lines.push(line(serde_json::json!({"role": "assistant", "content": "", "tool_calls": calls})));
+        }
+        lines.push(serde_json::json!({
+            "model": "mock",
+            "message": {"role": "assistant", "content": ""},
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 10,
+            "eval_count": 5
+        }));
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+/// A scripted answer to one request
+#[derive(Debug, Clone)]
+pub enum MockResponse {
+    /// 200 with an SSE body
+    Stream(String),
+    /// Error status with a body
+    Status(u16, String),
+    /// 200 with a binary body, such as an AWS event stream. Cassettes store bodies as
+    /// text, so these can't be recorded and replayed.
+    Bytes(Vec<u8>),
+}
+
+/// One request and its response, as stored in a cassette
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    pub path: String,
+    /// Request headers, lowercased; not saved so cassettes never hold API keys
+    #[serde(skip)]
+    pub headers: Vec<(String, String)>,
+    /// Request JSON body (`null` when empty)
+    pub request: serde_json::Value,
+    pub status: u16,
+    pub body: String,
+}
+
+enum Mode {
+    Script(VecDeque<MockResponse>),
+    Replay(VecDeque<Exchange>),
+    Record { upstream: String },
+}
+
+struct ServerState {
+    mode: Mode,
+    exchanges: Vec<Exchange>,
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+pub struct MockServer {
+    base_url: String,
+    state: Arc<Mutex<ServerState>>,
+    /// Where to save the exchanges when recording a cassette
+    record_to: Option<PathBuf>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Answer requests with `responses` in order
+    pub async fn scripted(responses: Vec<MockResponse>) -> Self {
+        Self::start(Mode::Script(responses.into()), None).await
+    }
+
+    /// Replay a cassette's exchanges in order
+    pub async fn replay(cassette: &str) -> Self {
+        let exchanges: Vec<Exchange> = serde_json::from_str(cassette).expect("invalid cassette");
+        Self::start(Mode::Replay(exchanges.into()), None).await
+    }
+
+    /// Proxy requests to `upstream`, keeping every exchange
+    pub async fn record(upstream: &str) -> Self {
+        Self::start(
+            Mode::Record {
+                upstream: upstream.trim_end_matches('/').to_string(),
+            },
+            None,
+        )
+        .await
+    }
+
+    /// Replay `fixtures/sessions/<name>.json`, or record it from `upstream`
+    /// when `KUSE_RECORD_FIXTURES` is set
+    pub async fn cassette(name: &str, upstream: &str) -> Self {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/llm_client/fixtures/sessions")
+            .join(format!("{}.json", name));
+
+        if Self::recording() {
+            let mut server = Self::record(upstream).await;
+            server.record_to = Some(path);
+            server
+        } else {
+            let cassette = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("cannot read cassette {}: {}", path.display(), e));
+            Self::replay(&cassette).await
+        }
+    }
+
+    /// Whether cassettes are being re-recorded against real APIs
+    pub fn recording() -> bool {
+        std::env::var(RECORD_ENV).is_ok_and(|v| !v.is_empty() && v != "0")
+    }
+
+    async fn start(mode: Mode, record_to: Option<PathBuf>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+        let base_url = format!("http://{}", listener.local_addr().expect("mock server address"));
+        let state = Arc::new(Mutex::new(ServerState {
+            mode,
+            exchanges: Vec::new(),
+        }));
+
+        let server_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(socket, state).await;
+                });
+            }
+        });
+
+        Self {
+            base_url,
+            state,
+            record_to,
+            task,
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Exchanges served so far
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.state.lock().unwrap().exchanges.clone()
+    }
+
+    /// Request bodies received so far
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.exchanges().into_iter().map(|e| e.request).collect()
+    }
+
+    /// Save the recorded cassette; does nothing when not recording
+    pub fn finish(&self) {
+        if let Some(path) = &self.record_to {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).expect("create cassette directory");
+            }
+            let json = serde_json::to_string_pretty(&self.exchanges()).expect("serialize cassette");
+            std::fs::write(path, json + "\n").expect("write cassette");
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(mut socket: TcpStream, state: Arc<Mutex<ServerState>>) -> std::io::Result<()> {
+    let request = read_request(&mut socket).await?;
+    let (status, body) = respond(&state, &request).await;
+
+    let content_type = if status == 200 { "text/event-stream" } else { "application/json" };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        if status == 200 { "OK" } else { "Error" },
+        content_type,
+        body.len()
+    );
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(&body).await?;
+    socket.shutdown().await
+}
+
+async fn read_request(socket: &mut TcpStream) -> std::io::Result<HttpRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().unwrap_or("").to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(k, _)| k == "content-length")
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(HttpRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// Pick the response for a request and log the exchange
+async fn respond(state: &Mutex<ServerState>, request: &HttpRequest) -> (u16, Vec<u8>) {
+    // Record mode answers outside the lock, once the upstream has replied
+    let upstream = {
+        let mut state = state.lock().unwrap();
+        let answer = match &mut state.mode {
+            Mode::Script(responses) => Ok(match responses.pop_front() {
+                Some(MockResponse::Stream(body)) => (200, body.into_bytes()),
+                Some(MockResponse::Status(status, body)) => (status, body.into_bytes()),
+                Some(MockResponse::Bytes(body)) => (200, body),
+                None => (500, b"mock script exhausted".to_vec()),
+            }),
+            Mode::Replay(exchanges) => Ok(match exchanges.pop_front() {
+                Some(e) if e.method == request.method && e.path == request.path => (e.status, e.body.into_bytes()),
+                Some(e) => (
+                    500,
+                    format!("cassette expected {} {}, got {} {}", e.method, e.path, request.method, request.path)
+                        .into_bytes(),
+                ),
+                None => (500, b"cassette exhausted".to_vec()),
+            }),
+            Mode::Record { upstream } => Err(upstream.clone()),
+        };
+
+        match answer {
+            Ok(answer) => {
+                log_exchange(&mut state, request, &answer);
+                return answer;
+            }
+            Err(upstream) => upstream,
+        }
+    };
+
+    let answer = forward(&upstream, request)
+        .await
+        .unwrap_or_else(|e| (502, format!("upstream request failed: {}", e).into_bytes()));
+    log_exchange(&mut state.lock().unwrap(), request, &answer);
+    answer
+}
+
+fn log_exchange(state: &mut ServerState, request: &HttpRequest, (status, body): &(u16, Vec<u8>)) {
+    state.exchanges.push(Exchange {
+        method: request.method.clone(),
+        path: request.path.clone(),
+        headers: request.headers.clone(),
+        request: serde_json::from_slice(&request.body).unwrap_or(serde_json::Value::Null),
+        status: *status,
+        body: String::from_utf8_lossy(body).to_string(),
+    });
+}
+
+/// Send a request on to the real API, passing its auth headers through
+async fn forward(upstream: &str, request: &HttpRequest) -> Result<(u16, Vec<u8>), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let method = reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::POST);
+    let mut builder = client.request(method, format!("{}{}", upstream, request.path));
+    for (key, value) in &request.headers {
+        if !matches!(key.as_str(), "host" | "content-length" | "connection") {
+            builder = builder.header(key, value);
+        }
+    }
+
+    let response = builder.body(request.body.clone()).send().await?;
+    let status = response.status().as_u16();
+    Ok((status, response.bytes().await?.to_vec()))
+}
@@ -0,0 +1,966 @@
+mod anthropic;
+mod bedrock;
+pub mod catalog;
+mod google;
+mod ollama;
+#[cfg(test)]
+pub(crate) mod mock;
+mod openai;
+mod openai_responses;
+pub mod routing;
+mod sigv4;
+pub mod sse;
+mod vertex;
+
+pub use anthropic::AnthropicProvider;
+pub use bedrock::BedrockProvider;
+pub use google::GoogleProvider;
+pub use ollama::{OllamaOptions, OllamaProvider, PullProgress};
+pub use openai::OpenAIProvider;
+pub use openai_responses::OpenAIResponsesProvider;
+pub use vertex::VertexProvider;
+
+use crate::agent::message_builder::{ApiContent, ApiMessage, ClaudeApiRequest};
+use crate::agent::{ContentBlock, TokenUsage, ToolUse};
+use futures::future::BoxFuture;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+#[derive(Error, Debug)]
+#[allow(dead_code)]
+pub enum LLMError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("API error: {0}")]
+    Api(String),
+    #[error("API error ({status}): {message}")]
+    Status { status: u16, message: String },
+    #[error("Parse error: {0}")]
+    Parse(String),
+    #[error("Unsupported provider: {0}")]
+    UnsupportedProvider(String),
+}
+
+impl LLMError {
+    /// Whether another provider might succeed where this one failed
+    /// (network failures, rate limits, overload and server errors)
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LLMError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            LLMError::Status { status, .. } => matches!(status, 408 | 409 | 429) || *status >= 500,
+            // Errors reported inside a stream, e.g. Anthropic's overloaded_error
+            LLMError::Api(message) => {
+                let lower = message.to_lowercase();
+                lower.contains("overloaded") || lower.contains("rate limit") || lower.contains("rate_limit")
+            }
+            LLMError::Parse(_) | LLMError::UnsupportedProvider(_) => false,
+        }
+    }
+}
+
+/// Azure OpenAI API version used when the base URL doesn't name one
+const AZURE_API_VERSION: &str = "2024-10-21";
+
+/// API format type
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiFormat {
+    #[default]
+    Anthropic,
+    // Spelled as in the frontend's ProviderConfig rather than kebab-cased "open-a-i"
+    #[serde(rename = "openai")]
+    OpenAI,
+    #[serde(rename = "openai-compatible")]
+    OpenAICompatible,
+    #[serde(rename = "openai-responses")]
+    OpenAIResponses,  // For GPT-5 series using /v1/responses endpoint
+    Google,
+    Minimax,
+    /// Azure OpenAI: Chat Completions on deployment URLs
+    Azure,
+    /// Amazon Bedrock: Anthropic models over AWS event streams
+    Bedrock,
+    /// Google Vertex AI: Claude or Gemini, depending on the model
+    Vertex,
+    /// Ollama's native `/api/chat`, streaming newline-delimited JSON
+    Ollama,
+}
+
+impl ApiFormat {
+    /// Message format spoken on the wire, which for cloud platforms depends on the model
+    pub fn wire_format(&self, model: &str) -> ApiFormat {
+        match self {
+            ApiFormat::Bedrock => ApiFormat::Anthropic,
+            ApiFormat::Vertex if model.to_lowercase().contains("claude") => ApiFormat::Anthropic,
+            ApiFormat::Vertex => ApiFormat::Google,
+            other => other.clone(),
+        }
+    }
+}
+
+/// Authentication type
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthType {
+    None,
+    #[default]
+    Bearer,
+    ApiKey,
+    QueryParam,
+    /// Signed per request with AWS credentials
+    #[serde(rename = "aws-sigv4")]
+    AwsSigV4,
+    /// Bearer tokens from a Google service account
+    #[serde(rename = "oauth")]
+    OAuth,
+}
+
+/// Provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub id: String,
+    pub name: String,
+    pub base_url: String,
+    pub api_format: ApiFormat,
+    pub auth_type: AuthType,
+}
+
+impl ProviderConfig {
+    /// Get preset configuration by provider ID
+    pub fn from_preset(provider_id: &str) -> Self {
+        match provider_id {
+            // Official APIs
+            "anthropic" => Self {
+                id: "anthropic".to_string(),
+                name: "Anthropic".to_string(),
+                base_url: "https://api.anthropic.com".to_string(),
+                api_format: ApiFormat::Anthropic,
+                auth_type: AuthType::ApiKey,
+            },
+            "openai" => Self {
+                id: "openai".to_string(),
+                name: "OpenAI".to_string(),
+                base_url: "https://api.openai.com".to_string(),
+                api_format: ApiFormat::OpenAI,
+                auth_type: AuthType::Bearer,
+            },
+            "google" => Self {
+                id: "google".to_string(),
+                name: "Google".to_string(),
+                base_url: "https://generativelanguage.googleapis.com".to_string(),
+                api_format: ApiFormat::Google,
+                auth_type: AuthType::QueryParam,
+            },
+            "minimax" => Self {
+                id: "minimax".to_string(),
+                name: "Minimax".to_string(),
+                base_url: "https://api.minimax.chat".to_string(),
+                api_format: ApiFormat::Minimax,
+                auth_type: AuthType::Bearer,
+            },
+            // Credentials are `ACCESS_KEY_ID:SECRET[:SESSION_TOKEN]`, a Bedrock API key,
+            // or empty for the AWS_* environment variables
+            "bedrock" => Self {
+                id: "bedrock".to_string(),
+                name: "Amazon Bedrock".to_string(),
+                base_url: "https://bedrock-runtime.us-east-1.amazonaws.com".to_string(),
+                api_format: ApiFormat::Bedrock,
+                auth_type: AuthType::AwsSigV4,
+            },
+            // Credentials are a service account key (JSON or file path) or an access token
+            "vertex" => Self {
+                id: "vertex".to_string(),
+                name: "Google Vertex AI".to_string(),
+                base_url: "https://us-east5-aiplatform.googleapis.com".to_string(),
+                api_format: ApiFormat::Vertex,
+                auth_type: AuthType::OAuth,
+            },
+            // The model is the deployment name; the base URL is the resource endpoint
+            "azure" => Self {
+                id: "azure".to_string(),
+                name: "Azure OpenAI".to_string(),
+                base_url: "https://your-resource.openai.azure.com".to_string(),
+                api_format: ApiFormat::Azure,
+                auth_type: AuthType::ApiKey,
+            },
+
+            // Local inference services
+            "ollama" => Self {
+                id: "ollama".to_string(),
+                name: "Ollama".to_string(),
+                base_url: "http://localhost:11434".to_string(),
+                api_format: ApiFormat::Ollama,
+                auth_type: AuthType::None,
+            },
+            "lm-studio" => Self {
+                id: "lm-studio".to_string(),
+                name: "LM Studio".to_string(),
+                base_url: "http://localhost:1234".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::None,
+            },
+            "localai" => Self {
+                id: "localai".to_string(),
+                name: "LocalAI".to_string(),
+                base_url: "http://localhost:8080".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::None,
+            },
+
+            // Cloud GPU inference
+            "vllm" => Self {
+                id: "vllm".to_string(),
+                name: "vLLM".to_string(),
+                base_url: "http://localhost:8000".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::None,
+            },
+            "tgi" => Self {
+                id: "tgi".to_string(),
+                name: "TGI".to_string(),
+                base_url: "http://localhost:8080".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::None,
+            },
+            "sglang" => Self {
+                id: "sglang".to_string(),
+                name: "SGLang".to_string(),
+                base_url: "http://localhost:30000".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::None,
+            },
+
+            // API aggregation services
+            "openrouter" => Self {
+                id: "openrouter".to_string(),
+                name: "OpenRouter".to_string(),
+                base_url: "https://openrouter.ai/api/v1".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::Bearer,
+            },
+            "together" => Self {
+                id: "together".to_string(),
+                name: "Together AI".to_string(),
+                base_url: "https://api.together.xyz/v1".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::Bearer,
+            },
+            "groq" => Self {
+                id: "groq".to_string(),
+                name: "Groq".to_string(),
+                base_url: "https://api.groq.com/openai/v1".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::Bearer,
+            },
+            "deepseek" => Self {
+                id: "deepseek".to_string(),
+                name: "DeepSeek".to_string(),
+                base_url: "https://api.deepseek.com".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::Bearer,
+            },
+            "siliconflow" => Self {
+                id: "siliconflow".to_string(),
+                name: "SiliconFlow".to_string(),
+                base_url: "https://api.siliconflow.cn/v1".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::Bearer,
+            },
+
+            // Default/Custom - assume OpenAI compatible
+            _ => Self {
+                id: provider_id.to_string(),
+                name: "Custom".to_string(),
+                base_url: "http://localhost:8000".to_string(),
+                api_format: ApiFormat::OpenAICompatible,
+                auth_type: AuthType::Bearer,
+            },
+        }
+    }
+
+    /// Infer provider from the model catalogue, or from the model name for unknown models
+    pub fn from_model(model: &str) -> Self {
+        if let Some(info) = catalog::catalog().find(model) {
+            let mut config = Self::from_preset(&info.provider);
+            if let Some(api_format) = &info.api_format {
+                config.api_format = api_format.clone();
+            }
+            return config;
+        }
+
+        let model_lower = model.to_lowercase();
+
+        // Check OpenRouter format first (contains slash with known prefix)
+        if model_lower.starts_with("anthropic/") || model_lower.starts_with("openai/") || model_lower.starts_with("meta-llama/") || model_lower.starts_with("deepseek/") {
+            return Self::from_preset("openrouter");
+        }
+
+        // Check Ollama format (contains colon, e.g., llama3.3:latest)
+        if model_lower.contains(":") {
+            return Self::from_preset("ollama");
+        }
+
+        // Direct provider detection by model name
+        if model_lower.contains("claude") {
+            Self::from_preset("anthropic")
+        } else if model_lower.starts_with("gpt-5") || model_lower.contains("gpt-5") {
+            // GPT-5 series uses Responses API
+            Self::from_preset_with_format("openai", ApiFormat::OpenAIResponses)
+        } else if model_lower.contains("gpt") {
+            Self::from_preset("openai")
+        } else if model_lower.contains("gemini") {
+            Self::from_preset("google")
+        } else if model_lower.contains("minimax") {
+            Self::from_preset("minimax")
+        } else {
+            // Default to Anthropic
+            Self::from_preset("anthropic")
+        }
+    }
+
+    /// Cloud platform preset for a base URL, where the model name alone can't tell
+    pub fn preset_for_url(url: &str) -> Option<&'static str> {
+        let url = url.to_lowercase();
+        if url.contains(".openai.azure.com") || url.contains(".cognitiveservices.azure.com") {
+            Some("azure")
+        } else if url.contains("bedrock-runtime.") && url.contains(".amazonaws.com") {
+            Some("bedrock")
+        } else if url.contains("aiplatform.googleapis.com") {
+            Some("vertex")
+        } else {
+            None
+        }
+    }
+
+    /// Get preset configuration with custom API format override
+    fn from_preset_with_format(provider_id: &str, api_format: ApiFormat) -> Self {
+        let mut config = Self::from_preset(provider_id);
+        config.api_format = api_format;
+        config
+    }
+}
+
+/// Incremental output while a response streams in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// Assistant text received so far
+    Text(String),
+    /// Thinking or reasoning text received so far
+    Thinking(String),
+}
+
+/// A complete assistant turn, in the agent's own format
+#[derive(Debug, Clone, Default)]
+pub struct LlmResponse {
+    pub text: String,
+    /// Thinking blocks, kept in tool-use turns so providers can verify them
+    pub thinking: Vec<ContentBlock>,
+    pub tool_uses: Vec<ToolUse>,
+    /// Token counts, when the provider reports them
+    pub usage: Option<TokenUsage>,
+}
+
+/// One LLM API, driven by the agent's request format
+pub trait LlmProvider: Send + Sync {
+    fn config(&self) -> &ProviderConfig;
+
+    /// Send a request, forwarding text and thinking to `tx` as they stream in
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>>;
+
+    /// Model ids offered by the provider
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>>;
+
+    /// Check that the service is reachable and accepts the credentials
+    fn check_connection<'a>(&'a self, model: &'a str) -> BoxFuture<'a, Result<(), LLMError>> {
+        Box::pin(async move {
+            let request = ClaudeApiRequest::chat(model, 10, None, vec![ping_message()]);
+            self.complete(&request).await.map(|_| ())
+        })
+    }
+
+    /// Send a request without listening to the stream
+    fn complete<'a>(&'a self, request: &'a ClaudeApiRequest) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        let (tx, _rx) = mpsc::channel(1);
+        self.stream(request, tx)
+    }
+}
+
+/// Minimal message used to check a connection
+fn ping_message() -> ApiMessage {
+    ApiMessage {
+        role: "user".to_string(),
+        content: ApiContent::Text("Hi".to_string()),
+    }
+}
+
+/// Connection details shared by all providers
+#[derive(Clone)]
+pub struct LLMClient {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    provider_config: ProviderConfig,
+    openai_organization: Option<String>,
+    openai_project: Option<String>,
+    ollama_options: OllamaOptions,
+}
+
+impl LLMClient {
+    pub fn new(api_key: String, base_url: Option<String>, provider_id: Option<&str>, model: Option<&str>) -> Self {
+        Self::new_with_openai_headers(api_key, base_url, provider_id, model, None, None)
+    }
+
+    pub fn new_with_openai_headers(
+        api_key: String,
+        base_url: Option<String>,
+        provider_id: Option<&str>,
+        model: Option<&str>,
+        openai_organization: Option<String>,
+        openai_project: Option<String>,
+    ) -> Self {
+        // Infer config from provider_id or model
+        let mut config = if let Some(pid) = provider_id {
+            ProviderConfig::from_preset(pid)
+        } else if let Some(m) = model {
+            ProviderConfig::from_model(m)
+        } else {
+            ProviderConfig::from_preset("anthropic")
+        };
+
+        // Override default with custom base_url if provided
+        if let Some(url) = base_url.filter(|u| !u.is_empty()) {
+            config.base_url = url;
+        }
+
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: config.base_url.clone(),
+            provider_config: config,
+            openai_organization,
+            openai_project,
+            ollama_options: OllamaOptions::default(),
+        }
+    }
+
+    /// Send requests through a client from the shared HTTP factory
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Context size and keep-alive for Ollama requests
+    pub fn with_ollama_options(mut self, options: OllamaOptions) -> Self {
+        self.ollama_options = options;
+        self
+    }
+
+    /// Create the provider implementation for this client's API format
+    pub fn into_provider(self) -> Arc<dyn LlmProvider> {
+        match self.provider_config.api_format {
+            ApiFormat::Anthropic => Arc::new(AnthropicProvider::new(self)),
+            ApiFormat::OpenAI | ApiFormat::OpenAICompatible | ApiFormat::Minimax | ApiFormat::Azure => {
+                Arc::new(OpenAIProvider::new(self))
+            }
+            ApiFormat::OpenAIResponses => Arc::new(OpenAIResponsesProvider::new(self)),
+            ApiFormat::Google => Arc::new(GoogleProvider::new(self)),
+            ApiFormat::Bedrock => Arc::new(BedrockProvider::new(self)),
+            ApiFormat::Vertex => Arc::new(VertexProvider::new(self)),
+            ApiFormat::Ollama => Arc::new(OllamaProvider::new(self)),
+        }
+    }
+
+    /// Get API format
+    #[allow(dead_code)]
+    pub fn api_format(&self) -> &ApiFormat {
+        &self.provider_config.api_format
+    }
+
+    /// URL under the OpenAI-style `/v1` prefix, which some base URLs already include
+    fn v1_url(&self, path: &str) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        if base.ends_with("/v1") {
+            format!("{}/{}", base, path)
+        } else {
+            format!("{}/v1/{}", base, path)
+        }
+    }
+
+    /// URL of an Ollama native endpoint; base URLs set up for the OpenAI-compatible
+    /// API end in `/v1`
+    fn ollama_url(&self, path: &str) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        format!("{}/{}", base.strip_suffix("/v1").unwrap_or(base), path)
+    }
+
+    /// Azure OpenAI URL for a deployment. Resource endpoints get the deployment path and
+    /// an `api-version` (taken from the base URL if it has one); endpoints ending in
+    /// `/openai/v1` use the version-less v1 API, where the deployment goes in the body.
+    fn azure_url(&self, deployment: &str, path: &str) -> String {
+        let (base, query) = self.base_url.split_once('?').unwrap_or((&self.base_url, ""));
+        let base = base.trim_end_matches('/');
+        if base.ends_with("/openai/v1") {
+            return format!("{}/{}", base, path);
+        }
+
+        let resource = base.find("/openai").map_or(base, |i| &base[..i]);
+        let api_version = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("api-version="))
+            .unwrap_or(AZURE_API_VERSION);
+        format!(
+            "{}/openai/deployments/{}/{}?api-version={}",
+            resource, deployment, path, api_version
+        )
+    }
+
+    /// Build request headers
+    fn build_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+
+        match self.provider_config.auth_type {
+            AuthType::None | AuthType::AwsSigV4 | AuthType::OAuth => {
+                // No auth required, or added per request by the provider
+            }
+            AuthType::Bearer => {
+                if !self.api_key.is_empty() {
+                    headers.push(("Authorization".to_string(), format!("Bearer {}", self.api_key)));
+                }
+            }
+            AuthType::ApiKey => {
+                if !self.api_key.is_empty() {
+                    let header = match self.provider_config.api_format {
+                        ApiFormat::Azure => "api-key",
+                        _ => "x-api-key",
+                    };
+                    headers.push((header.to_string(), self.api_key.clone()));
+                }
+            }
+            AuthType::QueryParam => {
+                // Google takes the key in the x-goog-api-key header
+                if !self.api_key.is_empty() {
+                    headers.push(("x-goog-api-key".to_string(), self.api_key.clone()));
+                }
+            }
+        }
+
+        // Add optional OpenAI organization and project headers
+        if let Some(ref org) = self.openai_organization {
+            if !org.is_empty() {
+                headers.push(("OpenAI-Organization".to_string(), org.clone()));
+            }
+        }
+        if let Some(ref project) = self.openai_project {
+            if !project.is_empty() {
+                headers.push(("OpenAI-Project".to_string(), project.clone()));
+            }
+        }
+
+        headers
+    }
+
+    fn with_headers(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        for (key, value) in self.build_headers() {
+            request = request.header(&key, &value);
+        }
+        request
+    }
+
+    /// POST a JSON body and fail on non-success status
+    async fn post_json(
+        &self,
+        url: &str,
+        body: &serde_json::Value,
+        extra_headers: &[(&str, &str)],
+    ) -> Result<reqwest::Response, LLMError> {
+        let mut request = self.with_headers(self.client.post(url));
+        for (key, value) in extra_headers {
+            request = request.header(*key, *value);
+        }
+
+        let response = request.json(body).send().await?;
+        Self::check_status(response).await
+    }
+
+    /// Turn a non-success response into `LLMError::Status`
+    async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, LLMError> {
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(LLMError::Status {
+                status: status.as_u16(),
+                message,
+            });
+        }
+        Ok(response)
+    }
+
+    /// GET a JSON document with the provider's auth headers
+    async fn get_json(&self, url: &str, extra_headers: &[(&str, &str)]) -> Result<serde_json::Value, LLMError> {
+        let mut request = self.with_headers(self.client.get(url));
+        for (key, value) in extra_headers {
+            request = request.header(*key, *value);
+        }
+
+        let response = Self::check_status(request.send().await?).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Check if a local service is reachable
+    async fn probe_local_service(&self) -> bool {
+        // Try OpenAI models endpoint
+        let models_url = self.v1_url("models");
+        match self.client.get(&models_url).timeout(std::time::Duration::from_secs(5)).send().await {
+            Ok(resp) if resp.status().is_success() => return true,
+            _ => {}
+        }
+
+        // Try Ollama specific endpoint
+        let ollama_url = self.ollama_url("api/tags");
+        match self.client.get(&ollama_url).timeout(std::time::Duration::from_secs(5)).send().await {
+            Ok(resp) if resp.status().is_success() => return true,
+            _ => {}
+        }
+
+        false
+    }
+
+    /// Discover models from an OpenAI-style `/v1/models` or Ollama `/api/tags` endpoint
+    async fn discover_models(&self) -> Result<Vec<String>, LLMError> {
+        if let Ok(data) = self.get_json(&self.v1_url("models"), &[]).await {
+            if let Some(models) = data["data"].as_array() {
+                return Ok(models
+                    .iter()
+                    .filter_map(|m| m["id"].as_str().map(|s| s.to_string()))
+                    .collect());
+            }
+        }
+
+        self.ollama_models().await
+    }
+
+    /// Models pulled into an Ollama server, from `/api/tags`
+    async fn ollama_models(&self) -> Result<Vec<String>, LLMError> {
+        let data = self.get_json(&self.ollama_url("api/tags"), &[]).await?;
+        Ok(data["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m["name"].as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::anthropic::AnthropicStream;
+    use super::google::{to_google_body, GoogleStream};
+    use super::ollama::{to_ollama_body, OllamaStream};
+    use super::openai::{to_openai_body, OpenAIStream};
+    use super::openai_responses::{to_responses_body, ResponsesStream};
+    use super::sse::{parse_ndjson, parse_sse};
+    use super::*;
+    use crate::agent::message_builder::{ThinkingConfig, ToolChoice};
+    use crate::agent::ToolDefinition;
+
+    /// Each fixture is the same assistant turn in one provider's stream format:
+    /// a thought, "Let me check." and a `read_file` call
+    fn assert_fixture_turn(format: &str, events: &[StreamEvent], response: &LlmResponse) {
+        assert_eq!(
+            events,
+            [
+                StreamEvent::Thinking("Need the ".to_string()),
+                StreamEvent::Thinking("Need the README.".to_string()),
+                StreamEvent::Text("Let me ".to_string()),
+                StreamEvent::Text("Let me check.".to_string()),
+            ],
+            "{format}"
+        );
+        assert_eq!(response.text, "Let me check.", "{format}");
+        assert!(
+            matches!(&response.thinking[..], [ContentBlock::Thinking { thinking, .. }] if thinking == "Need the README."),
+            "{format}"
+        );
+
+        assert_eq!(response.tool_uses.len(), 1, "{format}");
+        assert_eq!(response.tool_uses[0].name, "read_file", "{format}");
+        assert_eq!(response.tool_uses[0].input, serde_json::json!({"path": "README.md"}), "{format}");
+
+        let usage = response.usage.as_ref().expect(format);
+        assert_eq!((usage.input_tokens, usage.output_tokens), (120, 30), "{format}");
+    }
+
+    #[test]
+    fn test_stream_fixtures() {
+        let (events, response) = parse_sse(include_str!("fixtures/anthropic.sse"), AnthropicStream::default());
+        let response = response.unwrap();
+        assert_fixture_turn("anthropic", &events, &response);
+        assert_eq!(response.tool_uses[0].id, "toolu_01");
+        assert!(matches!(
+            &response.thinking[0],
+            ContentBlock::Thinking { signature: Some(sig), .. } if sig == "sig_anthropic"
+        ));
+
+        let (events, response) = parse_sse(include_str!("fixtures/openai.sse"), OpenAIStream::default());
+        let response = response.unwrap();
+        assert_fixture_turn("openai", &events, &response);
+        assert_eq!(response.tool_uses[0].id, "call_01");
+
+        let (events, response) =
+            parse_sse(include_str!("fixtures/openai_responses.sse"), ResponsesStream::default());
+        let response = response.unwrap();
+        assert_fixture_turn("openai_responses", &events, &response);
+        assert_eq!(response.tool_uses[0].id, "call_01");
+
+        let (events, response) = parse_sse(include_str!("fixtures/google.sse"), GoogleStream::default());
+        let response = response.unwrap();
+        assert_fixture_turn("google", &events, &response);
+        assert!(response.tool_uses[0].id.starts_with("fc_"));
+        assert_eq!(response.tool_uses[0].thought_signature.as_deref(), Some("sig_google"));
+
+        let (events, response) = parse_ndjson(include_str!("fixtures/ollama.ndjson"), OllamaStream::default());
+        let response = response.unwrap();
+        assert_fixture_turn("ollama", &events, &response);
+        assert!(response.tool_uses[0].id.starts_with("call_"));
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        // Cut off before message_stop
+        let transcript = include_str!("fixtures/anthropic.sse");
+        let truncated = &transcript[..transcript.find("event: message_stop").unwrap()];
+        assert!(parse_sse(truncated, AnthropicStream::default()).1.is_err());
+
+        let error = r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(matches!(
+            parse_sse(error, AnthropicStream::default()).1,
+            Err(LLMError::Api(message)) if message == "Overloaded"
+        ));
+
+        let error = r#"data: {"error":{"message":"Invalid key","type":"invalid_request_error"}}"#;
+        assert!(matches!(parse_sse(error, OpenAIStream::default()).1, Err(LLMError::Api(_))));
+
+        let error = r#"data: {"type":"response.failed","response":{"error":{"message":"Quota"}}}"#;
+        assert!(matches!(parse_sse(error, ResponsesStream::default()).1, Err(LLMError::Api(_))));
+
+        let error = r#"{"error":"model requires more system memory than is available"}"#;
+        assert!(matches!(parse_ndjson(error, OllamaStream::default()).1, Err(LLMError::Api(_))));
+        // Cut off before the done line
+        let transcript = include_str!("fixtures/ollama.ndjson");
+        let truncated = &transcript[..transcript.find(r#""done":true"#).unwrap()];
+        assert!(parse_ndjson(truncated, OllamaStream::default()).1.is_err());
+    }
+
+    /// A tool round trip: user asks, assistant calls read_file, tool result comes back
+    fn tool_round_trip_request(model: &str) -> ClaudeApiRequest {
+        let mut request = ClaudeApiRequest::chat(
+            model,
+            1024,
+            Some(0.5),
+            vec![
+                ApiMessage {
+                    role: "user".to_string(),
+                    content: ApiContent::Text("Summarize the README".to_string()),
+                },
+                ApiMessage {
+                    role: "assistant".to_string(),
+                    content: ApiContent::Blocks(vec![
+                        serde_json::json!({"type": "text", "text": "Let me check."}),
+                        serde_json::json!({
+                            "type": "tool_use",
+                            "id": "call_01",
+                            "name": "read_file",
+                            "input": {"path": "README.md"},
+                            "thought_signature": "sig_google"
+                        }),
+                    ]),
+                },
+                ApiMessage {
+                    role: "user".to_string(),
+                    content: ApiContent::Blocks(vec![serde_json::json!({
+                        "type": "tool_result",
+                        "tool_use_id": "call_01",
+                        "content": "# Project"
+                    })]),
+                },
+            ],
+        );
+        request.system = "You are helpful.".to_string();
+        request.tools = vec![ToolDefinition {
+            name: "read_file".to_string(),
+            description: "Read a file".to_string(),
+            input_schema: serde_json::json!({"type": "object"}),
+        }];
+        request
+    }
+
+    #[test]
+    fn test_openai_body() {
+        let request = tool_round_trip_request("gpt-4o");
+        let body = to_openai_body(&request, &ApiFormat::OpenAI);
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"], r#"{"path":"README.md"}"#);
+        assert_eq!(messages[3]["role"], "tool");
+        assert_eq!(messages[3]["tool_call_id"], "call_01");
+        assert_eq!(body["max_completion_tokens"], 1024);
+        assert_eq!(body["tool_choice"], "auto");
+
+        let mut request = request;
+        request.tool_choice = Some(ToolChoice::None);
+        let body = to_openai_body(&request, &ApiFormat::OpenAICompatible);
+        assert_eq!(body["max_tokens"], 1024);
+        assert_eq!(body["tool_choice"], "none");
+    }
+
+    #[test]
+    fn test_responses_body() {
+        let body = to_responses_body(&tool_round_trip_request("gpt-5"));
+
+        let input = body["input"].as_array().unwrap();
+        assert_eq!(input[1]["content"], "Let me check.");
+        assert_eq!(input[2]["type"], "function_call");
+        assert_eq!(input[3]["type"], "function_call_output");
+        assert_eq!(input[3]["call_id"], "call_01");
+        assert_eq!(body["instructions"], "You are helpful.");
+        // Reasoning models take no temperature
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn test_google_body() {
+        let body = to_google_body(&tool_round_trip_request("gemini-2.5-pro"));
+
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(contents[1]["role"], "model");
+        let response = &contents[2]["parts"][0];
+        assert_eq!(response["functionResponse"]["name"], "read_file");
+        assert_eq!(response["thoughtSignature"], "sig_google");
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "You are helpful.");
+    }
+
+    #[test]
+    fn test_ollama_body() {
+        let request = tool_round_trip_request("qwen3:8b");
+        let options = OllamaOptions {
+            num_ctx: Some(32768),
+            keep_alive: Some("10m".to_string()),
+        };
+        let body = to_ollama_body(&request, &options);
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[2]["content"], "Let me check.");
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"], serde_json::json!({"path": "README.md"}));
+        assert_eq!(messages[3], serde_json::json!({"role": "tool", "content": "# Project", "tool_name": "read_file"}));
+        assert_eq!(body["options"], serde_json::json!({"num_predict": 1024, "temperature": 0.5, "num_ctx": 32768}));
+        assert_eq!(body["keep_alive"], "10m");
+        assert_eq!(body["tools"][0]["function"]["name"], "read_file");
+        assert!(body.get("think").is_none());
+
+        let mut request = request;
+        request.tool_choice = Some(ToolChoice::None);
+        let body = to_ollama_body(&request, &OllamaOptions::default());
+        assert!(body.get("tools").is_none());
+        assert!(body["options"].get("num_ctx").is_none());
+        assert!(body.get("keep_alive").is_none());
+    }
+
+    #[test]
+    fn test_response_schema_bodies() {
+        let schema = serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "files": {"type": "array", "items": {"type": "string"}},
+                "summary": {"type": "string", "description": "One line"}
+            },
+            "required": ["files", "summary"],
+            "additionalProperties": false
+        });
+        let mut request = tool_round_trip_request("gpt-4o");
+        request.response_schema = Some(schema.clone());
+
+        let body = to_openai_body(&request, &ApiFormat::OpenAI);
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["schema"], schema);
+
+        let body = to_responses_body(&request);
+        assert_eq!(body["text"]["format"]["type"], "json_schema");
+        assert_eq!(body["text"]["format"]["schema"], schema);
+
+        let body = to_ollama_body(&request, &OllamaOptions::default());
+        assert_eq!(body["format"], schema);
+
+        // Gemini gets its OpenAPI subset and no tools
+        let body = to_google_body(&request);
+        let config = &body["generationConfig"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert!(config["responseSchema"].get("$schema").is_none());
+        assert!(config["responseSchema"].get("additionalProperties").is_none());
+        assert_eq!(config["responseSchema"]["properties"]["files"]["items"]["type"], "string");
+        assert_eq!(config["responseSchema"]["required"], serde_json::json!(["files", "summary"]));
+        assert!(body.get("tools").is_none());
+
+        // Anthropic is forced to call a tool taking the schema, without thinking
+        request.thinking = Some(ThinkingConfig::enabled(2048));
+        request.prompt_caching = true;
+        let body = request.to_anthropic_body();
+        let tools = body["tools"].as_array().unwrap();
+        assert_eq!(tools.last().unwrap()["name"], "structured_output");
+        assert_eq!(tools.last().unwrap()["input_schema"], schema);
+        assert_eq!(body["tool_choice"], serde_json::json!({"type": "tool", "name": "structured_output"}));
+        assert!(body.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_provider_from_model() {
+        // Claude model
+        let config = ProviderConfig::from_model("claude-sonnet-4-5-20250929");
+        assert_eq!(config.id, "anthropic");
+        assert_eq!(config.api_format, ApiFormat::Anthropic);
+
+        // Ollama model
+        let config = ProviderConfig::from_model("llama3.3:latest");
+        assert_eq!(config.id, "ollama");
+        assert_eq!(config.api_format, ApiFormat::Ollama);
+
+        // OpenRouter model
+        let config = ProviderConfig::from_model("anthropic/claude-3.5-sonnet");
+        assert_eq!(config.id, "openrouter");
+        assert_eq!(config.api_format, ApiFormat::OpenAICompatible);
+
+        // GPT-4 model (uses Chat Completions API)
+        let config = ProviderConfig::from_model("gpt-4o");
+        assert_eq!(config.id, "openai");
+        assert_eq!(config.api_format, ApiFormat::OpenAI);
+
+        // GPT-5 model (uses Responses API)
+        let config = ProviderConfig::from_model("gpt-5");
+        assert_eq!(config.id, "openai");
+        assert_eq!(config.api_format, ApiFormat::OpenAIResponses);
+
+        // GPT-5 mini model (uses Responses API)
+        let config = ProviderConfig::from_model("gpt-5-mini");
+        assert_eq!(config.id, "openai");
+        assert_eq!(config.api_format, ApiFormat::OpenAIResponses);
+
+        // GPT-5 nano model (uses Responses API)
+        let config = ProviderConfig::from_model("gpt-5-nano");
+        assert_eq!(config.id, "openai");
+        assert_eq!(config.api_format, ApiFormat::OpenAIResponses);
+    }
+}
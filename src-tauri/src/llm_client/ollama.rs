@@ -0,0 +1,396 @@
+use super::catalog::catalog;
+use super::sse::{read_ndjson, JsonLines, ResponseAccumulator, SseParser};
+use super::{LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::{ApiContent, ClaudeApiRequest, ToolChoice};
+use crate::agent::{TokenUsage, ToolUse};
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Ollama-specific request options, saved with the settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OllamaOptions {
+    /// Context window to load the model with; Ollama's default is small enough
+    /// to cut off long agent prompts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// How long the model stays loaded after a request, e.g. "10m" ("-1m" keeps it loaded)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+}
+
+/// One line of `/api/pull` progress
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Size of the layer being downloaded, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+}
+
+/// Ollama's native `/api/chat` API, streaming newline-delimited JSON
+pub struct OllamaProvider {
+    client: LLMClient,
+}
+
+impl OllamaProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self { client }
+    }
+
+    /// Download a model, forwarding progress to `tx` until the pull succeeds
+    pub async fn pull_model(&self, model: &str, tx: mpsc::Sender<PullProgress>) -> Result<(), LLMError> {
+        let body = serde_json::json!({ "model": model, "stream": true });
+        let response = self.client.post_json(&self.client.ollama_url("api/pull"), &body, &[]).await?;
+
+        let mut stream = response.bytes_stream();
+        let mut lines = JsonLines::default();
+        let mut succeeded = false;
+        loop {
+            let chunk = stream.next().await.transpose()?;
+            // A final newline flushes a last line the server didn't end
+            let batch = lines.push(chunk.as_deref().unwrap_or(b"\n"));
+            for line in batch {
+                let event: serde_json::Value =
+                    serde_json::from_str(&line).map_err(|e| LLMError::Parse(e.to_string()))?;
+                if let Some(error) = event.get("error").and_then(|e| e.as_str()) {
+                    return Err(LLMError::Api(error.to_string()));
+                }
+                let progress: PullProgress =
+                    serde_json::from_value(event).map_err(|e| LLMError::Parse(e.to_string()))?;
+                succeeded = progress.status == "success";
+                let _ = tx.send(progress).await;
+            }
+            if chunk.is_none() {
+                break;
+            }
+        }
+
+        if succeeded {
+            Ok(())
+        } else {
+            Err(LLMError::Parse(format!("Pull of {} ended before it succeeded", model)))
+        }
+    }
+}
+
+impl LlmProvider for OllamaProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            let url = self.client.ollama_url("api/chat");
+            let mut body = to_ollama_body(request, &self.client.ollama_options);
+            let response = match self.client.post_json(&url, &body, &[]).await {
+                // Models without a thinking mode reject `think` rather than ignore it
+                Err(LLMError::Status { status: 400, message }) if message.contains("does not support thinking") => {
+                    if let Some(body) = body.as_object_mut() {
+                        body.remove("think");
+                    }
+                    self.client.post_json(&url, &body, &[]).await?
+                }
+                response => response?,
+            };
+            read_ndjson(response, OllamaStream::default(), &tx).await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(self.client.ollama_models())
+    }
+
+    fn check_connection<'a>(&'a self, model: &'a str) -> BoxFuture<'a, Result<(), LLMError>> {
+        Box::pin(async move {
+            let models = self.client.ollama_models().await.map_err(|_| {
+                LLMError::Api("Cannot connect to Ollama, please ensure it is running".to_string())
+            })?;
+
+            // Tags without a version are pulled as `:latest`
+            let pulled = models
+                .iter()
+                .any(|m| m == model || m.strip_suffix(":latest") == Some(model));
+            if pulled {
+                Ok(())
+            } else {
+                Err(LLMError::Api(format!("Model {} has not been pulled", model)))
+            }
+        })
+    }
+}
+
+/// Convert a request in the agent's (Anthropic) format to an `/api/chat` body
+pub fn to_ollama_body(request: &ClaudeApiRequest, options: &OllamaOptions) -> serde_json::Value {
+    // Tool results carry the name of the tool, looked up by tool_use id
+    let mut tool_names: HashMap<&str, &serde_json::Value> = HashMap::new();
+    for msg in &request.messages {
+        if let ApiContent::Blocks(blocks) = &msg.content {
+            for block in blocks.iter().filter(|b| b["type"] == "tool_use") {
+                if let Some(id) = block["id"].as_str() {
+                    tool_names.insert(id, &block["name"]);
+                }
+            }
+        }
+    }
+
+    let mut messages: Vec<serde_json::Value> = Vec::new();
+    if !request.system.is_empty() {
+        messages.push(serde_json::json!({ "role": "system", "content": request.system }));
+    }
+
+    for msg in &request.messages {
+        let blocks = match &msg.content {
+            ApiContent::Text(text) => {
+                messages.push(serde_json::json!({ "role": msg.role, "content": text }));
+                continue;
+            }
+            ApiContent::Blocks(blocks) => blocks,
+        };
+
+        let mut text_parts: Vec<&str> = Vec::new();
+        let mut thinking_parts: Vec<&str> = Vec::new();
+        let mut tool_calls: Vec<serde_json::Value> = Vec::new();
+
+        for block in blocks {
+            match block["type"].as_str().unwrap_or("") {
+                "text" => text_parts.extend(block["text"].as_str()),
+                "thinking" => thinking_parts.extend(block["thinking"].as_str()),
+                // Arguments are sent as an object, not a JSON string
+                "tool_use" => tool_calls.push(serde_json::json!({
+                    "function": { "name": block["name"], "arguments": block["input"] }
+                })),
+                "tool_result" => {
+                    let tool_use_id = block["tool_use_id"].as_str().unwrap_or("");
+                    messages.push(serde_json::json!({
+                        "role": "tool",
+                        "content": tool_result_text(&block["content"]),
+                        "tool_name": tool_names.get(tool_use_id).copied().unwrap_or(&block["tool_use_id"]),
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        if text_parts.is_empty() && tool_calls.is_empty() {
+            continue;
+        }
+        let mut message = serde_json::json!({ "role": msg.role, "content": text_parts.join("\n") });
+        if !thinking_parts.is_empty() {
+            message["thinking"] = serde_json::json!(thinking_parts.join("\n"));
+        }
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = serde_json::json!(tool_calls);
+        }
+        messages.push(message);
+    }
+
+    let mut model_options = serde_json::json!({ "num_predict": request.max_tokens });
+    if let Some(temperature) = catalog().info(&request.model).temperature(request.temperature) {
+        model_options["temperature"] = serde_json::json!(temperature);
+    }
+    if let Some(num_ctx) = options.num_ctx {
+        model_options["num_ctx"] = serde_json::json!(num_ctx);
+    }
+
+    let mut body = serde_json::json!({
+        "model": request.model,
+        "stream": true,
+        "messages": messages,
+        "options": model_options,
+    });
+
+    if let Some(keep_alive) = &options.keep_alive {
+        body["keep_alive"] = serde_json::json!(keep_alive);
+    }
+    if request.thinking.is_some() {
+        body["think"] = serde_json::json!(true);
+    }
+
+    // Ollama has no tool_choice; leaving the tools out has the same effect
+    if !request.tools.is_empty() && request.tool_choice != Some(ToolChoice::None) {
+        let tools: Vec<serde_json::Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.input_schema
+                    }
+                })
+            })
+            .collect();
+        body["tools"] = serde_json::json!(tools);
+    }
+
+    if let Some(schema) = &request.response_schema {
+        body["format"] = schema.clone();
+    }
+
+    body
+}
+
+/// Tool output as plain text; Ollama takes a string where Anthropic allows blocks
+fn tool_result_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Parser for `/api/chat` stream lines
+#[derive(Debug, Default)]
+pub struct OllamaStream {
+    acc: ResponseAccumulator,
+    done: bool,
+    error: Option<String>,
+}
+
+impl SseParser for OllamaStream {
+    fn on_event(&mut self, event: &serde_json::Value, out: &mut Vec<StreamEvent>) {
+        if let Some(error) = event.get("error").and_then(|e| e.as_str()) {
+            self.error = Some(error.to_string());
+            return;
+        }
+
+        if let Some(message) = event.get("message") {
+            if let Some(thinking) = message["thinking"].as_str() {
+                self.acc.push_thinking(thinking, out);
+            }
+            if let Some(content) = message["content"].as_str() {
+                self.acc.push_text(content, out);
+            }
+            // Calls arrive whole, usually in a single line
+            for call in message["tool_calls"].as_array().into_iter().flatten() {
+                let function = &call["function"];
+                self.acc.tool_uses.push(ToolUse {
+                    // Ollama doesn't assign call ids
+                    id: format!("call_{}", uuid::Uuid::new_v4()),
+                    name: function["name"].as_str().unwrap_or("").to_string(),
+                    input: match &function["arguments"] {
+                        serde_json::Value::String(args) => serde_json::from_str(args).unwrap_or(serde_json::json!({})),
+                        serde_json::Value::Null => serde_json::json!({}),
+                        args => args.clone(),
+                    },
+                    thought_signature: None,
+                });
+            }
+        }
+
+        if event["done"].as_bool().unwrap_or(false) {
+            self.done = true;
+            let get = |key: &str| event[key].as_u64().unwrap_or(0);
+            self.acc.usage = Some(TokenUsage {
+                input_tokens: get("prompt_eval_count"),
+                output_tokens: get("eval_count"),
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: 0,
+            });
+        }
+    }
+
+    fn finish(self) -> Result<LlmResponse, LLMError> {
+        if let Some(error) = self.error {
+            return Err(LLMError::Api(error));
+        }
+        if !self.done {
+            return Err(LLMError::Parse("No response received".to_string()));
+        }
+        Ok(self.acc.into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock::{MockResponse, MockServer, MockTurn};
+    use super::super::ApiFormat;
+    use super::*;
+
+    fn ollama_client(base_url: &str) -> LLMClient {
+        LLMClient::new(String::new(), Some(base_url.to_string()), Some("ollama"), None)
+    }
+
+    #[tokio::test]
+    async fn test_pull_progress() {
+        let progress = [
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a0746a1ec1a","total":4661211424,"completed":1048576}"#,
+            r#"{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a0746a1ec1a","total":4661211424,"completed":4661211424}"#,
+            r#"{"status":"success"}"#,
+        ];
+        let server = MockServer::scripted(vec![
+            MockResponse::Stream(progress.join("\n")),
+            MockResponse::Stream(r#"{"error":"pull model manifest: file does not exist"}"#.to_string()),
+        ])
+        .await;
+        let provider = OllamaProvider::new(ollama_client(server.base_url()));
+
+        let (tx, mut rx) = mpsc::channel(10);
+        provider.pull_model("qwen3:8b", tx).await.unwrap();
+        let mut updates = Vec::new();
+        while let Some(update) = rx.recv().await {
+            updates.push(update);
+        }
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[1].completed, Some(1048576));
+        assert_eq!(updates[1].total, Some(4661211424));
+        assert_eq!(updates[3].status, "success");
+
+        let (tx, _rx) = mpsc::channel(10);
+        assert!(matches!(provider.pull_model("nope", tx).await, Err(LLMError::Api(_))));
+
+        let exchanges = server.exchanges();
+        assert_eq!(exchanges[0].path, "/api/pull");
+        assert_eq!(exchanges[0].request, serde_json::json!({"model": "qwen3:8b", "stream": true}));
+    }
+
+    #[tokio::test]
+    async fn test_native_endpoint() {
+        let server = MockServer::scripted(vec![
+            MockResponse::Status(400, r#"{"error":"\"gemma3:4b\" does not support thinking"}"#.to_string()),
+            MockTurn::text("Hi!").response(&ApiFormat::Ollama),
+            MockResponse::Stream(r#"{"models":[{"name":"gemma3:4b"},{"name":"qwen3:latest"}]}"#.to_string()),
+        ])
+        .await;
+        // Base URLs left over from the OpenAI-compatible preset still reach /api/chat
+        let client = ollama_client(&format!("{}/v1", server.base_url())).with_ollama_options(OllamaOptions {
+            num_ctx: Some(16384),
+            keep_alive: None,
+        });
+        let provider = client.into_provider();
+
+        let mut request = ClaudeApiRequest::chat("gemma3:4b", 100, None, vec![super::super::ping_message()]);
+        request.thinking = Some(crate::agent::message_builder::ThinkingConfig::enabled(2048));
+        let response = provider.complete(&request).await.unwrap();
+        assert_eq!(response.text, "Hi!");
+
+        // Retried without thinking after the model refused it
+        let exchanges = server.exchanges();
+        assert_eq!(exchanges[0].path, "/api/chat");
+        assert_eq!(exchanges[0].request["think"], true);
+        assert!(exchanges[1].request.get("think").is_none());
+        assert_eq!(exchanges[1].request["options"]["num_ctx"], 16384);
+
+        assert!(provider.check_connection("qwen3").await.is_ok());
+        assert_eq!(server.exchanges()[2].path, "/api/tags");
+    }
+}
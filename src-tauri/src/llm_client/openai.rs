@@ -0,0 +1,343 @@
+use super::sse::{read_sse, ResponseAccumulator, SseParser};
+use super::catalog::catalog;
+use super::{ApiFormat, AuthType, LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::{ApiContent, ClaudeApiRequest, ToolChoice};
+use crate::agent::TokenUsage;
+use futures::future::BoxFuture;
+use std::collections::BTreeMap;
+use tokio::sync::mpsc;
+
+/// OpenAI Chat Completions API, also spoken by OpenAI-compatible services and Minimax
+pub struct OpenAIProvider {
+    client: LLMClient,
+}
+
+impl OpenAIProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self { client }
+    }
+
+    fn endpoint(&self, model: &str) -> String {
+        match self.client.provider_config.api_format {
+            ApiFormat::Minimax => self.client.v1_url("text/chatcompletion_v2"),
+            ApiFormat::Azure => self.client.azure_url(model, "chat/completions"),
+            _ => self.client.v1_url("chat/completions"),
+        }
+    }
+}
+
+impl LlmProvider for OpenAIProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            let body = to_openai_body(request, &self.client.provider_config.api_format);
+            let response = self.client.post_json(&self.endpoint(&request.model), &body, &[]).await?;
+            read_sse(response, OpenAIStream::default(), &tx).await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(async move {
+            // Only the v1 API lists models; deployment names come from the Azure portal
+            if self.client.provider_config.api_format == ApiFormat::Azure
+                && !self.client.base_url.trim_end_matches('/').ends_with("/openai/v1")
+            {
+                return Err(LLMError::Api(
+                    "Azure OpenAI deployments can't be listed; enter the deployment name as the model".to_string(),
+                ));
+            }
+            self.client.discover_models().await
+        })
+    }
+
+    fn check_connection<'a>(&'a self, model: &'a str) -> BoxFuture<'a, Result<(), LLMError>> {
+        Box::pin(async move {
+            // Local services only need to be running
+            if self.client.provider_config.auth_type == AuthType::None {
+                return if self.client.probe_local_service().await {
+                    Ok(())
+                } else {
+                    Err(LLMError::Api(
+                        "Cannot connect to local service, please ensure it is running".to_string(),
+                    ))
+                };
+            }
+
+            let request = ClaudeApiRequest::chat(model, 10, None, vec![super::ping_message()]);
+            match self.complete(&request).await {
+                Ok(_) => Ok(()),
+                // Some compatible services reject tiny requests but are still usable
+                Err(e) if self.client.provider_config.api_format == ApiFormat::OpenAICompatible => {
+                    if self.client.probe_local_service().await {
+                        Ok(())
+                    } else {
+                        Err(e)
+                    }
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+}
+
+/// Convert a request in the agent's (Anthropic) format to a Chat Completions body
+pub fn to_openai_body(request: &ClaudeApiRequest, api_format: &ApiFormat) -> serde_json::Value {
+    // Build messages, including system prompt
+    let mut messages: Vec<serde_json::Value> = Vec::new();
+
+    // Add system message
+    if !request.system.is_empty() {
+        messages.push(serde_json::json!({
+            "role": "system",
+            "content": request.system
+        }));
+    }
+
+    // Compatible providers (e.g. DeepSeek) want reasoning back only for the tool calls
+    // of the current turn, i.e. after the latest plain user message
+    let current_turn_start = request
+        .messages
+        .iter()
+        .rposition(|m| m.role == "user" && matches!(m.content, ApiContent::Text(_)))
+        .unwrap_or(0);
+    let send_reasoning = *api_format == ApiFormat::OpenAICompatible;
+
+    // Convert conversation messages
+    for (index, msg) in request.messages.iter().enumerate() {
+        let role = &msg.role;
+
+        match &msg.content {
+            ApiContent::Text(text) => {
+                messages.push(serde_json::json!({
+                    "role": role,
+                    "content": text
+                }));
+            }
+            ApiContent::Blocks(blocks) => {
+                // Handle content blocks (text, thinking, tool_use, tool_result)
+                let mut text_parts: Vec<String> = Vec::new();
+                let mut reasoning_parts: Vec<String> = Vec::new();
+                let mut tool_calls: Vec<serde_json::Value> = Vec::new();
+
+                for block in blocks {
+                    let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
+
+                    match block_type {
+                        "text" => {
+                            if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
+                                text_parts.push(text.to_string());
+                            }
+                        }
+                        "thinking" => {
+                            if let Some(thinking) = block.get("thinking").and_then(|v| v.as_str()) {
+                                reasoning_parts.push(thinking.to_string());
+                            }
+                        }
+                        "tool_use" => {
+                            tool_calls.push(serde_json::json!({
+                                "id": block.get("id"),
+                                "type": "function",
+                                "function": {
+                                    "name": block.get("name"),
+                                    "arguments": serde_json::to_string(block.get("input").unwrap_or(&serde_json::json!({}))).unwrap_or_default()
+                                }
+                            }));
+                        }
+                        "tool_result" => {
+                            // OpenAI uses tool role to represent tool results
+                            messages.push(serde_json::json!({
+                                "role": "tool",
+                                "tool_call_id": block.get("tool_use_id"),
+                                "content": block.get("content")
+                            }));
+                        }
+                        _ => {}
+                    }
+                }
+
+                // If there's text content
+                if !text_parts.is_empty() {
+                    let mut msg_obj = serde_json::json!({
+                        "role": role,
+                        "content": text_parts.join("\n")
+                    });
+
+                    // If there are tool_calls
+                    if !tool_calls.is_empty() {
+                        msg_obj["tool_calls"] = serde_json::json!(tool_calls);
+                    }
+
+                    messages.push(msg_obj);
+                } else if !tool_calls.is_empty() {
+                    // Only tool_calls, no text
+                    messages.push(serde_json::json!({
+                        "role": role,
+                        "content": serde_json::Value::Null,
+                        "tool_calls": tool_calls
+                    }));
+                }
+
+                if send_reasoning && index > current_turn_start && !reasoning_parts.is_empty() {
+                    if let Some(last) = messages.last_mut().filter(|m| m.get("tool_calls").is_some()) {
+                        last["reasoning_content"] = serde_json::json!(reasoning_parts.join("\n"));
+                    }
+                }
+            }
+        }
+    }
+
+    // Convert tools definition
+    let tools: Vec<serde_json::Value> = request.tools.iter().map(|tool| {
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.input_schema
+            }
+        })
+    }).collect();
+
+    let mut body = serde_json::json!({
+        "model": request.model,
+        "stream": true,
+        "messages": messages
+    });
+
+    let model = catalog().info(&request.model);
+
+    if matches!(api_format, ApiFormat::OpenAI | ApiFormat::Azure) {
+        // Use correct max tokens parameter based on model
+        if model.legacy_max_tokens {
+            body["max_tokens"] = serde_json::json!(request.max_tokens);
+        } else {
+            body["max_completion_tokens"] = serde_json::json!(request.max_tokens);
+        }
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+    } else {
+        // Other OpenAI-compatible APIs use max_tokens
+        body["max_tokens"] = serde_json::json!(request.max_tokens);
+    }
+
+    // Reasoning models (o1, o3, gpt-5) don't support custom temperature
+    if model.supports_temperature() {
+        if let Some(temp) = model.temperature(request.temperature) {
+            body["temperature"] = serde_json::json!(temp);
+        }
+    } else if let (true, Some(thinking)) = (model.reasoning, &request.thinking) {
+        body["reasoning_effort"] = serde_json::json!(thinking.reasoning_effort());
+    }
+
+    if !tools.is_empty() {
+        body["tools"] = serde_json::json!(tools);
+        body["tool_choice"] = match request.tool_choice {
+            Some(ToolChoice::None) => serde_json::json!("none"),
+            _ => serde_json::json!("auto"),
+        };
+    }
+
+    if let Some(schema) = &request.response_schema {
+        body["response_format"] = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": schema }
+        });
+    }
+
+    body
+}
+
+/// Parser for Chat Completions `chat.completion.chunk` events
+#[derive(Debug, Default)]
+pub struct OpenAIStream {
+    acc: ResponseAccumulator,
+    /// Id, name and argument JSON of tool calls by index
+    tool_calls: BTreeMap<i64, (String, String, String)>,
+    error: Option<String>,
+}
+
+impl SseParser for OpenAIStream {
+    fn on_event(&mut self, event: &serde_json::Value, out: &mut Vec<StreamEvent>) {
+        if let Some(message) = event.get("error").and_then(|e| e.get("message")).and_then(|m| m.as_str()) {
+            self.error = Some(message.to_string());
+            return;
+        }
+
+        if let Some(usage) = event.get("usage").filter(|u| u.is_object()) {
+            let get = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+            let cached = usage
+                .pointer("/prompt_tokens_details/cached_tokens")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+            // prompt_tokens includes cached tokens; TokenUsage counts them separately
+            self.acc.usage = Some(TokenUsage {
+                input_tokens: get("prompt_tokens").saturating_sub(cached),
+                output_tokens: get("completion_tokens"),
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: cached,
+            });
+        }
+
+        let Some(choices) = event.get("choices").and_then(|v| v.as_array()) else {
+            return;
+        };
+
+        // Azure stops the stream this way when its content filter blocks the output
+        if choices.iter().any(|c| c["finish_reason"] == "content_filter") {
+            self.error = Some("Response blocked by the provider's content filter".to_string());
+        }
+
+        for delta in choices.iter().filter_map(|c| c.get("delta")) {
+            // Reasoning from OpenAI-compatible providers (DeepSeek, OpenRouter, vLLM)
+            if let Some(reasoning) = delta
+                .get("reasoning_content")
+                .or_else(|| delta.get("reasoning"))
+                .and_then(|v| v.as_str())
+            {
+                self.acc.push_thinking(reasoning, out);
+            }
+
+            if let Some(content) = delta.get("content").and_then(|v| v.as_str()) {
+                self.acc.push_text(content, out);
+            }
+
+            if let Some(tcs) = delta.get("tool_calls").and_then(|v| v.as_array()) {
+                for tc in tcs {
+                    let index = tc.get("index").and_then(|v| v.as_i64()).unwrap_or(0);
+                    let entry = self.tool_calls.entry(index).or_default();
+
+                    if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
+                        entry.0 = id.to_string();
+                    }
+                    if let Some(func) = tc.get("function") {
+                        if let Some(name) = func.get("name").and_then(|v| v.as_str()) {
+                            entry.1 = name.to_string();
+                        }
+                        if let Some(args) = func.get("arguments").and_then(|v| v.as_str()) {
+                            entry.2.push_str(args);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> Result<LlmResponse, LLMError> {
+        if let Some(error) = self.error {
+            return Err(LLMError::Api(error));
+        }
+
+        for (id, name, args) in std::mem::take(&mut self.tool_calls).into_values() {
+            if !id.is_empty() && !name.is_empty() {
+                self.acc.push_tool_use(id, name, &args, None);
+            }
+        }
+        Ok(self.acc.into_response())
+    }
+}
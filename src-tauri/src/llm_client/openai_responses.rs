@@ -0,0 +1,230 @@
+use super::sse::{read_sse, ResponseAccumulator, SseParser};
+use super::catalog::catalog;
+use super::{LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::{ApiContent, ClaudeApiRequest, ToolChoice};
+use crate::agent::TokenUsage;
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+
+/// OpenAI Responses API, used by the GPT-5 series
+pub struct OpenAIResponsesProvider {
+    client: LLMClient,
+}
+
+impl OpenAIResponsesProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self { client }
+    }
+}
+
+impl LlmProvider for OpenAIResponsesProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            let body = to_responses_body(request);
+            let response = self.client.post_json(&self.client.v1_url("responses"), &body, &[]).await?;
+            read_sse(response, ResponsesStream::default(), &tx).await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(self.client.discover_models())
+    }
+}
+
+/// Convert a request in the agent's (Anthropic) format to a Responses API body
+pub fn to_responses_body(request: &ClaudeApiRequest) -> serde_json::Value {
+    let mut input: Vec<serde_json::Value> = Vec::new();
+
+    for msg in &request.messages {
+        match &msg.content {
+            ApiContent::Text(text) => {
+                input.push(serde_json::json!({ "role": msg.role, "content": text }));
+            }
+            ApiContent::Blocks(blocks) => {
+                let mut text_parts: Vec<&str> = Vec::new();
+                let mut items: Vec<serde_json::Value> = Vec::new();
+
+                for block in blocks {
+                    match block.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+                        "text" => {
+                            if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
+                                text_parts.push(text);
+                            }
+                        }
+                        "tool_use" => {
+                            items.push(serde_json::json!({
+                                "type": "function_call",
+                                "call_id": block.get("id"),
+                                "name": block.get("name"),
+                                "arguments": serde_json::to_string(block.get("input").unwrap_or(&serde_json::json!({}))).unwrap_or_default()
+                            }));
+                        }
+                        "tool_result" => {
+                            items.push(serde_json::json!({
+                                "type": "function_call_output",
+                                "call_id": block.get("tool_use_id"),
+                                "output": block.get("content")
+                            }));
+                        }
+                        // Reasoning items can't be replayed without their encrypted ids
+                        _ => {}
+                    }
+                }
+
+                // Text precedes the calls it introduced
+                if !text_parts.is_empty() {
+                    input.push(serde_json::json!({ "role": msg.role, "content": text_parts.join("\n") }));
+                }
+                input.extend(items);
+            }
+        }
+    }
+
+    let mut body = serde_json::json!({
+        "model": request.model,
+        "input": input,
+        "max_output_tokens": request.max_tokens,
+        "stream": true
+    });
+
+    if !request.system.is_empty() {
+        body["instructions"] = serde_json::json!(request.system);
+    }
+
+    let model = catalog().info(&request.model);
+    if model.supports_temperature() {
+        if let Some(temp) = model.temperature(request.temperature) {
+            body["temperature"] = serde_json::json!(temp);
+        }
+    } else if let (true, Some(thinking)) = (model.reasoning, &request.thinking) {
+        body["reasoning"] = serde_json::json!({
+            "effort": thinking.reasoning_effort(),
+            "summary": "auto"
+        });
+    }
+
+    if !request.tools.is_empty() {
+        body["tools"] = serde_json::json!(request
+            .tools
+            .iter()
+            .map(|tool| serde_json::json!({
+                "type": "function",
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.input_schema
+            }))
+            .collect::<Vec<_>>());
+        body["tool_choice"] = match request.tool_choice {
+            Some(ToolChoice::None) => serde_json::json!("none"),
+            _ => serde_json::json!("auto"),
+        };
+    }
+
+    if let Some(schema) = &request.response_schema {
+        body["text"] = serde_json::json!({
+            "format": { "type": "json_schema", "name": "response", "schema": schema }
+        });
+    }
+
+    body
+}
+
+/// Parser for Responses API `response.*` stream events
+#[derive(Debug, Default)]
+pub struct ResponsesStream {
+    acc: ResponseAccumulator,
+    completed: bool,
+    error: Option<String>,
+}
+
+impl SseParser for ResponsesStream {
+    fn on_event(&mut self, event: &serde_json::Value, out: &mut Vec<StreamEvent>) {
+        let field = |key: &str| event.get(key).and_then(|v| v.as_str());
+
+        match field("type").unwrap_or("") {
+            "response.output_text.delta" => {
+                if let Some(delta) = field("delta") {
+                    self.acc.push_text(delta, out);
+                }
+            }
+            "response.reasoning_summary_text.delta" => {
+                if let Some(delta) = field("delta") {
+                    self.acc.push_thinking(delta, out);
+                }
+            }
+            "response.output_item.done" => {
+                let item = &event["item"];
+                if item["type"].as_str() == Some("function_call") {
+                    let call_id = item["call_id"].as_str().unwrap_or("").to_string();
+                    let name = item["name"].as_str().unwrap_or("").to_string();
+                    self.acc.push_tool_use(call_id, name, item["arguments"].as_str().unwrap_or("{}"), None);
+                }
+            }
+            "response.completed" => {
+                self.completed = true;
+                let response = &event["response"];
+
+                // Fall back to the final output if no deltas arrived
+                if self.acc.text.is_empty() {
+                    if let Some(text) = output_text(response) {
+                        self.acc.push_text(&text, out);
+                    }
+                }
+
+                let usage = &response["usage"];
+                if usage.is_object() {
+                    let get = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+                    let cached = usage
+                        .pointer("/input_tokens_details/cached_tokens")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(0);
+                    self.acc.usage = Some(TokenUsage {
+                        input_tokens: get("input_tokens").saturating_sub(cached),
+                        output_tokens: get("output_tokens"),
+                        cache_creation_input_tokens: 0,
+                        cache_read_input_tokens: cached,
+                    });
+                }
+            }
+            "response.failed" | "error" => {
+                let message = event
+                    .pointer("/response/error/message")
+                    .or_else(|| event.get("message"))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("Response failed");
+                self.error = Some(message.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> Result<LlmResponse, LLMError> {
+        if let Some(error) = self.error {
+            return Err(LLMError::Api(error));
+        }
+        if !self.completed {
+            return Err(LLMError::Parse("No response received".to_string()));
+        }
+        Ok(self.acc.into_response())
+    }
+}
+
+/// Text of the first message in a Responses API `output`
+fn output_text(response: &serde_json::Value) -> Option<String> {
+    // Response format: { output: [{ type: "message", content: [{ type: "output_text", text: "..." }] }] }
+    response["output"].as_array()?
+        .iter()
+        .find(|item| item["type"].as_str() == Some("message"))
+        .and_then(|msg| msg["content"].as_array())
+        .and_then(|content| content.iter().find(|c| c["type"].as_str() == Some("output_text")))
+        .and_then(|c| c["text"].as_str())
+        .map(|s| s.to_string())
+}
@@ -0,0 +1,60 @@
+//! Fallback chains and per-job model routing
+
+use super::LlmProvider;
+use crate::agent::message_builder::ClaudeApiRequest;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A provider/model pair as stored in settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelTarget {
+    /// Provider preset id; the API key comes from the settings' `provider_keys`
+    pub provider: String,
+    pub model: String,
+    /// Overrides the preset's base URL when not empty
+    #[serde(default)]
+    pub base_url: String,
+}
+
+/// Jobs that can be sent to a model other than the primary one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutePurpose {
+    /// Wrap-up summaries written when a run hits its turn limit
+    Summary,
+    /// Sub-agents started with the `delegate` tool
+    SubAgent,
+    /// Titles generated for new conversations and tasks
+    Title,
+}
+
+/// Send one kind of job to a specific model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRule {
+    pub purpose: RoutePurpose,
+    #[serde(flatten)]
+    pub target: ModelTarget,
+}
+
+/// A provider together with the model to request from it
+#[derive(Clone)]
+pub struct ModelRoute {
+    pub provider: Arc<dyn LlmProvider>,
+    pub model: String,
+}
+
+impl ModelRoute {
+    pub fn new(provider: Arc<dyn LlmProvider>, model: String) -> Self {
+        Self { provider, model }
+    }
+
+    /// Provider and model, as shown to the user
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.provider.config().id, self.model)
+    }
+
+    /// Copy of a request built for another model, adapted to this route
+    pub fn retarget(&self, request: &ClaudeApiRequest) -> ClaudeApiRequest {
+        request.retarget(&self.model, &self.provider.config().api_format)
+    }
+}
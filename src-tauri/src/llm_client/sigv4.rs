@@ -0,0 +1,180 @@
+//! AWS Signature Version 4 request signing, as used by Bedrock
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use ring::{digest, hmac};
+
+/// Long-term or temporary AWS credentials
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// Parse `ACCESS_KEY_ID:SECRET_ACCESS_KEY[:SESSION_TOKEN]`
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().splitn(3, ':');
+        let access_key_id = parts.next().filter(|s| !s.is_empty())?;
+        let secret_access_key = parts.next().filter(|s| !s.is_empty())?;
+        Some(Self {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: parts.next().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+        })
+    }
+
+    /// Credentials from the standard `AWS_*` environment variables
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Some(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+/// Headers that sign a request: `x-amz-date`, the session token if any, and
+/// `Authorization`. `headers` are the other headers to sign; `host` is always signed.
+#[allow(clippy::too_many_arguments)]
+pub fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Vec<(String, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = &amz_date[..8];
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+        .chain([("host".to_string(), host), ("x-amz-date".to_string(), amz_date.clone())])
+        .chain(credentials.session_token.iter().map(|t| ("x-amz-security-token".to_string(), t.clone())))
+        .collect();
+    signed.sort();
+
+    let canonical_headers: String = signed.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+    let signed_headers = signed.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(";");
+    let canonical_request = [
+        method,
+        &canonical_uri(url),
+        &canonical_query(url),
+        &canonical_headers,
+        &signed_headers,
+        &hex::encode(digest::digest(&digest::SHA256, body)),
+    ]
+    .join("\n");
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(digest::digest(&digest::SHA256, canonical_request.as_bytes()))
+    );
+
+    let key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", credentials.secret_access_key).into_bytes(), |key, part| {
+            hmac_sha256(&key, part.as_bytes())
+        });
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    let mut out = vec![("x-amz-date".to_string(), amz_date)];
+    if let Some(token) = &credentials.session_token {
+        out.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+    out.push((
+        "Authorization".to_string(),
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+    out
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+/// Path with each segment encoded again, as SigV4 requires for services other than S3
+fn canonical_uri(url: &Url) -> String {
+    let path = url.path();
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    path.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k), uri_encode(&v)))
+        .collect();
+    pairs.sort();
+    pairs.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&")
+}
+
+/// Percent-encode everything except unreserved characters
+pub fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Vectors from the AWS Signature Version 4 test suite
+    #[test]
+    fn test_signature_vectors() {
+        let credentials = AwsCredentials::parse("AKIDEXAMPLE:wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY").unwrap();
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = sign(&credentials, "us-east-1", "service", "GET", &url, &[], b"", now);
+        assert_eq!(headers[0], ("x-amz-date".to_string(), "20150830T123600Z".to_string()));
+        assert_eq!(
+            headers[1].1,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+
+        let url = Url::parse("https://example.amazonaws.com/?Param2=value2&Param1=value1").unwrap();
+        let headers = sign(&credentials, "us-east-1", "service", "GET", &url, &[], b"", now);
+        assert!(headers[1].1.ends_with("Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"));
+    }
+
+    #[test]
+    fn test_bedrock_model_path() {
+        // Model ids with a colon are sent encoded once and signed encoded twice
+        let url = Url::parse(
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/anthropic.claude-sonnet-4-20250514-v1%3A0/invoke",
+        )
+        .unwrap();
+        assert_eq!(canonical_uri(&url), "/model/anthropic.claude-sonnet-4-20250514-v1%253A0/invoke");
+
+        let credentials = AwsCredentials::parse("AKID:SECRET:TOKEN").unwrap();
+        assert_eq!(credentials.session_token.as_deref(), Some("TOKEN"));
+        assert!(AwsCredentials::parse("bedrock-api-key").is_none());
+    }
+}
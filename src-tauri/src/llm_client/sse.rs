@@ -0,0 +1,231 @@
+use super::{LLMError, LlmResponse, StreamEvent};
+use crate::agent::{ContentBlock, TokenUsage, ToolUse};
+use futures::StreamExt;
+use tokio::sync::mpsc;
+
+/// Turns the `data:` payloads of one provider's SSE stream (or the lines of an
+/// NDJSON stream) into an `LlmResponse`
+pub trait SseParser {
+    /// Handle one decoded event, pushing text and thinking updates to `out`
+    fn on_event(&mut self, event: &serde_json::Value, out: &mut Vec<StreamEvent>);
+
+    /// Assemble the response once the stream has ended
+    fn finish(self) -> Result<LlmResponse, LLMError>;
+}
+
+/// Response parts collected while a stream is parsed
+#[derive(Debug, Default)]
+pub struct ResponseAccumulator {
+    pub text: String,
+    /// All thinking text so far, for display
+    pub thinking_text: String,
+    /// Thinking blocks to send back, when the provider has them
+    pub thinking: Vec<ContentBlock>,
+    pub tool_uses: Vec<ToolUse>,
+    pub usage: Option<TokenUsage>,
+}
+
+impl ResponseAccumulator {
+    pub fn push_text(&mut self, delta: &str, out: &mut Vec<StreamEvent>) {
+        if !delta.is_empty() {
+            self.text.push_str(delta);
+            out.push(StreamEvent::Text(self.text.clone()));
+        }
+    }
+
+    pub fn push_thinking(&mut self, delta: &str, out: &mut Vec<StreamEvent>) {
+        if !delta.is_empty() {
+            self.thinking_text.push_str(delta);
+            out.push(StreamEvent::Thinking(self.thinking_text.clone()));
+        }
+    }
+
+    pub fn push_tool_use(&mut self, id: String, name: String, arguments: &str, thought_signature: Option<String>) {
+        let input = serde_json::from_str(arguments).unwrap_or(serde_json::json!({}));
+        self.tool_uses.push(ToolUse {
+            id,
+            name,
+            input,
+            thought_signature,
+        });
+    }
+
+    pub fn into_response(self) -> LlmResponse {
+        // Providers without signed blocks get their reasoning back as one unsigned block
+        let thinking = if self.thinking.is_empty() && !self.thinking_text.is_empty() {
+            vec![ContentBlock::Thinking {
+                thinking: self.thinking_text,
+                signature: None,
+            }]
+        } else {
+            self.thinking
+        };
+
+        LlmResponse {
+            text: self.text,
+            thinking,
+            tool_uses: self.tool_uses,
+            usage: self.usage,
+        }
+    }
+}
+
+/// Splits a byte stream into SSE `data:` payloads, keeping partial lines between chunks
+#[derive(Debug, Default)]
+pub struct SseLines {
+    buffer: String,
+}
+
+impl SseLines {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+
+        let mut data = Vec::new();
+        while let Some(pos) = self.buffer.find('\n') {
+            let line = self.buffer[..pos].trim().to_string();
+            self.buffer.drain(..=pos);
+
+            if let Some(payload) = line.strip_prefix("data:") {
+                let payload = payload.trim_start();
+                if !payload.is_empty() && payload != "[DONE]" {
+                    data.push(payload.to_string());
+                }
+            }
+        }
+        data
+    }
+}
+
+/// Splits a newline-delimited JSON stream into lines, keeping partial lines between chunks
+#[derive(Debug, Default)]
+pub struct JsonLines {
+    buffer: String,
+}
+
+impl JsonLines {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buffer.find('\n') {
+            let line = self.buffer[..pos].trim().to_string();
+            self.buffer.drain(..=pos);
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+}
+
+fn feed<P: SseParser>(parser: &mut P, payload: &str, out: &mut Vec<StreamEvent>) {
+    if let Ok(event) = serde_json::from_str::<serde_json::Value>(payload) {
+        parser.on_event(&event, out);
+    }
+}
+
+/// Read an SSE response to the end, forwarding stream events to `tx`
+pub async fn read_sse<P: SseParser>(
+    response: reqwest::Response,
+    parser: P,
+    tx: &mpsc::Sender<StreamEvent>,
+) -> Result<LlmResponse, LLMError> {
+    let mut lines = SseLines::default();
+    read_payloads(response, parser, tx, |chunk| lines.push(chunk)).await
+}
+
+/// Read an NDJSON response (one JSON object per line) to the end, forwarding stream events to `tx`
+pub async fn read_ndjson<P: SseParser>(
+    response: reqwest::Response,
+    parser: P,
+    tx: &mpsc::Sender<StreamEvent>,
+) -> Result<LlmResponse, LLMError> {
+    let mut lines = JsonLines::default();
+    read_payloads(response, parser, tx, |chunk| lines.push(chunk)).await
+}
+
+async fn read_payloads<P: SseParser>(
+    response: reqwest::Response,
+    mut parser: P,
+    tx: &mpsc::Sender<StreamEvent>,
+    mut split: impl FnMut(&[u8]) -> Vec<String>,
+) -> Result<LlmResponse, LLMError> {
+    let mut stream = response.bytes_stream();
+    let mut events = Vec::new();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        for payload in split(&chunk) {
+            feed(&mut parser, &payload, &mut events);
+            for event in events.drain(..) {
+                let _ = tx.send(event).await;
+            }
+        }
+    }
+    // A stream may end without a trailing newline
+    for payload in split(b"\n") {
+        feed(&mut parser, &payload, &mut events);
+        for event in events.drain(..) {
+            let _ = tx.send(event).await;
+        }
+    }
+
+    parser.finish()
+}
+
+/// Parse a complete SSE transcript, returning the stream events and the response
+#[allow(dead_code)]
+pub fn parse_sse<P: SseParser>(
+    transcript: &str,
+    mut parser: P,
+) -> (Vec<StreamEvent>, Result<LlmResponse, LLMError>) {
+    let mut lines = SseLines::default();
+    let mut events = Vec::new();
+
+    for payload in lines.push(transcript.as_bytes()) {
+        feed(&mut parser, &payload, &mut events);
+    }
+    // A transcript may end without a trailing newline
+    for payload in lines.push(b"\n") {
+        feed(&mut parser, &payload, &mut events);
+    }
+
+    (events, parser.finish())
+}
+
+/// Parse a complete NDJSON transcript, returning the stream events and the response
+#[allow(dead_code)]
+pub fn parse_ndjson<P: SseParser>(
+    transcript: &str,
+    mut parser: P,
+) -> (Vec<StreamEvent>, Result<LlmResponse, LLMError>) {
+    let mut lines = JsonLines::default();
+    let mut events = Vec::new();
+
+    for payload in lines.push(transcript.as_bytes()).into_iter().chain(lines.push(b"\n")) {
+        feed(&mut parser, &payload, &mut events);
+    }
+
+    (events, parser.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_lines_across_chunks() {
+        let mut lines = SseLines::default();
+        assert!(lines.push(b"event: ping\ndata: {\"a\":").is_empty());
+        assert_eq!(lines.push(b"1}\r\n\r\ndata: [DONE]\n"), vec!["{\"a\":1}"]);
+        assert_eq!(lines.push(b"data:{\"b\":2}\n"), vec!["{\"b\":2}"]);
+    }
+
+    #[test]
+    fn test_json_lines_across_chunks() {
+        let mut lines = JsonLines::default();
+        assert!(lines.push(b"{\"a\":").is_empty());
+        assert_eq!(lines.push(b"1}\n\n{\"b\":2}\r\n{\"c\""), vec!["{\"a\":1}", "{\"b\":2}"]);
+        assert_eq!(lines.push(b":3}\n"), vec!["{\"c\":3}"]);
+    }
+}
@@ -0,0 +1,286 @@
+use super::anthropic::AnthropicStream;
+use super::google::{to_google_body, GoogleStream};
+use super::sse::read_sse;
+use super::{ApiFormat, LLMClient, LLMError, LlmProvider, LlmResponse, ProviderConfig, StreamEvent};
+use crate::agent::message_builder::ClaudeApiRequest;
+use base64::Engine;
+use futures::future::BoxFuture;
+use reqwest::Url;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+
+const VERTEX_ANTHROPIC_VERSION: &str = "vertex-2023-10-16";
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// Tokens are refreshed this long before they expire
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// Fields of a service account key file used for the token exchange
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceAccount {
+    pub client_email: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub private_key_id: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<String>,
+    #[serde(default = "default_token_uri")]
+    pub token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+impl ServiceAccount {
+    /// Signed JWT asking for a cloud-platform token, valid for an hour from `issued_at`
+    pub fn assertion(&self, issued_at: u64) -> Result<String, LLMError> {
+        let encode = |value: &serde_json::Value| {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value.to_string())
+        };
+        let mut header = serde_json::json!({ "alg": "RS256", "typ": "JWT" });
+        if let Some(kid) = &self.private_key_id {
+            header["kid"] = serde_json::json!(kid);
+        }
+        let claims = serde_json::json!({
+            "iss": self.client_email,
+            "scope": CLOUD_PLATFORM_SCOPE,
+            "aud": self.token_uri,
+            "iat": issued_at,
+            "exp": issued_at + 3600,
+        });
+        let signing_input = format!("{}.{}", encode(&header), encode(&claims));
+
+        let key_pair = RsaKeyPair::from_pkcs8(&pem_to_der(&self.private_key)?)
+            .map_err(|e| LLMError::Api(format!("Invalid service account key: {}", e)))?;
+        let mut signature = vec![0; key_pair.public().modulus_len()];
+        key_pair
+            .sign(&RSA_PKCS1_SHA256, &SystemRandom::new(), signing_input.as_bytes(), &mut signature)
+            .map_err(|_| LLMError::Api("Failed to sign service account assertion".to_string()))?;
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+}
+
+/// DER bytes of a PEM block
+fn pem_to_der(pem: &str) -> Result<Vec<u8>, LLMError> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .map_err(|e| LLMError::Api(format!("Invalid service account key: {}", e)))
+}
+
+/// How Vertex requests are authorised
+enum VertexCredentials {
+    ServiceAccount(ServiceAccount),
+    /// Ready-made token, e.g. from `gcloud auth print-access-token`
+    AccessToken(String),
+}
+
+/// Google Vertex AI, serving Claude through `streamRawPredict` and Gemini through
+/// `streamGenerateContent`
+pub struct VertexProvider {
+    client: LLMClient,
+    /// Access token from the service account and when it expires
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl VertexProvider {
+    pub fn new(client: LLMClient) -> Self {
+        Self {
+            client,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// The API key field holds service account JSON, a path to it, or an access token;
+    /// when empty, `GOOGLE_APPLICATION_CREDENTIALS` names the key file
+    fn credentials(&self) -> Result<VertexCredentials, LLMError> {
+        let key = self.client.api_key.trim();
+        let key = if key.is_empty() {
+            std::env::var("GOOGLE_APPLICATION_CREDENTIALS").map_err(|_| {
+                LLMError::Api("No Vertex AI credentials: enter a service account key or access token".to_string())
+            })?
+        } else {
+            key.to_string()
+        };
+
+        let json = if key.starts_with('{') {
+            key
+        } else if std::path::Path::new(&key).is_file() {
+            std::fs::read_to_string(&key)
+                .map_err(|e| LLMError::Api(format!("Cannot read service account key {}: {}", key, e)))?
+        } else {
+            return Ok(VertexCredentials::AccessToken(key));
+        };
+
+        serde_json::from_str(&json)
+            .map(VertexCredentials::ServiceAccount)
+            .map_err(|e| LLMError::Api(format!("Invalid service account key: {}", e)))
+    }
+
+    /// Access token for the next request, exchanging a fresh assertion when needed
+    async fn access_token(&self, credentials: &VertexCredentials) -> Result<String, LLMError> {
+        let account = match credentials {
+            VertexCredentials::AccessToken(token) => return Ok(token.clone()),
+            VertexCredentials::ServiceAccount(account) => account,
+        };
+
+        let mut cached = self.token.lock().await;
+        if let Some((token, expires)) = cached.as_ref() {
+            if Instant::now() + TOKEN_MARGIN < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let issued_at = chrono::Utc::now().timestamp().max(0) as u64;
+        let body = format!(
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer&assertion={}",
+            account.assertion(issued_at)?
+        );
+        let response = self
+            .client
+            .client
+            .post(&account.token_uri)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await?;
+        let data: serde_json::Value = LLMClient::check_status(response).await?.json().await?;
+
+        let token = data["access_token"]
+            .as_str()
+            .ok_or_else(|| LLMError::Parse("Token response has no access_token".to_string()))?
+            .to_string();
+        let lifetime = Duration::from_secs(data["expires_in"].as_u64().unwrap_or(3600));
+        *cached = Some((token.clone(), Instant::now() + lifetime));
+        Ok(token)
+    }
+
+    /// `.../projects/<project>/locations/<location>` for the base URL. A base URL that
+    /// already names the project is used as is; otherwise the project comes from the
+    /// service account and the location from the `<location>-aiplatform` host.
+    fn location_url(&self, credentials: &VertexCredentials) -> Result<String, LLMError> {
+        let base = self.client.base_url.trim_end_matches('/');
+        if base.contains("/projects/") {
+            return Ok(base.to_string());
+        }
+
+        let project = match credentials {
+            VertexCredentials::ServiceAccount(account) => account.project_id.clone(),
+            VertexCredentials::AccessToken(_) => None,
+        }
+        .or_else(|| std::env::var("GOOGLE_CLOUD_PROJECT").ok())
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| {
+            LLMError::Api("No Google Cloud project: add it to the base URL as /v1/projects/<project>/locations/<location>".to_string())
+        })?;
+
+        Ok(format!("{}/v1/projects/{}/locations/{}", base, project, location_from_url(base)))
+    }
+}
+
+/// Location of a `<location>-aiplatform.googleapis.com` host; the bare host is `global`
+fn location_from_url(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().and_then(|h| h.strip_suffix("-aiplatform.googleapis.com")).map(|l| l.to_string()))
+        .unwrap_or_else(|| "global".to_string())
+}
+
+impl LlmProvider for VertexProvider {
+    fn config(&self) -> &ProviderConfig {
+        &self.client.provider_config
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ClaudeApiRequest,
+        tx: mpsc::Sender<StreamEvent>,
+    ) -> BoxFuture<'a, Result<LlmResponse, LLMError>> {
+        Box::pin(async move {
+            let credentials = self.credentials()?;
+            let token = self.access_token(&credentials).await?;
+            let authorization = format!("Bearer {}", token);
+            let headers = [("Authorization", authorization.as_str())];
+            let location = self.location_url(&credentials)?;
+
+            if self.client.provider_config.api_format.wire_format(&request.model) == ApiFormat::Anthropic {
+                // The model goes in the path and the API version in the body
+                let mut body = request.to_anthropic_body();
+                if let Some(body) = body.as_object_mut() {
+                    body.remove("model");
+                    body.insert("anthropic_version".to_string(), VERTEX_ANTHROPIC_VERSION.into());
+                    body.insert("stream".to_string(), true.into());
+                }
+                let url = format!("{}/publishers/anthropic/models/{}:streamRawPredict", location, request.model);
+                let response = self.client.post_json(&url, &body, &headers).await?;
+                read_sse(response, AnthropicStream::default(), &tx).await
+            } else {
+                let url = format!(
+                    "{}/publishers/google/models/{}:streamGenerateContent?alt=sse",
+                    location, request.model
+                );
+                let response = self.client.post_json(&url, &to_google_body(request), &headers).await?;
+                read_sse(response, GoogleStream::default(), &tx).await
+            }
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, LLMError>> {
+        Box::pin(async {
+            Err(LLMError::Api(
+                "Vertex AI models are enabled per project in Model Garden; enter the model id".to_string(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA256};
+
+    fn test_account() -> ServiceAccount {
+        serde_json::from_str(include_str!("fixtures/vertex_service_account.json")).unwrap()
+    }
+
+    #[test]
+    fn test_service_account_assertion() {
+        let account = test_account();
+        let jwt = account.assertion(1_700_000_000).unwrap();
+        let parts: Vec<&str> = jwt.split('.').collect();
+        assert_eq!(parts.len(), 3);
+
+        let decode = |part: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(part).unwrap();
+        let header: serde_json::Value = serde_json::from_slice(&decode(parts[0])).unwrap();
+        let claims: serde_json::Value = serde_json::from_slice(&decode(parts[1])).unwrap();
+        assert_eq!(header["alg"], "RS256");
+        assert_eq!(header["kid"], "test-key-1");
+        assert_eq!(claims["iss"], account.client_email);
+        assert_eq!(claims["aud"], account.token_uri);
+        assert_eq!(claims["exp"], 1_700_003_600);
+
+        // The signature verifies against the key's public half
+        let key_pair = RsaKeyPair::from_pkcs8(&pem_to_der(&account.private_key).unwrap()).unwrap();
+        let public_key = UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key_pair.public().as_ref());
+        let signed = format!("{}.{}", parts[0], parts[1]);
+        assert!(public_key.verify(signed.as_bytes(), &decode(parts[2])).is_ok());
+    }
+
+    #[test]
+    fn test_location_from_url() {
+        assert_eq!(location_from_url("https://us-east5-aiplatform.googleapis.com"), "us-east5");
+        assert_eq!(location_from_url("https://aiplatform.googleapis.com"), "global");
+    }
+}
@@ -1,5 +1,6 @@
 use super::http_client::HttpMcpClient;
 use super::types::*;
+use crate::http::{NetworkSettings, MCP_SCOPE};
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 use std::sync::Arc;
@@ -13,6 +14,8 @@ pub struct MCPClient {
 pub struct MCPManager {
     clients: Arc<RwLock<HashMap<String, MCPClient>>>,
     server_status: Arc<RwLock<HashMap<String, MCPServerStatus>>>,
+    /// Proxy, CA and header settings for MCP connections
+    network: std::sync::RwLock<NetworkSettings>,
 }
 
 impl MCPManager {
@@ -20,9 +23,27 @@ impl MCPManager {
         Self {
             clients: Arc::new(RwLock::new(HashMap::new())),
             server_status: Arc::new(RwLock::new(HashMap::new())),
+            network: std::sync::RwLock::new(NetworkSettings::default()),
         }
     }
 
+    /// Use new network settings for connections made from now on
+    pub fn set_network(&self, network: NetworkSettings) {
+        if let Ok(mut current) = self.network.write() {
+            *current = network;
+        }
+    }
+
+    /// HTTP client for MCP requests, built from the current network settings
+    fn http_client(&self) -> Result<reqwest::Client, Box<dyn std::error::Error + Send + Sync>> {
+        let network = self.network.read().map(|n| n.clone()).unwrap_or_default();
+        let mut builder = network.builder(MCP_SCOPE)?;
+        if network.timeout_secs.is_none() {
+            builder = builder.timeout(std::time::Duration::from_secs(30));
+        }
+        Ok(builder.build()?)
+    }
+
     pub async fn connect_server(&self, config: &MCPServerConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if !config.enabled {
             return Err("Server is not enabled".into());
@@ -55,7 +76,14 @@ impl MCPManager {
         };
 
         // Create HTTP MCP client
-        let mut http_client = HttpMcpClient::new(config.server_url.clone(), oauth_token);
+        let client = match self.http_client() {
+            Ok(client) => client,
+            Err(e) => {
+                self.update_status_error(&config.id, e.to_string()).await;
+                return Err(e);
+            }
+        };
+        let mut http_client = HttpMcpClient::new(client, config.server_url.clone(), oauth_token);
 
         // Initialize the connection
         match http_client.initialize().await {
@@ -257,7 +285,7 @@ impl MCPManager {
         oauth_url.push_str("oauth/token");
 
         // Create HTTP client for OAuth request
-        let client = reqwest::Client::new();
+        let client = self.http_client()?;
 
         // Prepare OAuth request body (Client Credentials Grant)
         let params = [
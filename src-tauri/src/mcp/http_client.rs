@@ -1,5 +1,4 @@
 use serde_json::{json, Value};
-use std::time::Duration;
 use reqwest;
 
 #[derive(Debug)]
@@ -12,12 +11,7 @@ pub struct HttpMcpClient {
 }
 
 impl HttpMcpClient {
-    pub fn new(server_url: String, oauth_token: Option<String>) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .expect("Failed to create HTTP client");
-
+    pub fn new(client: reqwest::Client, server_url: String, oauth_token: Option<String>) -> Self {
         // Ensure URL ends with /mcp
         let base_url = if server_url.ends_with("/mcp") {
             server_url
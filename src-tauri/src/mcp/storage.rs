@@ -1,54 +1,70 @@
 use super::types::MCPServerConfig;
 use crate::database::{Database, DbError};
-use rusqlite::params;
+use rusqlite::{params, Connection};
+
+/// Secret store entry of a server's OAuth client secret
+fn oauth_secret_name(server_id: &str) -> String {
+    format!("mcp_oauth_secret:{}", server_id)
+}
 
 impl Database {
-    pub fn create_mcp_tables(&self) -> Result<(), DbError> {
+    /// Move client secrets kept in the table by earlier versions into the secret store
+    pub(crate) fn migrate_mcp_secrets(&self) -> Result<(), DbError> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS mcp_servers (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                server_url TEXT NOT NULL,
-                oauth_client_id TEXT,
-                oauth_client_secret TEXT,
-                enabled BOOLEAN NOT NULL DEFAULT 0,
-                created_at TIMESTAMP NOT NULL,
-                updated_at TIMESTAMP NOT NULL
-            )",
-            [],
+        let mut stmt = conn.prepare(
+            "SELECT id, oauth_client_secret FROM mcp_servers
+             WHERE oauth_client_secret IS NOT NULL AND oauth_client_secret != ''"
         )?;
+        let plaintext = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(stmt);
+        if !plaintext.is_empty() {
+            for (id, secret) in &plaintext {
+                self.write_secret(&conn, &oauth_secret_name(id), secret)?;
+            }
+            conn.execute("UPDATE mcp_servers SET oauth_client_secret = NULL", [])?;
+            conn.execute_batch("VACUUM")?;
+        }
 
         Ok(())
     }
 
+    fn with_oauth_secret(&self, conn: &Connection, mut config: MCPServerConfig) -> Result<MCPServerConfig, DbError> {
+        config.oauth_client_secret = self.read_secret(conn, &oauth_secret_name(&config.id))?;
+        Ok(config)
+    }
+
     pub fn save_mcp_server(&self, config: &MCPServerConfig) -> Result<(), DbError> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
 
         conn.execute(
             "INSERT OR REPLACE INTO mcp_servers
              (id, name, server_url, oauth_client_id, oauth_client_secret, enabled, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+             VALUES (?1, ?2, ?3, ?4, NULL, ?5, ?6, ?7)",
             params![
                 config.id,
                 config.name,
                 config.server_url,
                 config.oauth_client_id,
-                config.oauth_client_secret,
                 config.enabled,
                 config.created_at,
                 config.updated_at,
             ],
         )?;
-        Ok(())
+        self.update_secret(
+            &conn,
+            &oauth_secret_name(&config.id),
+            config.oauth_client_secret.as_deref().unwrap_or_default(),
+        )
     }
 
     pub fn get_mcp_servers(&self) -> Result<Vec<MCPServerConfig>, DbError> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, server_url, oauth_client_id, oauth_client_secret, enabled, created_at, updated_at
+            "SELECT id, name, server_url, oauth_client_id, enabled, created_at, updated_at
              FROM mcp_servers ORDER BY name"
         )?;
 
@@ -58,16 +74,16 @@ impl Database {
                 name: row.get(1)?,
                 server_url: row.get(2)?,
                 oauth_client_id: row.get(3)?,
-                oauth_client_secret: row.get(4)?,
-                enabled: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+                oauth_client_secret: None,
+                enabled: row.get(4)?,
+                created_at: row.get(5)?,
+                updated_at: row.get(6)?,
             })
         })?;
 
         let mut servers = Vec::new();
         for server in server_iter {
-            servers.push(server?);
+            servers.push(self.with_oauth_secret(&conn, server?)?);
         }
         Ok(servers)
     }
@@ -76,7 +92,7 @@ impl Database {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, server_url, oauth_client_id, oauth_client_secret, enabled, created_at, updated_at
+            "SELECT id, name, server_url, oauth_client_id, enabled, created_at, updated_at
              FROM mcp_servers WHERE id = ?1"
         )?;
 
@@ -86,15 +102,15 @@ impl Database {
                 name: row.get(1)?,
                 server_url: row.get(2)?,
                 oauth_client_id: row.get(3)?,
-                oauth_client_secret: row.get(4)?,
-                enabled: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+                oauth_client_secret: None,
+                enabled: row.get(4)?,
+                created_at: row.get(5)?,
+                updated_at: row.get(6)?,
             })
         })?;
 
         match server_iter.next() {
-            Some(server) => Ok(Some(server?)),
+            Some(server) => Ok(Some(self.with_oauth_secret(&conn, server?)?)),
             None => Ok(None),
         }
     }
@@ -106,7 +122,7 @@ impl Database {
             "DELETE FROM mcp_servers WHERE id = ?1",
             params![id],
         )?;
-        Ok(())
+        Self::delete_secret(&conn, &oauth_secret_name(id))
     }
 
     pub fn update_mcp_server_enabled(&self, id: &str, enabled: bool) -> Result<(), DbError> {
@@ -12,6 +12,14 @@ pub struct MCPServerConfig {
     pub updated_at: String,
 }
 
+impl MCPServerConfig {
+    /// Copy safe to send to the frontend, with the client secret masked
+    pub fn masked(mut self) -> Self {
+        self.oauth_client_secret = self.oauth_client_secret.map(|secret| crate::secrets::mask(&secret));
+        self
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MCPTool {
     pub server_id: String,
@@ -0,0 +1,547 @@
+use crate::database::DbError;
+use rusqlite::{Connection, Transaction};
+
+/// A schema change. Migrations run in version order, each in its own transaction
+/// that also records the new version in `PRAGMA user_version`.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Every schema change so far. Append new ones with the next version; never edit
+/// one that has shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "Full-text search index",
+        up: search_index,
+    },
+    Migration {
+        version: 3,
+        description: "Tool calls on messages",
+        up: message_tool_calls,
+    },
+    Migration {
+        version: 4,
+        description: "Message branches",
+        up: message_branches,
+    },
+    Migration {
+        version: 5,
+        description: "Hand-edited titles",
+        up: edited_titles,
+    },
+    Migration {
+        version: 6,
+        description: "Tags, pinning and archiving",
+        up: history_organisation,
+    },
+    Migration {
+        version: 7,
+        description: "Trash",
+        up: trash,
+    },
+];
+
+/// Schema version of the current build
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the schema up to date. Returns the version the database had before.
+pub fn migrate(conn: &mut Connection) -> Result<u32, DbError> {
+    run(conn, MIGRATIONS)
+}
+
+fn run(conn: &mut Connection, migrations: &[Migration]) -> Result<u32, DbError> {
+    let current = schema_version(conn)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if current > latest {
+        return Err(DbError::NewerSchema { found: current, supported: latest });
+    }
+
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(current);
+    }
+    backup(conn, current)?;
+
+    for migration in pending {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)
+            .and_then(|()| tx.pragma_update(None, "user_version", migration.version))
+            .map_err(|source| DbError::Migration {
+                version: migration.version,
+                description: migration.description,
+                source,
+            })?;
+        tx.commit()?;
+    }
+    Ok(current)
+}
+
+/// Copy an existing database file aside before changing its schema
+fn backup(conn: &Connection, version: u32) -> Result<(), DbError> {
+    let Some(path) = conn.path().filter(|p| !p.is_empty()) else {
+        return Ok(());
+    };
+    let tables: u32 = conn.query_row("SELECT count(*) FROM sqlite_master WHERE type = 'table'", [], |row| {
+        row.get(0)
+    })?;
+    if tables == 0 {
+        return Ok(());
+    }
+
+    let backup_path = format!("{}.v{}-{}.bak", path, version, chrono::Utc::now().timestamp_millis());
+    conn.execute("VACUUM INTO ?1", [&backup_path])?;
+    Ok(())
+}
+
+/// Tables as created before versioning. `IF NOT EXISTS` adopts databases from
+/// those releases, which are at version 0 with some or all of them.
+fn initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS secrets (
+            name TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS secret_meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS conversations (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS messages (
+            id TEXT PRIMARY KEY,
+            conversation_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_messages_conversation
+            ON messages(conversation_id);
+
+        CREATE TABLE IF NOT EXISTS tasks (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            description TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'planning',
+            plan TEXT,
+            current_step INTEGER NOT NULL DEFAULT 0,
+            project_path TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS task_messages (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_task_messages_task
+            ON task_messages(task_id);
+
+        CREATE TABLE IF NOT EXISTS model_cache (
+            provider TEXT NOT NULL,
+            base_url TEXT NOT NULL,
+            models TEXT NOT NULL,
+            fetched_at INTEGER NOT NULL,
+            PRIMARY KEY (provider, base_url)
+        );
+
+        CREATE TABLE IF NOT EXISTS mcp_servers (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            server_url TEXT NOT NULL,
+            oauth_client_id TEXT,
+            oauth_client_secret TEXT,
+            enabled BOOLEAN NOT NULL DEFAULT 0,
+            created_at TIMESTAMP NOT NULL,
+            updated_at TIMESTAMP NOT NULL
+        );",
+    )
+}
+
+/// FTS5 index over message contents and task titles and descriptions. Each
+/// `search_docs` row maps an index row to its source, with what search filters on;
+/// triggers keep both in step with the source tables.
+fn search_index(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE search_docs (
+            id INTEGER PRIMARY KEY,
+            source TEXT NOT NULL,
+            source_id TEXT NOT NULL,
+            parent_id TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            UNIQUE (source, source_id)
+        );
+
+        CREATE INDEX idx_search_docs_parent ON search_docs(parent_id);
+
+        CREATE VIRTUAL TABLE search_index USING fts5(
+            title,
+            content,
+            tokenize = 'porter unicode61'
+        );
+
+        CREATE TRIGGER messages_search_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO search_docs (source, source_id, parent_id, timestamp)
+                VALUES ('message', new.id, new.conversation_id, new.timestamp);
+            INSERT INTO search_index (rowid, title, content)
+                VALUES (last_insert_rowid(), '', new.content);
+        END;
+
+        CREATE TRIGGER messages_search_update AFTER UPDATE OF content ON messages BEGIN
+            UPDATE search_index SET content = new.content
+                WHERE rowid = (SELECT id FROM search_docs WHERE source = 'message' AND source_id = old.id);
+        END;
+
+        CREATE TRIGGER messages_search_delete AFTER DELETE ON messages BEGIN
+            DELETE FROM search_index
+                WHERE rowid = (SELECT id FROM search_docs WHERE source = 'message' AND source_id = old.id);
+            DELETE FROM search_docs WHERE source = 'message' AND source_id = old.id;
+        END;
+
+        CREATE TRIGGER task_messages_search_insert AFTER INSERT ON task_messages BEGIN
+            INSERT INTO search_docs (source, source_id, parent_id, timestamp)
+                VALUES ('task_message', new.id, new.task_id, new.timestamp);
+            INSERT INTO search_index (rowid, title, content)
+                VALUES (last_insert_rowid(), '', new.content);
+        END;
+
+        CREATE TRIGGER task_messages_search_update AFTER UPDATE OF content ON task_messages BEGIN
+            UPDATE search_index SET content = new.content
+                WHERE rowid = (SELECT id FROM search_docs WHERE source = 'task_message' AND source_id = old.id);
+        END;
+
+        CREATE TRIGGER task_messages_search_delete AFTER DELETE ON task_messages BEGIN
+            DELETE FROM search_index
+                WHERE rowid = (SELECT id FROM search_docs WHERE source = 'task_message' AND source_id = old.id);
+            DELETE FROM search_docs WHERE source = 'task_message' AND source_id = old.id;
+        END;
+
+        CREATE TRIGGER tasks_search_insert AFTER INSERT ON tasks BEGIN
+            INSERT INTO search_docs (source, source_id, parent_id, timestamp)
+                VALUES ('task', new.id, new.id, new.created_at);
+            INSERT INTO search_index (rowid, title, content)
+                VALUES (last_insert_rowid(), new.title, new.description);
+        END;
+
+        CREATE TRIGGER tasks_search_update AFTER UPDATE OF title, description ON tasks BEGIN
+            UPDATE search_index SET title = new.title, content = new.description
+                WHERE rowid = (SELECT id FROM search_docs WHERE source = 'task' AND source_id = old.id);
+        END;
+
+        CREATE TRIGGER tasks_search_delete AFTER DELETE ON tasks BEGIN
+            DELETE FROM search_index
+                WHERE rowid = (SELECT id FROM search_docs WHERE source = 'task' AND source_id = old.id);
+            DELETE FROM search_docs WHERE source = 'task' AND source_id = old.id;
+        END;
+
+        INSERT INTO search_docs (source, source_id, parent_id, timestamp)
+            SELECT 'message', id, conversation_id, timestamp FROM messages;
+        INSERT INTO search_docs (source, source_id, parent_id, timestamp)
+            SELECT 'task_message', id, task_id, timestamp FROM task_messages;
+        INSERT INTO search_docs (source, source_id, parent_id, timestamp)
+            SELECT 'task', id, id, created_at FROM tasks;
+        INSERT INTO search_index (rowid, title, content)
+            SELECT d.id, '', m.content FROM search_docs d JOIN messages m ON d.source = 'message' AND m.id = d.source_id;
+        INSERT INTO search_index (rowid, title, content)
+            SELECT d.id, '', m.content FROM search_docs d
+            JOIN task_messages m ON d.source = 'task_message' AND m.id = d.source_id;
+        INSERT INTO search_index (rowid, title, content)
+            SELECT d.id, t.title, t.description FROM search_docs d JOIN tasks t ON d.source = 'task' AND t.id = d.source_id;",
+    )
+}
+
+/// JSON list of the tool calls behind an assistant message
+fn message_tool_calls(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE messages ADD COLUMN tool_calls TEXT;
+        ALTER TABLE task_messages ADD COLUMN tool_calls TEXT;",
+    )
+}
+
+/// Messages form a tree through `parent_id`; each conversation points at the
+/// last message of its active branch. Existing conversations become one branch.
+fn message_branches(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE messages ADD COLUMN parent_id TEXT;
+        ALTER TABLE conversations ADD COLUMN active_leaf_id TEXT;
+        CREATE INDEX idx_messages_parent ON messages(parent_id);
+
+        UPDATE messages SET parent_id = (
+            SELECT p.id FROM messages p
+            WHERE p.conversation_id = messages.conversation_id
+              AND (p.timestamp < messages.timestamp OR (p.timestamp = messages.timestamp AND p.rowid < messages.rowid))
+            ORDER BY p.timestamp DESC, p.rowid DESC
+            LIMIT 1
+        );
+        UPDATE conversations SET active_leaf_id = (
+            SELECT id FROM messages m WHERE m.conversation_id = conversations.id
+            ORDER BY timestamp DESC, rowid DESC
+            LIMIT 1
+        );",
+    )
+}
+
+/// Titles the user typed are kept; generated titles only replace the others
+fn edited_titles(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN title_edited INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE tasks ADD COLUMN title_edited INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+/// Tags group conversations and tasks like folders, but an item can have several.
+/// Conversations join tasks in belonging to a project.
+fn history_organisation(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN project_path TEXT;
+        ALTER TABLE conversations ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE conversations ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE tasks ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE tasks ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+        CREATE INDEX idx_conversations_list ON conversations(archived, pinned, updated_at);
+        CREATE INDEX idx_conversations_project ON conversations(project_path);
+        CREATE INDEX idx_tasks_list ON tasks(archived, pinned, updated_at);
+
+        CREATE TABLE tags (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            color TEXT,
+            created_at INTEGER NOT NULL
+        );
+        CREATE TABLE conversation_tags (
+            conversation_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            PRIMARY KEY (conversation_id, tag_id),
+            FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_conversation_tags_tag ON conversation_tags(tag_id);
+        CREATE TABLE task_tags (
+            task_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            PRIMARY KEY (task_id, tag_id),
+            FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_task_tags_tag ON task_tags(tag_id);",
+    )
+}
+
+/// Deleting sets `deleted_at`; rows are removed when the trash is purged
+fn trash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN deleted_at INTEGER;
+        ALTER TABLE tasks ADD COLUMN deleted_at INTEGER;
+        CREATE INDEX idx_conversations_deleted ON conversations(deleted_at);
+        CREATE INDEX idx_tasks_deleted ON tasks(deleted_at);",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// Schema of the first release: no model cache, secrets in plain settings
+    const RELEASE_0_1: &str = "
+        CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE conversations (id TEXT PRIMARY KEY, title TEXT NOT NULL,
+            created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL);
+        CREATE TABLE messages (id TEXT PRIMARY KEY, conversation_id TEXT NOT NULL, role TEXT NOT NULL,
+            content TEXT NOT NULL, timestamp INTEGER NOT NULL,
+            FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE);
+        CREATE INDEX idx_messages_conversation ON messages(conversation_id);
+        CREATE TABLE tasks (id TEXT PRIMARY KEY, title TEXT NOT NULL, description TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'planning', plan TEXT, current_step INTEGER NOT NULL DEFAULT 0,
+            project_path TEXT, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL);
+        CREATE TABLE task_messages (id TEXT PRIMARY KEY, task_id TEXT NOT NULL, role TEXT NOT NULL,
+            content TEXT NOT NULL, timestamp INTEGER NOT NULL,
+            FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE);
+        CREATE INDEX idx_task_messages_task ON task_messages(task_id);
+        CREATE TABLE mcp_servers (id TEXT PRIMARY KEY, name TEXT NOT NULL, server_url TEXT NOT NULL,
+            oauth_client_id TEXT, oauth_client_secret TEXT, enabled BOOLEAN NOT NULL DEFAULT 0,
+            created_at TIMESTAMP NOT NULL, updated_at TIMESTAMP NOT NULL);
+        INSERT INTO settings VALUES ('model', 'claude-sonnet-4-20250514');
+        INSERT INTO conversations VALUES ('c1', 'Hello', 1, 2);
+        INSERT INTO messages VALUES ('m1', 'c1', 'user', 'Hi', 1);
+        INSERT INTO messages VALUES ('m2', 'c1', 'assistant', 'Hello!', 2);
+        INSERT INTO tasks (id, title, description, created_at, updated_at) VALUES ('t1', 'Task', 'Do it', 1, 1);
+        INSERT INTO task_messages VALUES ('tm1', 't1', 'user', 'Go', 1);";
+
+    /// Later unversioned releases, which added the model cache and secret store
+    const RELEASE_0_2: &str = "
+        CREATE TABLE model_cache (provider TEXT NOT NULL, base_url TEXT NOT NULL, models TEXT NOT NULL,
+            fetched_at INTEGER NOT NULL, PRIMARY KEY (provider, base_url));
+        CREATE TABLE secrets (name TEXT PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE secret_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+        INSERT INTO model_cache VALUES ('openai', 'https://api.openai.com', '[\"gpt-4o\"]', 5);";
+
+    /// Databases as each historical version left them
+    fn fixtures() -> Vec<(&'static str, Connection)> {
+        let open = |sql: &[&str]| {
+            let conn = Connection::open_in_memory().unwrap();
+            for batch in sql {
+                conn.execute_batch(batch).unwrap();
+            }
+            conn
+        };
+        vec![
+            ("empty", open(&[])),
+            ("0.1", open(&[RELEASE_0_1])),
+            ("0.2", open(&[RELEASE_0_1, RELEASE_0_2])),
+        ]
+    }
+
+    /// Columns of every table, to compare upgraded and fresh databases
+    fn columns(conn: &Connection) -> BTreeMap<String, Vec<String>> {
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap();
+        let tables: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        tables
+            .into_iter()
+            .map(|table| {
+                let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+                let columns = stmt
+                    .query_map([], |row| Ok(format!("{} {}", row.get::<_, String>(1)?, row.get::<_, String>(2)?)))
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .collect();
+                (table, columns)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_upgrade_fixtures() {
+        let mut fresh = Connection::open_in_memory().unwrap();
+        migrate(&mut fresh).unwrap();
+        let expected = columns(&fresh);
+
+        for (name, mut conn) in fixtures() {
+            assert_eq!(migrate(&mut conn).unwrap(), 0, "{name}");
+            assert_eq!(schema_version(&conn).unwrap(), latest_version(), "{name}");
+            assert_eq!(columns(&conn), expected, "{name}");
+
+            // Rows from the old release survive
+            if name != "empty" {
+                let content: String = conn
+                    .query_row("SELECT content FROM messages WHERE id = 'm1'", [], |row| row.get(0))
+                    .unwrap();
+                assert_eq!(content, "Hi", "{name}");
+                // and are indexed for search
+                let indexed: u32 = conn
+                    .query_row("SELECT count(*) FROM search_index WHERE search_index MATCH 'hi OR task'", [], |row| {
+                        row.get(0)
+                    })
+                    .unwrap();
+                assert_eq!(indexed, 2, "{name}");
+                // and form the conversation's active branch
+                let (parent, leaf): (Option<String>, Option<String>) = conn
+                    .query_row(
+                        "SELECT m.parent_id, c.active_leaf_id FROM messages m JOIN conversations c ON c.id = m.conversation_id
+                         WHERE m.id = 'm2'",
+                        [],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .unwrap();
+                assert_eq!((parent.as_deref(), leaf.as_deref()), (Some("m1"), Some("m2")), "{name}");
+            }
+
+            // Running again is a no-op
+            assert_eq!(migrate(&mut conn).unwrap(), latest_version(), "{name}");
+        }
+    }
+
+    fn add_notes(tx: &Transaction) -> rusqlite::Result<()> {
+        tx.execute_batch("ALTER TABLE conversations ADD COLUMN notes TEXT")
+    }
+
+    fn broken(tx: &Transaction) -> rusqlite::Result<()> {
+        tx.execute_batch("ALTER TABLE conversations ADD COLUMN pinned INTEGER; SELECT * FROM missing_table")
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration { version: 1, description: "Initial schema", up: initial_schema },
+            Migration { version: 2, description: "Conversation notes", up: add_notes },
+            Migration { version: 3, description: "Broken", up: broken },
+        ];
+
+        let error = run(&mut conn, &migrations).unwrap_err();
+        assert!(matches!(error, DbError::Migration { version: 3, .. }), "{error}");
+        // Earlier migrations stay applied; the failed one leaves nothing behind
+        assert_eq!(schema_version(&conn).unwrap(), 2);
+        let columns = &columns(&conn)["conversations"];
+        assert!(columns.contains(&"notes TEXT".to_string()));
+        assert!(!columns.iter().any(|c| c.starts_with("pinned")));
+
+        // A database from a newer build is refused rather than misread
+        let error = run(&mut conn, &migrations[..1]).unwrap_err();
+        assert!(matches!(error, DbError::NewerSchema { found: 2, supported: 1 }), "{error}");
+    }
+
+    #[test]
+    fn test_backup_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("kuse-migrations-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kuse-cowork.db");
+
+        let mut conn = Connection::open(&path).unwrap();
+        conn.execute_batch(RELEASE_0_1).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let backups: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].to_string_lossy().contains("kuse-cowork.db.v0-"));
+        let backup = Connection::open(&backups[0]).unwrap();
+        assert_eq!(schema_version(&backup).unwrap(), 0);
+        assert!(!columns(&backup).contains_key("model_cache"));
+
+        // Up-to-date and brand new databases aren't copied
+        migrate(&mut conn).unwrap();
+        let mut new = Connection::open(dir.join("new.db")).unwrap();
+        migrate(&mut new).unwrap();
+        let count = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(count, 3);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
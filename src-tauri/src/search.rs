@@ -0,0 +1,177 @@
+use crate::database::{Database, DbError};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// Marks around matched terms in snippets
+pub const HIGHLIGHT_START: &str = "**";
+pub const HIGHLIGHT_END: &str = "**";
+
+const DEFAULT_LIMIT: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Conversation,
+    Task,
+}
+
+/// Narrows a history search. Times are in milliseconds since the epoch.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFilter {
+    #[serde(default)]
+    pub kind: Option<SearchKind>,
+    #[serde(default)]
+    pub from: Option<i64>,
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Only conversations and tasks in this project
+    #[serde(default)]
+    pub project_path: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    /// Conversation or task id
+    pub id: String,
+    pub title: String,
+    /// Matching message, when the hit isn't the task itself
+    pub message_id: Option<String>,
+    /// Matched text around the terms, which are wrapped in `**`
+    pub snippet: String,
+    pub timestamp: i64,
+    pub project_path: Option<String>,
+    /// BM25 score; lower is more relevant
+    pub rank: f64,
+}
+
+/// FTS5 query matching every word of free text, the last one as a prefix so
+/// results show while typing. Quoting keeps FTS syntax characters literal.
+pub fn match_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", terms.join(" ")))
+}
+
+impl Database {
+    pub fn search_history(&self, query: &str, filter: &SearchFilter) -> Result<Vec<SearchHit>, DbError> {
+        let Some(query) = match_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+
+        let kind = filter.kind.map(|kind| match kind {
+            SearchKind::Conversation => "conversation",
+            SearchKind::Task => "task",
+        });
+        let mut stmt = conn.prepare(
+            "SELECT d.source, d.source_id, d.parent_id, d.timestamp,
+                    COALESCE(c.title, t.title, ''), COALESCE(c.project_path, t.project_path),
+                    snippet(search_index, -1, ?2, ?3, '…', 16), bm25(search_index, 4.0, 1.0) AS rank
+             FROM search_index
+             JOIN search_docs d ON d.id = search_index.rowid
+             LEFT JOIN conversations c ON d.source = 'message' AND c.id = d.parent_id
+             LEFT JOIN tasks t ON d.source != 'message' AND t.id = d.parent_id
+             WHERE search_index MATCH ?1
+               AND COALESCE(c.deleted_at, t.deleted_at) IS NULL
+               AND (?4 IS NULL OR (?4 = 'conversation') = (d.source = 'message'))
+               AND (?5 IS NULL OR d.timestamp >= ?5)
+               AND (?6 IS NULL OR d.timestamp <= ?6)
+               AND (?7 IS NULL OR COALESCE(c.project_path, t.project_path) = ?7)
+             ORDER BY rank
+             LIMIT ?8",
+        )?;
+
+        let hits = stmt.query_map(
+            params![
+                query,
+                HIGHLIGHT_START,
+                HIGHLIGHT_END,
+                kind,
+                filter.from,
+                filter.to,
+                filter.project_path,
+                filter.limit.unwrap_or(DEFAULT_LIMIT),
+            ],
+            |row| {
+                let source: String = row.get(0)?;
+                let source_id: String = row.get(1)?;
+                Ok(SearchHit {
+                    kind: if source == "message" { SearchKind::Conversation } else { SearchKind::Task },
+                    id: row.get(2)?,
+                    title: row.get(4)?,
+                    message_id: (source != "task").then_some(source_id),
+                    snippet: row.get(6)?,
+                    timestamp: row.get(3)?,
+                    project_path: row.get(5)?,
+                    rank: row.get(7)?,
+                })
+            },
+        )?;
+        Ok(hits.collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(kind: Option<SearchKind>) -> SearchFilter {
+        SearchFilter { kind, ..Default::default() }
+    }
+
+    #[test]
+    fn test_search_history() {
+        let db = Database::in_memory();
+        db.create_conversation("c1", "Dev server", None).unwrap();
+        db.add_message("m1", "c1", "user", "The API calls fail with a CORS error").unwrap();
+        let reply = db.add_message("m2", "c1", "assistant", "Thinking...").unwrap();
+        db.create_task("t1", "Fix CORS headers", "Allow the dev origin", Some("/work/app")).unwrap();
+        db.add_task_message("tm1", "t1", "assistant", "Added the origin to the allow list").unwrap();
+        db.create_task("t2", "Write docs", "Document the API", Some("/work/docs")).unwrap();
+
+        let hits = db.search_history("cors", &SearchFilter::default()).unwrap();
+        assert_eq!(hits.len(), 2);
+        // Title matches weigh more than body text
+        assert_eq!((hits[0].kind, hits[0].id.as_str(), hits[0].message_id.as_deref()), (SearchKind::Task, "t1", None));
+        assert_eq!(hits[0].snippet, "Fix **CORS** headers");
+        assert_eq!((hits[1].title.as_str(), hits[1].message_id.as_deref()), ("Dev server", Some("m1")));
+
+        // Prefix matching and stemming; syntax characters are taken literally
+        assert_eq!(db.search_history("fail origi", &SearchFilter::default()).unwrap().len(), 0);
+        assert_eq!(db.search_history("origi", &SearchFilter::default()).unwrap().len(), 2);
+        assert_eq!(db.search_history("calling", &SearchFilter::default()).unwrap().len(), 1);
+        assert!(db.search_history("\"cors\" OR (", &SearchFilter::default()).unwrap().is_empty());
+        assert!(db.search_history("  ", &SearchFilter::default()).unwrap().is_empty());
+
+        // Updates and deletes reach the index
+        db.update_message_content(&reply.id, "Configure CORS in the proxy").unwrap();
+        assert_eq!(db.search_history("cors", &filter(Some(SearchKind::Conversation))).unwrap().len(), 2);
+        assert_eq!(db.search_history("cors", &filter(Some(SearchKind::Task))).unwrap().len(), 1);
+        db.delete_conversation("c1").unwrap();
+        assert!(db.search_history("cors", &filter(Some(SearchKind::Conversation))).unwrap().is_empty());
+
+        let api = |project: &str| SearchFilter {
+            project_path: Some(project.to_string()),
+            ..Default::default()
+        };
+        let hits = db.search_history("api", &api("/work/docs")).unwrap();
+        assert_eq!(hits.iter().map(|h| h.id.as_str()).collect::<Vec<_>>(), ["t2"]);
+        assert_eq!(hits[0].project_path.as_deref(), Some("/work/docs"));
+
+        let future = SearchFilter {
+            from: Some(hits[0].timestamp + 1),
+            ..Default::default()
+        };
+        assert!(db.search_history("api", &future).unwrap().is_empty());
+        db.delete_task("t2").unwrap();
+        assert!(db.search_history("api", &api("/work/docs")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+use thiserror::Error;
+
+/// Environment variable holding a passphrase to derive the key from, in place of the OS keyring
+pub const PASSPHRASE_ENV: &str = "KUSE_COWORK_PASSPHRASE";
+
+/// Keyring entry holding the randomly generated key
+const KEYRING_SERVICE: &str = "kuse-cowork";
+const KEYRING_USER: &str = "secret-store-key";
+
+/// PBKDF2-HMAC-SHA256 rounds for passphrase-derived keys (OWASP recommendation)
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Version prefix of stored ciphertexts
+const CIPHERTEXT_PREFIX: &str = "v1:";
+
+/// Start of a masked secret as sent to the frontend
+pub const MASK_PREFIX: &str = "••••••••";
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("OS keyring unavailable ({0}); set {PASSPHRASE_ENV} to use a passphrase instead")]
+    Keyring(String),
+    #[error("Secret store key does not match; was it created with a different passphrase or keyring?")]
+    WrongKey,
+    #[error("The OS keyring has no key for the saved secrets; were they saved with {PASSPHRASE_ENV}?")]
+    MissingKey,
+    #[error("Secret store is locked; unlock it or reset the saved keys in Settings")]
+    Locked,
+    #[error("Corrupt secret: {0}")]
+    Corrupt(String),
+    #[error("Encryption failed")]
+    Crypto,
+}
+
+/// AES-256-GCM key for the secret store
+pub struct SecretKey(LessSafeKey);
+
+impl SecretKey {
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        // A 32-byte key is always valid for AES-256-GCM
+        Self(LessSafeKey::new(UnboundKey::new(&AES_256_GCM, bytes).expect("AES-256 key length")))
+    }
+
+    /// Key kept in the OS keyring. A missing one is only generated when `create` is
+    /// set, so existing secrets never end up under a new key.
+    pub fn from_keyring(create: bool) -> Result<Self, SecretError> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|e| SecretError::Keyring(e.to_string()))?;
+        let encoded = match entry.get_password() {
+            Ok(encoded) => encoded,
+            Err(keyring::Error::NoEntry) if !create => return Err(SecretError::MissingKey),
+            Err(keyring::Error::NoEntry) => {
+                let encoded = STANDARD.encode(random_bytes::<32>()?);
+                entry.set_password(&encoded).map_err(|e| SecretError::Keyring(e.to_string()))?;
+                encoded
+            }
+            Err(e) => return Err(SecretError::Keyring(e.to_string())),
+        };
+
+        let bytes: [u8; 32] = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| SecretError::Keyring("stored key is not 32 bytes of base64".to_string()))?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    /// Key derived from a passphrase with PBKDF2
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Self {
+        let mut bytes = [0u8; 32];
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ITERATIONS).expect("non-zero iterations"),
+            salt,
+            passphrase.as_bytes(),
+            &mut bytes,
+        );
+        Self::from_bytes(&bytes)
+    }
+
+    /// Encrypt a secret. The name is authenticated with it, so a ciphertext can't be
+    /// moved to another slot.
+    pub fn encrypt(&self, name: &str, plaintext: &str) -> Result<String, SecretError> {
+        let nonce = random_bytes::<NONCE_LEN>()?;
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.0
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(name.as_bytes()), &mut in_out)
+            .map_err(|_| SecretError::Crypto)?;
+
+        let mut stored = nonce.to_vec();
+        stored.extend_from_slice(&in_out);
+        Ok(format!("{}{}", CIPHERTEXT_PREFIX, STANDARD.encode(stored)))
+    }
+
+    pub fn decrypt(&self, name: &str, stored: &str) -> Result<String, SecretError> {
+        let encoded = stored
+            .strip_prefix(CIPHERTEXT_PREFIX)
+            .ok_or_else(|| SecretError::Corrupt(format!("{} has an unknown format", name)))?;
+        let mut bytes = STANDARD
+            .decode(encoded)
+            .map_err(|e| SecretError::Corrupt(format!("{}: {}", name, e)))?;
+        if bytes.len() < NONCE_LEN {
+            return Err(SecretError::Corrupt(format!("{} is truncated", name)));
+        }
+
+        let mut in_out = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).map_err(|_| SecretError::Crypto)?;
+        let plaintext = self
+            .0
+            .open_in_place(nonce, Aad::from(name.as_bytes()), &mut in_out)
+            .map_err(|_| SecretError::WrongKey)?;
+        String::from_utf8(plaintext.to_vec()).map_err(|e| SecretError::Corrupt(format!("{}: {}", name, e)))
+    }
+}
+
+pub fn random_bytes<const N: usize>() -> Result<[u8; N], SecretError> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| SecretError::Crypto)?;
+    Ok(bytes)
+}
+
+/// Secret as shown in the frontend: only the last four characters of longer ones
+pub fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    match chars.len() {
+        0 => String::new(),
+        n if n < 12 => MASK_PREFIX.to_string(),
+        n => format!("{}{}", MASK_PREFIX, chars[n - 4..].iter().collect::<String>()),
+    }
+}
+
+/// Whether a value sent back by the frontend is a masked placeholder
+pub fn is_masked(value: &str) -> bool {
+    value.starts_with(MASK_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = SecretKey::from_bytes(&[7; 32]);
+        let stored = key.encrypt("api_key", "sk-ant-secret").unwrap();
+        assert!(stored.starts_with("v1:"));
+        assert!(!stored.contains("sk-ant"));
+        assert_eq!(key.decrypt("api_key", &stored).unwrap(), "sk-ant-secret");
+
+        // Fresh nonce each time
+        assert_ne!(key.encrypt("api_key", "sk-ant-secret").unwrap(), stored);
+
+        // Wrong key, wrong slot and tampering are all rejected
+        assert!(matches!(SecretKey::from_bytes(&[8; 32]).decrypt("api_key", &stored), Err(SecretError::WrongKey)));
+        assert!(matches!(key.decrypt("provider_key:openai", &stored), Err(SecretError::WrongKey)));
+        assert!(matches!(key.decrypt("api_key", "sk-plain"), Err(SecretError::Corrupt(_))));
+
+        let passphrase = SecretKey::from_passphrase("correct horse", b"salt-1234567890");
+        let stored = passphrase.encrypt("api_key", "sk-1").unwrap();
+        assert_eq!(SecretKey::from_passphrase("correct horse", b"salt-1234567890").decrypt("api_key", &stored).unwrap(), "sk-1");
+        assert!(SecretKey::from_passphrase("battery staple", b"salt-1234567890").decrypt("api_key", &stored).is_err());
+    }
+
+    #[test]
+    fn test_mask() {
+        assert_eq!(mask(""), "");
+        assert_eq!(mask("short"), MASK_PREFIX);
+        assert_eq!(mask("sk-ant-api03-abcdwxyz"), format!("{}wxyz", MASK_PREFIX));
+        assert!(is_masked(&mask("sk-ant-api03-abcdwxyz")));
+        assert!(!is_masked("sk-ant-api03-abcdwxyz"));
+    }
+}
@@ -129,7 +129,6 @@ pub fn get_available_skills() -> Vec<SkillMetadata> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
 
     #[test]
     fn test_parse_skill_metadata() {
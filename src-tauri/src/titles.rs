@@ -0,0 +1,120 @@
+//! Short titles for conversations and tasks, written by a model after the first exchange
+
+use crate::agent::message_builder::{ApiContent, ApiMessage, ClaudeApiRequest};
+use crate::llm_client::routing::ModelRoute;
+use serde::Serialize;
+
+/// Longest title kept, in characters
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Characters of the prompt used as the title until a generated one arrives
+const PLACEHOLDER_CHARS: usize = 30;
+
+/// Characters of each message shown to the titling model
+const EXCERPT_CHARS: usize = 2000;
+
+/// Room for the title, plus reasoning on models that count it against the limit
+const TITLE_MAX_TOKENS: u32 = 256;
+
+const TITLE_PROMPT: &str = "Write a title of at most six words for the conversation below, in the language of the \
+user's message. Reply with the title only, without quotes or a final full stop.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleKind {
+    Conversation,
+    Task,
+}
+
+/// Payload of the `title-changed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct TitleChanged {
+    pub kind: TitleKind,
+    pub id: String,
+    pub title: String,
+}
+
+/// Start of the first prompt, shown while the generated title is pending
+pub fn placeholder(prompt: &str) -> String {
+    let prompt = prompt.trim();
+    match prompt.char_indices().nth(PLACEHOLDER_CHARS) {
+        Some((end, _)) => format!("{}...", &prompt[..end]),
+        None => prompt.to_string(),
+    }
+}
+
+/// Request asking the route's model to title an exchange
+pub fn title_request(model: &str, prompt: &str, reply: &str) -> ClaudeApiRequest {
+    let text = format!(
+        "{}\n\n<user>\n{}\n</user>\n\n<assistant>\n{}\n</assistant>",
+        TITLE_PROMPT,
+        excerpt(prompt),
+        excerpt(reply)
+    );
+    ClaudeApiRequest::chat(
+        model,
+        TITLE_MAX_TOKENS,
+        None,
+        vec![ApiMessage {
+            role: "user".to_string(),
+            content: ApiContent::Text(text),
+        }],
+    )
+}
+
+/// Title for an exchange from the route's model
+pub async fn generate(route: &ModelRoute, prompt: &str, reply: &str) -> Result<String, String> {
+    let response = route
+        .provider
+        .complete(&title_request(&route.model, prompt, reply))
+        .await
+        .map_err(|e| e.to_string())?;
+    clean(&response.text).ok_or_else(|| format!("{} returned an empty title", route.label()))
+}
+
+/// First line of a model's answer without the decoration models like to add
+pub fn clean(answer: &str) -> Option<String> {
+    let line = answer.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = match line.get(..6) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("title:") => &line[6..],
+        _ => line,
+    };
+    let line = line
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '`' | '*' | '#' | '“' | '”'))
+        .trim_end_matches('.');
+
+    let mut title = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    if let Some((end, _)) = title.char_indices().nth(MAX_TITLE_CHARS) {
+        title.truncate(end);
+        title.truncate(title.trim_end().len());
+    }
+    (!title.is_empty()).then_some(title)
+}
+
+fn excerpt(text: &str) -> &str {
+    match text.char_indices().nth(EXCERPT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(clean("Fixing CORS errors").as_deref(), Some("Fixing CORS errors"));
+        assert_eq!(clean("\n  Title: \"Fixing CORS errors.\"\nThis covers...").as_deref(), Some("Fixing CORS errors"));
+        assert_eq!(clean("**Débogage   du proxy**").as_deref(), Some("Débogage du proxy"));
+        assert_eq!(clean("“Rust lifetimes”").as_deref(), Some("Rust lifetimes"));
+        assert_eq!(clean(&"word ".repeat(30)).unwrap().chars().count(), 59);
+        assert_eq!(clean("  \n\"\"\n"), None);
+    }
+
+    #[test]
+    fn test_placeholder() {
+        assert_eq!(placeholder("Short question"), "Short question");
+        assert_eq!(placeholder(&"é".repeat(40)), format!("{}...", "é".repeat(30)));
+    }
+}
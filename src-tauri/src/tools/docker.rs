@@ -1,4 +1,4 @@
-use crate::agent::{ToolDefinition, ToolResult, ToolUse};
+use crate::agent::{DockerDefaults, ToolDefinition, ToolResult, ToolUse};
 use bollard::container::{
     Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions,
     WaitContainerOptions,
@@ -66,19 +66,19 @@ pub fn get_docker_tools() -> Vec<ToolDefinition> {
 }
 
 /// Execute a Docker tool (sync wrapper for non-async contexts)
-pub fn execute_docker_tool(tool_use: &ToolUse, project_path: &Option<String>) -> ToolResult {
+pub fn execute_docker_tool(tool_use: &ToolUse, project_path: &Option<String>, defaults: &DockerDefaults) -> ToolResult {
     // Use a separate thread to avoid blocking the async runtime
     std::thread::scope(|s| {
         s.spawn(|| {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
-                execute_docker_tool_inner(tool_use, project_path).await
+                execute_docker_tool_inner(tool_use, project_path, defaults).await
             })
         }).join().unwrap()
     })
 }
 
-async fn execute_docker_tool_inner(tool_use: &ToolUse, project_path: &Option<String>) -> ToolResult {
+async fn execute_docker_tool_inner(tool_use: &ToolUse, project_path: &Option<String>, defaults: &DockerDefaults) -> ToolResult {
     let docker = match Docker::connect_with_local_defaults() {
         Ok(d) => d,
         Err(e) => {
@@ -90,16 +90,17 @@ async fn execute_docker_tool_inner(tool_use: &ToolUse, project_path: &Option<Str
     };
 
     match tool_use.name.as_str() {
-        "docker_run" => docker_run(&docker, tool_use, project_path).await,
+        "docker_run" => docker_run(&docker, tool_use, project_path, defaults).await,
         "docker_list" => docker_list(&docker, tool_use).await,
         "docker_images" => docker_images(&docker, tool_use).await,
         _ => ToolResult::error(tool_use.id.clone(), format!("Unknown docker tool: {}", tool_use.name)),
     }
 }
 
-async fn docker_run(docker: &Docker, tool_use: &ToolUse, project_path: &Option<String>) -> ToolResult {
+async fn docker_run(docker: &Docker, tool_use: &ToolUse, project_path: &Option<String>, defaults: &DockerDefaults) -> ToolResult {
     let image = tool_use.input.get("image")
         .and_then(|v| v.as_str())
+        .or(defaults.image.as_deref())
         .unwrap_or("python:3.11-alpine");
 
     let command = match tool_use.input.get("command").and_then(|v| v.as_str()) {
@@ -123,6 +124,9 @@ async fn docker_run(docker: &Docker, tool_use: &ToolUse, project_path: &Option<S
     let skills_dir = crate::skills::ensure_skills_directory();
     binds.push(format!("{}:/skills:ro", skills_dir.display()));
 
+    // Add project-configured mounts
+    binds.extend(defaults.mounts.iter().cloned());
+
     // Add custom mounts
     if let Some(mounts) = tool_use.input.get("mounts").and_then(|v| v.as_array()) {
         for mount in mounts {
@@ -0,0 +1,495 @@
+use crate::agent::{AgentContent, AgentMessage, ContentBlock};
+use crate::database::{tool_calls_json, Conversation, Database, DbError, Message, PlanStep, Task, TaskMessage};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Version of the JSON export format; imports accept this one and older.
+/// Version 2 holds every branch of a conversation, version 1 its active branch only.
+pub const EXPORT_VERSION: u32 = 2;
+
+const EXPORT_APP: &str = "kuse-cowork";
+
+/// A tool call made while producing an assistant message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    pub tool: String,
+    pub input: Value,
+    pub result: String,
+    pub success: bool,
+}
+
+/// Tool calls in a run's agent messages, in call order, with their results
+pub fn tool_calls(messages: &[AgentMessage]) -> Vec<ToolCallRecord> {
+    let results: HashMap<&str, _> = messages
+        .iter()
+        .filter_map(|m| match &m.content {
+            AgentContent::ToolResults(results) => Some(results),
+            _ => None,
+        })
+        .flatten()
+        .map(|r| (r.tool_use_id.as_str(), r))
+        .collect();
+
+    messages
+        .iter()
+        .filter_map(|m| match &m.content {
+            AgentContent::Blocks(blocks) => Some(blocks),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, input, .. } => Some(ToolCallRecord {
+                tool: name.clone(),
+                input: input.clone(),
+                result: results.get(id.as_str()).map(|r| r.content.clone()).unwrap_or_default(),
+                success: results.get(id.as_str()).is_some_and(|r| r.is_error.is_none()),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ExportData {
+    Conversation {
+        conversation: Conversation,
+        /// Every message on every branch, oldest first, linked by `parent_id`
+        messages: Vec<Message>,
+        /// Last message of the active branch; unset in version 1 exports, whose
+        /// messages are the active branch
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        active_leaf_id: Option<String>,
+    },
+    Task {
+        task: Task,
+        messages: Vec<TaskMessage>,
+    },
+}
+
+/// Lossless export of a conversation or task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Export {
+    pub app: String,
+    pub version: u32,
+    pub exported_at: i64,
+    #[serde(flatten)]
+    pub data: ExportData,
+}
+
+/// What an import created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Imported {
+    pub kind: String,
+    pub id: String,
+}
+
+impl Export {
+    fn new(data: ExportData) -> Self {
+        Self {
+            app: EXPORT_APP.to_string(),
+            version: EXPORT_VERSION,
+            exported_at: chrono::Utc::now().timestamp_millis(),
+            data,
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let export: Export = serde_json::from_str(json).map_err(|e| format!("Not a valid export: {}", e))?;
+        if export.app != EXPORT_APP {
+            return Err(format!("Not a Kuse Cowork export (app is \"{}\")", export.app));
+        }
+        if export.version > EXPORT_VERSION {
+            return Err(format!(
+                "Export format version {} is newer than this app supports ({}); please update the app",
+                export.version, EXPORT_VERSION
+            ));
+        }
+        Ok(export)
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            ExportFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    /// Readable transcript, with each tool call in a collapsible section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let messages: Vec<(&str, &str, i64, &[ToolCallRecord])> = match &self.data {
+            ExportData::Conversation { conversation, messages, active_leaf_id } => {
+                out.push_str(&format!("# {}\n\n", conversation.title));
+                out.push_str(&format!("_Conversation started {}_\n\n", format_time(conversation.created_at)));
+                active_branch(messages, active_leaf_id.as_deref())
+                    .into_iter()
+                    .map(|m| (m.role.as_str(), m.content.as_str(), m.timestamp, m.tool_calls.as_slice()))
+                    .collect()
+            }
+            ExportData::Task { task, messages } => {
+                out.push_str(&format!("# {}\n\n", task.title));
+                out.push_str(&format!("{}\n\n", task.description));
+                out.push_str(&format!("- **Status:** {}\n", task.status));
+                if let Some(path) = &task.project_path {
+                    out.push_str(&format!("- **Project:** `{}`\n", path));
+                }
+                out.push_str(&format!("- **Created:** {}\n\n", format_time(task.created_at)));
+                if let Some(plan) = task.plan.as_ref().filter(|p| !p.is_empty()) {
+                    out.push_str("## Plan\n\n");
+                    for step in plan {
+                        out.push_str(&plan_line(step));
+                    }
+                    out.push('\n');
+                }
+                messages
+                    .iter()
+                    .map(|m| (m.role.as_str(), m.content.as_str(), m.timestamp, m.tool_calls.as_slice()))
+                    .collect()
+            }
+        };
+
+        for (role, content, timestamp, tool_calls) in messages {
+            let role = match role {
+                "user" => "User",
+                "assistant" => "Assistant",
+                other => other,
+            };
+            out.push_str(&format!("## {} · {}\n\n", role, format_time(timestamp)));
+            for call in tool_calls {
+                out.push_str(&tool_call_section(call));
+            }
+            out.push_str(content.trim_end());
+            out.push_str("\n\n");
+        }
+        out
+    }
+}
+
+/// Messages from the first one down to `leaf`; all of them when there's no leaf
+fn active_branch<'a>(messages: &'a [Message], leaf: Option<&str>) -> Vec<&'a Message> {
+    let Some(leaf) = leaf else {
+        return messages.iter().collect();
+    };
+    let by_id: HashMap<&str, &Message> = messages.iter().map(|m| (m.id.as_str(), m)).collect();
+
+    let mut branch = Vec::new();
+    let mut next = by_id.get(leaf).copied();
+    while let Some(message) = next {
+        branch.push(message);
+        next = message.parent_id.as_deref().and_then(|id| by_id.get(id).copied());
+    }
+    branch.reverse();
+    branch
+}
+
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn plan_line(step: &PlanStep) -> String {
+    let check = if step.status == "completed" { "x" } else { " " };
+    let note = match step.status.as_str() {
+        "completed" | "pending" => String::new(),
+        status => format!(" _({})_", status),
+    };
+    format!("- [{}] {}. {}{}\n", check, step.step, step.description, note)
+}
+
+/// Code block whose fence is longer than any backtick run in the text
+fn code_block(text: &str, language: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{language}\n{}\n{fence}\n", text.trim_end())
+}
+
+fn tool_call_section(call: &ToolCallRecord) -> String {
+    let status = if call.success { "" } else { " (failed)" };
+    let input = serde_json::to_string_pretty(&call.input).unwrap_or_default();
+    format!(
+        "<details>\n<summary>Tool: {}{}</summary>\n\n**Input**\n\n{}\n**Result**\n\n{}\n</details>\n\n",
+        call.tool,
+        status,
+        code_block(&input, "json"),
+        code_block(&call.result, "")
+    )
+}
+
+impl Database {
+    pub fn export_conversation(&self, id: &str) -> Result<Option<Export>, DbError> {
+        let conversation = self.get_conversation(id)?;
+        let Some(conversation) = conversation else {
+            return Ok(None);
+        };
+        let active_leaf_id = self.get_messages(id)?.pop().map(|m| m.id);
+        let messages = self.get_message_tree(id)?;
+        Ok(Some(Export::new(ExportData::Conversation {
+            conversation,
+            messages,
+            active_leaf_id,
+        })))
+    }
+
+    pub fn export_task(&self, id: &str) -> Result<Option<Export>, DbError> {
+        let Some(task) = self.get_task(id)? else {
+            return Ok(None);
+        };
+        let messages = self.get_task_messages(id)?;
+        Ok(Some(Export::new(ExportData::Task { task, messages })))
+    }
+
+    /// Restore an export under new ids, keeping titles and timestamps
+    pub fn import(&self, export: &Export) -> Result<Imported, DbError> {
+        let mut conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let tx = conn.transaction()?;
+        let new_id = || uuid::Uuid::new_v4().to_string();
+        let id = new_id();
+
+        let kind = match &export.data {
+            ExportData::Conversation { conversation, messages, active_leaf_id } => {
+                tx.execute(
+                    "INSERT INTO conversations (id, title, project_path, pinned, archived, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        id,
+                        conversation.title,
+                        conversation.project_path,
+                        conversation.pinned,
+                        conversation.archived,
+                        conversation.created_at,
+                        conversation.updated_at
+                    ],
+                )?;
+                // Version 1 exports hold the active branch only, so the copy is one chain
+                let ids: HashMap<&str, String> = messages.iter().map(|m| (m.id.as_str(), new_id())).collect();
+                let mut previous: Option<&str> = None;
+                for message in messages {
+                    let message_id = &ids[message.id.as_str()];
+                    let parent_id = match active_leaf_id {
+                        Some(_) => message.parent_id.as_deref().and_then(|id| ids.get(id)),
+                        None => previous.and_then(|id| ids.get(id)),
+                    };
+                    tx.execute(
+                        "INSERT INTO messages (id, conversation_id, role, content, timestamp, tool_calls, parent_id)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            message_id,
+                            id,
+                            message.role,
+                            message.content,
+                            message.timestamp,
+                            tool_calls_json(&message.tool_calls),
+                            parent_id
+                        ],
+                    )?;
+                    previous = Some(message.id.as_str());
+                }
+                let leaf = active_leaf_id.as_deref().or(previous).and_then(|id| ids.get(id));
+                tx.execute(
+                    "UPDATE conversations SET active_leaf_id = ?1 WHERE id = ?2",
+                    params![leaf, id],
+                )?;
+                "conversation"
+            }
+            ExportData::Task { task, messages } => {
+                // The exporting app may have been mid-run; the copy isn't
+                let status = if task.status == "running" { "paused" } else { task.status.as_str() };
+                let plan = task.plan.as_ref().map(|p| serde_json::to_string(p).unwrap_or_default());
+                tx.execute(
+                    "INSERT INTO tasks (id, title, description, status, plan, current_step, project_path, pinned, archived,
+                         created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        id,
+                        task.title,
+                        task.description,
+                        status,
+                        plan,
+                        task.current_step,
+                        task.project_path,
+                        task.pinned,
+                        task.archived,
+                        task.created_at,
+                        task.updated_at
+                    ],
+                )?;
+                for message in messages {
+                    tx.execute(
+                        "INSERT INTO task_messages (id, task_id, role, content, timestamp, tool_calls)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            new_id(),
+                            id,
+                            message.role,
+                            message.content,
+                            message.timestamp,
+                            tool_calls_json(&message.tool_calls)
+                        ],
+                    )?;
+                }
+                "task"
+            }
+        };
+
+        tx.commit()?;
+        Ok(Imported { kind: kind.to_string(), id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::ToolResult;
+    use serde_json::json;
+
+    fn read_call() -> ToolCallRecord {
+        ToolCallRecord {
+            tool: "read_file".to_string(),
+            input: json!({"path": "README.md"}),
+            result: "# Demo\n```sh\nmake\n```".to_string(),
+            success: true,
+        }
+    }
+
+    #[test]
+    fn test_tool_calls_from_transcript() {
+        let messages = vec![
+            AgentMessage {
+                role: "assistant".to_string(),
+                content: AgentContent::Blocks(vec![
+                    ContentBlock::Text { text: "Reading".to_string() },
+                    ContentBlock::ToolUse {
+                        id: "t1".to_string(),
+                        name: "read_file".to_string(),
+                        input: json!({"path": "README.md"}),
+                        thought_signature: None,
+                    },
+                    ContentBlock::ToolUse {
+                        id: "t2".to_string(),
+                        name: "bash".to_string(),
+                        input: json!({"command": "false"}),
+                        thought_signature: None,
+                    },
+                ]),
+            },
+            AgentMessage {
+                role: "user".to_string(),
+                content: AgentContent::ToolResults(vec![
+                    ToolResult::error("t2".to_string(), "exit 1".to_string()),
+                    ToolResult::success("t1".to_string(), "# Demo\n```sh\nmake\n```".to_string()),
+                ]),
+            },
+        ];
+
+        let calls = tool_calls(&messages);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0], read_call());
+        assert_eq!((calls[1].tool.as_str(), calls[1].success), ("bash", false));
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let db = Database::in_memory();
+        db.create_conversation("c1", "CORS bug", None).unwrap();
+        db.add_message("m1", "c1", "user", "Why does the fetch fail?").unwrap();
+        db.add_message("m2", "c1", "assistant", "The origin was missing.").unwrap();
+        db.set_message_tool_calls("m2", &[read_call()]).unwrap();
+
+        let export = db.export_conversation("c1").unwrap().unwrap();
+        let markdown = export.render(ExportFormat::Markdown);
+        assert!(markdown.starts_with("# CORS bug\n"), "{markdown}");
+        assert!(markdown.contains("## Assistant · "), "{markdown}");
+        assert!(markdown.contains("<summary>Tool: read_file</summary>"), "{markdown}");
+        // The result's own fence doesn't end the block
+        assert!(markdown.contains("````\n# Demo\n```sh\nmake\n```\n````"), "{markdown}");
+
+        // JSON round-trips into a copy with new ids
+        let json = export.render(ExportFormat::Json);
+        let imported = db.import(&Export::from_json(&json).unwrap()).unwrap();
+        assert_eq!(imported.kind, "conversation");
+        assert_ne!(imported.id, "c1");
+        let copy = db.get_messages(&imported.id).unwrap();
+        let original = db.get_messages("c1").unwrap();
+        assert_eq!(copy.len(), 2);
+        assert!(copy.iter().all(|m| !original.iter().any(|o| o.id == m.id)));
+        assert_eq!(copy[1].tool_calls, vec![read_call()]);
+        assert_eq!(copy[1].timestamp, original[1].timestamp);
+
+        db.create_task("t1", "Fix CORS", "Allow the dev origin", Some("/work/app")).unwrap();
+        let plan = vec![
+            PlanStep { step: 1, description: "Find the config".to_string(), status: "completed".to_string() },
+            PlanStep { step: 2, description: "Add the origin".to_string(), status: "running".to_string() },
+        ];
+        db.update_task_plan("t1", &plan).unwrap();
+        db.add_task_message("tm1", "t1", "assistant", "Working on it").unwrap();
+
+        let export = db.export_task("t1").unwrap().unwrap();
+        let markdown = export.to_markdown();
+        assert!(markdown.contains("- [x] 1. Find the config\n- [ ] 2. Add the origin _(running)_\n"), "{markdown}");
+        assert!(markdown.contains("- **Project:** `/work/app`"), "{markdown}");
+
+        let imported = db.import(&Export::from_json(&export.render(ExportFormat::Json)).unwrap()).unwrap();
+        let task = db.get_task(&imported.id).unwrap().unwrap();
+        assert_eq!(task.plan.unwrap().len(), 2);
+        assert_eq!(task.status, "paused");
+        assert_eq!(db.get_task_messages(&imported.id).unwrap()[0].content, "Working on it");
+
+        assert!(db.export_task("missing").unwrap().is_none());
+        let newer = json.replacen("\"version\": 2", "\"version\": 99", 1);
+        assert!(Export::from_json(&newer).unwrap_err().contains("newer"));
+        assert!(Export::from_json("{\"app\": \"other\"}").is_err());
+    }
+
+    #[test]
+    fn test_export_keeps_branches() {
+        let db = Database::in_memory();
+        db.create_conversation("c1", "Colours", None).unwrap();
+        db.add_message("u1", "c1", "user", "Name a colour").unwrap();
+        db.add_message("a1", "c1", "assistant", "Red").unwrap();
+        db.add_message_after("a1b", "c1", "assistant", "Blue", Some("u1")).unwrap();
+        db.add_message("u2", "c1", "user", "Another").unwrap();
+        db.add_message("a2", "c1", "assistant", "Green").unwrap();
+        db.switch_branch("c1", "a1").unwrap();
+
+        let export = db.export_conversation("c1").unwrap().unwrap();
+        let markdown = export.to_markdown();
+        assert!(markdown.contains("Red") && !markdown.contains("Blue"), "{markdown}");
+
+        // The copy has the whole tree and the same active branch
+        let json = export.render(ExportFormat::Json);
+        let imported = db.import(&Export::from_json(&json).unwrap()).unwrap();
+        let contents = |messages: Vec<Message>| messages.into_iter().map(|m| m.content).collect::<Vec<_>>();
+        assert_eq!(contents(db.get_messages(&imported.id).unwrap()), ["Name a colour", "Red"]);
+        assert_eq!(db.get_messages(&imported.id).unwrap()[1].branches.len(), 2);
+        assert_eq!(db.get_message_tree(&imported.id).unwrap().len(), 5);
+        let blue = db.get_message_tree(&imported.id).unwrap().into_iter().find(|m| m.content == "Blue").unwrap();
+        db.switch_branch(&imported.id, &blue.id).unwrap();
+        assert_eq!(contents(db.get_messages(&imported.id).unwrap()), ["Name a colour", "Blue", "Another", "Green"]);
+
+        // Version 1 exports hold the active branch as a list
+        let mut v1: Value = serde_json::from_str(&json).unwrap();
+        v1["version"] = json!(1);
+        v1.as_object_mut().unwrap().remove("active_leaf_id");
+        v1["messages"] = json!([v1["messages"][0], v1["messages"][1]]);
+        for message in v1["messages"].as_array_mut().unwrap() {
+            message.as_object_mut().unwrap().remove("parent_id");
+        }
+        let imported = db.import(&Export::from_json(&v1.to_string()).unwrap()).unwrap();
+        assert_eq!(contents(db.get_messages(&imported.id).unwrap()), ["Name a colour", "Red"]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Deleted conversations and tasks, kept until restored or purged
+
+use crate::database::{Conversation, Database, DbError, Task};
+use rusqlite::Transaction;
+use serde::{Deserialize, Serialize};
+
+/// Days deleted items are kept when settings don't say otherwise
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Contents of the trash, most recently deleted first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trash {
+    pub conversations: Vec<Conversation>,
+    pub tasks: Vec<Task>,
+}
+
+impl Database {
+    pub fn list_trash(&self) -> Result<Trash, DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM conversations c WHERE c.deleted_at IS NOT NULL ORDER BY c.deleted_at DESC, c.id",
+            Conversation::COLUMNS
+        ))?;
+        let conversations = stmt.query_map([], Conversation::from_row)?.collect::<Result<_, _>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM tasks t WHERE t.deleted_at IS NOT NULL ORDER BY t.deleted_at DESC, t.id",
+            Task::COLUMNS
+        ))?;
+        let tasks = stmt.query_map([], Task::from_row)?.collect::<Result<_, _>>()?;
+
+        Ok(Trash { conversations, tasks })
+    }
+
+    pub fn restore_conversation(&self, id: &str) -> Result<(), DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute("UPDATE conversations SET deleted_at = NULL WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    pub fn restore_task(&self, id: &str) -> Result<(), DbError> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute("UPDATE tasks SET deleted_at = NULL WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    /// Permanently delete items trashed at or before `before` (milliseconds since
+    /// the epoch), or everything in the trash. Returns how many were deleted.
+    pub fn purge_trash(&self, before: Option<i64>) -> Result<usize, DbError> {
+        let mut conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let tx = conn.transaction()?;
+        let before = before.unwrap_or(i64::MAX);
+        let purged = purge(&tx, "conversations", &["messages", "conversation_tags"], "conversation_id", before)?
+            + purge(&tx, "tasks", &["task_messages", "task_tags"], "task_id", before)?;
+        tx.commit()?;
+        Ok(purged)
+    }
+
+    /// Purge items that have been in the trash longer than `retention_days`;
+    /// 0 keeps them
+    pub fn purge_expired_trash(&self, retention_days: u32) -> Result<usize, DbError> {
+        if retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = chrono::Utc::now().timestamp_millis() - i64::from(retention_days) * DAY_MS;
+        self.purge_trash(Some(cutoff))
+    }
+}
+
+/// Delete trashed rows of `table` with the rows of `children` that point at them
+fn purge(tx: &Transaction, table: &str, children: &[&str], parent_column: &str, before: i64) -> rusqlite::Result<usize> {
+    let trashed = format!("SELECT id FROM {table} WHERE deleted_at IS NOT NULL AND deleted_at <= ?1");
+    for child in children {
+        tx.execute(&format!("DELETE FROM {child} WHERE {parent_column} IN ({trashed})"), [before])?;
+    }
+    tx.execute(&format!("DELETE FROM {table} WHERE id IN ({trashed})"), [before])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Settings;
+    use crate::history::ListQuery;
+
+    fn count(db: &Database, sql: &str) -> u32 {
+        db.conn.lock().unwrap().query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_trash() {
+        let db = Database::in_memory();
+        db.create_conversation("c1", "Chat", None).unwrap();
+        db.add_message("m1", "c1", "user", "Keep the transcript").unwrap();
+        db.create_task("t1", "Task", "Do it", None).unwrap();
+        db.add_task_message("tm1", "t1", "user", "Do it").unwrap();
+
+        db.delete_conversation("c1").unwrap();
+        db.delete_task("t1").unwrap();
+        assert!(db.list_conversations(&ListQuery::default()).unwrap().is_empty());
+        assert!(db.list_tasks(&ListQuery::default()).unwrap().is_empty());
+        assert!(db.search_history("transcript", &Default::default()).unwrap().is_empty());
+
+        let trash = db.list_trash().unwrap();
+        assert_eq!((trash.conversations.len(), trash.tasks.len()), (1, 1));
+        assert!(trash.conversations[0].deleted_at.is_some());
+        assert_eq!(db.get_messages("c1").unwrap().len(), 1);
+
+        db.restore_conversation("c1").unwrap();
+        let restored = db.list_conversations(&ListQuery::default()).unwrap();
+        assert_eq!((restored.len(), restored[0].deleted_at), (1, None));
+        assert_eq!(db.search_history("transcript", &Default::default()).unwrap().len(), 1);
+
+        // Emptying the trash removes the task for good
+        assert_eq!(db.purge_trash(None).unwrap(), 1);
+        assert!(db.get_task("t1").unwrap().is_none());
+        assert_eq!(count(&db, "SELECT count(*) FROM task_messages"), 0);
+        assert_eq!(count(&db, "SELECT count(*) FROM messages"), 1);
+    }
+
+    #[test]
+    fn test_retention() {
+        let db = Database::in_memory();
+        db.save_settings(&Settings { trash_retention_days: 7, ..Settings::default() }).unwrap();
+        assert_eq!(db.get_settings().unwrap().trash_retention_days, 7);
+
+        for id in ["old", "recent"] {
+            db.create_conversation(id, id, None).unwrap();
+            db.add_message(&format!("{id}-m"), id, "user", "Hi").unwrap();
+            db.delete_conversation(id).unwrap();
+        }
+        let eight_days_ago = chrono::Utc::now().timestamp_millis() - 8 * DAY_MS;
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE conversations SET deleted_at = ?1 WHERE id = 'old'", [eight_days_ago])
+            .unwrap();
+
+        assert_eq!(db.purge_expired_trash(0).unwrap(), 0);
+        db.run_maintenance().unwrap();
+        let trash = db.list_trash().unwrap();
+        assert_eq!(trash.conversations.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ["recent"]);
+        assert_eq!(count(&db, "SELECT count(*) FROM messages"), 1);
+    }
+}
@@ -1,6 +1,6 @@
-import { Component, Show, createSignal, onMount } from "solid-js";
+import { Component, Show, createSignal, onCleanup, onMount } from "solid-js";
 import { useSettings, loadSettings } from "./stores/settings";
-import { Task, TaskMessage, AgentEvent, listTasks, createTask, deleteTask, runTaskAgent, getTask, getTaskMessages } from "./lib/tauri-api";
+import { Task, TaskMessage, AgentEvent, listTasks, createTask, deleteTask, runTaskAgent, getTask, getTaskMessages, onTitleChanged } from "./lib/tauri-api";
 import AgentMain from "./components/AgentMain";
 import Settings from "./components/Settings";
 import SkillsList from "./components/SkillsList";
@@ -32,6 +32,16 @@ const App: Component = () => {
   onMount(async () => {
     await loadSettings();
     await refreshTasks();
+
+    // Generated titles arrive after the first exchange
+    const unlisten = await onTitleChanged((change) => {
+      if (change.kind !== "task") return;
+      setTasks((tasks) => tasks.map((t) => (t.id === change.id ? { ...t, title: change.title } : t)));
+      if (activeTask()?.id === change.id) {
+        setActiveTask({ ...activeTask()!, title: change.title });
+      }
+    });
+    onCleanup(unlisten);
   });
 
   const toggleSkills = () => {
@@ -131,10 +141,12 @@ const App: Component = () => {
           if (!prev) return prev;
           return {
             ...prev,
+            current_step:
+              event.steps.find((s) => s.status === "running")?.step ?? prev.current_step,
             plan: event.steps.map((s) => ({
               step: s.step,
               description: s.description,
-              status: "pending" as const,
+              status: s.status,
             })),
           };
         });
@@ -184,12 +196,21 @@ const App: Component = () => {
           return { ...prev, status: "completed" };
         });
         break;
+      case "warning":
+        console.warn("Agent warning:", event.message);
+        break;
       case "error":
         setActiveTask((prev) => {
           if (!prev) return prev;
           return { ...prev, status: "failed" };
         });
         break;
+      case "stopped":
+        setActiveTask((prev) => {
+          if (!prev) return prev;
+          return { ...prev, status: event.reason === "turn_limit" ? "paused" : "stopped" };
+        });
+        break;
     }
   };
 
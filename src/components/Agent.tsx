@@ -70,6 +70,7 @@ const Agent: Component = () => {
         setIsRunning(false);
         break;
       case "error":
+      case "stopped":
         setError(event.message);
         setIsRunning(false);
         break;
@@ -82,6 +82,9 @@ const Chat: Component = () => {
         });
         scrollToBottom();
         break;
+      case "warning":
+        console.warn("Chat warning:", event.message);
+        break;
       case "done":
         updateLastMessage(event.final_text);
         scrollToBottom();
@@ -1,5 +1,6 @@
 import { Component, For, Show, createSignal, createMemo, onMount } from "solid-js";
-import { AVAILABLE_MODELS, PROVIDER_PRESETS, ProviderConfig } from "../stores/settings";
+import { availableModels, PROVIDER_PRESETS, ProviderConfig, ModelOption } from "../stores/settings";
+import { listModels, DiscoveredModel } from "../lib/tauri-api";
 import "./ModelSelector.css";
 
 // Ollama model info interface
@@ -46,7 +47,7 @@ interface ModelSelectorProps {
 const ModelSelector: Component<ModelSelectorProps> = (props) => {
   // Determine current model's provider type
   const getCurrentProviderType = (): ProviderType => {
-    const model = AVAILABLE_MODELS.find(m => m.id === props.value);
+    const model = availableModels().find(m => m.id === props.value);
     if (!model) {
       // Check if it's an Ollama model (non-preset)
       if (props.value && !props.value.includes("/")) {
@@ -63,6 +64,7 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
   const [ollamaStatus, setOllamaStatus] = createSignal<OllamaStatus>("checking");
   const [ollamaModels, setOllamaModels] = createSignal<OllamaModel[]>([]);
   const [ollamaBaseUrl, _setOllamaBaseUrl] = createSignal("http://localhost:11434");
+  const [customModels, setCustomModels] = createSignal<DiscoveredModel[]>([]);
 
   // Cloud provider categories
   const cloudProviderCategories = {
@@ -78,10 +80,10 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
 
   // Get cloud models grouped by provider
   const cloudModels = createMemo(() => {
-    const result: Record<string, { name: string; models: typeof AVAILABLE_MODELS }> = {};
+    const result: Record<string, { name: string; models: ModelOption[] }> = {};
 
     for (const [key, category] of Object.entries(cloudProviderCategories)) {
-      const models = AVAILABLE_MODELS.filter(m => category.providers.includes(m.provider));
+      const models = availableModels().filter(m => category.providers.includes(m.provider));
       if (models.length > 0) {
         result[key] = { name: category.name, models };
       }
@@ -114,6 +116,15 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
     }
   };
 
+  // Models offered by the configured custom endpoint, suggested while typing
+  const loadCustomModels = async () => {
+    try {
+      setCustomModels(await listModels());
+    } catch {
+      setCustomModels([]);
+    }
+  };
+
   // Check Ollama status on mount
   onMount(() => {
     checkOllamaStatus();
@@ -126,7 +137,7 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
 
   // Handle model selection
   const handleCloudModelChange = (modelId: string) => {
-    const model = AVAILABLE_MODELS.find(m => m.id === modelId);
+    const model = availableModels().find(m => m.id === modelId);
     if (model) {
       props.onChange(modelId, model.baseUrl);
     }
@@ -138,7 +149,7 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
 
   // Get current provider info
   const currentProviderInfo = createMemo((): ProviderConfig | null => {
-    const model = AVAILABLE_MODELS.find(m => m.id === props.value);
+    const model = availableModels().find(m => m.id === props.value);
     if (!model) return null;
     return PROVIDER_PRESETS[model.provider] || null;
   });
@@ -166,7 +177,10 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
         </button>
         <button
           class={`provider-tab ${providerType() === "custom" ? "active" : ""}`}
-          onClick={() => setProviderType("custom")}
+          onClick={() => {
+            setProviderType("custom");
+            loadCustomModels();
+          }}
         >
           <span class="tab-icon">⚙️</span>
           <span class="tab-label">Custom</span>
@@ -291,8 +305,14 @@ const ModelSelector: Component<ModelSelectorProps> = (props) => {
                 type="text"
                 value={props.value === "custom-model" ? "" : props.value}
                 placeholder="e.g., meta-llama/Llama-3.2-8B"
+                list="custom-model-ids"
                 onInput={(e) => props.onChange(e.currentTarget.value || "custom-model")}
               />
+              <datalist id="custom-model-ids">
+                <For each={customModels()}>
+                  {(model) => <option value={model.id}>{model.info?.name}</option>}
+                </For>
+              </datalist>
             </div>
             <p class="hint">Configure API URL and key in Settings</p>
           </div>
@@ -1,18 +1,72 @@
 import { Component, createSignal, createMemo, Show } from "solid-js";
-import { useSettings, AVAILABLE_MODELS, PROVIDER_PRESETS, getProviderFromModel } from "../stores/settings";
-import { testConnection } from "../lib/tauri-api";
+import { useSettings, availableModels, getCatalogModel, PROVIDER_PRESETS, getProviderFromModel } from "../stores/settings";
+import { testConnection, getSettings, NetworkSettings, unlockSecretStore, resetSecretStore } from "../lib/tauri-api";
 import ModelSelector from "./ModelSelector";
 import "./Settings.css";
 
 const Settings: Component = () => {
-  const { settings, updateSetting, toggleSettings } = useSettings();
+  const { settings, updateSetting, toggleSettings, secretStore, loadSettings } = useSettings();
   const [testing, setTesting] = createSignal(false);
   const [testResult, setTestResult] = createSignal<string | null>(null);
 
+  // Stored API keys are masked; the full key is only fetched on request
+  const [revealedKey, setRevealedKey] = createSignal<string | null>(null);
+  const toggleReveal = async () => {
+    if (revealedKey() !== null) {
+      setRevealedKey(null);
+      return;
+    }
+    const stored = await getSettings(true);
+    const provider = getProviderFromModel(settings().model);
+    setRevealedKey(stored.provider_keys?.[provider] || stored.api_key || "");
+  };
+
+  const updateNetwork = (changes: Partial<NetworkSettings>) =>
+    updateSetting("network", { ...settings().network, ...changes });
+
+  // Extra headers are edited as JSON and applied once they parse
+  const [headersText, setHeadersText] = createSignal(
+    JSON.stringify(settings().network.headers || {}, null, 2)
+  );
+  const [headersError, setHeadersError] = createSignal<string | null>(null);
+  const applyHeaders = () => {
+    const text = headersText().trim();
+    try {
+      const headers = text ? JSON.parse(text) : {};
+      if (typeof headers !== "object" || Array.isArray(headers) || headers === null) {
+        throw new Error("Expected an object");
+      }
+      setHeadersError(null);
+      updateNetwork({ headers });
+    } catch (e) {
+      setHeadersError(`Invalid headers: ${e instanceof Error ? e.message : e}`);
+    }
+  };
+
+  // A locked secret store is unlocked with its passphrase, or reset so keys can be entered again
+  const [passphrase, setPassphrase] = createSignal("");
+  const [secretStoreError, setSecretStoreError] = createSignal<string | null>(null);
+  const handleSecretStore = async (action: "unlock" | "reset") => {
+    if (action === "reset" && !confirm("Delete all saved API keys, proxy credentials and MCP client secrets?")) {
+      return;
+    }
+    setSecretStoreError(null);
+    try {
+      if (action === "unlock") {
+        await unlockSecretStore(passphrase());
+      } else {
+        await resetSecretStore(passphrase());
+      }
+      setPassphrase("");
+      await loadSettings();
+    } catch (e) {
+      setSecretStoreError((e as { message?: string }).message || String(e));
+    }
+  };
 
   // Get current selected model's provider info
   const currentProviderInfo = createMemo(() => {
-    const model = AVAILABLE_MODELS.find(m => m.id === settings().model);
+    const model = availableModels().find(m => m.id === settings().model);
     if (model) {
       return PROVIDER_PRESETS[model.provider];
     }
@@ -31,6 +85,12 @@ const Settings: Component = () => {
     return null;
   });
 
+  // Catalogue entry of the selected model
+  const modelDetails = createMemo(() => getCatalogModel(settings().model));
+
+  const formatTokens = (n: number) =>
+    n >= 1000000 ? `${(n / 1000000).toFixed(1)}M` : `${Math.round(n / 1000)}K`;
+
   // Check if it's a true local service (authType === "none", no API Key needed at all)
   const isNoAuthProvider = createMemo(() => {
     const info = currentProviderInfo();
@@ -79,6 +139,40 @@ const Settings: Component = () => {
       </div>
 
       <div class="settings-content">
+        <Show when={secretStore().locked}>
+          <div class="settings-section">
+            <h3>Saved Keys Locked</h3>
+            <p class="test-error" style={{ margin: 0 }}>
+              {secretStore().error}
+            </p>
+            <div class="form-group">
+              <label for="secretPassphrase">Passphrase</label>
+              <input
+                id="secretPassphrase"
+                type="password"
+                value={passphrase()}
+                onInput={(e) => setPassphrase(e.currentTarget.value)}
+              />
+              <span class="hint">
+                Unlock with the passphrase the keys were saved with; set KUSE_COWORK_PASSPHRASE to unlock
+                them at startup. Resetting deletes the saved keys so you can enter them again, encrypted
+                with this passphrase or, if left empty, a new key in your system keychain.
+              </span>
+            </div>
+            <div class="form-group">
+              <button class="test-btn" onClick={() => handleSecretStore("unlock")} disabled={!passphrase()}>
+                Unlock
+              </button>
+              <button class="test-btn" onClick={() => handleSecretStore("reset")}>
+                Reset Saved Keys
+              </button>
+              <Show when={secretStoreError()}>
+                <span class="test-error">{secretStoreError()}</span>
+              </Show>
+            </div>
+          </div>
+        </Show>
+
         <div class="settings-section">
           <h3>Model Selection</h3>
 
@@ -91,6 +185,31 @@ const Settings: Component = () => {
               }
             }}
           />
+
+          <Show when={modelDetails()}>
+            {(info) => (
+              <div class="model-details">
+                <span class="model-detail">{formatTokens(info().context_window)} context</span>
+                <span class="model-detail">{formatTokens(info().max_output_tokens)} max output</span>
+                <Show when={info().tools}>
+                  <span class="model-detail">Tools</span>
+                </Show>
+                <Show when={info().vision}>
+                  <span class="model-detail">Vision</span>
+                </Show>
+                <Show when={info().reasoning}>
+                  <span class="model-detail">Reasoning</span>
+                </Show>
+                <Show when={info().pricing}>
+                  {(pricing) => (
+                    <span class="model-detail">
+                      ${pricing().input} in / ${pricing().output} out per 1M tokens
+                    </span>
+                  )}
+                </Show>
+              </div>
+            )}
+          </Show>
         </div>
 
         <div class="settings-section">
@@ -116,13 +235,22 @@ const Settings: Component = () => {
                   <span class="optional-tag">(Optional)</span>
                 </Show>
               </label>
-              <input
-                id="apiKey"
-                type="password"
-                value={settings().apiKey}
-                onInput={(e) => updateSetting("apiKey", e.currentTarget.value)}
-                placeholder={currentProviderInfo()?.authType === "bearer" ? "sk-..." : "your-api-key"}
-              />
+              <div class="secret-input">
+                <input
+                  id="apiKey"
+                  type={revealedKey() !== null ? "text" : "password"}
+                  value={revealedKey() ?? settings().apiKey}
+                  onInput={(e) => {
+                    const value = e.currentTarget.value;
+                    if (revealedKey() !== null) setRevealedKey(value);
+                    updateSetting("apiKey", value);
+                  }}
+                  placeholder={currentProviderInfo()?.authType === "bearer" ? "sk-..." : "your-api-key"}
+                />
+                <button class="reveal-btn" type="button" onClick={toggleReveal} disabled={!settings().apiKey}>
+                  {revealedKey() !== null ? "Hide" : "Show"}
+                </button>
+              </div>
               <span class="hint">
                 <Show
                   when={currentProviderInfo()?.id === "anthropic"}
@@ -207,7 +335,7 @@ const Settings: Component = () => {
                 updateSetting("maxTokens", parseInt(e.currentTarget.value) || 4096)
               }
               min={1}
-              max={200000}
+              max={modelDetails()?.max_output_tokens ?? 200000}
             />
           </div>
 
@@ -228,13 +356,113 @@ const Settings: Component = () => {
           </div>
         </div>
 
+        <div class="settings-section">
+          <h3>Network</h3>
+
+          <div class="form-group">
+            <label for="proxy">
+              Proxy
+              <span class="optional-tag">(Optional)</span>
+            </label>
+            <input
+              id="proxy"
+              type="text"
+              value={settings().network.proxy || ""}
+              onInput={(e) => updateNetwork({ proxy: e.currentTarget.value || undefined })}
+              placeholder="http://proxy.corp:8080 or socks5://127.0.0.1:1080"
+            />
+            <span class="hint">
+              Used for model providers and MCP servers. System proxy variables apply when empty.
+              A saved password is shown masked and kept unless you replace it.
+            </span>
+          </div>
+
+          <div class="form-group">
+            <label for="noProxy">
+              No Proxy
+              <span class="optional-tag">(Optional)</span>
+            </label>
+            <input
+              id="noProxy"
+              type="text"
+              value={settings().network.no_proxy || ""}
+              onInput={(e) => updateNetwork({ no_proxy: e.currentTarget.value || undefined })}
+              placeholder="localhost,127.0.0.1,.internal"
+            />
+          </div>
+
+          <div class="form-group">
+            <label for="caBundles">
+              Extra CA Certificates
+              <span class="optional-tag">(Optional)</span>
+            </label>
+            <textarea
+              id="caBundles"
+              rows={2}
+              value={(settings().network.ca_bundles || []).join("\n")}
+              onInput={(e) =>
+                updateNetwork({
+                  ca_bundles: e.currentTarget.value.split("\n").map((p) => p.trim()).filter(Boolean),
+                })
+              }
+              placeholder="/etc/ssl/corp-root.pem"
+            />
+            <span class="hint">PEM files to trust besides the system certificates, one per line</span>
+          </div>
+
+          <div class="form-group">
+            <label for="timeout">
+              Request Timeout (seconds)
+              <span class="optional-tag">(Optional)</span>
+            </label>
+            <input
+              id="timeout"
+              type="number"
+              min={0}
+              value={settings().network.timeout_secs ?? ""}
+              onInput={(e) => updateNetwork({ timeout_secs: parseInt(e.currentTarget.value) || undefined })}
+            />
+          </div>
+
+          <div class="form-group">
+            <label for="extraHeaders">
+              Extra Headers
+              <span class="optional-tag">(Optional)</span>
+            </label>
+            <textarea
+              id="extraHeaders"
+              rows={3}
+              value={headersText()}
+              onInput={(e) => setHeadersText(e.currentTarget.value)}
+              onBlur={applyHeaders}
+              placeholder={'{ "*": { "X-Team": "data" }, "openai": { "X-Gateway-Key": "..." } }'}
+            />
+            <span class={headersError() ? "test-error" : "hint"}>
+              {headersError() || 'JSON object keyed by provider id, "mcp", or "*" for every request. Saved values are stored encrypted and shown masked; masked ones are kept.'}
+            </span>
+          </div>
+        </div>
+
         <div class="settings-section">
           <h3>Data Storage</h3>
           <p class="hint" style={{ margin: 0 }}>
             All data is stored locally on your computer in SQLite database.
             <br />
-            API key is securely stored and never sent to any server except Anthropic's API.
+            API keys and MCP client secrets are encrypted with a key kept in your system keychain
+            (or derived from KUSE_COWORK_PASSPHRASE) and only sent to the provider they belong to.
           </p>
+
+          <div class="form-group">
+            <label for="trashRetention">Keep Deleted Items (days)</label>
+            <input
+              id="trashRetention"
+              type="number"
+              min={0}
+              value={settings().trashRetentionDays}
+              onInput={(e) => updateSetting("trashRetentionDays", Math.max(0, parseInt(e.currentTarget.value) || 0))}
+            />
+            <span class="hint">Deleted conversations and tasks are purged from the trash after this long; 0 keeps them</span>
+          </div>
         </div>
       </div>
     </div>
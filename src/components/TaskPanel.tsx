@@ -23,6 +23,8 @@ const TaskPanel: Component<TaskPanelProps> = (props) => {
         return "●";
       case "failed":
         return "✗";
+      case "skipped":
+        return "–";
       default:
         return "○";
     }
@@ -59,6 +61,8 @@ const TaskPanel: Component<TaskPanelProps> = (props) => {
               <div class={`task-status ${task().status}`}>
                 {task().status === "planning" && "Planning..."}
                 {task().status === "running" && "Running"}
+                {task().status === "paused" && "Paused (turn limit) - send a message to continue"}
+                {task().status === "stopped" && "Stopped (repeating the same tool call) - send a message to redirect it"}
                 {task().status === "completed" && "Completed"}
                 {task().status === "failed" && "Failed"}
               </div>
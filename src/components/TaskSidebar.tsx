@@ -21,6 +21,10 @@ const TaskSidebar: Component<TaskSidebarProps> = (props) => {
         return "●";
       case "failed":
         return "✗";
+      case "paused":
+        return "⏸";
+      case "stopped":
+        return "■";
       default:
         return "○";
     }
@@ -1,6 +1,7 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen, UnlistenFn } from "@tauri-apps/api/event";
-import { open } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
+import bundledModels from "../../src-tauri/src/llm_client/models.json";
 
 // Types matching Rust structs
 export interface Settings {
@@ -12,6 +13,77 @@ export interface Settings {
   provider_keys: Record<string, string>;  // Provider-specific API keys
   openai_organization?: string;  // Optional OpenAI Organization ID
   openai_project?: string;  // Optional OpenAI Project ID
+  fallbacks?: ModelTarget[];  // Tried in order when the primary model fails with a retryable error
+  routes?: RoutingRule[];  // Jobs sent to a model other than the primary one
+  ollama?: OllamaOptions;  // Options for the native Ollama API
+  network?: NetworkSettings;  // Proxy, trusted CAs, timeouts and extra headers
+  trash_retention_days?: number;  // Days deleted items are kept; 0 keeps them until the trash is emptied
+}
+
+export interface NetworkSettings {
+  proxy?: string;  // http(s)://, socks5:// or socks5h:// URL; system proxy when unset
+  no_proxy?: string;  // Comma-separated hosts that bypass the proxy
+  ca_bundles?: string[];  // PEM files with extra trusted CA certificates
+  timeout_secs?: number;  // Limit for a whole request
+  connect_timeout_secs?: number;  // Limit for establishing a connection
+  headers?: Record<string, Record<string, string>>;  // By provider id, "mcp", or "*" for all
+}
+
+export interface OllamaOptions {
+  num_ctx?: number;  // Context window to load models with
+  keep_alive?: string;  // How long models stay loaded, e.g. "10m"
+}
+
+// One progress update while Ollama downloads a model
+export interface PullProgress {
+  status: string;
+  digest?: string;
+  total?: number;  // Layer size in bytes
+  completed?: number;
+}
+
+// Provider/model pair; the API key comes from provider_keys
+export interface ModelTarget {
+  provider: string;
+  model: string;
+  base_url?: string;  // Overrides the provider's default URL when set
+}
+
+// A "summary" rule writes the wrap-up of every run that hits its turn limit
+export interface RoutingRule extends ModelTarget {
+  purpose: "summary" | "sub_agent" | "title";
+}
+
+// Model catalogue entry (prices in USD per million tokens)
+export interface ModelPricing {
+  input: number;
+  output: number;
+  cache_read?: number;
+  cache_write?: number;
+}
+
+export interface ModelInfo {
+  id: string;
+  name: string;
+  description: string;
+  provider: string;
+  api_format?: "anthropic" | "openai" | "openai-compatible" | "openai-responses" | "google" | "minimax" | "azure" | "bedrock" | "vertex" | "ollama";
+  prefixes?: string[];
+  context_window: number;
+  max_output_tokens: number;
+  vision: boolean;
+  tools: boolean;
+  reasoning: boolean;
+  max_temperature: number | null;  // null: only the model's default temperature
+  legacy_max_tokens?: boolean;
+  hidden: boolean;
+  pricing: ModelPricing | null;
+}
+
+// Model reported by a provider; info is set for models in the catalogue
+export interface DiscoveredModel {
+  id: string;
+  info: ModelInfo | null;
 }
 
 export interface Conversation {
@@ -19,6 +91,19 @@ export interface Conversation {
   title: string;
   created_at: number;
   updated_at: number;
+  project_path?: string | null;
+  pinned?: boolean;
+  archived?: boolean;
+  tags?: string[]; // tag ids
+  deleted_at?: number; // set while in the trash
+}
+
+// Tool call made while producing an assistant message
+export interface ToolCallRecord {
+  tool: string;
+  input: Record<string, unknown>;
+  result: string;
+  success: boolean;
 }
 
 export interface Message {
@@ -27,6 +112,11 @@ export interface Message {
   role: "user" | "assistant";
   content: string;
   timestamp: number;
+  tool_calls?: ToolCallRecord[];
+  /** Message this one follows; null for the first */
+  parent_id?: string | null;
+  /** Ids of this message and its alternatives, oldest first; absent when there are none */
+  branches?: string[];
 }
 
 interface StreamPayload {
@@ -40,22 +130,57 @@ export interface AgentRequest {
   project_path?: string;
   system_prompt?: string;
   max_turns?: number;
+  thinking_budget?: number;
+  /** JSON Schema the final answer must match */
+  response_schema?: JsonSchema;
 }
 
+/** JSON Schema object for structured output */
+export type JsonSchema = Record<string, unknown>;
+
 export type AgentEvent =
   | { type: "text"; content: string }
+  | { type: "thinking"; content: string }
+  | ({ type: "usage" } & TokenUsage)
   | { type: "plan"; steps: PlanStepInfo[] }
   | { type: "step_start"; step: number }
   | { type: "step_done"; step: number }
   | { type: "tool_start"; tool: string; input: Record<string, unknown> }
   | { type: "tool_end"; tool: string; result: string; success: boolean }
   | { type: "turn_complete"; turn: number }
+  | { type: "structured_output"; output: unknown }
   | { type: "done"; total_turns: number }
-  | { type: "error"; message: string };
+  | { type: "error"; message: string }
+  | { type: "warning"; message: string }
+  | { type: "stopped"; reason: StopReason; message: string }
+  | {
+      type: "project_context";
+      config_file: string | null;
+      instruction_files: InstructionFileInfo[];
+      error?: string;
+    }
+  | { type: "sub_agent_start"; sub_agent_id: string; depth: number; task: string }
+  | { type: "sub_agent"; sub_agent_id: string; event: AgentEvent };
+
+export interface TokenUsage {
+  input_tokens: number;
+  output_tokens: number;
+  cache_creation_input_tokens: number;
+  cache_read_input_tokens: number;
+}
+
+export type StopReason = "loop_detected" | "turn_limit";
+
+export interface InstructionFileInfo {
+  path: string;
+  bytes: number;
+  truncated: boolean;
+}
 
 export interface PlanStepInfo {
   step: number;
   description: string;
+  status: PlanStep["status"];
 }
 
 // Task types
@@ -63,18 +188,22 @@ export interface Task {
   id: string;
   title: string;
   description: string;
-  status: "planning" | "running" | "completed" | "failed";
+  status: "planning" | "running" | "paused" | "stopped" | "completed" | "failed";
   plan: PlanStep[] | null;
   current_step: number;
   project_path: string | null;
   created_at: number;
   updated_at: number;
+  pinned?: boolean;
+  archived?: boolean;
+  tags?: string[]; // tag ids
+  deleted_at?: number; // set while in the trash
 }
 
 export interface PlanStep {
   step: number;
   description: string;
-  status: "pending" | "running" | "completed" | "failed";
+  status: "pending" | "running" | "completed" | "skipped" | "failed";
 }
 
 export interface TaskAgentRequest {
@@ -82,6 +211,9 @@ export interface TaskAgentRequest {
   message: string;
   project_path?: string;
   max_turns?: number;
+  thinking_budget?: number;
+  /** JSON Schema the final answer must match */
+  response_schema?: JsonSchema;
 }
 
 export interface TaskMessage {
@@ -90,6 +222,7 @@ export interface TaskMessage {
   role: "user" | "assistant";
   content: string;
   timestamp: number;
+  tool_calls?: ToolCallRecord[];
 }
 
 export interface SkillMetadata {
@@ -103,12 +236,15 @@ export interface EnhancedChatRequest {
   content: string;
   project_path?: string;
   enable_tools: boolean;
+  /** JSON Schema the reply must match */
+  response_schema?: JsonSchema;
 }
 
 export type ChatEvent =
   | { type: "text"; content: string }
   | { type: "tool_start"; tool: string; input: Record<string, unknown> }
   | { type: "tool_end"; tool: string; result: string; success: boolean }
+  | { type: "warning"; message: string }
   | { type: "done"; final_text: string };
 
 // Check if running in Tauri (Tauri 2.x uses __TAURI_INTERNALS__)
@@ -118,7 +254,8 @@ export function isTauri(): boolean {
 }
 
 // Settings API
-export async function getSettings(): Promise<Settings> {
+// API keys come back masked unless `reveal` is set; saving a masked key keeps the stored one
+export async function getSettings(reveal = false): Promise<Settings> {
   if (!isTauri()) {
     // Fallback for web dev
     const stored = localStorage.getItem("kuse-cowork-settings");
@@ -142,7 +279,7 @@ export async function getSettings(): Promise<Settings> {
       provider_keys: {},
     };
   }
-  return invoke<Settings>("get_settings");
+  return invoke<Settings>("get_settings", { reveal });
 }
 
 export async function saveSettings(settings: Settings): Promise<void> {
@@ -163,6 +300,45 @@ export async function saveSettings(settings: Settings): Promise<void> {
   return invoke("save_settings", { settings });
 }
 
+// Secret store API
+// The store is locked when its key is missing or wrong; secrets then read as empty
+export interface SecretStoreStatus {
+  locked: boolean;
+  error?: string;
+}
+
+export async function getSecretStoreStatus(): Promise<SecretStoreStatus> {
+  if (!isTauri()) {
+    return { locked: false };
+  }
+  return invoke<SecretStoreStatus>("get_secret_store_status");
+}
+
+export async function unlockSecretStore(passphrase: string): Promise<void> {
+  return invoke("unlock_secret_store", { passphrase });
+}
+
+// Deletes every saved secret; the new key is derived from `passphrase` if given, else kept in the keychain
+export async function resetSecretStore(passphrase?: string): Promise<void> {
+  return invoke("reset_secret_store", { passphrase: passphrase || null });
+}
+
+// Model catalogue, including models added in the user's models.json
+export async function getModelCatalog(): Promise<ModelInfo[]> {
+  if (!isTauri()) {
+    return bundledModels as unknown as ModelInfo[];
+  }
+  return invoke<ModelInfo[]>("get_model_catalog");
+}
+
+// Models offered by a provider (the current one by default), cached by the backend
+export async function listModels(provider?: string, refresh = false): Promise<DiscoveredModel[]> {
+  if (!isTauri()) {
+    return [];
+  }
+  return invoke<DiscoveredModel[]>("list_models", { provider, refresh });
+}
+
 export async function testConnection(): Promise<string> {
   console.log("testConnection called, isTauri:", isTauri());
   if (!isTauri()) {
@@ -188,16 +364,35 @@ export async function testConnection(): Promise<string> {
   return result;
 }
 
+// Download a model into Ollama, reporting progress as it goes
+export async function pullOllamaModel(
+  model: string,
+  onProgress: (progress: PullProgress) => void
+): Promise<void> {
+  if (!isTauri()) {
+    throw new Error("Pulling models requires the desktop app");
+  }
+
+  const unlisten = await listen<PullProgress>("ollama-pull-progress", (event) => {
+    onProgress(event.payload);
+  });
+  try {
+    await invoke("pull_ollama_model", { model });
+  } finally {
+    unlisten();
+  }
+}
+
 // Conversations API
-export async function listConversations(): Promise<Conversation[]> {
+export async function listConversations(query?: ListQuery): Promise<Conversation[]> {
   if (!isTauri()) {
     const stored = localStorage.getItem("kuse-cowork-conversations");
     return stored ? JSON.parse(stored) : [];
   }
-  return invoke<Conversation[]>("list_conversations");
+  return invoke<Conversation[]>("list_conversations", { query });
 }
 
-export async function createConversation(title: string): Promise<Conversation> {
+export async function createConversation(title: string, projectPath?: string): Promise<Conversation> {
   if (!isTauri()) {
     const conv: Conversation = {
       id: crypto.randomUUID(),
@@ -210,7 +405,7 @@ export async function createConversation(title: string): Promise<Conversation> {
     localStorage.setItem("kuse-cowork-conversations", JSON.stringify(conversations));
     return conv;
   }
-  return invoke<Conversation>("create_conversation", { title });
+  return invoke<Conversation>("create_conversation", { title, projectPath });
 }
 
 export async function updateConversationTitle(
@@ -230,6 +425,21 @@ export async function updateConversationTitle(
   return invoke("update_conversation_title", { id, title });
 }
 
+// Payload of the "title-changed" event
+export interface TitleChanged {
+  kind: "conversation" | "task";
+  id: string;
+  title: string;
+}
+
+// Titles generated after the first exchange of a conversation or task
+export async function onTitleChanged(handler: (change: TitleChanged) => void): Promise<UnlistenFn> {
+  if (!isTauri()) {
+    return () => {};
+  }
+  return listen<TitleChanged>("title-changed", (event) => handler(event.payload));
+}
+
 export async function deleteConversation(id: string): Promise<void> {
   if (!isTauri()) {
     const conversations = await listConversations();
@@ -261,10 +471,11 @@ function saveMessagesLocal(conversationId: string, messages: Message[]) {
 export async function sendChatMessage(
   conversationId: string,
   content: string,
-  onStream: (text: string) => void
+  onStream: (text: string) => void,
+  responseSchema?: JsonSchema
 ): Promise<string> {
   if (!isTauri()) {
-    // Web fallback - direct API call
+    // Web fallback - direct API call (structured output needs the desktop app)
     const settings = await getSettings();
     const messages = await getMessages(conversationId);
 
@@ -325,6 +536,7 @@ export async function sendChatMessage(
     const response = await invoke<string>("send_chat_message", {
       conversationId,
       content,
+      responseSchema,
     });
 
     return response;
@@ -367,23 +579,28 @@ export async function runAgent(
 export async function sendChatWithTools(
   request: EnhancedChatRequest,
   onEvent: (event: ChatEvent) => void
+): Promise<string> {
+  return invokeChatCommand("send_chat_with_tools", { request }, onEvent);
+}
+
+// Run a tool-enabled chat command, forwarding its chat events while it runs
+async function invokeChatCommand(
+  command: string,
+  args: Record<string, unknown>,
+  onEvent: (event: ChatEvent) => void
 ): Promise<string> {
   if (!isTauri()) {
-    // Web fallback - tools require Tauri backend
     throw new Error("Tool-enabled chat requires the desktop app");
   }
 
   let unlisten: UnlistenFn | undefined;
 
   try {
-    // Listen for chat events
     unlisten = await listen<ChatEvent>("chat-event", (event) => {
       onEvent(event.payload);
     });
 
-    // Send chat with tools via Rust
-    const response = await invoke<string>("send_chat_with_tools", { request });
-    return response;
+    return await invoke<string>(command, args);
   } finally {
     if (unlisten) {
       unlisten();
@@ -391,13 +608,39 @@ export async function sendChatWithTools(
   }
 }
 
+// Replace an earlier user message with request.content and answer it on a new branch
+export async function editAndResend(
+  messageId: string,
+  request: EnhancedChatRequest,
+  onEvent: (event: ChatEvent) => void
+): Promise<string> {
+  return invokeChatCommand("edit_and_resend", { messageId, request }, onEvent);
+}
+
+// Answer the prompt before an assistant message again, on a new branch
+export async function regenerateReply(
+  messageId: string,
+  request: EnhancedChatRequest,
+  onEvent: (event: ChatEvent) => void
+): Promise<string> {
+  return invokeChatCommand("regenerate_reply", { messageId, request }, onEvent);
+}
+
+// Make the branch through a message active; returns the new active path
+export async function switchBranch(conversationId: string, messageId: string): Promise<Message[]> {
+  if (!isTauri()) {
+    return getMessages(conversationId);
+  }
+  return invoke<Message[]>("switch_branch", { conversationId, messageId });
+}
+
 // Task API
-export async function listTasks(): Promise<Task[]> {
+export async function listTasks(query?: ListQuery): Promise<Task[]> {
   if (!isTauri()) {
     const stored = localStorage.getItem("kuse-cowork-tasks");
     return stored ? JSON.parse(stored) : [];
   }
-  return invoke<Task[]>("list_tasks");
+  return invoke<Task[]>("list_tasks", { query });
 }
 
 export async function getTask(id: string): Promise<Task | null> {
@@ -433,6 +676,21 @@ export async function createTask(
   return invoke<Task>("create_task", { title, description, projectPath });
 }
 
+// Rename a task by hand; generated titles no longer replace it
+export async function updateTaskTitle(id: string, title: string): Promise<void> {
+  if (!isTauri()) {
+    const tasks = await listTasks();
+    const idx = tasks.findIndex((t) => t.id === id);
+    if (idx >= 0) {
+      tasks[idx].title = title;
+      tasks[idx].updated_at = Date.now();
+      localStorage.setItem("kuse-cowork-tasks", JSON.stringify(tasks));
+    }
+    return;
+  }
+  return invoke("update_task_title", { id, title });
+}
+
 export async function deleteTask(id: string): Promise<void> {
   if (!isTauri()) {
     const tasks = await listTasks();
@@ -476,6 +734,176 @@ export async function getTaskMessages(taskId: string): Promise<TaskMessage[]> {
   return invoke<TaskMessage[]>("get_task_messages", { taskId });
 }
 
+// History organisation API
+// Filters, order and page for listConversations and listTasks; pinned items come first
+export interface ListQuery {
+  archived?: boolean; // list archived items instead of the others
+  pinned?: boolean;
+  tag?: string; // tag id
+  project_path?: string;
+  status?: Task["status"]; // tasks only
+  sort?: "updated" | "created" | "title";
+  direction?: "asc" | "desc"; // newest first for dates, A to Z for titles by default
+  limit?: number;
+  offset?: number;
+}
+
+export interface Tag {
+  id: string;
+  name: string;
+  color: string | null;
+  created_at: number;
+}
+
+export async function listTags(): Promise<Tag[]> {
+  if (!isTauri()) return [];
+  return invoke<Tag[]>("list_tags");
+}
+
+export async function createTag(name: string, color?: string): Promise<Tag> {
+  return invoke<Tag>("create_tag", { name, color });
+}
+
+export async function updateTag(id: string, name: string, color?: string): Promise<void> {
+  return invoke("update_tag", { id, name, color });
+}
+
+export async function deleteTag(id: string): Promise<void> {
+  return invoke("delete_tag", { id });
+}
+
+export async function setConversationTags(id: string, tagIds: string[]): Promise<void> {
+  return invoke("set_conversation_tags", { id, tagIds });
+}
+
+export async function setTaskTags(id: string, tagIds: string[]): Promise<void> {
+  return invoke("set_task_tags", { id, tagIds });
+}
+
+export async function setConversationPinned(id: string, pinned: boolean): Promise<void> {
+  return invoke("set_conversation_pinned", { id, pinned });
+}
+
+export async function setConversationArchived(id: string, archived: boolean): Promise<void> {
+  return invoke("set_conversation_archived", { id, archived });
+}
+
+export async function setConversationProject(id: string, projectPath: string | null): Promise<void> {
+  return invoke("set_conversation_project", { id, projectPath });
+}
+
+export async function setTaskPinned(id: string, pinned: boolean): Promise<void> {
+  return invoke("set_task_pinned", { id, pinned });
+}
+
+export async function setTaskArchived(id: string, archived: boolean): Promise<void> {
+  return invoke("set_task_archived", { id, archived });
+}
+
+// Trash API
+// Deleted conversations and tasks, most recently deleted first
+export interface Trash {
+  conversations: Conversation[];
+  tasks: Task[];
+}
+
+export async function listTrash(): Promise<Trash> {
+  if (!isTauri()) return { conversations: [], tasks: [] };
+  return invoke<Trash>("list_trash");
+}
+
+export async function restoreConversation(id: string): Promise<void> {
+  return invoke("restore_conversation", { id });
+}
+
+export async function restoreTask(id: string): Promise<void> {
+  return invoke("restore_task", { id });
+}
+
+// Permanently delete everything in the trash; resolves to the number of items removed
+export async function emptyTrash(): Promise<number> {
+  return invoke<number>("empty_trash");
+}
+
+// History search API
+export type SearchKind = "conversation" | "task";
+
+export interface SearchFilter {
+  kind?: SearchKind;
+  from?: number; // ms since epoch
+  to?: number;
+  project_path?: string;
+  limit?: number;
+}
+
+export interface SearchHit {
+  kind: SearchKind;
+  id: string; // conversation or task id
+  title: string;
+  message_id: string | null;
+  snippet: string; // matched terms wrapped in **
+  timestamp: number;
+  project_path: string | null;
+  rank: number; // lower is more relevant
+}
+
+export async function searchHistory(query: string, filter?: SearchFilter): Promise<SearchHit[]> {
+  if (!isTauri()) {
+    return [];
+  }
+  return invoke<SearchHit[]>("search_history", { query, filter });
+}
+
+// Export/import API
+export type ExportFormat = "markdown" | "json";
+
+export interface Imported {
+  kind: "conversation" | "task";
+  id: string;
+}
+
+// Asks where to save and writes the export there; returns the path, or null if cancelled
+async function exportTo(
+  command: "export_conversation" | "export_task",
+  id: string,
+  format: ExportFormat,
+  defaultName: string
+): Promise<string | null> {
+  if (!isTauri()) {
+    return null;
+  }
+  const extension = format === "json" ? "json" : "md";
+  const path = await save({
+    defaultPath: `${defaultName}.${extension}`,
+    filters: [{ name: format === "json" ? "JSON" : "Markdown", extensions: [extension] }],
+  });
+  if (!path) return null;
+  await invoke<string>(command, { id, format, path });
+  return path;
+}
+
+export function exportConversation(id: string, format: ExportFormat, defaultName = "conversation") {
+  return exportTo("export_conversation", id, format, defaultName);
+}
+
+export function exportTask(id: string, format: ExportFormat, defaultName = "task") {
+  return exportTo("export_task", id, format, defaultName);
+}
+
+// Picks a JSON export and restores it with new ids; null if cancelled
+export async function importHistory(): Promise<Imported | null> {
+  if (!isTauri()) {
+    return null;
+  }
+  const path = await open({
+    multiple: false,
+    filters: [{ name: "Kuse Cowork export", extensions: ["json"] }],
+    title: "Import conversation or task",
+  });
+  if (!path) return null;
+  return invoke<Imported>("import_history", { path: path as string });
+}
+
 // File/Folder picker API
 export async function openFolderDialog(): Promise<string | null> {
   if (!isTauri()) {
@@ -2,8 +2,17 @@ import { createSignal } from "solid-js";
 import {
   getSettings as getSettingsApi,
   saveSettings as saveSettingsApi,
+  getModelCatalog,
+  getSecretStoreStatus,
+  SecretStoreStatus,
   Settings as ApiSettings,
+  ModelInfo,
+  ModelTarget,
+  RoutingRule,
+  OllamaOptions,
+  NetworkSettings,
 } from "../lib/tauri-api";
+import bundledModels from "../../src-tauri/src/llm_client/models.json";
 
 export interface Settings {
   apiKey: string;  // Current active API key (for display)
@@ -14,6 +23,11 @@ export interface Settings {
   providerKeys: Record<string, string>;  // Provider-specific API keys
   openaiOrganization?: string;  // Optional OpenAI Organization ID
   openaiProject?: string;  // Optional OpenAI Project ID
+  fallbacks: ModelTarget[];  // Tried in order when the primary model fails
+  routes: RoutingRule[];  // Jobs sent to a model other than the primary one
+  ollama: OllamaOptions;  // Context size and keep-alive for Ollama models
+  network: NetworkSettings;  // Proxy, trusted CAs, timeouts and extra headers
+  trashRetentionDays: number;  // Days deleted items are kept; 0 keeps them
 }
 
 // Provider configuration type
@@ -21,8 +35,8 @@ export interface ProviderConfig {
   id: string;
   name: string;
   baseUrl: string;
-  apiFormat: "anthropic" | "openai" | "openai-compatible" | "openai-responses" | "google" | "minimax";
-  authType: "none" | "bearer" | "api-key" | "query-param";
+  apiFormat: "anthropic" | "openai" | "openai-compatible" | "openai-responses" | "google" | "minimax" | "azure" | "bedrock" | "vertex" | "ollama";
+  authType: "none" | "bearer" | "api-key" | "query-param" | "aws-sigv4" | "oauth";
   authHeader?: string;  // Custom auth header name
   description?: string;
 }
@@ -62,13 +76,38 @@ export const PROVIDER_PRESETS: Record<string, ProviderConfig> = {
     authType: "bearer",
     description: "Minimax Official API",
   },
+  azure: {
+    id: "azure",
+    name: "Azure OpenAI",
+    baseUrl: "https://your-resource.openai.azure.com",
+    apiFormat: "azure",
+    authType: "api-key",
+    authHeader: "api-key",
+    description: "OpenAI models on Azure; use the deployment name as the model",
+  },
+  bedrock: {
+    id: "bedrock",
+    name: "Amazon Bedrock",
+    baseUrl: "https://bedrock-runtime.us-east-1.amazonaws.com",
+    apiFormat: "bedrock",
+    authType: "aws-sigv4",
+    description: "Claude on AWS; key is ACCESS_KEY_ID:SECRET_ACCESS_KEY or a Bedrock API key",
+  },
+  vertex: {
+    id: "vertex",
+    name: "Google Vertex AI",
+    baseUrl: "https://us-east5-aiplatform.googleapis.com",
+    apiFormat: "vertex",
+    authType: "oauth",
+    description: "Claude and Gemini on Google Cloud; key is a service account JSON or access token",
+  },
 
   // Local inference services
   ollama: {
     id: "ollama",
     name: "Ollama (Local)",
     baseUrl: "http://localhost:11434",
-    apiFormat: "openai-compatible",
+    apiFormat: "ollama",
     authType: "none",
     description: "Local, free and private",
   },
@@ -160,62 +199,66 @@ export const PROVIDER_PRESETS: Record<string, ProviderConfig> = {
   },
 };
 
-export const AVAILABLE_MODELS = [
-  // ========== Official API Services ==========
-  // Claude Models (Anthropic)
-  { id: "claude-opus-4-5-20251101", name: "Claude Opus 4.5", description: "Most capable", provider: "anthropic", baseUrl: "https://api.anthropic.com" },
-  { id: "claude-sonnet-4-5-20250929", name: "Claude Sonnet 4.5", description: "Enhanced balanced model", provider: "anthropic", baseUrl: "https://api.anthropic.com" },
-
-  // GPT Models (OpenAI)
-  // GPT-5 series uses Responses API
-  { id: "gpt-5", name: "GPT-5", description: "Latest flagship model", provider: "openai", baseUrl: "https://api.openai.com", apiFormat: "responses" as const },
-  { id: "gpt-5-mini", name: "GPT-5 Mini", description: "Fast and efficient", provider: "openai", baseUrl: "https://api.openai.com", apiFormat: "responses" as const },
-  { id: "gpt-5-nano", name: "GPT-5 Nano", description: "Ultra-fast, lightweight", provider: "openai", baseUrl: "https://api.openai.com", apiFormat: "responses" as const },
-  // Legacy GPT models use Chat Completions API
-  { id: "gpt-4o", name: "GPT-4o", description: "Multimodal model", provider: "openai", baseUrl: "https://api.openai.com" },
-  { id: "gpt-4-turbo", name: "GPT-4 Turbo", description: "Fast GPT-4", provider: "openai", baseUrl: "https://api.openai.com" },
-
-  // Gemini Models (Google)
-  { id: "gemini-3-pro-preview", name: "Gemini 3 Pro", description: "Google's latest model", provider: "google", baseUrl: "https://generativelanguage.googleapis.com" },
-
-  // Minimax Models
-  { id: "minimax-m2.1", name: "Minimax M2.1", description: "Advanced Chinese model", provider: "minimax", baseUrl: "https://api.minimax.chat" },
-
-  // ========== Local Inference (Ollama) ==========
-  { id: "llama3.3:latest", name: "Llama 3.3 8B", description: "Meta's latest open source model", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "llama3.3:70b", name: "Llama 3.3 70B", description: "Large model, requires 32GB+ RAM", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "qwen2.5:latest", name: "Qwen 2.5 7B", description: "Alibaba's model, good for Chinese", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "qwen2.5:32b", name: "Qwen 2.5 32B", description: "Large Chinese model", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "deepseek-r1:latest", name: "DeepSeek R1", description: "Strong reasoning capability", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "codellama:latest", name: "Code Llama", description: "Code-specialized model", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "mistral:latest", name: "Mistral 7B", description: "Efficient European model", provider: "ollama", baseUrl: "http://localhost:11434" },
-  { id: "phi3:latest", name: "Phi-3", description: "Microsoft small model, efficient", provider: "ollama", baseUrl: "http://localhost:11434" },
-
-  // ========== OpenRouter ==========
-  { id: "anthropic/claude-3.5-sonnet", name: "Claude 3.5 Sonnet", description: "via OpenRouter", provider: "openrouter", baseUrl: "https://openrouter.ai/api/v1" },
-  { id: "openai/gpt-4o", name: "GPT-4o", description: "via OpenRouter", provider: "openrouter", baseUrl: "https://openrouter.ai/api/v1" },
-  { id: "meta-llama/llama-3.3-70b-instruct", name: "Llama 3.3 70B", description: "via OpenRouter", provider: "openrouter", baseUrl: "https://openrouter.ai/api/v1" },
-  { id: "deepseek/deepseek-r1", name: "DeepSeek R1", description: "via OpenRouter", provider: "openrouter", baseUrl: "https://openrouter.ai/api/v1" },
-
-  // ========== Together AI ==========
-  { id: "meta-llama/Llama-3.3-70B-Instruct-Turbo", name: "Llama 3.3 70B Turbo", description: "via Together", provider: "together", baseUrl: "https://api.together.xyz/v1" },
-  { id: "Qwen/Qwen2.5-72B-Instruct-Turbo", name: "Qwen 2.5 72B Turbo", description: "via Together", provider: "together", baseUrl: "https://api.together.xyz/v1" },
-
-  // ========== Groq ==========
-  { id: "llama-3.3-70b-versatile", name: "Llama 3.3 70B", description: "via Groq (ultra-fast)", provider: "groq", baseUrl: "https://api.groq.com/openai/v1" },
-  { id: "mixtral-8x7b-32768", name: "Mixtral 8x7B", description: "via Groq (ultra-fast)", provider: "groq", baseUrl: "https://api.groq.com/openai/v1" },
-
-  // ========== DeepSeek Official ==========
-  { id: "deepseek-chat", name: "DeepSeek Chat", description: "DeepSeek Official", provider: "deepseek", baseUrl: "https://api.deepseek.com" },
-  { id: "deepseek-reasoner", name: "DeepSeek Reasoner", description: "Reasoning enhanced", provider: "deepseek", baseUrl: "https://api.deepseek.com" },
-
-  // ========== SiliconFlow ==========
-  { id: "Qwen/Qwen2.5-72B-Instruct", name: "Qwen 2.5 72B", description: "via SiliconFlow", provider: "siliconflow", baseUrl: "https://api.siliconflow.cn/v1" },
-  { id: "deepseek-ai/DeepSeek-V3", name: "DeepSeek V3", description: "via SiliconFlow", provider: "siliconflow", baseUrl: "https://api.siliconflow.cn/v1" },
-
-  // ========== Custom ==========
-  { id: "custom-model", name: "Custom Model", description: "Enter your model ID", provider: "custom", baseUrl: "http://localhost:8000" },
-];
+// Model offered in the model picker
+export interface ModelOption {
+  id: string;
+  name: string;
+  description: string;
+  provider: string;
+  baseUrl: string;
+  apiFormat?: "responses";
+  info?: ModelInfo;  // Catalogue entry: limits, capabilities and prices
+}
+
+// Placeholder for models entered by hand
+const CUSTOM_MODEL: ModelOption = {
+  id: "custom-model",
+  name: "Custom Model",
+  description: "Enter your model ID",
+  provider: "custom",
+  baseUrl: "http://localhost:8000",
+};
+
+// Model catalogue; starts with the bundled list and is replaced by the backend's on load
+const [modelCatalog, setModelCatalog] = createSignal<ModelInfo[]>(bundledModels as unknown as ModelInfo[]);
+
+// Models for the picker, in catalogue order
+export function availableModels(): ModelOption[] {
+  const models = modelCatalog()
+    .filter(m => !m.hidden)
+    .map((m): ModelOption => ({
+      id: m.id,
+      name: m.name,
+      description: m.description,
+      provider: m.provider,
+      baseUrl: PROVIDER_PRESETS[m.provider]?.baseUrl || "",
+      apiFormat: m.api_format === "openai-responses" ? "responses" : undefined,
+      info: m,
+    }));
+  return [...models, CUSTOM_MODEL];
+}
+
+// Catalogue entry for a model, including hidden ones and dated snapshots
+export function getCatalogModel(modelId: string): ModelInfo | undefined {
+  const lower = modelId.toLowerCase();
+  const catalog = modelCatalog();
+  const exact = catalog.find(m => m.id.toLowerCase() === lower);
+  if (exact) return exact;
+
+  // Longest matching id or prefix, as in the backend catalogue
+  let best: ModelInfo | undefined;
+  let bestLen = 0;
+  for (const m of catalog) {
+    for (const prefix of [m.id, ...(m.prefixes || [])]) {
+      const p = prefix.toLowerCase();
+      if (lower.startsWith(p) && p.length > bestLen) {
+        best = m;
+        bestLen = p.length;
+      }
+    }
+  }
+  return best;
+}
 
 const DEFAULT_SETTINGS: Settings = {
   apiKey: "",
@@ -224,24 +267,22 @@ const DEFAULT_SETTINGS: Settings = {
   maxTokens: 4096,
   temperature: 0.7,
   providerKeys: {},
+  fallbacks: [],
+  routes: [],
+  ollama: {},
+  network: {},
+  trashRetentionDays: 30,
 };
 
 // Get provider ID from model
 export function getProviderFromModel(modelId: string): string {
-  const model = AVAILABLE_MODELS.find(m => m.id === modelId);
-  return model?.provider || "anthropic";
+  const model = availableModels().find(m => m.id === modelId);
+  return model?.provider || getCatalogModel(modelId)?.provider || "anthropic";
 }
 
 // Check if a model uses the OpenAI Responses API (GPT-5 series)
 export function usesResponsesApi(modelId: string): boolean {
-  // Check if model is in AVAILABLE_MODELS with apiFormat: "responses"
-  const model = AVAILABLE_MODELS.find(m => m.id === modelId);
-  if (model && 'apiFormat' in model && model.apiFormat === "responses") {
-    return true;
-  }
-  // Fallback: detect GPT-5 models by name pattern
-  const lower = modelId.toLowerCase();
-  return lower.startsWith("gpt-5") || lower.match(/^gpt-5[\.-]/) !== null;
+  return getCatalogModel(modelId)?.api_format === "openai-responses";
 }
 
 // Convert between frontend and API formats
@@ -262,6 +303,11 @@ function fromApiSettings(api: ApiSettings): Settings {
     providerKeys,
     openaiOrganization: api.openai_organization,
     openaiProject: api.openai_project,
+    fallbacks: api.fallbacks || [],
+    routes: api.routes || [],
+    ollama: api.ollama || {},
+    network: api.network || {},
+    trashRetentionDays: api.trash_retention_days ?? 30,
   };
 }
 
@@ -282,19 +328,31 @@ function toApiSettings(settings: Settings): ApiSettings {
     provider_keys: providerKeys,
     openai_organization: settings.openaiOrganization,
     openai_project: settings.openaiProject,
+    fallbacks: settings.fallbacks,
+    routes: settings.routes,
+    ollama: settings.ollama,
+    network: settings.network,
+    trash_retention_days: settings.trashRetentionDays,
   };
 }
 
 const [settings, setSettings] = createSignal<Settings>(DEFAULT_SETTINGS);
 const [showSettings, setShowSettings] = createSignal(false);
 const [isLoading, setIsLoading] = createSignal(true);
+const [secretStore, setSecretStore] = createSignal<SecretStoreStatus>({ locked: false });
 
 // Load settings on startup
 export async function loadSettings() {
   setIsLoading(true);
   try {
+    setModelCatalog(await getModelCatalog());
     const apiSettings = await getSettingsApi();
     setSettings(fromApiSettings(apiSettings));
+    // Saved API keys can't be read until the store is unlocked, so say so right away
+    setSecretStore(await getSecretStoreStatus());
+    if (secretStore().locked) {
+      setShowSettings(true);
+    }
   } catch (e) {
     console.error("Failed to load settings:", e);
   } finally {
@@ -313,7 +371,7 @@ async function persistSettings(newSettings: Settings) {
 
 // Helper function to get model info
 export function getModelInfo(modelId: string) {
-  return AVAILABLE_MODELS.find(m => m.id === modelId);
+  return availableModels().find(m => m.id === modelId);
 }
 
 // Helper function to get default base URL for a model
@@ -391,6 +449,7 @@ export function useSettings() {
     // This allows users to explore the app and switch to local providers without being blocked
     isConfigured: () => true,
     loadSettings,
+    secretStore,
     getModelInfo,
     getDefaultBaseUrl,
     getProviderFromModel,